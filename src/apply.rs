@@ -0,0 +1,237 @@
+//! Applies [`SimState`]s received from a sim onto a client world. Entities are matched across
+//! states by their [`SimEntityId`] through an [`EntityMap`], so the client world keeps its own
+//! bevy entities. Keep the same map, for example as a resource, for every state applied to a world.
+//!
+//! Entity references inside components are remapped onto the client entities, see
+//! [`GameSerDeRegistry::register_map_entities`].
+
+use bevy::{
+    prelude::{Entity, Resource, World},
+    utils::HashMap,
+};
+
+use crate::{
+    entity_id::SimEntityId,
+    requests::SimState,
+    saving::{DeserializeReport, GameSerDeRegistry},
+};
+
+/// Maps the entities and players of a sim onto the entities of a client world
+#[derive(Default, Clone, Debug, Resource)]
+pub struct EntityMap {
+    /// The client entity of each sim entity
+    pub entities: HashMap<SimEntityId, Entity>,
+    /// The client entity of each player, by player id
+    pub players: HashMap<usize, Entity>,
+}
+
+impl EntityMap {
+    pub fn new() -> EntityMap {
+        EntityMap::default()
+    }
+
+    /// Returns the client entity of the given sim entity
+    pub fn get(&self, id: SimEntityId) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+
+    /// Maps the given sim entity onto an existing client entity, such as an entity the client
+    /// spawned optimistically before the sim assigned it an id
+    pub fn insert(&mut self, id: SimEntityId, entity: Entity) {
+        self.entities.insert(id, entity);
+    }
+}
+
+/// Applies the given state onto the given client world. Despawned entities are despawned, entities
+/// that aren't in the map yet are spawned with their [`SimEntityId`], and the components, component
+/// deltas, removed components, players, and resources of the state are applied. Returns every
+/// component, player component, and resource that failed to deserialize
+pub fn apply_sim_state(
+    world: &mut World,
+    state: &SimState,
+    registry: &GameSerDeRegistry,
+    entity_map: &mut EntityMap,
+) -> DeserializeReport {
+    let mut report = DeserializeReport::default();
+
+    for id in state.despawned_objects.iter() {
+        if let Some(entity) = entity_map.entities.remove(id) {
+            world.despawn(entity);
+        }
+    }
+
+    // Every entity is spawned before any component is applied so references between entities in
+    // the same state can be mapped
+    for entity_state in state.entities.iter() {
+        let id = entity_state.entity;
+        if entity_map
+            .get(id)
+            .is_none_or(|entity| world.get_entity(entity).is_none())
+        {
+            entity_map.insert(id, world.spawn(id).id());
+        }
+    }
+
+    for entity_state in state.entities.iter() {
+        let mut entity = world.entity_mut(entity_map.entities[&entity_state.entity]);
+        for component in entity_state.components.iter() {
+            if let Err(error) = registry.deserialize_component_onto_mapped(
+                component,
+                &mut entity,
+                &entity_map.entities,
+            ) {
+                report.entities.push((entity_state.entity, error));
+            }
+        }
+        for delta in entity_state.component_deltas.iter() {
+            if let Err(error) = registry.deserialize_component_delta_onto(delta, &mut entity) {
+                report.entities.push((entity_state.entity, error));
+            }
+        }
+    }
+
+    for (id, component) in state.removed_components.iter() {
+        if let Some(mut entity) = entity_map
+            .get(*id)
+            .and_then(|entity| world.get_entity_mut(entity))
+        {
+            registry.remove_component(*component, &mut entity);
+        }
+    }
+
+    for player_state in state.players.iter() {
+        let player_id = player_state.player_id.id();
+        let entity = match entity_map.players.get(&player_id) {
+            Some(entity) if world.get_entity(*entity).is_some() => *entity,
+            _ => {
+                let entity = world.spawn(player_state.player_id).id();
+                entity_map.players.insert(player_id, entity);
+                entity
+            }
+        };
+        let mut entity = world.entity_mut(entity);
+        for component in player_state.components.iter() {
+            if let Err(error) = registry.deserialize_component_onto_mapped(
+                component,
+                &mut entity,
+                &entity_map.entities,
+            ) {
+                report.players.push((player_id, error));
+            }
+        }
+    }
+
+    for resource in state.resources.iter() {
+        if let Err(error) = registry.deserialize_resource(resource.clone(), world) {
+            report.resources.push(error);
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::World;
+
+    use crate::{
+        change_detection::DespawnTracked,
+        entity_id::SimEntityId,
+        player::Player,
+        requests::{all_state::AllState, PlayerState, SimState},
+        saving::{ComponentBinaryState, DeserializeError},
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    use super::{apply_sim_state, EntityMap};
+
+    #[test]
+    fn test_apply_sim_state() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+        let unit = sim.sim_world.world.spawn(TestComponent(1)).id();
+        sim.simulate();
+        let id = *sim.sim_world.world.get::<SimEntityId>(unit).unwrap();
+
+        let mut client = World::new();
+        let mut entity_map = EntityMap::new();
+        let state = sim.request(AllState);
+        assert!(apply_sim_state(
+            &mut client,
+            &state,
+            &sim.sim_world.registry,
+            &mut entity_map
+        )
+        .is_ok());
+        let client_unit = entity_map.get(id).unwrap();
+        assert_eq!(
+            client.get::<TestComponent>(client_unit),
+            Some(&TestComponent(1))
+        );
+
+        sim.sim_world
+            .world
+            .entity_mut(unit)
+            .insert(TestComponent(2));
+        let state = sim.request(AllState);
+        apply_sim_state(
+            &mut client,
+            &state,
+            &sim.sim_world.registry,
+            &mut entity_map,
+        );
+        assert_eq!(entity_map.get(id), Some(client_unit));
+        assert_eq!(
+            client.get::<TestComponent>(client_unit),
+            Some(&TestComponent(2))
+        );
+
+        sim.sim_world.world.entity_mut(unit).insert(DespawnTracked);
+        sim.simulate();
+        let state = sim.request(AllState);
+        apply_sim_state(
+            &mut client,
+            &state,
+            &sim.sim_world.registry,
+            &mut entity_map,
+        );
+        assert!(entity_map.get(id).is_none());
+        assert!(client.get_entity(client_unit).is_none());
+    }
+
+    #[test]
+    fn test_apply_sim_state_reports_player_components() {
+        let game = test_game();
+        let sim = game.build_standalone();
+
+        let mut client = World::new();
+        let mut entity_map = EntityMap::new();
+        let state = SimState {
+            players: vec![PlayerState {
+                player_id: Player::new(1, false),
+                components: vec![ComponentBinaryState {
+                    id: 9999,
+                    version: 0,
+                    component: vec![],
+                }],
+                changed_tick: 0,
+            }],
+            ..Default::default()
+        };
+        let report = apply_sim_state(
+            &mut client,
+            &state,
+            &sim.sim_world.registry,
+            &mut entity_map,
+        );
+        assert!(!report.is_ok());
+        assert_eq!(report.players.len(), 1);
+        assert_eq!(report.players[0].0, 1);
+        assert!(matches!(
+            report.players[0].1,
+            DeserializeError::UnregisteredComponent { id: 9999 }
+        ));
+        assert!(entity_map.players.contains_key(&1));
+    }
+}
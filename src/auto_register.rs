@@ -0,0 +1,121 @@
+//! Opt in automatic registration of components and resources, enabled with the `auto_register`
+//! feature. Types submitted with [`auto_register_component!`](crate::auto_register_component) or
+//! [`auto_register_resource!`](crate::auto_register_resource) are collected at link time and
+//! registered all at once by [`GameBuilder::register_auto`](crate::game_builder::GameBuilder::register_auto).
+//!
+//! ```ignore
+//! #[derive(Component, Serialize, Deserialize)]
+//! struct Health(u32);
+//!
+//! bevy_sim_world::auto_register_component!(Health);
+//! ```
+
+use bevy::prelude::{Component, IntoSystemConfigs, Resource, Schedule, World};
+use bevy_trait_query::RegisterExt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    change_detection::{track_resource_changes, ComponentChangeTrackers},
+    runner::PostBaseSets,
+    saving::{GameSerDeRegistry, SaveId},
+};
+
+pub use inventory;
+
+/// A single registration collected at link time
+pub struct AutoRegistration {
+    pub register: AutoRegisterFn,
+}
+
+inventory::collect!(AutoRegistration);
+
+pub type AutoRegisterFn =
+    fn(registry: &mut GameSerDeRegistry, game_world: &mut World, game_post_schedule: &mut Schedule);
+
+/// Registers the given component the same way as
+/// [`GameBuilder::register_component`](crate::game_builder::GameBuilder::register_component)
+pub fn auto_register_component<C>(
+    registry: &mut GameSerDeRegistry,
+    game_world: &mut World,
+    _game_post_schedule: &mut Schedule,
+) where
+    C: Component + SaveId + Serialize + DeserializeOwned,
+{
+    if registry.register_component::<C>().is_err() {
+        return;
+    }
+    game_world.register_component_as::<dyn SaveId, C>();
+    game_world
+        .get_resource_or_insert_with(ComponentChangeTrackers::default)
+        .track::<C>();
+}
+
+/// Registers the given resource the same way as
+/// [`GameBuilder::register_resource`](crate::game_builder::GameBuilder::register_resource)
+pub fn auto_register_resource<R>(
+    registry: &mut GameSerDeRegistry,
+    _game_world: &mut World,
+    game_post_schedule: &mut Schedule,
+) where
+    R: Resource + SaveId + Serialize + DeserializeOwned,
+{
+    if registry.register_resource::<R>().is_err() {
+        return;
+    }
+    game_post_schedule.add_systems(track_resource_changes::<R>.in_set(PostBaseSets::Main));
+}
+
+/// Submits the given component to be registered by
+/// [`GameBuilder::register_auto`](crate::game_builder::GameBuilder::register_auto)
+#[macro_export]
+macro_rules! auto_register_component {
+    ($component:ty) => {
+        $crate::auto_register::inventory::submit! {
+            $crate::auto_register::AutoRegistration {
+                register: $crate::auto_register::auto_register_component::<$component>,
+            }
+        }
+    };
+}
+
+/// Submits the given resource to be registered by
+/// [`GameBuilder::register_auto`](crate::game_builder::GameBuilder::register_auto)
+#[macro_export]
+macro_rules! auto_register_resource {
+    ($resource:ty) => {
+        $crate::auto_register::inventory::submit! {
+            $crate::auto_register::AutoRegistration {
+                register: $crate::auto_register::auto_register_resource::<$resource>,
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        saving::SaveId,
+        testing::{fixtures::bincode_save_id, test_game},
+    };
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct TestComponent(u32);
+
+    bincode_save_id!(TestComponent, 25);
+
+    crate::auto_register_component!(TestComponent);
+
+    #[test]
+    fn test_auto_register() {
+        let mut game = test_game();
+        game.register_auto();
+
+        assert!(game
+            .game_serde_registry
+            .component_de_map
+            .contains_key(&TestComponent::save_id_const()));
+    }
+}
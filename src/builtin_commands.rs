@@ -0,0 +1,484 @@
+//! Ready made [`GameCommand`]s for common mutations of the sim world. Every command stores what it
+//! changed when executed so that it can be rolled back exactly. Register them with
+//! [`GameBuilder::register_builtin_commands`](crate::game_builder::GameBuilder::register_builtin_commands)
+//!
+//! Entities are referenced by their [`SimEntityId`] so the commands stay valid across saves and
+//! between sims. Components and resources are passed in their serialized form and must be
+//! registered in the [`GameSerDeRegistry`]
+
+use bevy::prelude::{Entity, Mut, Reflect, World};
+
+use crate::change_detection::{self, TrackedDespawns};
+use crate::command::{CommandError, GameCommand};
+use crate::entity_id::{find_sim_entity, SimEntityId};
+use crate::requests::ResourceState;
+use crate::saving::{ComponentBinaryState, GameSerDeRegistry, SimComponentId};
+
+/// Returns the entity with the given [`SimEntityId`]
+fn find_entity(world: &mut World, id: SimEntityId) -> Result<Entity, CommandError> {
+    find_sim_entity(world, id)
+        .ok_or_else(|| CommandError::ValidationFailed(format!("no entity with id {:?}", id)))
+}
+
+/// Runs the given function with the worlds [`GameSerDeRegistry`]
+fn with_registry<T>(
+    world: &mut World,
+    f: impl FnOnce(&mut World, &GameSerDeRegistry) -> Result<T, CommandError>,
+) -> Result<T, CommandError> {
+    if !world.contains_resource::<GameSerDeRegistry>() {
+        return Err(CommandError::Custom(String::from(
+            "no GameSerDeRegistry in the world",
+        )));
+    }
+    world.resource_scope(|world, registry: Mut<GameSerDeRegistry>| f(world, &registry))
+}
+
+/// Spawns a new entity with the given components. Rolling back despawns the entity again, rolling
+/// forward spawns it with the same [`SimEntityId`], reusing the entity if it hasn't been despawned
+/// yet
+#[derive(Clone, Debug, Reflect)]
+pub struct SpawnTracked {
+    pub components: Vec<ComponentBinaryState>,
+    #[reflect(ignore)]
+    pub spawned: Option<Entity>,
+    #[reflect(ignore)]
+    pub spawned_id: Option<SimEntityId>,
+}
+
+impl SpawnTracked {
+    pub fn new(components: Vec<ComponentBinaryState>) -> SpawnTracked {
+        SpawnTracked {
+            components,
+            spawned: None,
+            spawned_id: None,
+        }
+    }
+}
+
+impl GameCommand for SpawnTracked {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let components = &self.components;
+        let spawned_id = self.spawned_id;
+        // A rolled back entity is only marked to be despawned, so reuse it to not spawn its id twice
+        let pending = spawned_id.and_then(|id| find_sim_entity(world, id));
+        let entity = with_registry(world, |world, registry| {
+            let mut entity = match pending {
+                Some(entity) => world.entity_mut(entity),
+                None => world.spawn_empty(),
+            };
+            entity.remove::<change_detection::DespawnTracked>();
+            if let Some(id) = spawned_id {
+                entity.insert(id);
+            }
+            for component in components.iter() {
+                registry
+                    .deserialize_component_onto(component, &mut entity)
+                    .map_err(|error| CommandError::Custom(error.to_string()))?;
+            }
+            Ok(entity.id())
+        })?;
+        if let (Some(id), Some(mut despawns)) =
+            (spawned_id, world.get_resource_mut::<TrackedDespawns>())
+        {
+            despawns.despawned_objects.remove(&id);
+        }
+        self.spawned = Some(entity);
+        Ok(())
+    }
+
+    fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let Some(entity) = self.spawned.take() else {
+            return Err(CommandError::RollbackMismatch(String::from(
+                "entity was never spawned",
+            )));
+        };
+        let Some(mut entity) = world.get_entity_mut(entity) else {
+            return Err(CommandError::MissingEntity(entity));
+        };
+        self.spawned_id = entity.get::<SimEntityId>().copied();
+        entity.insert(change_detection::DespawnTracked);
+        Ok(())
+    }
+}
+
+/// Despawns the entity with the given [`SimEntityId`]. Rolling back respawns it with the same id
+/// and its registered components. Relations to other entities that aren't stored in components,
+/// such as its children, aren't restored
+#[derive(Clone, Debug, Reflect)]
+pub struct DespawnTracked {
+    pub entity: SimEntityId,
+    #[reflect(ignore)]
+    pub despawned: Vec<ComponentBinaryState>,
+}
+
+impl DespawnTracked {
+    pub fn new(entity: SimEntityId) -> DespawnTracked {
+        DespawnTracked {
+            entity,
+            despawned: vec![],
+        }
+    }
+}
+
+impl GameCommand for DespawnTracked {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let entity = find_entity(world, self.entity)?;
+        self.despawned = with_registry(world, |world, registry| {
+            Ok(registry.save_entity(None, world, entity))
+        })?;
+        world
+            .entity_mut(entity)
+            .insert(change_detection::DespawnTracked);
+        Ok(())
+    }
+
+    fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+        if let Ok(entity) = find_entity(world, self.entity) {
+            world
+                .entity_mut(entity)
+                .remove::<change_detection::DespawnTracked>();
+            return Ok(());
+        }
+        let id = self.entity;
+        let components = std::mem::take(&mut self.despawned);
+        with_registry(world, |world, registry| {
+            let mut entity = world.spawn(id);
+            for component in components.iter() {
+                registry
+                    .deserialize_component_onto(component, &mut entity)
+                    .map_err(|error| CommandError::Custom(error.to_string()))?;
+            }
+            Ok(())
+        })?;
+        if let Some(mut despawns) = world.get_resource_mut::<TrackedDespawns>() {
+            despawns.despawned_objects.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+/// Inserts the given component onto the entity with the given [`SimEntityId`], replacing the
+/// component if the entity already has it. Rolling back restores the replaced component
+#[derive(Clone, Debug, Reflect)]
+pub struct InsertComponent {
+    pub entity: SimEntityId,
+    pub component: ComponentBinaryState,
+    #[reflect(ignore)]
+    pub previous: Option<ComponentBinaryState>,
+}
+
+impl InsertComponent {
+    pub fn new(entity: SimEntityId, component: ComponentBinaryState) -> InsertComponent {
+        InsertComponent {
+            entity,
+            component,
+            previous: None,
+        }
+    }
+}
+
+impl GameCommand for InsertComponent {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let entity = find_entity(world, self.entity)?;
+        let component = &self.component;
+        self.previous = with_registry(world, |world, registry| {
+            let previous = registry.serialize_component(component.id, world, entity);
+            registry
+                .deserialize_component_onto(component, &mut world.entity_mut(entity))
+                .map_err(|error| CommandError::Custom(error.to_string()))?;
+            Ok(previous)
+        })?;
+        Ok(())
+    }
+
+    fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let entity = find_entity(world, self.entity)?;
+        let id = self.component.id;
+        let previous = self.previous.take();
+        with_registry(world, |world, registry| {
+            let mut entity = world.entity_mut(entity);
+            match previous {
+                Some(previous) => registry
+                    .deserialize_component_onto(&previous, &mut entity)
+                    .map_err(|error| CommandError::Custom(error.to_string())),
+                None if registry.remove_component(id, &mut entity) => Ok(()),
+                None => Err(CommandError::RollbackMismatch(format!(
+                    "component {} can't be removed",
+                    id
+                ))),
+            }
+        })
+    }
+}
+
+/// Removes the component with the given id from the entity with the given [`SimEntityId`]. Rolling
+/// back inserts the removed component again
+#[derive(Clone, Debug, Reflect)]
+pub struct RemoveComponent {
+    pub entity: SimEntityId,
+    pub component: SimComponentId,
+    #[reflect(ignore)]
+    pub removed: Option<ComponentBinaryState>,
+}
+
+impl RemoveComponent {
+    pub fn new(entity: SimEntityId, component: SimComponentId) -> RemoveComponent {
+        RemoveComponent {
+            entity,
+            component,
+            removed: None,
+        }
+    }
+}
+
+impl GameCommand for RemoveComponent {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let entity = find_entity(world, self.entity)?;
+        let id = self.component;
+        self.removed = with_registry(world, |world, registry| {
+            let removed = registry.serialize_component(id, world, entity);
+            if !registry.remove_component(id, &mut world.entity_mut(entity)) {
+                return Err(CommandError::ValidationFailed(format!(
+                    "component {} isn't registered",
+                    id
+                )));
+            }
+            Ok(removed)
+        })?;
+        Ok(())
+    }
+
+    fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let Some(removed) = self.removed.take() else {
+            return Ok(());
+        };
+        let entity = find_entity(world, self.entity)?;
+        with_registry(world, |world, registry| {
+            registry
+                .deserialize_component_onto(&removed, &mut world.entity_mut(entity))
+                .map_err(|error| CommandError::Custom(error.to_string()))
+        })
+    }
+}
+
+/// Inserts the given resource into the world, replacing it if it already exists. Rolling back
+/// restores the replaced resource
+#[derive(Clone, Debug, Reflect)]
+pub struct SetResource {
+    pub resource: ResourceState,
+    #[reflect(ignore)]
+    pub previous: Option<ResourceState>,
+}
+
+impl SetResource {
+    pub fn new(resource: ResourceState) -> SetResource {
+        SetResource {
+            resource,
+            previous: None,
+        }
+    }
+}
+
+impl GameCommand for SetResource {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let resource = self.resource.clone();
+        self.previous = with_registry(world, |world, registry| {
+            let previous = registry.serialize_resource(&resource.resource_id, world);
+            registry
+                .deserialize_resource(resource, world)
+                .map_err(|error| CommandError::Custom(error.to_string()))?;
+            Ok(previous)
+        })?;
+        Ok(())
+    }
+
+    fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let id = self.resource.resource_id;
+        let previous = self.previous.take();
+        with_registry(world, |world, registry| match previous {
+            Some(previous) => registry
+                .deserialize_resource(previous, world)
+                .map_err(|error| CommandError::Custom(error.to_string())),
+            None if registry.remove_resource(id, world) => Ok(()),
+            None => Err(CommandError::RollbackMismatch(format!(
+                "resource {} can't be removed",
+                id
+            ))),
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Resource, World};
+    use serde::{Deserialize, Serialize};
+
+    use super::{DespawnTracked, InsertComponent, RemoveComponent, SetResource, SpawnTracked};
+    use crate::{
+        change_detection,
+        command::{GameCommand, GameCommands},
+        entity_id::{assign_sim_entity_ids, SimEntityId},
+        requests::ResourceState,
+        saving::{ComponentBinaryState, GameSerDeRegistry},
+        testing::fixtures::{bincode_save_id, TestComponent},
+    };
+
+    #[derive(Clone, Debug, PartialEq, Resource, Serialize, Deserialize)]
+    struct Score(u32);
+
+    bincode_save_id!(Score, 26);
+
+    fn health(world: &World, health: u32) -> ComponentBinaryState {
+        ComponentBinaryState {
+            id: 25,
+            version: 0,
+            component: world
+                .resource::<GameSerDeRegistry>()
+                .pack_payload(bincode::serialize(&TestComponent(health)).unwrap()),
+        }
+    }
+
+    fn score(world: &World, score: u32) -> ResourceState {
+        ResourceState {
+            resource_id: 26,
+            version: 0,
+            resource: world
+                .resource::<GameSerDeRegistry>()
+                .pack_payload(bincode::serialize(&Score(score)).unwrap()),
+            changed_tick: 0,
+        }
+    }
+
+    #[test]
+    fn test_builtin_commands_rollback() {
+        let mut world = World::new();
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        registry.register_resource::<Score>().unwrap();
+        world.insert_resource(registry);
+        world.insert_resource(change_detection::TrackedDespawns {
+            despawned_objects: Default::default(),
+        });
+
+        let mut spawn = SpawnTracked::new(vec![health(&world, 5)]);
+        spawn.execute(&mut world).unwrap();
+        assign_sim_entity_ids(&mut world);
+        let entity = spawn.spawned.unwrap();
+        let id = *world.get::<SimEntityId>(entity).unwrap();
+        assert_eq!(world.get::<TestComponent>(entity), Some(&TestComponent(5)));
+
+        let mut insert = InsertComponent::new(id, health(&world, 9));
+        insert.execute(&mut world).unwrap();
+        assert_eq!(world.get::<TestComponent>(entity), Some(&TestComponent(9)));
+        insert.rollback(&mut world).unwrap();
+        assert_eq!(world.get::<TestComponent>(entity), Some(&TestComponent(5)));
+
+        let mut remove = RemoveComponent::new(id, 25);
+        remove.execute(&mut world).unwrap();
+        assert_eq!(world.get::<TestComponent>(entity), None);
+        remove.rollback(&mut world).unwrap();
+        assert_eq!(world.get::<TestComponent>(entity), Some(&TestComponent(5)));
+
+        let mut set = SetResource::new(score(&world, 3));
+        set.execute(&mut world).unwrap();
+        assert_eq!(world.get_resource::<Score>(), Some(&Score(3)));
+        set.rollback(&mut world).unwrap();
+        assert_eq!(world.get_resource::<Score>(), None);
+
+        let mut despawn = DespawnTracked::new(id);
+        despawn.execute(&mut world).unwrap();
+        world.despawn(entity);
+        despawn.rollback(&mut world).unwrap();
+        let mut query = world.query::<(&SimEntityId, &TestComponent)>();
+        assert_eq!(query.single(&world), (&id, &TestComponent(5)));
+    }
+
+    #[test]
+    fn test_spawn_rollforward_reuses_pending_entity() {
+        let mut world = World::new();
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        world.insert_resource(registry);
+
+        let mut spawn = SpawnTracked::new(vec![health(&world, 5)]);
+        spawn.execute(&mut world).unwrap();
+        assign_sim_entity_ids(&mut world);
+        let entity = spawn.spawned.unwrap();
+        spawn.rollback(&mut world).unwrap();
+        assert!(world
+            .get::<change_detection::DespawnTracked>(entity)
+            .is_some());
+
+        spawn.execute(&mut world).unwrap();
+        assert_eq!(spawn.spawned, Some(entity));
+        assert!(world
+            .get::<change_detection::DespawnTracked>(entity)
+            .is_none());
+        let mut query = world.query::<&SimEntityId>();
+        assert_eq!(query.iter(&world).count(), 1);
+    }
+
+    #[test]
+    fn test_preview_command() {
+        let mut world = World::new();
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        world.insert_resource(registry);
+        let entity = world.spawn(TestComponent(5)).id();
+        assign_sim_entity_ids(&mut world);
+        let id = *world.get::<SimEntityId>(entity).unwrap();
+
+        let command = InsertComponent::new(id, health(&world, 9));
+        let state = GameCommands::new().preview(&mut world, command).unwrap();
+        assert_eq!(world.get::<TestComponent>(entity), Some(&TestComponent(5)));
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, id);
+        assert_eq!(state.entities[0].changed_components, vec![25]);
+        assert_eq!(state.entities[0].components, vec![health(&world, 9)]);
+    }
+
+    #[test]
+    fn test_spawned_ids_returned() {
+        let mut world = World::new();
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        world.insert_resource(registry);
+        world.spawn(TestComponent(1));
+
+        let mut game_commands = GameCommands::new();
+        game_commands.add(SpawnTracked::new(vec![health(&world, 5)]));
+        let results = game_commands.execute_buffer(&mut world);
+        let entity = game_commands.history.history[0]
+            .command
+            .as_reflect()
+            .downcast_ref::<SpawnTracked>()
+            .unwrap()
+            .spawned
+            .unwrap();
+        assert_eq!(
+            results[0].spawned,
+            vec![*world.get::<SimEntityId>(entity).unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_spawned_ids_include_reused_ids() {
+        let mut world = World::new();
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        world.insert_resource(registry);
+        world.spawn(TestComponent(1));
+
+        let mut spawn = SpawnTracked::new(vec![health(&world, 5)]);
+        spawn.spawned_id = Some(SimEntityId(7));
+        let mut game_commands = GameCommands::new();
+        game_commands.add(spawn);
+        let results = game_commands.execute_buffer(&mut world);
+        assert_eq!(results[0].spawned, vec![SimEntityId(7)]);
+
+        game_commands.add(SpawnTracked::new(vec![health(&world, 6)]));
+        let results = game_commands.execute_buffer(&mut world);
+        assert_eq!(results[0].spawned.len(), 1);
+        assert_ne!(results[0].spawned, vec![SimEntityId(7)]);
+    }
+}
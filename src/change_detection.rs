@@ -1,28 +1,103 @@
 use bevy::{
+    ecs::{component::ComponentId, system::BoxedSystem},
     prelude::{
-        Commands, Component, DespawnRecursiveExt, DetectChanges, Entity, Mut, Query,
-        RemovedComponents, ResMut, Resource, With, World,
+        Children, Commands, Component, DespawnRecursiveExt, DetectChanges, Entity,
+        HierarchyQueryExt, IntoSystem, Mut, Parent, Query, RemovedComponents, Res, ResMut,
+        Resource, With, World,
     },
     reflect::Reflect,
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
 use serde::{Deserialize, Serialize};
+use std::{any::TypeId, collections::VecDeque};
 
 use crate::{
+    change_filter::{ChangeFilter, ReplicationInterval},
+    entity_id::SimEntityId,
     player::Player,
-    saving::{SaveId, SimResourceId},
+    runner::SimTick,
+    saving::{ResourceSaveComponentIdMap, SaveId, SimResourceId},
 };
 
-#[derive(Default, Clone, Eq, Debug, PartialEq, Component, Reflect, Serialize, Deserialize)]
+/// A set of player ids stored as a bitmask, one bit per player id
+#[derive(Default, Clone, Eq, Hash, Debug, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct PlayerMask {
+    words: Vec<u64>,
+}
+
+impl PlayerMask {
+    pub fn contains(&self, id: usize) -> bool {
+        self.words
+            .get(id / 64)
+            .is_some_and(|word| word & (1 << (id % 64)) != 0)
+    }
+
+    pub fn insert(&mut self, id: usize) {
+        let word = id / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (id % 64);
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if let Some(word) = self.words.get_mut(id / 64) {
+            *word &= !(1 << (id % 64));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    /// Keeps only the ids the given function returns true for
+    pub fn retain(&mut self, mut keep: impl FnMut(usize) -> bool) {
+        for id in self.iter().collect::<Vec<usize>>() {
+            if !keep(id) {
+                self.remove(id);
+            }
+        }
+    }
+
+    /// Returns every id in the mask in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(index, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| index * 64 + bit)
+        })
+    }
+
+    /// Drops trailing empty words and releases unused capacity
+    pub fn shrink_to_fit(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+        self.words.shrink_to_fit();
+    }
+}
+
+/// A change to an entity, despawn, or resource and the players that have been sent it
+#[derive(Default, Clone, Eq, Debug, PartialEq, Reflect, Serialize, Deserialize)]
 pub struct SimChanged {
-    pub players_seen: Vec<usize>,
+    pub players_seen: PlayerMask,
+    /// The sim tick the change happened at, see [`SimTick`]
+    pub tick: u64,
 }
 
 impl SimChanged {
+    /// Creates a change that happened at the given sim tick and hasn't been seen by any player
+    pub fn at_tick(tick: u64) -> SimChanged {
+        SimChanged {
+            players_seen: PlayerMask::default(),
+            tick,
+        }
+    }
+
     /// Checks if all players that are marked as needs_state have been registered and returns the result
-    pub fn all_seen(&self, players: &Vec<Player>) -> bool {
+    pub fn all_seen(&self, players: &[Player]) -> bool {
         for player in players.iter() {
-            if player.needs_state && !self.players_seen.contains(&player.id()) {
+            if player.needs_state && !self.players_seen.contains(player.id()) {
                 return false;
             }
         }
@@ -33,37 +108,305 @@ impl SimChanged {
     /// id hasn't seen the changes then it marks it as seen and returns false. If the player id has seen
     /// the changes then it does nothing and returns true.
     pub fn check_and_register_seen(&mut self, id: usize) -> bool {
-        return if self.players_seen.contains(&id) {
+        if self.players_seen.contains(id) {
             true
         } else {
             self.register_seen(id);
             false
-        };
+        }
     }
 
     /// Registers the given id.
     pub fn register_seen(&mut self, id: usize) {
-        self.players_seen.push(id);
+        self.players_seen.insert(id);
+    }
+
+    /// Unregisters the given id so the change is sent to that player again
+    pub fn unregister_seen(&mut self, id: usize) {
+        self.players_seen.remove(id);
     }
 
     /// Checks if the given player id has been registered and returns the results
     pub fn was_seen(&mut self, id: usize) -> bool {
-        return self.players_seen.contains(&id);
+        self.players_seen.contains(id)
+    }
+}
+
+/// Resource inserted into the world holding the [`SimChanged`] of every changed entity. Kept in a
+/// single resource instead of a component so marking thousands of entities changed every tick
+/// doesn't move them between archetypes
+#[derive(Default, Clone, Eq, Debug, PartialEq, Resource)]
+pub struct ChangeSet {
+    pub entities: HashMap<Entity, SimChanged>,
+    /// The components that changed on each changed entity since it was last removed from the set
+    pub components: HashMap<Entity, Vec<TypeId>>,
+}
+
+impl ChangeSet {
+    /// Marks the given entity as changed at the given tick, resetting the players that have seen it
+    pub fn mark_changed(&mut self, entity: Entity, tick: u64) {
+        self.entities.insert(entity, SimChanged::at_tick(tick));
+    }
+
+    /// Marks the given entity as changed at the given tick and records that the component with the
+    /// given type id changed
+    pub fn mark_component_changed(&mut self, entity: Entity, component: TypeId, tick: u64) {
+        self.mark_changed(entity, tick);
+        let components = self.components.entry(entity).or_default();
+        if !components.contains(&component) {
+            components.push(component);
+        }
+    }
+
+    /// Returns the type ids of the components that changed on the given entity
+    pub fn changed_components(&self, entity: Entity) -> &[TypeId] {
+        self.components
+            .get(&entity)
+            .map(|components| components.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&SimChanged> {
+        self.entities.get(&entity)
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut SimChanged> {
+        self.entities.get_mut(&entity)
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<SimChanged> {
+        self.components.remove(&entity);
+        self.entities.remove(&entity)
+    }
+
+    /// Keeps only the changed entities the given predicate passes
+    pub fn retain(&mut self, mut keep: impl FnMut(Entity, &mut SimChanged) -> bool) {
+        self.entities
+            .retain(|entity, changed| keep(*entity, changed));
+        let entities = &self.entities;
+        self.components
+            .retain(|entity, _| entities.contains_key(entity));
+    }
+
+    /// Returns the changed entities sorted by entity so state built from them is deterministic
+    pub fn sorted_entities(&self) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.entities.keys().copied().collect();
+        entities.sort();
+        entities
     }
 }
 
 /// Resource inserted into the world that will be used to drive sending despawned object updates
 #[derive(Clone, Eq, Debug, PartialEq, Resource, Reflect, Serialize, Deserialize)]
 pub struct TrackedDespawns {
-    pub despawned_objects: HashMap<Entity, SimChanged>,
+    pub despawned_objects: HashMap<SimEntityId, SimChanged>,
 }
 
-/// Resource inserted into the world that will be used to drive sending resource changed updates
-#[derive(Clone, Eq, Debug, PartialEq, Resource)]
+/// Resource inserted into the world that will be used to drive sending resource changed updates.
+/// Changes are keyed by the resources [`ComponentId`] so resources sharing a save id are tracked
+/// separately
+#[derive(Default, Clone, Eq, Debug, PartialEq, Resource)]
 pub struct ResourceChangeTracking {
-    pub resources: HashMap<SimResourceId, SimChanged>,
+    pub resources: HashMap<ComponentId, SimChanged>,
+    /// The save ids of every tracked resource
+    pub resource_ids: ResourceSaveComponentIdMap,
+}
+
+impl ResourceChangeTracking {
+    /// Marks the resource with the given save id changed. Returns false if no resource with the id
+    /// has been tracked yet
+    pub fn mark_changed(&mut self, id: SimResourceId, tick: u64) -> bool {
+        let Some(component_id) = self.resource_ids.get_component_id(id).copied() else {
+            return false;
+        };
+        self.resources
+            .insert(component_id, SimChanged::at_tick(tick));
+        true
+    }
+
+    /// Returns the change of the resource with the given save id
+    pub fn get_by_save_id(&self, id: SimResourceId) -> Option<&SimChanged> {
+        self.resource_ids
+            .get_component_id(id)
+            .and_then(|component_id| self.resources.get(component_id))
+    }
+
+    /// Returns the save id and change of every changed resource
+    pub fn changed(&self) -> impl Iterator<Item = (SimResourceId, &SimChanged)> {
+        self.resources.iter().filter_map(|(component_id, changed)| {
+            self.resource_ids
+                .get_save_id(*component_id)
+                .map(|id| (*id, changed))
+        })
+    }
+}
+
+/// The entities, despawns, and resources that changed at a single sim tick
+#[derive(Default, Clone, Eq, Debug, PartialEq)]
+pub struct TickChanges {
+    pub tick: u64,
+    pub entities: Vec<SimEntityId>,
+    pub despawned_objects: Vec<SimEntityId>,
+    pub resources: Vec<SimResourceId>,
 }
 
+/// Resource holding what changed in each of the last [`ChangeHistory::capacity`] ticks, oldest
+/// first. Used by [`StateSince`](crate::requests::state_since::StateSince) to send a reconnecting
+/// player only the changes they missed
+#[derive(Clone, Eq, Debug, PartialEq, Resource)]
+pub struct ChangeHistory {
+    /// The maximum number of ticks kept
+    pub capacity: usize,
+    ticks: VecDeque<TickChanges>,
+}
+
+impl ChangeHistory {
+    pub fn new(capacity: usize) -> ChangeHistory {
+        ChangeHistory {
+            capacity,
+            ticks: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Stores the changes of a tick, replacing any stored ticks at or after it, and prunes the
+    /// oldest ticks if the history is over capacity
+    pub fn push(&mut self, changes: TickChanges) {
+        self.ticks.retain(|tick| tick.tick < changes.tick);
+        self.ticks.push_back(changes);
+        while self.ticks.len() > self.capacity {
+            self.ticks.pop_front();
+        }
+    }
+
+    /// Returns true if the history holds every tick after the given tick, so the changes since it
+    /// can be served from the history
+    pub fn covers(&self, since_tick: u64) -> bool {
+        self.ticks
+            .front()
+            .is_some_and(|oldest| oldest.tick <= since_tick.saturating_add(1))
+    }
+
+    /// Returns the changes of every stored tick after the given tick, oldest first
+    pub fn since(&self, since_tick: u64) -> impl Iterator<Item = &TickChanges> {
+        self.ticks.iter().filter(move |tick| tick.tick > since_tick)
+    }
+
+    pub fn oldest_tick(&self) -> Option<u64> {
+        self.ticks.front().map(|tick| tick.tick)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+    }
+}
+
+/// Records what changed in the current tick into the [`ChangeHistory`] of the given world if it has
+/// one. Called by the [`GameRuntime`](crate::runner::GameRuntime) after every simulated tick
+pub fn record_change_history(world: &mut World) {
+    if !world.contains_resource::<ChangeHistory>() {
+        return;
+    }
+    let tick = world
+        .get_resource::<SimTick>()
+        .map(|tick| tick.0)
+        .unwrap_or_default();
+    let mut changes = TickChanges {
+        tick,
+        ..Default::default()
+    };
+
+    if let Some(change_set) = world.get_resource::<ChangeSet>() {
+        for (entity, changed) in change_set.entities.iter() {
+            if changed.tick != tick {
+                continue;
+            }
+            if let Some(id) = world.get::<SimEntityId>(*entity) {
+                changes.entities.push(*id);
+            }
+        }
+        changes.entities.sort();
+    }
+    if let Some(despawns) = world.get_resource::<TrackedDespawns>() {
+        for (id, changed) in despawns.despawned_objects.iter() {
+            if changed.tick == tick {
+                changes.despawned_objects.push(*id);
+            }
+        }
+    }
+    if let Some(tracking) = world.get_resource::<ResourceChangeTracking>() {
+        for (id, changed) in tracking.changed() {
+            if changed.tick == tick && !changes.resources.contains(&id) {
+                changes.resources.push(id);
+            }
+        }
+    }
+
+    world.resource_mut::<ChangeHistory>().push(changes);
+}
+
+/// Resource that makes marking an entity changed also mark the rest of its hierarchy changed, so
+/// clients don't receive a moved parent without its children. See [`propagate_hierarchy_changes`]
+#[derive(Default, Clone, Copy, Eq, Debug, PartialEq, Resource)]
+pub struct HierarchyPropagation {
+    /// Marks every descendant of a changed entity changed
+    pub to_children: bool,
+    /// Marks every ancestor of a changed entity changed
+    pub to_parents: bool,
+}
+
+/// Marks the descendants and or ancestors of every entity changed in the current tick changed,
+/// depending on the [`HierarchyPropagation`] resource
+pub fn propagate_hierarchy_changes(
+    propagation: Res<HierarchyPropagation>,
+    mut change_set: ResMut<ChangeSet>,
+    children: Query<&Children>,
+    parents: Query<&Parent>,
+    ignored: Query<(), With<ReplicationIgnore>>,
+    tick: Option<Res<SimTick>>,
+) {
+    let tick = tick.map(|tick| tick.0).unwrap_or_default();
+    let changed: Vec<Entity> = change_set
+        .entities
+        .iter()
+        .filter(|(_, changed)| changed.tick == tick)
+        .map(|(entity, _)| *entity)
+        .collect();
+
+    let mut related: Vec<Entity> = vec![];
+    for entity in changed {
+        if propagation.to_children {
+            related.extend(children.iter_descendants(entity));
+        }
+        if propagation.to_parents {
+            related.extend(parents.iter_ancestors(entity));
+        }
+    }
+    for entity in related {
+        if ignored.contains(entity)
+            || change_set
+                .get(entity)
+                .is_some_and(|changed| changed.tick == tick && changed.players_seen.is_empty())
+        {
+            continue;
+        }
+        change_set.mark_changed(entity, tick);
+    }
+}
+
+/// Marker component for server only entities, such as pathfinding scratch or AI blackboards. Entities
+/// with it are never marked changed and never included in any state request. Insert it when the
+/// entity is spawned, entities already sent to players aren't removed from them
+#[derive(Default, Clone, Copy, Debug, Component, Reflect)]
+pub struct ReplicationIgnore;
+
 /// Component inserted onto an entity that despawns it and includes that entity into [`TrackedDespawns`] resource
 #[derive(Component)]
 pub struct DespawnTracked;
@@ -72,48 +415,195 @@ pub struct DespawnTracked;
 /// entities and updating the DespawnedObjects resource
 pub fn despawn_objects(
     mut commands: Commands,
-    query: Query<Entity, With<DespawnTracked>>,
+    query: Query<(Entity, Option<&SimEntityId>), With<DespawnTracked>>,
     mut despawns: ResMut<TrackedDespawns>,
+    mut change_set: ResMut<ChangeSet>,
+    tick: Option<Res<SimTick>>,
 ) {
-    for entity in query.iter() {
-        despawns
-            .despawned_objects
-            .insert(entity, SimChanged::default());
+    let tick = tick.map(|tick| tick.0).unwrap_or_default();
+    for (entity, opt_id) in query.iter() {
+        change_set.remove(entity);
+        if let Some(id) = opt_id {
+            despawns
+                .despawned_objects
+                .insert(*id, SimChanged::at_tick(tick));
+        }
 
         commands.entity(entity).despawn_recursive();
     }
 }
 
-/// For every entity containing the given component that has changed, inserts a Changed::default() component
+/// Component inserted onto an entity that had tracked components removed. Removed components stay
+/// listed until they are added back and are reported in
+/// [`SimState::removed_components`](crate::requests::SimState::removed_components)
+#[derive(Default, Clone, Eq, Debug, PartialEq, Component)]
+pub struct SimRemovedComponents {
+    pub components: Vec<TypeId>,
+}
+
+/// For every entity containing the given component that has changed, marks the entity changed in the
+/// [`ChangeSet`]. Changes are skipped if the components [`ChangeFilter`] doesn't pass them, and held
+/// back until the next due tick if the component has a [`ReplicationInterval`]. Removals of the component
+/// are also recorded in [`SimRemovedComponents`]
 pub fn track_component_changes<C: Component>(
     mut commands: Commands,
-    query: Query<Entity, bevy::prelude::Changed<C>>,
+    query: Query<(Entity, &C, Option<&SimRemovedComponents>), bevy::prelude::Changed<C>>,
+    ignored: Query<(), With<ReplicationIgnore>>,
     mut removed_components: RemovedComponents<C>,
+    mut filter: Option<ResMut<ChangeFilter<C>>>,
+    mut interval: Option<ResMut<ReplicationInterval<C>>>,
+    (mut change_set, tick): (ResMut<ChangeSet>, Option<Res<SimTick>>),
 ) {
-    for entity in query.iter() {
-        commands.entity(entity).insert(SimChanged::default());
+    let tick = tick.map(|tick| tick.0).unwrap_or_default();
+    let due = interval
+        .as_ref()
+        .is_none_or(|interval| interval.is_due(tick));
+    for (entity, component, removed) in query.iter() {
+        if ignored.contains(entity) {
+            continue;
+        }
+        if filter
+            .as_mut()
+            .is_none_or(|filter| filter.0.changed(entity, component))
+        {
+            match interval.as_mut() {
+                Some(interval) if !due => {
+                    interval.pending.insert(entity);
+                }
+                _ => change_set.mark_component_changed(entity, TypeId::of::<C>(), tick),
+            }
+        }
+        if removed.is_some_and(|removed| removed.components.contains(&TypeId::of::<C>())) {
+            commands.add(move |world: &mut World| {
+                if let Some(mut removed) = world.get_mut::<SimRemovedComponents>(entity) {
+                    removed
+                        .components
+                        .retain(|type_id| *type_id != TypeId::of::<C>());
+                }
+            });
+        }
+    }
+
+    if let Some(interval) = interval.as_mut().filter(|_| due) {
+        for entity in interval.pending.drain() {
+            if commands.get_entity(entity).is_some() {
+                change_set.mark_component_changed(entity, TypeId::of::<C>(), tick);
+            }
+        }
     }
 
     for entity in removed_components.read() {
-        if let Some(mut entity_commands) = commands.get_entity(entity) {
-            entity_commands.insert(SimChanged::default());
+        if let Some(filter) = filter.as_mut() {
+            filter.0.removed(entity);
+        }
+        if commands.get_entity(entity).is_some() && !ignored.contains(entity) {
+            change_set.mark_changed(entity, tick);
+            commands.add(move |world: &mut World| {
+                let Some(mut entity) = world.get_entity_mut(entity) else {
+                    return;
+                };
+                if entity.contains::<C>() {
+                    return;
+                }
+                match entity.get_mut::<SimRemovedComponents>() {
+                    Some(mut removed) if !removed.components.contains(&TypeId::of::<C>()) => {
+                        removed.components.push(TypeId::of::<C>())
+                    }
+                    Some(_) => {}
+                    None => {
+                        entity.insert(SimRemovedComponents {
+                            components: vec![TypeId::of::<C>()],
+                        });
+                    }
+                }
+            });
         }
     }
 }
 
+/// Resource holding the change tracking system of every tracked component. They are all run by the
+/// single [`track_registered_components`] system instead of each being added to the game post
+/// schedule, so registering hundreds of components doesn't add hundreds of systems
+#[derive(Default, Resource)]
+pub struct ComponentChangeTrackers {
+    tracked: HashSet<TypeId>,
+    systems: Vec<BoxedSystem>,
+    initialized: usize,
+}
+
+impl ComponentChangeTrackers {
+    /// Tracks changes of the given component with [`track_component_changes`]. Returns false if the
+    /// component is already tracked
+    pub fn track<C: Component>(&mut self) -> bool {
+        if !self.tracked.insert(TypeId::of::<C>()) {
+            return false;
+        }
+        self.systems.push(Box::new(IntoSystem::into_system(
+            track_component_changes::<C>,
+        )));
+        true
+    }
+
+    /// Returns true if changes of the given component are tracked
+    pub fn is_tracked<C: Component>(&self) -> bool {
+        self.tracked.contains(&TypeId::of::<C>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+}
+
+/// Exclusive system that runs the tracking system of every component in the
+/// [`ComponentChangeTrackers`], then applies their commands. Added to the game post schedule by
+/// [`GameBuilder::default_game_post_schedule`](crate::game_builder::GameBuilder::default_game_post_schedule)
+pub fn track_registered_components(world: &mut World) {
+    if !world.contains_resource::<ComponentChangeTrackers>() {
+        return;
+    }
+    world.resource_scope(|world, mut trackers: Mut<ComponentChangeTrackers>| {
+        let trackers = trackers.as_mut();
+        for system in trackers.systems[trackers.initialized..].iter_mut() {
+            system.initialize(world);
+        }
+        trackers.initialized = trackers.systems.len();
+        for system in trackers.systems.iter_mut() {
+            system.run((), world);
+        }
+        for system in trackers.systems.iter_mut() {
+            system.apply_deferred(world);
+        }
+    });
+}
+
 /// Checks if the given resource has changed and if so inserts its ComponentId into the
-/// ResourceChangeTracking resource
+/// ResourceChangeTracking resource. Also records the resources save id the first time it runs
 pub fn track_resource_changes<R: Resource + SaveId>(world: &mut World) {
     if !world.contains_resource::<R>() {
         return;
     }
+    let Some(component_id) = world.components().resource_id::<R>() else {
+        return;
+    };
+    let tick = world
+        .get_resource::<SimTick>()
+        .map(|tick| tick.0)
+        .unwrap_or_default();
     world.resource_scope(|world, resource: Mut<R>| {
+        let mut tracking = world.resource_mut::<ResourceChangeTracking>();
+        if tracking.resource_ids.get_save_id(component_id).is_none() {
+            tracking
+                .resource_ids
+                .register_resource(component_id, resource.save_id());
+        }
         if resource.is_changed() {
-            world.resource_scope(|_world, mut resources: Mut<ResourceChangeTracking>| {
-                resources
-                    .resources
-                    .insert(resource.save_id(), SimChanged::default());
-            });
+            tracking
+                .resources
+                .insert(component_id, SimChanged::at_tick(tick));
         }
     });
 }
@@ -121,48 +611,34 @@ pub fn track_resource_changes<R: Resource + SaveId>(world: &mut World) {
 #[cfg(test)]
 pub mod test {
     use bevy::{
-        prelude::{Component, Mut, Resource, World},
+        prelude::{BuildWorldChildren, Component, Mut, Resource, World},
         reflect::Reflect,
     };
     use serde::{Deserialize, Serialize};
 
+    use super::{
+        ChangeHistory, ChangeSet, ComponentChangeTrackers, HierarchyPropagation, PlayerMask,
+        ReplicationIgnore, ResourceChangeTracking, TickChanges,
+    };
     use crate::{
-        game_builder::GameBuilder,
-        requests::state_dif::StateDif,
+        entity_id::SimEntityId,
+        player::PlayerList,
+        requests::{all_state::AllState, state_dif::StateDif},
         runner::{GameRuntime, TurnBasedGameRunner},
-        saving::{SaveId, SimComponentId},
+        saving::SimComponentId,
+        testing::{
+            fixtures::{bincode_save_id, TestComponent},
+            mock_players, test_game,
+        },
         SimWorld,
     };
 
-    #[derive(Default, Component, Serialize, Deserialize, Reflect)]
-    struct TestComponent(u32);
-
-    impl SaveId for TestComponent {
-        fn save_id(&self) -> SimComponentId {
-            25
-        }
-
-        fn save_id_const() -> SimComponentId
-        where
-            Self: Sized,
-        {
-            25
-        }
-
-        #[doc = r" Serializes the state of the object at the given tick into binary. Only saves the keyframe and not the curve itself"]
-        fn to_binary(&self) -> Option<Vec<u8>> {
-            bincode::serialize(self).ok()
-        }
-    }
-
     // TODO: write tests for this
     #[test]
     fn test_component_change_tracking() {
         let mut world = World::new();
-        let mut game = GameBuilder::<TurnBasedGameRunner>::new_game(TurnBasedGameRunner {
-            turn_schedule: Default::default(),
-        });
-        game.register_component::<TestComponent>();
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
         game.build(&mut world);
 
         let mut game = world.remove_resource::<SimWorld>().unwrap();
@@ -174,7 +650,7 @@ pub mod test {
 
         game_runtime.simulate(&mut game.world);
 
-        let mut first_state = game.request(StateDif { for_player: 0 });
+        let first_state = game.request(StateDif { for_player: 0 });
 
         let mut entity_mut = game.world.entity_mut(entity);
         let mut component = entity_mut.get_mut::<TestComponent>().unwrap();
@@ -182,74 +658,334 @@ pub mod test {
 
         game_runtime.simulate(&mut game.world);
 
-        let mut second_state = game.request(StateDif { for_player: 0 });
+        let second_state = game.request(StateDif { for_player: 0 });
 
-        let components = first_state.entities.pop().unwrap().components;
+        let (_, test_component_1) = first_state.components::<TestComponent>().next().unwrap();
+        let (_, test_component_2) = second_state.components::<TestComponent>().next().unwrap();
 
-        let test_component_1 = components
-            .iter()
-            .find(|item| {
-                if let Some(_) = bincode::deserialize::<TestComponent>(&item.component).ok() {
-                    return true;
-                }
-                false
-            })
+        assert_eq!(test_component_1.0, 0);
+        assert_eq!(test_component_2.0, 1);
+    }
+
+    #[derive(Default, Clone, Debug, PartialEq, Component, Serialize, Deserialize)]
+    struct OtherTrackedComponent(u32);
+
+    bincode_save_id!(OtherTrackedComponent, 26);
+
+    #[test]
+    fn test_component_trackers_share_one_system() {
+        let mut game = test_game();
+        let systems = game.game_post_schedule.graph().systems().count();
+        game.register_component::<TestComponent>().unwrap();
+        game.register_component::<OtherTrackedComponent>().unwrap();
+        game.register_component_track_changes::<TestComponent>();
+        assert_eq!(game.game_post_schedule.graph().systems().count(), systems);
+        assert_eq!(
+            game.game_world.resource::<ComponentChangeTrackers>().len(),
+            2
+        );
+
+        let mut world = World::new();
+        game.build(&mut world);
+        let mut game = world.remove_resource::<SimWorld>().unwrap();
+        let mut game_runtime = world
+            .remove_resource::<GameRuntime<TurnBasedGameRunner>>()
             .unwrap();
-        let Ok(test_component_1) =
-            bincode::deserialize::<TestComponent>(&test_component_1.component)
-        else {
-            panic!("Couldn't find component")
-        };
 
-        let components = second_state.entities.pop().unwrap().components;
+        let first = game.world.spawn(TestComponent(0)).id();
+        let second = game.world.spawn(OtherTrackedComponent(0)).id();
+        game_runtime.simulate(&mut game.world);
+        game.world.resource_mut::<ChangeSet>().entities.clear();
 
-        let test_component_2 = components
-            .iter()
-            .find(|item| {
-                if let Some(_) = bincode::deserialize::<TestComponent>(&item.component).ok() {
-                    return true;
-                }
-                false
+        game.world
+            .get_mut::<OtherTrackedComponent>(second)
+            .unwrap()
+            .0 += 1;
+        game_runtime.simulate(&mut game.world);
+
+        let change_set = game.world.resource::<ChangeSet>();
+        assert!(change_set.get(first).is_none());
+        assert!(change_set.get(second).is_some());
+    }
+
+    #[test]
+    fn test_change_set() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let players = mock_players(&mut game, 1);
+        let mut sim = game.build_standalone();
+        let entity = sim.sim_world.world.spawn(TestComponent(0)).id();
+        sim.simulate();
+        let archetype = sim.sim_world.world.entity(entity).archetype().id();
+
+        assert!(sim
+            .sim_world
+            .world
+            .resource::<ChangeSet>()
+            .get(entity)
+            .is_some());
+        assert_eq!(
+            sim.request(StateDif {
+                for_player: players[0]
             })
-            .unwrap();
-        let Ok(test_component_2) =
-            bincode::deserialize::<TestComponent>(&test_component_2.component)
-        else {
-            panic!("Couldn't find component")
-        };
+            .entities
+            .len(),
+            1
+        );
+        assert!(sim
+            .request(StateDif {
+                for_player: players[0]
+            })
+            .entities
+            .is_empty());
 
-        assert_eq!(test_component_1.0, 0);
-        assert_eq!(test_component_2.0, 1);
+        let player_list = sim.sim_world.player_list.clone();
+        sim.sim_world.clear_changed(&player_list);
+        assert!(sim
+            .sim_world
+            .world
+            .resource::<ChangeSet>()
+            .entities
+            .is_empty());
+
+        sim.sim_world
+            .world
+            .get_mut::<TestComponent>(entity)
+            .unwrap()
+            .0 += 1;
+        sim.simulate();
+        assert!(sim
+            .sim_world
+            .world
+            .resource::<ChangeSet>()
+            .get(entity)
+            .is_some());
+        assert_eq!(
+            sim.sim_world.world.entity(entity).archetype().id(),
+            archetype
+        );
     }
 
-    #[derive(Default, Resource, Reflect, Serialize, Deserialize)]
-    struct TestResource(u32);
+    #[test]
+    fn test_changed_components() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.register_component::<OtherTrackedComponent>().unwrap();
+        let players = mock_players(&mut game, 1);
+        let mut sim = game.build_standalone();
+        let entity = sim
+            .sim_world
+            .world
+            .spawn((TestComponent(0), OtherTrackedComponent(0)))
+            .id();
+        sim.simulate();
 
-    impl SaveId for TestResource {
-        fn save_id(&self) -> SimComponentId {
-            25
-        }
+        let state = sim.request(StateDif {
+            for_player: players[0],
+        });
+        let mut changed = state.entities[0].changed_components.clone();
+        changed.sort();
+        assert_eq!(changed, vec![25, 26]);
 
-        fn save_id_const() -> SimComponentId
-        where
-            Self: Sized,
-        {
-            25
+        let player_list = sim.sim_world.player_list.clone();
+        sim.sim_world.clear_changed(&player_list);
+        sim.sim_world
+            .world
+            .get_mut::<OtherTrackedComponent>(entity)
+            .unwrap()
+            .0 += 1;
+        sim.simulate();
+
+        let state = sim.request(StateDif {
+            for_player: players[0],
+        });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].changed_components, vec![26]);
+    }
+
+    #[test]
+    fn test_state_sequences() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+        sim.sim_world.world.spawn(TestComponent(0));
+
+        sim.simulate();
+        let first_state = sim.request(StateDif { for_player: 0 });
+        sim.simulate();
+        let second_state = sim.request(StateDif { for_player: 0 });
+
+        assert_eq!(first_state.sequence, Some(0));
+        assert_eq!(second_state.sequence, Some(1));
+        assert_eq!(sim.sim_world.latest_sequence(0), Some(1));
+    }
+
+    #[test]
+    fn test_replication_ignore() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+
+        let entity = sim
+            .sim_world
+            .world
+            .spawn((TestComponent(0), ReplicationIgnore))
+            .id();
+        sim.simulate();
+        assert!(sim
+            .sim_world
+            .world
+            .resource::<ChangeSet>()
+            .get(entity)
+            .is_none());
+        assert!(sim.request(StateDif { for_player: 0 }).entities.is_empty());
+        assert!(sim.request(AllState).entities.is_empty());
+    }
+
+    #[test]
+    fn test_hierarchy_propagation() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_hierarchy_propagation(HierarchyPropagation {
+            to_children: true,
+            to_parents: false,
+        });
+        let mut sim = game.build_standalone();
+
+        let child = sim.sim_world.world.spawn(TestComponent(0)).id();
+        let parent = sim
+            .sim_world
+            .world
+            .spawn(TestComponent(0))
+            .push_children(&[child])
+            .id();
+        sim.simulate();
+        sim.request(StateDif { for_player: 0 });
+        let player_list = sim.sim_world.world.resource::<PlayerList>().clone();
+        sim.sim_world.clear_changed(&player_list);
+
+        sim.sim_world
+            .world
+            .get_mut::<TestComponent>(parent)
+            .unwrap()
+            .0 = 1;
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(state.entities.len(), 2);
+        let parent_id = *sim.sim_world.world.get::<SimEntityId>(parent).unwrap();
+        for entity_state in state.entities.iter() {
+            let expected: Vec<SimComponentId> = if entity_state.entity == parent_id {
+                vec![25]
+            } else {
+                vec![]
+            };
+            assert_eq!(entity_state.changed_components, expected);
         }
 
-        #[doc = r" Serializes the state of the object at the given tick into binary. Only saves the keyframe and not the curve itself"]
-        fn to_binary(&self) -> Option<Vec<u8>> {
-            bincode::serialize(self).ok()
+        sim.sim_world
+            .world
+            .get_mut::<TestComponent>(child)
+            .unwrap()
+            .0 = 1;
+        sim.simulate();
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+    }
+
+    #[test]
+    fn test_clear_changed_for_player() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.add_player(true);
+        game.add_player(true);
+        let mut sim = game.build_standalone();
+        let entity = sim.sim_world.world.spawn(TestComponent(0)).id();
+        sim.simulate();
+        let player_list = sim.sim_world.player_list.clone();
+
+        sim.sim_world.clear_changed_for_player(0, &player_list);
+        let change_set = sim.sim_world.world.resource::<ChangeSet>();
+        assert!(change_set.get(entity).unwrap().players_seen.contains(0));
+        assert!(!change_set.get(entity).unwrap().players_seen.contains(1));
+
+        sim.sim_world.clear_changed_for_player(1, &player_list);
+        assert!(sim
+            .sim_world
+            .world
+            .resource::<ChangeSet>()
+            .get(entity)
+            .is_none());
+    }
+
+    #[test]
+    fn test_player_mask() {
+        let mut mask = PlayerMask::default();
+        mask.insert(3);
+        mask.insert(130);
+        assert!(mask.contains(3) && mask.contains(130));
+        assert!(!mask.contains(4) && !mask.contains(1000));
+        mask.remove(130);
+        mask.shrink_to_fit();
+        assert_eq!(mask.iter().collect::<Vec<usize>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_change_history_covers() {
+        let mut history = ChangeHistory::new(2);
+        for tick in 1..=3 {
+            history.push(TickChanges {
+                tick,
+                ..Default::default()
+            });
         }
+        assert_eq!(history.oldest_tick(), Some(2));
+        assert!(!history.covers(0));
+        assert!(history.covers(1));
+        assert!(history.covers(u64::MAX));
+    }
+
+    #[test]
+    fn test_component_removal_tracking() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+
+        let entity = sim.sim_world.world.spawn(TestComponent(0)).id();
+        sim.simulate();
+        let id = *sim.sim_world.world.get::<SimEntityId>(entity).unwrap();
+        assert!(sim
+            .request(StateDif { for_player: 0 })
+            .removed_components
+            .is_empty());
+
+        sim.sim_world
+            .world
+            .entity_mut(entity)
+            .remove::<TestComponent>();
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(state.removed_components, vec![(id, 25)]);
+
+        sim.sim_world
+            .world
+            .entity_mut(entity)
+            .insert(TestComponent(1));
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert!(state.removed_components.is_empty());
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].changed_tick, 3);
     }
 
+    #[derive(Default, Resource, Reflect, Serialize, Deserialize)]
+    struct TestResource(u32);
+
+    bincode_save_id!(TestResource, 25);
+
     #[test]
     fn test_resource_change_tracking() {
         let mut world = World::new();
-        let mut game = GameBuilder::<TurnBasedGameRunner>::new_game(TurnBasedGameRunner {
-            turn_schedule: Default::default(),
-        });
-        game.register_resource::<TestResource>();
+        let mut game = test_game();
+        game.register_resource::<TestResource>().unwrap();
         game.build(&mut world);
 
         let mut game = world.remove_resource::<SimWorld>().unwrap();
@@ -288,4 +1024,25 @@ pub mod test {
         assert_eq!(test_component_1.0, 0);
         assert_eq!(test_component_2.0, 1);
     }
+
+    #[derive(Default, Resource, Serialize, Deserialize)]
+    struct SharedIdResource(u32);
+
+    bincode_save_id!(SharedIdResource, 25);
+
+    #[test]
+    fn test_resources_sharing_save_id() {
+        let mut game = test_game();
+        game.register_resource::<TestResource>().unwrap();
+        game.register_resource_track_changes::<SharedIdResource>();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.insert_resource(TestResource(0));
+        sim.sim_world.world.insert_resource(SharedIdResource(0));
+        sim.simulate();
+
+        let tracking = sim.sim_world.world.resource::<ResourceChangeTracking>();
+        assert_eq!(tracking.resources.len(), 2);
+        assert!(tracking.get_by_save_id(25).is_some());
+    }
 }
@@ -0,0 +1,241 @@
+//! Filters deciding whether a change to a tracked component is worth sending. Bevy marks a
+//! component changed on every mutable access, even if the value didn't change, and tiny float
+//! jitter marks an entity as [`SimChanged`](crate::change_detection::SimChanged) every tick.
+//! Register a filter with
+//! [`GameBuilder::register_change_filter`](crate::game_builder::GameBuilder::register_change_filter)
+//! and [`track_component_changes`](crate::change_detection::track_component_changes) only marks the
+//! entity changed when the filter passes.
+//!
+//! The strategies are:
+//! - Bevy change ticks, the default when a component has no filter
+//! - [`EqualityFilter`], changed when the value isn't equal to the last reported value
+//! - [`HashFilter`], changed when the hash of the value differs from the last reported hash. Use
+//!   this for large components to avoid keeping a copy of every value
+//! - [`ThresholdFilter`], changed when a predicate against the last reported value passes
+//!
+//! Independently of the filter, a [`ReplicationInterval`] registered with
+//! [`GameBuilder::register_replication_interval`](crate::game_builder::GameBuilder::register_replication_interval)
+//! limits a component to marking entities changed at most every N ticks, for high frequency but low
+//! importance data such as animation timers.
+
+use bevy::{
+    prelude::{Component, Entity, Resource},
+    utils::{HashMap, HashSet},
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+/// Decides whether a change to the component C on an entity should mark the entity changed
+pub trait ComponentChangeFilter<C>: Send + Sync + 'static {
+    /// Returns true if the given changed value should mark the entity changed
+    fn changed(&mut self, entity: Entity, component: &C) -> bool;
+
+    /// Called when the component is removed from the entity or the entity is despawned
+    fn removed(&mut self, entity: Entity);
+}
+
+/// Resource holding the [`ComponentChangeFilter`] of the component C
+#[derive(Resource)]
+pub struct ChangeFilter<C: Component>(pub Box<dyn ComponentChangeFilter<C>>);
+
+/// Resource limiting how often changes to the component C mark entities changed. Changes made between
+/// due ticks are held back and marked on the next due tick
+#[derive(Resource)]
+pub struct ReplicationInterval<C: Component> {
+    /// The number of ticks between due ticks
+    pub ticks: u64,
+    pub pending: HashSet<Entity>,
+    marker: PhantomData<fn(&C)>,
+}
+
+impl<C: Component> ReplicationInterval<C> {
+    pub fn new(ticks: u64) -> ReplicationInterval<C> {
+        ReplicationInterval {
+            ticks,
+            pending: HashSet::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns true if changes should be marked at the given tick
+    pub fn is_due(&self, tick: u64) -> bool {
+        self.ticks <= 1 || tick % self.ticks == 0
+    }
+}
+
+/// Marks an entity changed only when the given predicate passes against the last value that marked
+/// it changed, such as a position moving more than a small distance
+pub struct ThresholdFilter<C, F> {
+    predicate: F,
+    baselines: HashMap<Entity, C>,
+}
+
+impl<C, F> ThresholdFilter<C, F>
+where
+    F: Fn(&C, &C) -> bool,
+{
+    /// Creates a filter from a predicate taking the last reported value and the current value
+    pub fn new(predicate: F) -> ThresholdFilter<C, F> {
+        ThresholdFilter {
+            predicate,
+            baselines: HashMap::default(),
+        }
+    }
+}
+
+impl<C, F> ComponentChangeFilter<C> for ThresholdFilter<C, F>
+where
+    C: Clone + Send + Sync + 'static,
+    F: Fn(&C, &C) -> bool + Send + Sync + 'static,
+{
+    fn changed(&mut self, entity: Entity, component: &C) -> bool {
+        let changed = self
+            .baselines
+            .get(&entity)
+            .is_none_or(|baseline| (self.predicate)(baseline, component));
+        if changed {
+            self.baselines.insert(entity, component.clone());
+        }
+        changed
+    }
+
+    fn removed(&mut self, entity: Entity) {
+        self.baselines.remove(&entity);
+    }
+}
+
+/// Marks an entity changed only when the component isn't equal to the last value that marked it
+/// changed
+pub struct EqualityFilter<C> {
+    baselines: HashMap<Entity, C>,
+}
+
+impl<C> Default for EqualityFilter<C> {
+    fn default() -> Self {
+        EqualityFilter {
+            baselines: HashMap::default(),
+        }
+    }
+}
+
+impl<C> ComponentChangeFilter<C> for EqualityFilter<C>
+where
+    C: PartialEq + Clone + Send + Sync + 'static,
+{
+    fn changed(&mut self, entity: Entity, component: &C) -> bool {
+        if self.baselines.get(&entity) == Some(component) {
+            return false;
+        }
+        self.baselines.insert(entity, component.clone());
+        true
+    }
+
+    fn removed(&mut self, entity: Entity) {
+        self.baselines.remove(&entity);
+    }
+}
+
+/// Marks an entity changed only when the hash of the component differs from the hash of the last
+/// value that marked it changed
+pub struct HashFilter<C> {
+    hashes: HashMap<Entity, u64>,
+    marker: PhantomData<fn(&C)>,
+}
+
+impl<C> Default for HashFilter<C> {
+    fn default() -> Self {
+        HashFilter {
+            hashes: HashMap::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<C> ComponentChangeFilter<C> for HashFilter<C>
+where
+    C: Hash + 'static,
+{
+    fn changed(&mut self, entity: Entity, component: &C) -> bool {
+        let mut hasher = DefaultHasher::new();
+        component.hash(&mut hasher);
+        let hash = hasher.finish();
+        self.hashes.insert(entity, hash) != Some(hash)
+    }
+
+    fn removed(&mut self, entity: Entity) {
+        self.hashes.remove(&entity);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        requests::state_dif::StateDif,
+        testing::{fixtures::bincode_save_id, test_game},
+    };
+
+    use super::EqualityFilter;
+
+    #[derive(Default, Clone, PartialEq, Component, Serialize, Deserialize)]
+    struct Position(f32);
+
+    bincode_save_id!(Position, 25);
+
+    #[test]
+    fn test_change_threshold() {
+        let mut game = test_game();
+        game.register_component::<Position>().unwrap();
+        game.register_change_threshold::<Position>(|previous, current| {
+            (current.0 - previous.0).abs() > 0.01
+        });
+        let mut sim = game.build_standalone();
+
+        let unit = sim.sim_world.world.spawn(Position(0.0)).id();
+        sim.simulate();
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+
+        sim.sim_world.world.get_mut::<Position>(unit).unwrap().0 = 0.005;
+        sim.simulate();
+        assert!(sim.request(StateDif { for_player: 0 }).entities.is_empty());
+
+        sim.sim_world.world.get_mut::<Position>(unit).unwrap().0 = 0.02;
+        sim.simulate();
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+    }
+
+    #[test]
+    fn test_replication_interval() {
+        let mut game = test_game();
+        game.register_component::<Position>().unwrap();
+        game.register_replication_interval::<Position>(2);
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(Position(0.0));
+        sim.simulate();
+        assert!(sim.request(StateDif { for_player: 0 }).entities.is_empty());
+        sim.simulate();
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+    }
+
+    #[test]
+    fn test_equality_filter() {
+        let mut game = test_game();
+        game.register_component::<Position>().unwrap();
+        game.register_change_filter::<Position>(EqualityFilter::default());
+        let mut sim = game.build_standalone();
+
+        let unit = sim.sim_world.world.spawn(Position(1.0)).id();
+        sim.simulate();
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+
+        sim.sim_world.world.get_mut::<Position>(unit).unwrap().0 = 1.0;
+        sim.simulate();
+        assert!(sim.request(StateDif { for_player: 0 }).entities.is_empty());
+    }
+}
@@ -2,33 +2,16 @@
 //! through the [`GameCommands`] to enable saving, rollback, and more. A command should be entirely
 //! self contained, everything needed to accurately recreate the command should be included. A command
 //! **cannot** rely on any actions outside of it, only data. Eg, for MoveObject, you can't rely on
-//! the moving object having an up to date [`CurrentMovementInformation`](crate::movement::CurrentMovementInformation)
-//! component, you must calculate the move in the command
+//! the moving object having an up to date movement information component, you must calculate the
+//! move in the command
 //!
-//! To use in a system, request the [`GameCommands`] Resource, get the commands field, and call a defined
-//! command or submit a custom command using commands.add().
+//! The [`GameCommands`] of a sim live in its sim world, see [`SimWorld::game_commands`]. To use in
+//! a system, request the [`SimWorld`] Resource and submit a custom command using
+//! sim_world.game_commands_mut().add().
 //! ```rust
-//! use bevy::prelude::{Bundle, Reflect, ResMut, World};
-//! use bevy_ecs_tilemap::prelude::TilePos;
-//! use bevy_ggf::game_core::command::{GameCommand, GameCommands};
-//! use bevy_ggf::mapping::MapId;
-//!
-//! #[derive(Bundle, Default, + Reflect)]
-//! pub struct CustomBundle{
-//!     // Whatever components you want in your bundle - GameCommands::spawn_object will automatically
-//!     // insert the GameId struct with the next id
-//! }
-//!     
-//! fn spawn_object_built_in_command(
-//!     // Request the GameCommands Resource - all actions in the game should be communicated through
-//!     // this
-//!     mut game_commands: ResMut<GameCommands>,
-//! ){
-//!     // Call whatever command on GameCommands - Add your own commands by writing an extension trait
-//!     // and implementing that for GameCommands//!
-//!
-//!     game_commands.spawn_object(CustomBundle::default(), TilePos::new(1, 1), MapId{id: 0}, 0);
-//! }
+//! use bevy::prelude::{Reflect, ResMut, World};
+//! use bevy_sim_world::command::{CommandError, GameCommand};
+//! use bevy_sim_world::SimWorld;
 //!
 //! // Create a struct for your custom command, use this to store whatever data you need to execute
 //! // and rollback the commands
@@ -37,67 +20,141 @@
 //!
 //! // Impl GameCommand for your struct
 //! impl GameCommand for MyCustomCommand{
-//!     fn execute(&mut self, world: &mut World) -> Result<Option<Box<(dyn GameCommand + 'static)>>, String> {
+//!     fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
 //!         todo!() // Implement whatever your custom command should do here
 //!     }
 //!
-//!     fn rollback(&mut self, world: &mut World) -> Result<Option<Box<(dyn GameCommand + 'static)>>, String> {
+//!     fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
 //!         todo!() // Implement how to reverse your custom command - you can use your struct to save
-//!                 // any data you might need, like the GameId of an entity spawned, the transform
+//!                 // any data you might need, like the Entity of an object spawned, the transform
 //!                 // that the entity was at before, etc
 //!     }
 //! }
 //!
 //! fn spawn_object_custom_command(
-//!    mut game: ResMut<GameCommands>,
+//!    mut sim_world: ResMut<SimWorld>,
 //! ){
-//!     game.commands.add(MyCustomCommand);
+//!     sim_world.game_commands_mut().add(MyCustomCommand);
 //! }
 //!
 //! ```
 
+use crate::change_detection::{ChangeSet, ResourceChangeTracking, TrackedDespawns};
+use crate::command_rate_limit::CommandRateLimiter;
+use crate::command_registry::CommandRegistry;
+use crate::entity_id::{assign_sim_entity_ids, sim_entity_map, SimEntityId, SimEntityIndex};
+use crate::input_delay::InputDelay;
+use crate::player::Player;
+use crate::replay::{record_command, record_merged, record_tick};
+use crate::requests::{EntityState, ResourceState, SimState};
+use crate::runner::{game_inputs_ready, SimTick};
+use crate::saving::checksum::world_checksum;
+use crate::saving::delta::SnapshotDelta;
+use crate::saving::history::snapshot_world;
+use crate::saving::snapshot::SimSnapshot;
+use crate::saving::{ComponentBinaryState, GameSerDeRegistry, SimFormat, SimSerializer};
 use crate::SimWorld;
-use bevy::log::info;
-use bevy::prelude::{Mut, Reflect, Resource, World};
+use bevy::ecs::component::Tick;
+use bevy::ecs::reflect::AppTypeRegistry;
+use bevy::ecs::system::{SystemParam, SystemParamItem, SystemState};
+use bevy::log::{info, warn};
+use bevy::prelude::{Changed, Entity, Event, Events, Mut, Reflect, Resource, World};
+use bevy::reflect::serde::{ReflectSerializer, UntypedReflectDeserializer};
+use bevy::reflect::{
+    reflect_trait, ReflectDeserialize, ReflectFromReflect, ReflectSerialize, TypeRegistry,
+};
+use bevy::utils::HashMap;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::mem::{size_of, size_of_val};
+use std::sync::Arc;
 
-/// Executes all stored game commands by calling the command queue execute buffer function
-pub fn execute_game_commands_buffer(world: &mut World) {
-    world.resource_scope(|world, mut game_commands: Mut<GameCommands>| {
-        world.resource_scope(|_world, mut game: Mut<SimWorld>| {
-            game_commands.execute_buffer(&mut game.world);
-        });
+/// Creates a world holding the given snapshot of the given sim world, along with the resources a
+/// sim world has for executing commands, see [`GameCommands::preview`]
+fn fork_world(world: &World, baseline: &SimSnapshot) -> World {
+    let registry = world.resource::<GameSerDeRegistry>().clone();
+    let mut fork = World::new();
+    baseline.apply(&mut fork, &registry);
+    fork.insert_resource(registry);
+    fork.insert_resource(ChangeSet::default());
+    fork.insert_resource(TrackedDespawns {
+        despawned_objects: Default::default(),
     });
+    fork.insert_resource(ResourceChangeTracking::default());
+    let entities = sim_entity_map(&mut fork);
+    fork.insert_resource(SimEntityIndex { entities });
+    fork.init_resource::<GameCommands>();
+    if let Some(command_registry) = world.get_resource::<CommandRegistry>() {
+        fork.insert_resource(command_registry.clone());
+    }
+    if let Some(type_registry) = world.get_resource::<AppTypeRegistry>() {
+        fork.insert_resource(type_registry.clone());
+    }
+    fork
 }
 
-/// Executes all rollbacks requested - panics if a rollback fails
+/// Executes all commands queued into the [`GameCommands`] of the [`SimWorld`] in the given main
+/// world against it, see [`SimWorld::execute_game_commands`]. The result of every command is sent
+/// as a [`CommandExecuted`] event if the main world has the event added. Does nothing while the
+/// command sets of some players haven't arrived yet, see [`InputDelay`]
+pub fn execute_game_commands_buffer(world: &mut World) {
+    if !game_inputs_ready(world) {
+        return;
+    }
+    let results = world.resource_mut::<SimWorld>().execute_game_commands();
+    if let Some(mut events) = world.get_resource_mut::<Events<CommandExecuted>>() {
+        events.extend(results);
+    }
+}
+
+/// Event sent into the main world for every command executed by [`execute_game_commands_buffer`],
+/// including commands that failed or were rejected. Add it to the app with
+/// `app.add_event::<CommandExecuted>()` to receive it
+#[derive(Clone, Event)]
+pub struct CommandExecuted {
+    pub meta: GameCommandMeta,
+    pub result: Result<(), CommandError>,
+    /// The [`SimEntityId`]s given to entities while the command executed, sorted by id. Includes new
+    /// entities and ids the command inserted itself, such as [`SpawnTracked`] respawning an entity
+    /// with its old id. Lets the issuing client correlate its optimistic local entities with the
+    /// authoritative ones
+    ///
+    /// [`SpawnTracked`]: crate::builtin_commands::SpawnTracked
+    pub spawned: Vec<SimEntityId>,
+}
+
+/// Returns the [`SimEntityId`]s inserted after the given tick, sorted by id. Ids are never mutated
+/// in place, so a changed id was inserted onto its entity
+fn sim_entity_ids_inserted_since(world: &mut World, tick: Tick) -> Vec<SimEntityId> {
+    world.last_change_tick_scope(tick, |world| {
+        let mut query = world.query_filtered::<&SimEntityId, Changed<SimEntityId>>();
+        let mut ids: Vec<SimEntityId> = query.iter(world).copied().collect();
+        ids.sort();
+        ids
+    })
+}
+
+/// Executes all rollbacks requested against the [`SimWorld`] - panics if a rollback fails
 pub fn execute_game_rollbacks_buffer(world: &mut World) {
-    world.resource_scope(|world, mut game: Mut<GameCommands>| {
-        while game.history.rollbacks != 0 {
-            if let Some(mut command) = game.history.pop() {
-                command.command.rollback(world).expect("Rollback failed");
-                game.history.rolledback_history.push(command);
-                info!("Rollbacked command");
-            }
-            game.history.rollbacks -= 1;
-        }
-    });
+    world
+        .resource_mut::<SimWorld>()
+        .world
+        .resource_scope(|world, mut game: Mut<GameCommands>| {
+            game.execute_rollbacks(world);
+        });
 }
 
-/// Executes all rollforwards requested - panics if an execute fails
+/// Executes all rollforwards requested against the [`SimWorld`] - panics if an execute fails
 pub fn execute_game_rollforward_buffer(world: &mut World) {
-    world.resource_scope(|world, mut game: Mut<GameCommands>| {
-        while game.history.rollforwards != 0 {
-            if let Some(mut command) = game.history.rolledback_history.pop() {
-                if let Ok(_) = command.command.execute(world) {
-                    game.history.push(command.clone());
-                } else {
-                    info!("Rolledforward failed");
-                }
-            }
-            game.history.rollforwards -= 1;
-        }
-    });
+    world
+        .resource_mut::<SimWorld>()
+        .world
+        .resource_scope(|world, mut game: Mut<GameCommands>| {
+            game.execute_rollforwards(world);
+        });
 }
 
 pub enum CommandType {
@@ -108,34 +165,275 @@ pub enum CommandType {
 #[derive(Clone)]
 pub struct GameCommandMeta {
     pub command: Box<dyn GameCommand>,
-    pub command_time: DateTime<Utc>,
+    /// The sim tick the command was executed at. 0 until the command is executed
+    pub tick: u64,
+    /// The order the command was executed in within its tick. Together with the tick this orders
+    /// every command in the history deterministically
+    pub sequence: u32,
+    /// The wall clock time the command was queued at. Only recorded when
+    /// [`GameCommandQueue::record_wall_clock`] is set, and only meant as debug info as it isn't
+    /// deterministic across machines
+    pub command_time: Option<DateTime<Utc>>,
+    /// The player that issued the command. Commands issued by a player are checked with
+    /// [`GameCommand::authorize`] before they are executed
+    pub issued_by: Option<Player>,
+    /// The sim tick the command should be executed at. Commands scheduled for a later tick stay
+    /// queued until the sim reaches it
+    pub execute_at_tick: Option<u64>,
+    /// The order the command was queued in, see [`GameCommandMeta::ordering_key`]
+    pub queue_sequence: u64,
     //command_type: CommandType,
 }
 
+impl GameCommandMeta {
+    /// Creates the meta for a command that hasn't been executed yet
+    pub fn new(command: Box<dyn GameCommand>, record_wall_clock: bool) -> GameCommandMeta {
+        GameCommandMeta {
+            command,
+            tick: 0,
+            sequence: 0,
+            command_time: record_wall_clock.then(Utc::now),
+            issued_by: None,
+            execute_at_tick: None,
+            queue_sequence: 0,
+        }
+    }
+
+    /// Returns an estimate of the memory used by the command, excluding any heap allocations owned
+    /// by the command
+    pub fn estimated_bytes(&self) -> usize {
+        size_of::<GameCommandMeta>() + size_of_val(self.command.as_ref())
+    }
+
+    /// Returns the handle of the command, which can be used to cancel it while it is queued
+    pub fn handle(&self) -> CommandHandle {
+        CommandHandle(self.queue_sequence)
+    }
+
+    /// Returns the key queued commands are executed in order of. Commands are sorted by the tick
+    /// they are due at, then by the id of the player that issued them with commands not issued by a
+    /// player first, then by the order they were queued in. As none of these depend on wall clock
+    /// time, every peer in a lockstep game that queues the same commands executes them identically
+    pub fn ordering_key(&self, tick: u64) -> (u64, Option<usize>, u64) {
+        (
+            self.execute_at_tick.unwrap_or(tick),
+            self.issued_by.map(|player| player.id()),
+            self.queue_sequence,
+        )
+    }
+
+    /// Serializes the command using reflection. Returns an error if the command type isn't
+    /// registered in the given type registry, see [`ReflectGameCommand`]
+    pub fn save(
+        &self,
+        type_registry: &TypeRegistry,
+        format: SimFormat,
+    ) -> Result<SavedCommand, String> {
+        Ok(SavedCommand {
+            command: encode_command(self.command.as_ref(), type_registry, format)?,
+            tick: self.tick,
+            sequence: self.sequence,
+            command_time: self.command_time,
+            issued_by: self.issued_by,
+            execute_at_tick: self.execute_at_tick,
+            queue_sequence: self.queue_sequence,
+        })
+    }
+
+    /// Deserializes a command saved with [`GameCommandMeta::save`]
+    pub fn load(
+        saved: &SavedCommand,
+        type_registry: &TypeRegistry,
+        format: SimFormat,
+    ) -> Result<GameCommandMeta, String> {
+        Ok(GameCommandMeta {
+            command: decode_command(&saved.command, type_registry, format)?,
+            tick: saved.tick,
+            sequence: saved.sequence,
+            command_time: saved.command_time,
+            issued_by: saved.issued_by,
+            execute_at_tick: saved.execute_at_tick,
+            queue_sequence: saved.queue_sequence,
+        })
+    }
+}
+
+/// Serializes the given command using reflection, including the inner commands of a
+/// [`CompositeCommand`]
+fn encode_command(
+    command: &dyn GameCommand,
+    type_registry: &TypeRegistry,
+    format: SimFormat,
+) -> Result<Vec<u8>, String> {
+    let command = command.as_reflect();
+    if type_registry
+        .get_type_data::<ReflectGameCommand>(command.type_id())
+        .is_none()
+    {
+        return Err(format!(
+            "Command {} is not registered",
+            command.reflect_type_path()
+        ));
+    }
+    let composite = match command.downcast_ref::<CompositeCommand>() {
+        Some(composite) => Some(CompositeCommand {
+            commands: vec![],
+            saved_commands: composite
+                .commands
+                .iter()
+                .map(|command| encode_command(command.as_ref(), type_registry, format))
+                .collect::<Result<Vec<Vec<u8>>, String>>()?,
+        }),
+        None => None,
+    };
+    let command = composite
+        .as_ref()
+        .map_or(command, |composite| composite.as_reflect());
+    format
+        .encode(&ReflectSerializer::new(command, type_registry))
+        .ok_or_else(|| {
+            format!(
+                "Command {} failed to serialize",
+                command.reflect_type_path()
+            )
+        })
+}
+
+/// Deserializes a command serialized with [`encode_command`]
+fn decode_command(
+    data: &[u8],
+    type_registry: &TypeRegistry,
+    format: SimFormat,
+) -> Result<Box<dyn GameCommand>, String> {
+    let command = format
+        .decode_seed(UntypedReflectDeserializer::new(type_registry), data)
+        .map_err(|error| error.to_string())?;
+    let type_path = command.reflect_type_path().to_string();
+    let Some(registration) = command
+        .get_represented_type_info()
+        .and_then(|type_info| type_registry.get(type_info.type_id()))
+    else {
+        return Err(format!("Command {} is not registered", type_path));
+    };
+    let (Some(from_reflect), Some(reflect_command)) = (
+        registration.data::<ReflectFromReflect>(),
+        registration.data::<ReflectGameCommand>(),
+    ) else {
+        return Err(format!("Command {} is not registered", type_path));
+    };
+    let mut command = from_reflect
+        .from_reflect(command.as_reflect())
+        .and_then(|command| reflect_command.get_boxed(command).ok())
+        .ok_or_else(|| format!("Command {} failed to deserialize", type_path))?;
+    if let Some(composite) = command.as_reflect_mut().downcast_mut::<CompositeCommand>() {
+        composite.commands = std::mem::take(&mut composite.saved_commands)
+            .iter()
+            .map(|data| decode_command(data, type_registry, format))
+            .collect::<Result<Vec<Box<dyn GameCommand>>, String>>()?;
+    }
+    Ok(command)
+}
+
+/// The ways a [`GameCommand`] can fail
+#[derive(Clone, Eq, Debug, PartialEq)]
+pub enum CommandError {
+    /// The command isn't valid for the current state of the world
+    ValidationFailed(String),
+    /// An entity the command acts on doesn't exist
+    MissingEntity(Entity),
+    /// The world didn't match what the command expected while rolling it back
+    RollbackMismatch(String),
+    /// The player that issued the command isn't allowed to, see [`GameCommand::authorize`]
+    Unauthorized(String),
+    /// The player that issued the command queued too many commands, see [`CommandRateLimiter`]
+    RateLimited(Player),
+    /// Any other failure
+    Custom(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::ValidationFailed(reason) => write!(f, "validation failed: {}", reason),
+            CommandError::MissingEntity(entity) => write!(f, "entity {:?} doesn't exist", entity),
+            CommandError::RollbackMismatch(reason) => write!(f, "rollback mismatch: {}", reason),
+            CommandError::Unauthorized(reason) => write!(f, "unauthorized: {}", reason),
+            CommandError::RateLimited(player) => {
+                write!(f, "player {} is over its command rate limit", player.id())
+            }
+            CommandError::Custom(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+impl From<String> for CommandError {
+    fn from(reason: String) -> Self {
+        CommandError::Custom(reason)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(reason: &str) -> Self {
+        CommandError::Custom(reason.to_string())
+    }
+}
+
+/// Identifies a queued command, see [`GameCommands::cancel`]
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct CommandHandle(pub u64);
+
+/// A [`GameCommandMeta`] with its command serialized using reflection
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedCommand {
+    pub command: Vec<u8>,
+    pub tick: u64,
+    pub sequence: u32,
+    pub command_time: Option<DateTime<Utc>>,
+    pub issued_by: Option<Player>,
+    pub execute_at_tick: Option<u64>,
+    pub queue_sequence: u64,
+}
+
+/// The queue and history of [`GameCommands`] serialized for a save, see [`GameCommands::save`]
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedCommands {
+    pub queue: Vec<SavedCommand>,
+    pub history: Vec<SavedCommand>,
+    pub rolledback_history: Vec<SavedCommand>,
+}
+
 /// A base trait defining an action that affects the game. Define your own to implement your own
 /// custom commands that will be automatically saved, executed, and rolledback. The rollback function
 /// **MUST** exactly roll the world back to as it was, excluding entity IDs.
 /// ```rust
 /// use bevy::prelude::World;
 /// use bevy::reflect::Reflect;
-/// use bevy_ggf::game_core::command::GameCommand;
+/// use bevy_sim_world::command::{CommandError, GameCommand};
 /// #[derive(Clone, Debug, Reflect)]
 ///  struct MyCustomCommand;
 ///
 ///  impl GameCommand for MyCustomCommand{
-///     fn execute(&mut self, world: &mut World) -> Result<(), String> {
+///     fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
 ///          todo!() // Implement whatever your custom command should do here
 ///      }
 ///
-///     fn rollback(&mut self, world: &mut World) -> Result<(), String> {
+///     fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
 ///          todo!() // Implement how to reverse your custom command
 ///      }
 ///  }
 ///
 /// ```
+///
+/// To include commands in saves, register them with
+/// [`GameBuilder::register_command`](crate::game_builder::GameBuilder::register_command) or add
+/// `#[reflect(GameCommand)]` and register the type in the sim worlds
+/// [`AppTypeRegistry`](bevy::ecs::reflect::AppTypeRegistry)
+#[reflect_trait]
 pub trait GameCommand: Send + GameCommandClone + Sync + Reflect + 'static {
     /// Execute the command
-    fn execute(&mut self, world: &mut World) -> Result<(), String>;
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError>;
 
     /// Command to rollback a given command. Must undo exactly what execute did to return the game state
     /// to exactly the same state as before the execute was done.
@@ -144,26 +442,189 @@ pub trait GameCommand: Send + GameCommandClone + Sync + Reflect + 'static {
     /// dont want to use rollback you aren't required to implement it for your commands. However if
     /// you **do** want to use it make sure you implement it correctly.
     //#[cfg(feature = "command_rollback")]
-    fn rollback(&mut self, _world: &mut World) -> Result<(), String> {
+    fn rollback(&mut self, _world: &mut World) -> Result<(), CommandError> {
         Ok(())
     }
+
+    /// Checks if the given player is allowed to issue the command, eg that they own the entities it
+    /// manipulates. Only called for commands issued by a player, commands that return an error are
+    /// not executed
+    fn authorize(&self, _world: &World, _player: &Player) -> Result<(), CommandError> {
+        Ok(())
+    }
+
+    /// Merges the given command, executed right after this one in the same tick and by the same
+    /// player, into this one so both take a single entry in the history. Return true if the
+    /// commands were merged, after which rolling back this command must undo both. Use this to
+    /// collapse repeated commands such as path updates. Use
+    /// `next.as_reflect().downcast_ref::<Self>()` to check if the command is compatible
+    ///
+    /// NOTE: This has a default implementation that never merges
+    fn merge(&mut self, _next: &dyn GameCommand) -> bool {
+        false
+    }
 }
 
-/* TODO: Figure out if a closure is possible. Probably not since we have two functions, but either way
- it would be nice if we can but they can still do whatever they need otherwise
-impl<F> GameCommand for F
-    where
-        F: FnOnce(&mut World) + Sync + Copy + Debug + GameCommandClone + Send + 'static,
+type BoxedCommandFn = Arc<dyn Fn(&mut World) + Send + Sync>;
+
+/// The closure run by a [`FnCommand`]
+#[derive(Default, Clone)]
+pub struct CommandFn(Option<BoxedCommandFn>);
+
+impl Debug for CommandFn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CommandFn")
+    }
+}
+
+/// A command that runs a closure, added with [`GameCommands::add_fn`]. Rolling it back does
+/// nothing, and it can't be saved or replayed as the closure can't be serialized
+#[derive(Clone, Debug, Reflect)]
+pub struct FnCommand {
+    #[reflect(ignore)]
+    pub function: CommandFn,
+}
+
+impl FnCommand {
+    pub fn new(function: impl Fn(&mut World) + Send + Sync + 'static) -> FnCommand {
+        FnCommand {
+            function: CommandFn(Some(Arc::new(function))),
+        }
+    }
+}
+
+impl GameCommand for FnCommand {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        let Some(function) = &self.function.0 else {
+            return Err(CommandError::Custom(String::from(
+                "command has no closure to run",
+            )));
+        };
+        function(world);
+        Ok(())
+    }
+}
+
+/// Fetches the given system params from the world and runs the given closure with them, then
+/// applies any deferred [`Commands`](bevy::prelude::Commands). Use this in
+/// [`GameCommand::execute`] and [`GameCommand::rollback`] to access the world through params such
+/// as [`Query`](bevy::prelude::Query) and [`Res`](bevy::prelude::Res) instead of the raw [`World`]
+/// ```rust
+/// use bevy::prelude::{Component, Query, World};
+/// use bevy_sim_world::command::with_params;
+///
+/// #[derive(Component)]
+/// struct Health(u32);
+///
+/// fn heal_all(world: &mut World) {
+///     with_params::<Query<&mut Health>, _>(world, |mut query| {
+///         for mut health in query.iter_mut() {
+///             health.0 += 1;
+///         }
+///     });
+/// }
+/// ```
+pub fn with_params<P, R>(
+    world: &mut World,
+    f: impl for<'w, 's> FnOnce(SystemParamItem<'w, 's, P>) -> R,
+) -> R
+where
+    P: SystemParam + 'static,
 {
-    fn execute(self: &mut F, world: &mut World) -> Result<(), String> {
-        Ok(self(world))
+    let mut state = SystemState::<P>::new(world);
+    let result = f(state.get_mut(world));
+    state.apply(world);
+    result
+}
+
+/// A command made of several commands that are executed in order and rolled back as one unit, so
+/// a single player action built from several commands is undone atomically. If one of the commands
+/// fails the ones already executed are rolled back and its error is returned, failed rollbacks are
+/// only logged. The inner commands are saved along with the
+/// composite, so they must be registered as well
+#[derive(Clone, Default, Reflect)]
+#[reflect_value(Serialize, Deserialize)]
+pub struct CompositeCommand {
+    pub commands: Vec<Box<dyn GameCommand>>,
+    /// The inner commands serialized while the composite is saved, see [`GameCommandMeta::save`]
+    saved_commands: Vec<Vec<u8>>,
+}
+
+impl Serialize for CompositeCommand {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.saved_commands.serialize(serializer)
     }
-    fn rollback(self: &mut F, world: &mut World) -> Result<(), String> {
-        Ok(self(world))
+}
+
+impl<'de> Deserialize<'de> for CompositeCommand {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(CompositeCommand {
+            commands: vec![],
+            saved_commands: Vec::deserialize(deserializer)?,
+        })
     }
 }
 
- */
+impl CompositeCommand {
+    pub fn new() -> CompositeCommand {
+        CompositeCommand::default()
+    }
+
+    /// Adds the given command to the end of the composite
+    pub fn with(mut self, command: impl GameCommand) -> CompositeCommand {
+        self.push(command);
+        self
+    }
+
+    /// Adds the given command to the end of the composite
+    pub fn push(&mut self, command: impl GameCommand) {
+        self.commands.push(Box::new(command));
+    }
+}
+
+impl Debug for CompositeCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.commands
+                    .iter()
+                    .map(|command| command.as_reflect().reflect_type_path()),
+            )
+            .finish()
+    }
+}
+
+impl GameCommand for CompositeCommand {
+    fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+        for index in 0..self.commands.len() {
+            if let Err(error) = self.commands[index].execute(world) {
+                for command in self.commands[..index].iter_mut().rev() {
+                    if let Err(rollback_error) = command.rollback(world) {
+                        warn!(
+                            "rolling back a failed composite command failed with: {:?}",
+                            rollback_error
+                        );
+                    }
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+        self.commands
+            .iter_mut()
+            .rev()
+            .try_for_each(|command| command.rollback(world))
+    }
+
+    fn authorize(&self, world: &World, player: &Player) -> Result<(), CommandError> {
+        self.commands
+            .iter()
+            .try_for_each(|command| command.authorize(world, player))
+    }
+}
 
 impl Clone for Box<dyn GameCommand> {
     fn clone(&self) -> Self {
@@ -178,7 +639,7 @@ pub trait GameCommandClone {
 
 impl<T> GameCommandClone for T
 where
-    T: 'static + GameCommand + Clone + ?Sized,
+    T: 'static + GameCommand + Clone,
 {
     fn clone_box(&self) -> Box<dyn GameCommand> {
         Box::new(self.clone())
@@ -189,6 +650,10 @@ where
 #[derive(Default)]
 pub struct GameCommandQueue {
     pub queue: Vec<GameCommandMeta>,
+    /// Records the wall clock time of every pushed command as debug info
+    pub record_wall_clock: bool,
+    /// The [`GameCommandMeta::queue_sequence`] given to the next pushed command
+    pub next_sequence: u64,
 }
 
 impl GameCommandQueue {
@@ -197,12 +662,58 @@ impl GameCommandQueue {
     where
         C: GameCommand,
     {
-        let utc: DateTime<Utc> = Utc::now();
-        let command_meta = GameCommandMeta {
-            command: Box::from(command),
-            command_time: utc,
-        };
+        let command_meta = GameCommandMeta::new(Box::from(command), self.record_wall_clock);
+        self.push_meta(command_meta);
+    }
+
+    /// Push an already boxed command to the end of the queue
+    pub fn push_boxed(&mut self, command: Box<dyn GameCommand>) {
+        let command_meta = GameCommandMeta::new(command, self.record_wall_clock);
+        self.push_meta(command_meta);
+    }
+
+    /// Push an already boxed command issued by the given player to the end of the queue. It must
+    /// pass [`GameCommand::authorize`] for the player to be executed
+    pub fn push_boxed_from_player(&mut self, command: Box<dyn GameCommand>, player: Player) {
+        let mut command_meta = GameCommandMeta::new(command, self.record_wall_clock);
+        command_meta.issued_by = Some(player);
+        self.push_meta(command_meta);
+    }
+
+    /// Push a command meta to the end of the queue, assigning it the next queue sequence. Returns
+    /// the handle of the command
+    pub fn push_meta(&mut self, mut command_meta: GameCommandMeta) -> CommandHandle {
+        command_meta.queue_sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let handle = command_meta.handle();
         self.queue.push(command_meta);
+        handle
+    }
+
+    /// Returns the queued command with the given handle
+    pub fn get(&self, handle: CommandHandle) -> Option<&GameCommandMeta> {
+        self.queue.iter().find(|command| command.handle() == handle)
+    }
+
+    /// Removes the queued command with the given handle and returns it. Returns None if the command
+    /// isn't queued, eg because it was already executed
+    pub fn remove(&mut self, handle: CommandHandle) -> Option<GameCommandMeta> {
+        let index = self
+            .queue
+            .iter()
+            .position(|command| command.handle() == handle)?;
+        Some(self.queue.remove(index))
+    }
+
+    /// Push a new command to the end of the queue that is executed once the sim reaches the given
+    /// tick
+    pub fn push_at_tick<C>(&mut self, command: C, tick: u64)
+    where
+        C: GameCommand,
+    {
+        let mut command_meta = GameCommandMeta::new(Box::from(command), self.record_wall_clock);
+        command_meta.execute_at_tick = Some(tick);
+        self.push_meta(command_meta);
     }
 
     /// Take the last command in the queue. Returns None if queue is empty
@@ -211,15 +722,124 @@ impl GameCommandQueue {
     }
 }
 
+/// Filters the commands returned by [`GameCommandsHistory::query`]. Matches every command by default
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct CommandHistoryFilter {
+    /// Only match commands issued by the player with the given id
+    pub player: Option<usize>,
+    /// Only match commands executed within the given ticks, inclusive
+    pub ticks: Option<(u64, u64)>,
+    /// Only match commands queued within the given wall clock times, inclusive. Commands without a
+    /// recorded time never match, see [`GameCommandQueue::record_wall_clock`]
+    pub time: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Only match commands whose reflected short type path or full type path equals the given name,
+    /// so both the bare type name and the full path can be used
+    pub type_name: Option<String>,
+}
+
+impl CommandHistoryFilter {
+    pub fn new() -> CommandHistoryFilter {
+        CommandHistoryFilter::default()
+    }
+
+    /// Only match commands issued by the player with the given id
+    pub fn issued_by(mut self, player: usize) -> CommandHistoryFilter {
+        self.player = Some(player);
+        self
+    }
+
+    /// Only match commands executed from the first to the last given tick
+    pub fn ticks(mut self, first: u64, last: u64) -> CommandHistoryFilter {
+        self.ticks = Some((first, last));
+        self
+    }
+
+    /// Only match commands queued from the first to the last given time
+    pub fn time(mut self, first: DateTime<Utc>, last: DateTime<Utc>) -> CommandHistoryFilter {
+        self.time = Some((first, last));
+        self
+    }
+
+    /// Only match commands of the type with the given name. The name is compared against both the
+    /// short type path, such as `Increment`, and the full type path, such as `my_game::Increment`
+    pub fn type_name(mut self, type_name: impl Into<String>) -> CommandHistoryFilter {
+        self.type_name = Some(type_name.into());
+        self
+    }
+
+    /// Returns true if the given command passes the filter
+    pub fn matches(&self, command: &GameCommandMeta) -> bool {
+        self.player.is_none_or(|player| {
+            command
+                .issued_by
+                .is_some_and(|issued_by| issued_by.id() == player)
+        }) && self
+            .ticks
+            .is_none_or(|(first, last)| (first..=last).contains(&command.tick))
+            && self.time.is_none_or(|(first, last)| {
+                command
+                    .command_time
+                    .is_some_and(|time| (first..=last).contains(&time))
+            })
+            && self.type_name.as_ref().is_none_or(|type_name| {
+                command.command.reflect_short_type_path() == type_name
+                    || command.command.reflect_type_path() == type_name
+            })
+    }
+}
+
+/// Limits on the size of a [`GameCommandsHistory`]. Unlimited by default
+#[derive(Default, Clone, Copy, Eq, Debug, PartialEq)]
+pub struct HistoryLimit {
+    /// The maximum number of commands kept in the history
+    pub max_commands: Option<usize>,
+    /// The maximum estimated memory used by the commands kept in the history, see
+    /// [`GameCommandMeta::estimated_bytes`]
+    pub max_bytes: Option<usize>,
+}
+
+impl HistoryLimit {
+    /// Returns true if the given commands use more than the given fraction of the limit
+    fn exceeds(&self, commands: &[GameCommandMeta], fraction: usize) -> bool {
+        self.max_commands
+            .is_some_and(|max| commands.len() * fraction > max)
+            || self.max_bytes.is_some_and(|max| {
+                commands
+                    .iter()
+                    .map(GameCommandMeta::estimated_bytes)
+                    .sum::<usize>()
+                    * fraction
+                    > max
+            })
+    }
+}
+
 /// The history of all commands sent for this [`Game`] instance - if a command rollback occurs the
 /// command is discarded from the history. This means that the history contains only the commands
 /// that led to this instance of the game
+///
+/// If the history has a [`HistoryLimit`] old commands are compacted into [`GameCommandsHistory::baseline`],
+/// a snapshot of the world from before the oldest command still in the history
 #[derive(Default)]
 pub struct GameCommandsHistory {
     pub history: Vec<GameCommandMeta>,
     pub rolledback_history: Vec<GameCommandMeta>,
     rollbacks: u32,
     rollforwards: u32,
+    /// The limit the history is compacted to, see [`GameCommandsHistory::compact`]
+    pub limit: HistoryLimit,
+    /// A snapshot of the world from before the oldest command in the history. None until the
+    /// history is first compacted
+    pub baseline: Option<SimSnapshot>,
+    /// The length of the history and a snapshot of the world at the point the history is next
+    /// compacted up to
+    compaction_point: Option<(usize, SimSnapshot)>,
+    /// Whether a checksum of the world is recorded after every executed command batch, see
+    /// [`GameCommands::compare_checksum`]
+    pub record_checksums: bool,
+    /// The checksum of the world after the command batch of each tick was executed, see
+    /// [`world_checksum`]
+    pub checksums: BTreeMap<u64, u32>,
 }
 
 impl GameCommandsHistory {
@@ -246,6 +866,86 @@ impl GameCommandsHistory {
     pub fn clear_rollback_history(&mut self) {
         self.rolledback_history.clear();
     }
+
+    /// Compacts the history if it exceeds its [`HistoryLimit`]. Once the history uses half its limit
+    /// a snapshot of the given world is taken as the compaction point, and once it exceeds its limit
+    /// every command older than the compaction point is dropped and the snapshot becomes the
+    /// [`GameCommandsHistory::baseline`]. The history keeps between half and all of its limit,
+    /// so commands can still be rolled back after compacting
+    pub fn compact(&mut self, world: &mut World) {
+        if self.limit.exceeds(&self.history, 1) {
+            if let Some((index, snapshot)) = self.compaction_point.take() {
+                self.history.drain(..index);
+                self.baseline = Some(snapshot);
+                if let Some(oldest) = self.history.first() {
+                    self.checksums = self.checksums.split_off(&oldest.tick);
+                }
+            }
+        }
+        if self.compaction_point.is_none() && self.limit.exceeds(&self.history, 2) {
+            self.compaction_point =
+                snapshot_world(world).map(|snapshot| (self.history.len(), snapshot));
+        }
+    }
+
+    /// Returns an iterator over every command in the history executed at or after the given tick
+    pub fn since_tick(&self, tick: u64) -> impl Iterator<Item = &GameCommandMeta> {
+        self.history
+            .iter()
+            .filter(move |command| command.tick >= tick)
+    }
+
+    /// Returns an iterator over every command in the history that matches the given filter, in
+    /// execution order
+    pub fn query<'a>(
+        &'a self,
+        filter: &'a CommandHistoryFilter,
+    ) -> impl Iterator<Item = &'a GameCommandMeta> {
+        self.history
+            .iter()
+            .filter(move |command| filter.matches(command))
+    }
+
+    /// Removes every command executed at or after the given tick from the history and returns
+    /// them, oldest first. Their recorded checksums are removed as well
+    pub fn take_since_tick(&mut self, tick: u64) -> Vec<GameCommandMeta> {
+        let index = self
+            .history
+            .iter()
+            .position(|command| command.tick >= tick)
+            .unwrap_or(self.history.len());
+        if self
+            .compaction_point
+            .as_ref()
+            .is_some_and(|(point, _)| *point > index)
+        {
+            self.compaction_point = None;
+        }
+        self.checksums.split_off(&tick);
+        self.history.split_off(index)
+    }
+}
+
+/// Middleware that sees every command executed by a [`GameCommands`], for logging, metrics, cheat
+/// detection, achievements, and so on without editing every command
+pub trait CommandHook: Send + Sync + 'static {
+    /// Called before the given command is executed. Returning an error rejects the command
+    fn before_execute(
+        &mut self,
+        _world: &World,
+        _command: &GameCommandMeta,
+    ) -> Result<(), CommandError> {
+        Ok(())
+    }
+
+    /// Called with the result of every command, including commands that were rejected
+    fn after_execute(
+        &mut self,
+        _world: &World,
+        _command: &GameCommandMeta,
+        _result: &Result<(), CommandError>,
+    ) {
+    }
 }
 
 /// A struct to hold, execute, and rollback [`GameCommand`]s. Use associated actions to access and
@@ -254,6 +954,15 @@ impl GameCommandsHistory {
 pub struct GameCommands {
     pub queue: GameCommandQueue,
     pub history: GameCommandsHistory,
+    /// Hooks that see every command executed by these commands, see [`CommandHook`]
+    pub hooks: Vec<Box<dyn CommandHook>>,
+    /// Limits on the number of commands each player can queue, see [`CommandRateLimiter`]
+    pub rate_limiter: CommandRateLimiter,
+    /// Commands that were rejected when they were queued, reported by the next
+    /// [`GameCommands::execute_buffer`]
+    pub rejected: Vec<CommandExecuted>,
+    /// Schedules commands issued by players ahead for lockstep play, see [`InputDelay`]
+    pub input_delay: Option<InputDelay>,
 }
 
 impl GameCommands {
@@ -261,23 +970,187 @@ impl GameCommands {
         GameCommands {
             queue: Default::default(),
             history: Default::default(),
+            hooks: vec![],
+            rate_limiter: Default::default(),
+            rejected: vec![],
+            input_delay: None,
         }
     }
 
+    /// Queues the given command issued by the given player if any, executed at the given tick if
+    /// any. Commands issued by a player without a tick are checked against the players
+    /// [`CommandRateLimiter`] limits and rejected if over them, then scheduled by the [`InputDelay`]
+    /// if there is one. Commands with a tick are lockstep inputs that were already limited by the
+    /// peer that issued them, and every peer must queue them the same way, so they skip the wall
+    /// clock based limiter
+    pub(crate) fn push_attributed(
+        &mut self,
+        command: Box<dyn GameCommand>,
+        issued_by: Option<Player>,
+        execute_at_tick: Option<u64>,
+    ) {
+        let mut meta = GameCommandMeta::new(command, self.queue.record_wall_clock);
+        meta.issued_by = issued_by;
+        meta.execute_at_tick = execute_at_tick;
+        let (Some(player), None) = (issued_by, execute_at_tick) else {
+            self.queue.push_meta(meta);
+            return;
+        };
+        if let Err(error) = self.rate_limiter.check(&player) {
+            self.rejected.push(CommandExecuted {
+                meta,
+                result: Err(error),
+                spawned: vec![],
+            });
+            return;
+        }
+        if let Some(input_delay) = self.input_delay.as_ref() {
+            meta.execute_at_tick = Some(input_delay.input_tick());
+        }
+        self.queue.push_meta(meta);
+    }
+
+    /// Adds a hook that sees every command executed by these commands and its result
+    pub fn add_hook(&mut self, hook: impl CommandHook) {
+        self.hooks.push(Box::new(hook));
+    }
+
     /// Drains the command buffer and attempts to execute each command. Will only push commands that
-    /// succeed to the history. If commands dont succeed they are silently failed.
-    pub fn execute_buffer(&mut self, world: &mut World) {
-        for mut command in self.queue.queue.drain(..).into_iter() {
-            match command.command.execute(world) {
+    /// succeed to the history. Commands issued by a player that fail [`GameCommand::authorize`] or
+    /// are rejected by a [`CommandHook`] are discarded without executing, and commands scheduled for
+    /// a later tick are kept in the queue. Commands are executed in the deterministic order given
+    /// by [`GameCommandMeta::ordering_key`]. Returns the result of every drained command, preceded
+    /// by every command that was rejected when it was queued
+    pub fn execute_buffer(&mut self, world: &mut World) -> Vec<CommandExecuted> {
+        let tick = world
+            .get_resource::<SimTick>()
+            .map(|tick| tick.0)
+            .unwrap_or_default();
+        let mut results = std::mem::take(&mut self.rejected);
+        self.rate_limiter.next_tick();
+        record_tick(world, tick);
+        assign_sim_entity_ids(world);
+        let mut deferred = vec![];
+        let mut queue = std::mem::take(&mut self.queue.queue);
+        queue.sort_by_key(|command| command.ordering_key(tick));
+        for mut command in queue {
+            if command
+                .execute_at_tick
+                .is_some_and(|execute_at_tick| execute_at_tick > tick)
+            {
+                deferred.push(command);
+                continue;
+            }
+            command.tick = tick;
+            let checked = match command.issued_by {
+                Some(player) => command.command.authorize(world, &player),
+                None => Ok(()),
+            }
+            .and_then(|_| {
+                self.hooks
+                    .iter_mut()
+                    .try_for_each(|hook| hook.before_execute(world, &command))
+            });
+            if let Err(error) = checked {
+                info!("command rejected with: {:?}", error);
+                let result = Err(error);
+                for hook in self.hooks.iter_mut() {
+                    hook.after_execute(world, &command, &result);
+                }
+                results.push(CommandExecuted {
+                    meta: command,
+                    result,
+                    spawned: vec![],
+                });
+                continue;
+            }
+            command.sequence = match self.history.history.last() {
+                Some(last) if last.tick == tick => last.sequence + 1,
+                _ => 0,
+            };
+            let before = world.increment_change_tick();
+            let result = command.command.execute(world);
+            assign_sim_entity_ids(world);
+            let spawned = sim_entity_ids_inserted_since(world, before);
+            for hook in self.hooks.iter_mut() {
+                hook.after_execute(world, &command, &result);
+            }
+            match result {
                 Ok(_) => {
-                    self.history.push(command);
+                    record_command(world, &command);
+                    results.push(CommandExecuted {
+                        meta: command.clone(),
+                        result: Ok(()),
+                        spawned,
+                    });
+                    let merged = match self.history.history.last_mut() {
+                        Some(last) if last.tick == tick && last.issued_by == command.issued_by => {
+                            last.command.merge(command.command.as_ref())
+                        }
+                        _ => false,
+                    };
+                    match self.history.history.last() {
+                        Some(last) if merged => record_merged(world, last, &command),
+                        _ => self.history.push(command),
+                    }
                 }
                 Err(error) => {
                     info!("execution failed with: {:?}", error);
+                    results.push(CommandExecuted {
+                        meta: command,
+                        result: Err(error),
+                        spawned: vec![],
+                    });
                 }
             }
             self.history.clear_rollback_history();
         }
+        self.queue.queue = deferred;
+        if self.history.record_checksums {
+            if let Some(checksum) = world_checksum(world) {
+                self.history.checksums.insert(tick, checksum);
+            }
+        }
+        self.history.compact(world);
+        if let Some(input_delay) = self.input_delay.as_mut() {
+            input_delay.executed(tick);
+        }
+        results
+    }
+
+    /// Rolls back every requested rollback against the given world - panics if a rollback fails
+    pub fn execute_rollbacks(&mut self, world: &mut World) {
+        while self.history.rollbacks != 0 {
+            if let Some(mut command) = self.history.pop() {
+                command.command.rollback(world).expect("Rollback failed");
+                self.history.checksums.split_off(&command.tick);
+                if self
+                    .history
+                    .compaction_point
+                    .as_ref()
+                    .is_some_and(|(index, _)| *index > self.history.history.len())
+                {
+                    self.history.compaction_point = None;
+                }
+                self.history.rolledback_history.push(command);
+                info!("Rollbacked command");
+            }
+            self.history.rollbacks -= 1;
+        }
+    }
+
+    /// Executes every requested rollforward against the given world
+    pub fn execute_rollforwards(&mut self, world: &mut World) {
+        while self.history.rollforwards != 0 {
+            if let Some(mut command) = self.history.rolledback_history.pop() {
+                if command.command.execute(world).is_ok() {
+                    self.history.push(command.clone());
+                } else {
+                    info!("Rolledforward failed");
+                }
+            }
+            self.history.rollforwards -= 1;
+        }
     }
 
     /// Request a single rollback - The game will attempt to rollback the next time
@@ -292,10 +1165,206 @@ impl GameCommands {
         self.history.rollbacks += amount;
     }
 
+    /// Request rollbacks of every command in the history executed after the given tick - The game
+    /// will attempt these rollbacks the next time [`execute_game_rollbacks_buffer`] is called
+    pub fn rollback_to_tick(&mut self, tick: u64) {
+        let amount = self
+            .history
+            .history
+            .iter()
+            .rev()
+            .take_while(|command| command.tick > tick)
+            .count();
+        self.rollback_amount(amount as u32);
+    }
+
+    /// Executes the given command against a temporary fork of the given sim world and returns the
+    /// state it would change, without changing the sim world or the history. Use this to show the
+    /// predicted outcome of a command using the same logic that executes it. The command is checked
+    /// by the [`CommandHook`]s against the sim world like [`GameCommands::execute_buffer`] does, but
+    /// [`CommandHook::after_execute`] isn't called. The fork is made from a [`SimSnapshot`] so it
+    /// only holds registered components and resources, along with the bookkeeping resources of a
+    /// sim world such as the [`ChangeSet`] and [`SimEntityIndex`]
+    pub fn preview<T>(&mut self, world: &mut World, command: T) -> Result<SimState, CommandError>
+    where
+        T: GameCommand,
+    {
+        self.preview_attributed(world, Box::new(command), None)
+    }
+
+    /// Previews the given command like [`GameCommands::preview`] as if it was issued by the given
+    /// player, so it must also pass [`GameCommand::authorize`]
+    pub fn preview_from_player<T>(
+        &mut self,
+        world: &mut World,
+        command: T,
+        player: Player,
+    ) -> Result<SimState, CommandError>
+    where
+        T: GameCommand,
+    {
+        self.preview_attributed(world, Box::new(command), Some(player))
+    }
+
+    fn preview_attributed(
+        &mut self,
+        world: &mut World,
+        command: Box<dyn GameCommand>,
+        issued_by: Option<Player>,
+    ) -> Result<SimState, CommandError> {
+        let tick = world
+            .get_resource::<SimTick>()
+            .map(|tick| tick.0)
+            .unwrap_or_default();
+        let mut command = GameCommandMeta::new(command, false);
+        command.issued_by = issued_by;
+        command.tick = tick;
+        if let Some(player) = command.issued_by {
+            command.command.authorize(world, &player)?;
+        }
+        for hook in self.hooks.iter_mut() {
+            hook.before_execute(world, &command)?;
+        }
+
+        let Some(baseline) = snapshot_world(world) else {
+            return Err(CommandError::Custom(String::from(
+                "no GameSerDeRegistry in the world",
+            )));
+        };
+        let mut fork = fork_world(world, &baseline);
+        command.command.execute(&mut fork)?;
+        assign_sim_entity_ids(&mut fork);
+        let Some(snapshot) = snapshot_world(&mut fork) else {
+            return Err(CommandError::Custom(String::from(
+                "command removed the GameSerDeRegistry",
+            )));
+        };
+
+        let delta = SnapshotDelta::new(&baseline, &snapshot);
+        let baseline_entities: HashMap<SimEntityId, &Vec<ComponentBinaryState>> = baseline
+            .entities
+            .iter()
+            .map(|entity| (entity.entity, &entity.components))
+            .collect();
+        let entities = delta
+            .changed_entities
+            .into_iter()
+            .map(|entity| {
+                let previous = baseline_entities.get(&entity.entity);
+                EntityState {
+                    entity: entity.entity,
+                    changed_components: entity
+                        .components
+                        .iter()
+                        .filter(|component| {
+                            previous.is_none_or(|previous| !previous.contains(component))
+                        })
+                        .map(|component| component.id)
+                        .collect(),
+                    components: entity.components,
+                    component_deltas: vec![],
+                    changed_tick: snapshot.tick,
+                    is_new: previous.is_none(),
+                }
+            })
+            .collect();
+        Ok(SimState {
+            resources: delta
+                .changed_resources
+                .into_iter()
+                .map(|resource| ResourceState {
+                    resource_id: resource.resource_id,
+                    version: resource.version,
+                    resource: resource.resource,
+                    changed_tick: snapshot.tick,
+                })
+                .collect(),
+            entities,
+            despawn_ticks: delta
+                .removed_entities
+                .iter()
+                .map(|entity| (*entity, snapshot.tick))
+                .collect(),
+            despawned_objects: delta.removed_entities,
+            tick: snapshot.tick,
+            ..Default::default()
+        })
+    }
+
     pub fn rollforward(&mut self, amount: u32) {
         self.history.rollforwards += amount;
     }
 
+    /// Serializes the queue and history so they can be included in a save. Every command type must
+    /// be registered in the given type registry, see [`ReflectGameCommand`]
+    pub fn save(
+        &self,
+        type_registry: &TypeRegistry,
+        format: SimFormat,
+    ) -> Result<SavedCommands, String> {
+        let save_all = |commands: &Vec<GameCommandMeta>| {
+            commands
+                .iter()
+                .map(|command| command.save(type_registry, format))
+                .collect::<Result<Vec<SavedCommand>, String>>()
+        };
+        Ok(SavedCommands {
+            queue: save_all(&self.queue.queue)?,
+            history: save_all(&self.history.history)?,
+            rolledback_history: save_all(&self.history.rolledback_history)?,
+        })
+    }
+
+    /// Replaces the queue and history with the ones in the given save. Pending rollbacks and
+    /// rollforwards are cleared
+    pub fn load(
+        &mut self,
+        saved: &SavedCommands,
+        type_registry: &TypeRegistry,
+        format: SimFormat,
+    ) -> Result<(), String> {
+        let load_all = |commands: &Vec<SavedCommand>| {
+            commands
+                .iter()
+                .map(|command| GameCommandMeta::load(command, type_registry, format))
+                .collect::<Result<Vec<GameCommandMeta>, String>>()
+        };
+        let queue = load_all(&saved.queue)?;
+        let history = load_all(&saved.history)?;
+        let rolledback_history = load_all(&saved.rolledback_history)?;
+        self.queue.next_sequence = queue
+            .iter()
+            .map(|command| command.queue_sequence + 1)
+            .max()
+            .unwrap_or_default();
+        self.queue.queue = queue;
+        self.history = GameCommandsHistory {
+            history,
+            rolledback_history,
+            rollbacks: 0,
+            rollforwards: 0,
+            limit: self.history.limit,
+            baseline: None,
+            compaction_point: None,
+            record_checksums: self.history.record_checksums,
+            checksums: BTreeMap::new(),
+        };
+        Ok(())
+    }
+
+    /// Returns the checksum of the world recorded after the command batch of the given tick was
+    /// executed. Only recorded when [`GameCommandsHistory::record_checksums`] is set
+    pub fn checksum(&self, tick: u64) -> Option<u32> {
+        self.history.checksums.get(&tick).copied()
+    }
+
+    /// Compares the given checksum received from a peer with the one recorded for the given tick.
+    /// Returns false if the peer desynced, or None if no checksum was recorded for the tick
+    pub fn compare_checksum(&self, tick: u64, peer_checksum: u32) -> Option<bool> {
+        self.checksum(tick)
+            .map(|checksum| checksum == peer_checksum)
+    }
+
     /// Add a custom command to the queue
     pub fn add<T>(&mut self, command: T) -> T
     where
@@ -304,4 +1373,598 @@ impl GameCommands {
         self.queue.push(command.clone());
         command
     }
+
+    /// Add a custom command to the queue and return its handle, which can be used to cancel the
+    /// command before it is executed
+    pub fn add_with_handle<T>(&mut self, command: T) -> CommandHandle
+    where
+        T: GameCommand,
+    {
+        self.queue.push_meta(GameCommandMeta::new(
+            Box::new(command),
+            self.queue.record_wall_clock,
+        ))
+    }
+
+    /// Returns every command waiting in the queue, in the order they were queued
+    pub fn pending(&self) -> &[GameCommandMeta] {
+        &self.queue.queue
+    }
+
+    /// Cancels the queued command with the given handle so it is never executed. Returns the
+    /// cancelled command, or None if it isn't queued anymore
+    pub fn cancel(&mut self, handle: CommandHandle) -> Option<GameCommandMeta> {
+        self.queue.remove(handle)
+    }
+
+    /// Add a closure to the queue that runs against the world when executed. Use this for fire and
+    /// forget mutations that don't need rollback or saving, see [`FnCommand`]
+    pub fn add_fn(&mut self, function: impl Fn(&mut World) + Send + Sync + 'static) {
+        self.queue.push(FnCommand::new(function));
+    }
+
+    /// Add a custom command to the queue that is executed once the sim reaches the given tick. Use
+    /// this for input delay in lockstep games and for effects issued ahead of time
+    pub fn add_at_tick<T>(&mut self, command: T, tick: u64) -> T
+    where
+        T: GameCommand + Clone,
+    {
+        self.queue.push_at_tick(command.clone(), tick);
+        command
+    }
+
+    /// Add a custom command issued by the given player to the queue. It is only executed if
+    /// [`GameCommand::authorize`] passes for the player, and is rejected if the player is over its
+    /// [`CommandRateLimiter`] limits
+    pub fn add_from_player<T>(&mut self, command: T, player: Player) -> T
+    where
+        T: GameCommand + Clone,
+    {
+        self.push_attributed(Box::new(command.clone()), Some(player), None);
+        command
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::{Commands, Mut, Reflect, ResMut, Resource, World},
+        reflect::{TypePath, TypeRegistry},
+    };
+
+    use super::{
+        execute_game_commands_buffer, execute_game_rollbacks_buffer, with_params, CommandError,
+        CommandHistoryFilter, CommandHook, CompositeCommand, GameCommand, GameCommandMeta,
+        GameCommands, HistoryLimit, ReflectGameCommand,
+    };
+    use crate::{
+        change_detection::ChangeSet,
+        command_rate_limit::CommandRateLimiter,
+        game_builder::GameBuilder,
+        player::Player,
+        runner::{SimTick, TurnBasedGameRunner},
+        saving::{GameSerDeRegistry, SimFormat},
+        testing::{fixtures::TestComponent, test_game},
+        SimWorld,
+    };
+
+    #[derive(Resource)]
+    struct Counter(u32);
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Increment;
+
+    impl GameCommand for Increment {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.get_resource_or_insert_with(|| Counter(0)).0 += 1;
+            Ok(())
+        }
+
+        fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.resource_mut::<Counter>().0 -= 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_commands_target_sim_world() {
+        let mut main_world = World::new();
+        test_game().build(&mut main_world);
+
+        assert!(!main_world.contains_resource::<GameCommands>());
+        fn game_commands(world: &mut World) -> Mut<'_, GameCommands> {
+            world
+                .resource_mut::<SimWorld>()
+                .into_inner()
+                .game_commands_mut()
+        }
+        game_commands(&mut main_world).add(Increment);
+        game_commands(&mut main_world).add(Increment);
+        execute_game_commands_buffer(&mut main_world);
+        let counter = |world: &World| world.resource::<SimWorld>().world.resource::<Counter>().0;
+        assert_eq!(counter(&main_world), 2);
+        let sequences: Vec<u32> = main_world
+            .resource::<SimWorld>()
+            .game_commands()
+            .history
+            .history
+            .iter()
+            .map(|command| command.sequence)
+            .collect();
+        assert_eq!(sequences, vec![0, 1]);
+
+        game_commands(&mut main_world).rollback_one();
+        execute_game_rollbacks_buffer(&mut main_world);
+        assert_eq!(counter(&main_world), 1);
+    }
+
+    #[derive(Resource)]
+    struct Executed(u64);
+
+    #[derive(Clone, Debug, Reflect)]
+    struct RecordTick;
+
+    impl GameCommand for RecordTick {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            let tick = world.resource::<SimTick>().0;
+            world.insert_resource(Executed(tick));
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct NoIncrementsAfterTwo {
+        executed: u32,
+    }
+
+    impl CommandHook for NoIncrementsAfterTwo {
+        fn before_execute(
+            &mut self,
+            _world: &World,
+            _command: &GameCommandMeta,
+        ) -> Result<(), CommandError> {
+            if self.executed >= 2 {
+                return Err(CommandError::ValidationFailed(String::from("too many")));
+            }
+            Ok(())
+        }
+
+        fn after_execute(
+            &mut self,
+            _world: &World,
+            _command: &GameCommandMeta,
+            result: &Result<(), CommandError>,
+        ) {
+            if result.is_ok() {
+                self.executed += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_hooks() {
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        game_commands.add_hook(NoIncrementsAfterTwo::default());
+        for _ in 0..3 {
+            game_commands.add(Increment);
+        }
+        let results = game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 2);
+        assert!(results[2].result.is_err());
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct SpawnChanged;
+
+    impl GameCommand for SpawnChanged {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            let entity = world.spawn(TestComponent(3)).id();
+            world.resource_mut::<ChangeSet>().mark_changed(entity, 0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_preview_matches_execution() {
+        let mut sim = test_game();
+        sim.register_component::<TestComponent>().unwrap();
+        let mut sim = sim.build_standalone();
+        let world = &mut sim.sim_world.world;
+
+        let mut game_commands = GameCommands::new();
+        let state = game_commands.preview(world, SpawnChanged).unwrap();
+        assert_eq!(state.entities.len(), 1);
+        assert!(state.entities[0].is_new);
+        let mut query = world.query::<&TestComponent>();
+        assert_eq!(query.iter(world).count(), 0);
+
+        game_commands.add_hook(NoIncrementsAfterTwo { executed: 2 });
+        assert_eq!(
+            game_commands.preview(world, Increment).err(),
+            Some(CommandError::ValidationFailed(String::from("too many")))
+        );
+        assert!(!world.contains_resource::<Counter>());
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct HostOnly;
+
+    impl GameCommand for HostOnly {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.get_resource_or_insert_with(|| Counter(0)).0 += 1;
+            Ok(())
+        }
+
+        fn authorize(&self, _world: &World, player: &Player) -> Result<(), CommandError> {
+            if player.id() == 0 {
+                Ok(())
+            } else {
+                Err(CommandError::Unauthorized(String::from("host only")))
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_authorization() {
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        game_commands.add(HostOnly);
+        game_commands.add_from_player(HostOnly, Player::new(1, false));
+        game_commands
+            .queue
+            .push_boxed_from_player(Box::new(HostOnly), Player::new(0, false));
+        let results = game_commands.execute_buffer(&mut world);
+
+        let result_for = |player: Option<usize>| {
+            results
+                .iter()
+                .find(|executed| executed.meta.issued_by.map(|player| player.id()) == player)
+                .map(|executed| executed.result.clone())
+                .unwrap()
+        };
+        assert_eq!(result_for(None), Ok(()));
+        assert_eq!(result_for(Some(0)), Ok(()));
+        assert_eq!(
+            result_for(Some(1)),
+            Err(CommandError::Unauthorized(String::from("host only")))
+        );
+        assert_eq!(world.resource::<Counter>().0, 2);
+        assert_eq!(game_commands.history.history.len(), 2);
+    }
+
+    #[test]
+    fn test_scheduled_commands() {
+        let mut world = World::new();
+        world.insert_resource(SimTick(0));
+        let mut game_commands = GameCommands::new();
+        game_commands.add_at_tick(RecordTick, 2);
+
+        game_commands.execute_buffer(&mut world);
+        world.resource_mut::<SimTick>().0 = 1;
+        game_commands.execute_buffer(&mut world);
+        assert!(!world.contains_resource::<Executed>());
+
+        world.resource_mut::<SimTick>().0 = 2;
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Executed>().0, 2);
+        assert!(game_commands.queue.queue.is_empty());
+    }
+
+    #[test]
+    fn test_fn_commands() {
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        game_commands.add_fn(|world| world.get_resource_or_insert_with(|| Counter(0)).0 += 5);
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 5);
+
+        game_commands.rollback_one();
+        game_commands.execute_rollbacks(&mut world);
+        game_commands.rollforward(1);
+        game_commands.execute_rollforwards(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 10);
+    }
+
+    #[derive(Default, Resource)]
+    struct ExecutionOrder(Vec<u32>);
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Record(u32);
+
+    impl GameCommand for Record {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world
+                .get_resource_or_insert_with(ExecutionOrder::default)
+                .0
+                .push(self.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_deterministic_command_order() {
+        let mut world = World::new();
+        world.insert_resource(SimTick(3));
+        let mut game_commands = GameCommands::new();
+        game_commands.add_from_player(Record(0), Player::new(2, false));
+        game_commands.add_from_player(Record(1), Player::new(1, false));
+        game_commands.add(Record(2));
+        game_commands.add_at_tick(Record(3), 1);
+        game_commands.add_from_player(Record(4), Player::new(1, false));
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<ExecutionOrder>().0, vec![3, 2, 1, 4, 0]);
+    }
+
+    #[test]
+    fn test_cancel_queued_command() {
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        game_commands.add(Record(0));
+        let handle = game_commands.add_with_handle(Record(1));
+        assert_eq!(game_commands.pending().len(), 2);
+
+        assert!(game_commands.cancel(handle).is_some());
+        assert!(game_commands.cancel(handle).is_none());
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<ExecutionOrder>().0, vec![0]);
+    }
+
+    #[test]
+    fn test_cancel_after_initial_commands() {
+        let mut game = GameBuilder::new_game_with_commands(
+            vec![Box::new(Record(0)), Box::new(Record(1))],
+            TurnBasedGameRunner {
+                turn_schedule: Default::default(),
+            },
+        );
+        let game_commands = game.commands.as_mut().unwrap();
+        let handle = game_commands.add_with_handle(Record(2));
+        assert!(game_commands.cancel(handle).is_some());
+
+        let mut sim = game.build_standalone();
+        assert_eq!(
+            sim.sim_world.world.resource::<ExecutionOrder>().0,
+            vec![0, 1]
+        );
+        assert_eq!(sim.take_command_results().len(), 2);
+    }
+
+    #[test]
+    fn test_history_compaction() {
+        let mut world = World::new();
+        world.insert_resource(GameSerDeRegistry::new());
+        let mut game_commands = GameCommands::new();
+        game_commands.history.limit = HistoryLimit {
+            max_commands: Some(4),
+            max_bytes: None,
+        };
+        for _ in 0..10 {
+            game_commands.add(Increment);
+            game_commands.execute_buffer(&mut world);
+            assert!(game_commands.history.history.len() <= 4);
+        }
+        assert!(game_commands.history.baseline.is_some());
+        assert!(game_commands.history.history.len() >= 2);
+    }
+
+    #[test]
+    fn test_batch_checksums() {
+        let mut world = World::new();
+        world.insert_resource(GameSerDeRegistry::new());
+        world.insert_resource(SimTick(1));
+        let mut game_commands = GameCommands::new();
+        game_commands.history.record_checksums = true;
+        game_commands.add(Increment);
+        game_commands.execute_buffer(&mut world);
+
+        let checksum = game_commands.checksum(1).unwrap();
+        assert_eq!(game_commands.compare_checksum(1, checksum), Some(true));
+        assert_eq!(game_commands.compare_checksum(1, checksum ^ 1), Some(false));
+        assert_eq!(game_commands.compare_checksum(2, checksum), None);
+
+        game_commands.rollback_one();
+        game_commands.execute_rollbacks(&mut world);
+        assert_eq!(game_commands.checksum(1), None);
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Fail;
+
+    impl GameCommand for Fail {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Err(CommandError::from("always fails"))
+        }
+    }
+
+    #[test]
+    fn test_composite_commands() {
+        let mut world = World::new();
+        world.insert_resource(Counter(0));
+        let mut game_commands = GameCommands::new();
+        game_commands.add(CompositeCommand::new().with(Increment).with(Increment));
+        game_commands.add(CompositeCommand::new().with(Increment).with(Fail));
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 2);
+        assert_eq!(game_commands.history.history.len(), 1);
+
+        game_commands.rollback_one();
+        game_commands.execute_rollbacks(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 0);
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct FailRollback;
+
+    impl GameCommand for FailRollback {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Ok(())
+        }
+
+        fn rollback(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Err(CommandError::from("rollback fails"))
+        }
+    }
+
+    #[test]
+    fn test_composite_returns_execution_error() {
+        let mut world = World::new();
+        world.insert_resource(Counter(0));
+        let mut composite = CompositeCommand::new()
+            .with(Increment)
+            .with(FailRollback)
+            .with(Fail);
+        let error = composite.execute(&mut world).unwrap_err();
+        assert!(matches!(error, CommandError::Custom(reason) if reason == "always fails"));
+        assert_eq!(world.resource::<Counter>().0, 0);
+    }
+
+    #[test]
+    fn test_save_composite_command() {
+        let mut type_registry = TypeRegistry::default();
+        type_registry.register::<Increment>();
+        type_registry.register_type_data::<Increment, ReflectGameCommand>();
+        type_registry.register::<CompositeCommand>();
+        type_registry.register_type_data::<CompositeCommand, ReflectGameCommand>();
+
+        let composite = CompositeCommand::new().with(Increment).with(Increment);
+        let saved = GameCommandMeta::new(Box::new(composite), false)
+            .save(&type_registry, SimFormat::default())
+            .unwrap();
+        let mut loaded =
+            GameCommandMeta::load(&saved, &type_registry, SimFormat::default()).unwrap();
+
+        let mut world = World::new();
+        assert!(loaded.command.execute(&mut world).is_ok());
+        assert_eq!(world.resource::<Counter>().0, 2);
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct IncrementWithParams;
+
+    impl GameCommand for IncrementWithParams {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            with_params::<(ResMut<Counter>, Commands), _>(world, |(mut counter, mut commands)| {
+                counter.0 += 1;
+                commands.insert_resource(Executed(counter.0 as u64));
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_command_params() {
+        let mut world = World::new();
+        world.insert_resource(Counter(0));
+        let mut game_commands = GameCommands::new();
+        game_commands.add(IncrementWithParams);
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 1);
+        assert_eq!(world.resource::<Executed>().0, 1);
+    }
+
+    #[test]
+    fn test_command_rate_limit() {
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        game_commands.rate_limiter = CommandRateLimiter::new(Some(2), None);
+        let player = Player::new(0, false);
+        for _ in 0..3 {
+            game_commands.add_from_player(Increment, player);
+        }
+        game_commands.add_from_player(Increment, Player::new(1, false));
+
+        let results = game_commands.execute_buffer(&mut world);
+        assert_eq!(results[0].result, Err(CommandError::RateLimited(player)));
+        assert_eq!(results.len(), 4);
+        assert_eq!(world.resource::<Counter>().0, 3);
+
+        game_commands.add_from_player(Increment, player);
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 4);
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct SetCounter {
+        value: u32,
+        previous: Option<u32>,
+    }
+
+    impl GameCommand for SetCounter {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            let mut counter = world.get_resource_or_insert_with(|| Counter(0));
+            self.previous = Some(counter.0);
+            counter.0 = self.value;
+            Ok(())
+        }
+
+        fn rollback(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.resource_mut::<Counter>().0 = self.previous.unwrap_or_default();
+            Ok(())
+        }
+
+        fn merge(&mut self, next: &dyn GameCommand) -> bool {
+            let Some(next) = next.as_reflect().downcast_ref::<SetCounter>() else {
+                return false;
+            };
+            self.value = next.value;
+            true
+        }
+    }
+
+    #[test]
+    fn test_command_coalescing() {
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        for value in 1..=3 {
+            game_commands.add(SetCounter {
+                value,
+                previous: None,
+            });
+        }
+        game_commands.add(Increment);
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 4);
+        assert_eq!(game_commands.history.history.len(), 2);
+
+        game_commands.rollback_amount(2);
+        game_commands.execute_rollbacks(&mut world);
+        assert_eq!(world.resource::<Counter>().0, 0);
+    }
+
+    #[test]
+    fn test_history_query() {
+        let mut world = World::new();
+        world.insert_resource(SimTick(1));
+        let mut game_commands = GameCommands::new();
+        game_commands.add_from_player(Increment, Player::new(0, false));
+        game_commands.add_from_player(Record(0), Player::new(1, false));
+        game_commands.execute_buffer(&mut world);
+        world.insert_resource(SimTick(2));
+        game_commands.add_from_player(Increment, Player::new(1, false));
+        game_commands.execute_buffer(&mut world);
+
+        let count = |filter: CommandHistoryFilter| game_commands.history.query(&filter).count();
+        assert_eq!(count(CommandHistoryFilter::new()), 3);
+        assert_eq!(count(CommandHistoryFilter::new().issued_by(1)), 2);
+        assert_eq!(count(CommandHistoryFilter::new().ticks(2, 5)), 1);
+        assert_eq!(count(CommandHistoryFilter::new().type_name("Increment")), 2);
+        assert_eq!(
+            count(CommandHistoryFilter::new().type_name(Increment::type_path())),
+            2
+        );
+        assert_eq!(count(CommandHistoryFilter::new().type_name("crement")), 0);
+        assert_eq!(
+            count(CommandHistoryFilter::new().type_name("test::Record")),
+            0
+        );
+        assert_eq!(
+            count(
+                CommandHistoryFilter::new()
+                    .issued_by(1)
+                    .type_name("Increment")
+            ),
+            1
+        );
+    }
 }
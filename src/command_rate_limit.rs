@@ -0,0 +1,64 @@
+//! Per player limits on the number of commands that can be queued, protecting server sims from
+//! input flooding. Limits are enforced when a command issued by a player is queued with
+//! [`GameCommands::add_from_player`](crate::command::GameCommands::add_from_player) or
+//! [`GameCommands::add_encoded`](crate::command::GameCommands::add_encoded). Commands over the limit
+//! are never queued and are reported as [`CommandError::RateLimited`] in the results of the next
+//! [`GameCommands::execute_buffer`](crate::command::GameCommands::execute_buffer).
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use bevy::utils::HashMap;
+
+use crate::{command::CommandError, player::Player};
+
+/// Limits on the number of commands each player can queue. Unlimited by default
+#[derive(Default, Clone)]
+pub struct CommandRateLimiter {
+    /// The maximum number of commands a player can queue each tick
+    pub per_tick: Option<u32>,
+    /// The maximum number of commands a player can queue within any second of wall clock time
+    pub per_second: Option<u32>,
+    queued_this_tick: HashMap<usize, u32>,
+    queued_times: HashMap<usize, VecDeque<Instant>>,
+}
+
+impl CommandRateLimiter {
+    pub fn new(per_tick: Option<u32>, per_second: Option<u32>) -> CommandRateLimiter {
+        CommandRateLimiter {
+            per_tick,
+            per_second,
+            ..Default::default()
+        }
+    }
+
+    /// Counts a command queued by the given player. Returns an error without counting it if the
+    /// player is over one of its limits
+    pub fn check(&mut self, player: &Player) -> Result<(), CommandError> {
+        let queued = self.queued_this_tick.entry(player.id()).or_default();
+        if self.per_tick.is_some_and(|per_tick| *queued >= per_tick) {
+            return Err(CommandError::RateLimited(*player));
+        }
+        if let Some(per_second) = self.per_second {
+            let now = Instant::now();
+            let times = self.queued_times.entry(player.id()).or_default();
+            while times
+                .front()
+                .is_some_and(|time| now.duration_since(*time) >= Duration::from_secs(1))
+            {
+                times.pop_front();
+            }
+            if times.len() >= per_second as usize {
+                return Err(CommandError::RateLimited(*player));
+            }
+            times.push_back(now);
+        }
+        *queued += 1;
+        Ok(())
+    }
+
+    /// Resets the per tick counts. Called after every executed command batch
+    pub fn next_tick(&mut self) {
+        self.queued_this_tick.clear();
+    }
+}
@@ -0,0 +1,230 @@
+//! Registration of [`GameCommand`]s that can be sent over the network. Commands implementing
+//! [`SimCommand`] are registered into a [`CommandRegistry`] with a hand assigned
+//! [`SimCommandId`], mirroring how components and resources are registered into the
+//! [`GameSerDeRegistry`](crate::saving::GameSerDeRegistry).
+//!
+//! A client encodes its commands with [`CommandRegistry::encode`] and sends the resulting
+//! [`EncodedCommand`] to the server, which decodes and queues it with [`GameCommands::add_encoded`].
+
+use std::any::{Any, TypeId};
+
+use bevy::{prelude::Resource, utils::HashMap};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    command::{GameCommand, GameCommands},
+    player::Player,
+    saving::{RegistryError, SimFormat, SimSerializer},
+};
+
+/// An id hand assigned to commands using the [`SimCommand`] trait that identifies each command
+///
+/// Is simply a u16 under the type
+pub type SimCommandId = u16;
+
+/// A [`GameCommand`] that can be encoded and sent to another sim. Register it with
+/// [`GameBuilder::register_sim_command`](crate::game_builder::GameBuilder::register_sim_command)
+pub trait SimCommand: GameCommand + Serialize + DeserializeOwned {
+    /// The id the command is encoded with. Must be unique across every registered command
+    fn command_id() -> SimCommandId;
+}
+
+/// A command encoded with [`CommandRegistry::encode`]
+#[derive(Clone, Eq, Hash, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EncodedCommand {
+    pub id: SimCommandId,
+    pub command: Vec<u8>,
+}
+
+pub type CommandSerializeFn = fn(format: SimFormat, command: &dyn GameCommand) -> Option<Vec<u8>>;
+
+pub type CommandDeserializeFn =
+    fn(format: SimFormat, data: &[u8]) -> Result<Box<dyn GameCommand>, String>;
+
+/// A registry that contains serialization functions for every [`SimCommand`]
+#[derive(Resource, Clone, Default)]
+pub struct CommandRegistry {
+    /// The format commands are encoded with. Set to the format of the games
+    /// [`GameSerDeRegistry`](crate::saving::GameSerDeRegistry) when the game is built
+    pub format: SimFormat,
+    pub command_ids: HashMap<TypeId, SimCommandId>,
+    pub command_se_map: HashMap<SimCommandId, CommandSerializeFn>,
+    pub command_de_map: HashMap<SimCommandId, CommandDeserializeFn>,
+    pub command_type_names: HashMap<SimCommandId, &'static str>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> CommandRegistry {
+        CommandRegistry::default()
+    }
+
+    /// Registers a command so it can be encoded and decoded. Returns an error without registering
+    /// if the id is already used
+    pub fn register<C>(&mut self) -> Result<(), RegistryError>
+    where
+        C: SimCommand,
+    {
+        let type_name = std::any::type_name::<C>();
+        if let Some(existing) = self.command_type_names.get(&C::command_id()) {
+            return Err(RegistryError::DuplicateCommandId {
+                id: C::command_id(),
+                existing,
+                new: type_name,
+            });
+        }
+        self.command_ids.insert(TypeId::of::<C>(), C::command_id());
+        self.command_se_map
+            .insert(C::command_id(), command_serialize::<C>);
+        self.command_de_map
+            .insert(C::command_id(), command_deserialize::<C>);
+        self.command_type_names.insert(C::command_id(), type_name);
+        Ok(())
+    }
+
+    /// Returns the id of the given command if its type is registered
+    pub fn command_id(&self, command: &dyn GameCommand) -> Option<SimCommandId> {
+        self.command_ids
+            .get(&Any::type_id(command.as_any()))
+            .copied()
+    }
+
+    /// Encodes the given command. Returns None if its type isn't registered or it fails to serialize
+    pub fn encode(&self, command: &dyn GameCommand) -> Option<EncodedCommand> {
+        let id = self.command_id(command)?;
+        let serialize_fn = self.command_se_map.get(&id)?;
+        Some(EncodedCommand {
+            id,
+            command: serialize_fn(self.format, command)?,
+        })
+    }
+
+    /// Decodes a command encoded with [`CommandRegistry::encode`]
+    pub fn decode(&self, encoded: &EncodedCommand) -> Result<Box<dyn GameCommand>, String> {
+        let Some(deserialize_fn) = self.command_de_map.get(&encoded.id) else {
+            return Err(format!("Command id {} is not registered", encoded.id));
+        };
+        deserialize_fn(self.format, &encoded.command)
+    }
+}
+
+/// Serializes the given command if it is a C
+pub fn command_serialize<C>(format: SimFormat, command: &dyn GameCommand) -> Option<Vec<u8>>
+where
+    C: SimCommand,
+{
+    let command = command.as_any().downcast_ref::<C>()?;
+    format.encode(command)
+}
+
+/// Deserializes a C from the given data
+pub fn command_deserialize<C>(
+    format: SimFormat,
+    data: &[u8],
+) -> Result<Box<dyn GameCommand>, String>
+where
+    C: SimCommand,
+{
+    format
+        .try_decode::<C>(data)
+        .map(|command| Box::new(command) as Box<dyn GameCommand>)
+        .map_err(|error| error.to_string())
+}
+
+impl GameCommands {
+    /// Decodes the given command with the given registry and adds it to the queue, issued by the
+    /// given player if any
+    pub fn add_encoded(
+        &mut self,
+        registry: &CommandRegistry,
+        encoded: &EncodedCommand,
+        issued_by: Option<Player>,
+    ) -> Result<(), String> {
+        let command = registry.decode(encoded)?;
+        self.push_attributed(command, issued_by, None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Reflect, World};
+    use serde::{Deserialize, Serialize};
+
+    use super::{CommandRegistry, SimCommand, SimCommandId};
+    use crate::{
+        command::{CommandError, GameCommand, GameCommands},
+        player::Player,
+        saving::RegistryError,
+        testing::test_game,
+    };
+
+    #[derive(Clone, Debug, Reflect, Serialize, Deserialize)]
+    struct SetScore(u32);
+
+    impl GameCommand for SetScore {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.insert_resource(Score(self.0));
+            Ok(())
+        }
+
+        fn authorize(&self, _world: &World, player: &Player) -> Result<(), CommandError> {
+            if player.id() == 0 {
+                Ok(())
+            } else {
+                Err(CommandError::Unauthorized(String::from(
+                    "only the host can set the score",
+                )))
+            }
+        }
+    }
+
+    impl SimCommand for SetScore {
+        fn command_id() -> SimCommandId {
+            1
+        }
+    }
+
+    #[derive(bevy::prelude::Resource)]
+    struct Score(u32);
+
+    #[test]
+    fn test_encoded_commands() {
+        let mut registry = CommandRegistry::new();
+        registry.register::<SetScore>().unwrap();
+        assert!(registry.register::<SetScore>().is_err());
+
+        let encoded = registry.encode(&SetScore(7)).unwrap();
+        assert_eq!(encoded.id, 1);
+
+        let mut world = World::new();
+        let mut game_commands = GameCommands::new();
+        game_commands
+            .add_encoded(&registry, &encoded, Some(Player::new(0, true)))
+            .unwrap();
+        game_commands.execute_buffer(&mut world);
+        assert_eq!(world.resource::<Score>().0, 7);
+
+        let encoded = registry.encode(&SetScore(9)).unwrap();
+        game_commands
+            .add_encoded(&registry, &encoded, Some(Player::new(1, true)))
+            .unwrap();
+        let results = game_commands.execute_buffer(&mut world);
+        assert!(matches!(
+            results[0].result,
+            Err(CommandError::Unauthorized(_))
+        ));
+        assert_eq!(world.resource::<Score>().0, 7);
+        assert_eq!(game_commands.history.history.len(), 1);
+    }
+
+    #[test]
+    fn test_builder_returns_command_collisions() {
+        let mut game = test_game();
+        game.register_sim_command::<SetScore>().unwrap();
+        assert!(matches!(
+            game.register_sim_command::<SetScore>(),
+            Err(RegistryError::DuplicateCommandId { id: 1, .. })
+        ));
+        assert_eq!(game.validate().unwrap_err().len(), 1);
+    }
+}
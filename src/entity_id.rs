@@ -0,0 +1,156 @@
+//! Stable ids for sim entities. Bevy [`Entity`] values aren't stable across save and load or across
+//! machines, so every entity in the sim world is assigned a [`SimEntityId`] that is used as its key
+//! in [`SimState`](crate::requests::SimState)s, [`TrackedDespawns`](crate::change_detection::TrackedDespawns),
+//! and saves instead.
+//!
+//! Components that store [`Entity`] references can be registered with
+//! [`GameSerDeRegistry::register_map_entities`](crate::saving::GameSerDeRegistry::register_map_entities).
+//! Their references are serialized as portable entities that encode the [`SimEntityId`] of the
+//! referenced entity, see [`SimEntityId::to_portable`], and remapped onto the matching entities when
+//! the component is deserialized.
+
+use bevy::{
+    ecs::entity::EntityMapper,
+    prelude::{Component, Entity, Resource, Without, World},
+    reflect::Reflect,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+/// The stable id of an entity in the sim world. Assigned automatically to every entity that
+/// doesn't have one, see [`assign_sim_entity_ids`]
+#[derive(
+    Clone,
+    Copy,
+    Eq,
+    Hash,
+    Debug,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Component,
+    Reflect,
+    Serialize,
+    Deserialize,
+)]
+pub struct SimEntityId(pub u64);
+
+impl SimEntityId {
+    /// Encodes the id as an [`Entity`] so it can be stored in place of an entity reference. The
+    /// portable entity never refers to a live entity and must be turned back into an id with
+    /// [`SimEntityId::from_portable`]
+    pub fn to_portable(self) -> Entity {
+        let generation = (self.0 >> 32) + 1;
+        Entity::from_bits((generation << 32) | (self.0 & u32::MAX as u64))
+    }
+
+    /// Decodes an id encoded with [`SimEntityId::to_portable`]
+    pub fn from_portable(entity: Entity) -> SimEntityId {
+        SimEntityId(((entity.generation() as u64 - 1) << 32) | entity.index() as u64)
+    }
+}
+
+/// [`EntityMapper`] that turns entities in the given world into portable entities. Entities
+/// without a [`SimEntityId`] are mapped to [`Entity::PLACEHOLDER`]
+pub struct PortableEntityMapper<'w> {
+    pub world: &'w World,
+}
+
+impl EntityMapper for PortableEntityMapper<'_> {
+    fn map_entity(&mut self, entity: Entity) -> Entity {
+        self.world
+            .get::<SimEntityId>(entity)
+            .map(|id| id.to_portable())
+            .unwrap_or(Entity::PLACEHOLDER)
+    }
+}
+
+/// [`EntityMapper`] that turns portable entities back into entities using the given map, see
+/// [`sim_entity_map`]. Ids that aren't in the map are mapped to [`Entity::PLACEHOLDER`]
+pub struct SimEntityIdMapper<'a> {
+    pub map: &'a HashMap<SimEntityId, Entity>,
+}
+
+impl EntityMapper for SimEntityIdMapper<'_> {
+    fn map_entity(&mut self, entity: Entity) -> Entity {
+        if entity == Entity::PLACEHOLDER {
+            return entity;
+        }
+        self.map
+            .get(&SimEntityId::from_portable(entity))
+            .copied()
+            .unwrap_or(Entity::PLACEHOLDER)
+    }
+}
+
+/// Resource inserted into the sim world that hands out [`SimEntityId`]s
+#[derive(Default, Clone, Copy, Eq, Debug, PartialEq, Resource, Serialize, Deserialize)]
+pub struct SimEntityIdAllocator {
+    /// The id that will be assigned next
+    pub next: u64,
+}
+
+impl SimEntityIdAllocator {
+    /// Returns a new unique id
+    pub fn allocate(&mut self) -> SimEntityId {
+        let id = SimEntityId(self.next);
+        self.next += 1;
+        id
+    }
+}
+
+/// Assigns a [`SimEntityId`] to every entity in the world that doesn't have one yet. Runs
+/// automatically in the game post schedule, when the game is built, after every command executed by
+/// [`GameCommands::execute_buffer`](crate::command::GameCommands::execute_buffer), and before every
+/// request made through [`SimWorld::request`](crate::SimWorld::request)
+pub fn assign_sim_entity_ids(world: &mut World) {
+    let mut query = world.query_filtered::<Entity, Without<SimEntityId>>();
+    let entities: Vec<Entity> = query.iter(world).collect();
+    if entities.is_empty() {
+        return;
+    }
+
+    let mut allocator = world.get_resource_or_insert_with(SimEntityIdAllocator::default);
+    let ids: Vec<SimEntityId> = entities.iter().map(|_| allocator.allocate()).collect();
+    for (entity, id) in entities.iter().zip(ids.iter()) {
+        world.entity_mut(*entity).insert(*id);
+    }
+    if let Some(mut index) = world.get_resource_mut::<SimEntityIndex>() {
+        index.entities.extend(ids.into_iter().zip(entities));
+    }
+}
+
+/// Resource inserted into the sim world that maps every [`SimEntityId`] to the entity holding it, see
+/// [`find_sim_entity`]. Kept up to date by [`assign_sim_entity_ids`] and rebuilt whenever a lookup
+/// misses, so ids inserted any other way are found as well
+#[derive(Default, Clone, Debug, Resource)]
+pub struct SimEntityIndex {
+    pub entities: HashMap<SimEntityId, Entity>,
+}
+
+/// Returns the entity with the given [`SimEntityId`] using the [`SimEntityIndex`] of the world. The
+/// entry is checked against the entity, and the index is rebuilt from every entity if the id isn't
+/// in it or its entry is stale, so only looking up ids that aren't in the world is O(N)
+pub fn find_sim_entity(world: &mut World, id: SimEntityId) -> Option<Entity> {
+    let indexed = world
+        .get_resource::<SimEntityIndex>()
+        .and_then(|index| index.entities.get(&id).copied());
+    if let Some(entity) = indexed {
+        if world.get::<SimEntityId>(entity) == Some(&id) {
+            return Some(entity);
+        }
+    }
+    let entities = sim_entity_map(world);
+    let entity = entities.get(&id).copied();
+    world.insert_resource(SimEntityIndex { entities });
+    entity
+}
+
+/// Returns a map from every [`SimEntityId`] in the world to the entity holding it
+pub fn sim_entity_map(world: &mut World) -> HashMap<SimEntityId, Entity> {
+    let mut query = world.query::<(Entity, &SimEntityId)>();
+    query
+        .iter(world)
+        .map(|(entity, id)| (*id, entity))
+        .collect()
+}
@@ -1,17 +1,48 @@
-use crate::change_detection::{despawn_objects, track_component_changes, track_resource_changes};
-use crate::change_detection::{ResourceChangeTracking, TrackedDespawns};
-use crate::command::{GameCommand, GameCommandMeta, GameCommandQueue, GameCommands};
+use crate::builtin_commands;
+use crate::change_detection::{
+    despawn_objects, track_registered_components, track_resource_changes,
+};
+use crate::change_detection::{
+    propagate_hierarchy_changes, ChangeHistory, ChangeSet, ComponentChangeTrackers,
+    HierarchyPropagation, ResourceChangeTracking, TrackedDespawns,
+};
+use crate::change_filter::{
+    ChangeFilter, ComponentChangeFilter, ReplicationInterval, ThresholdFilter,
+};
+use crate::command::{
+    CommandExecuted, CompositeCommand, GameCommand, GameCommandMeta, GameCommands, HistoryLimit,
+    ReflectGameCommand,
+};
+use crate::command_registry::{CommandRegistry, SimCommand};
+use crate::entity_id::{assign_sim_entity_ids, SimEntityIdAllocator, SimEntityIndex};
+use crate::input_delay::InputDelay;
+use crate::interest::PlayerInterests;
 use crate::player::{Player, PlayerList, PlayerMarker};
-use crate::runner::{GameRunner, GameRuntime, PostBaseSets, PreBaseSets};
+use crate::replay::ReplayRecorder;
+use crate::requests::{
+    acks::PendingStateAcks,
+    all_state::AllStateCache,
+    middleware::{RequestMetrics, RequestMetricsRecorder, RequestMiddleware},
+    SentEntities, SimReadQueries, StateSequences,
+};
+use crate::runner::{GameRunner, GameRuntime, PostBaseSets, PreBaseSets, SimTick, StandaloneSim};
+use crate::stats::ReplicationStats;
 use crate::SimWorld;
+use bevy::ecs::entity::MapEntities;
+use bevy::ecs::schedule::ExecutorKind;
 use bevy::prelude::*;
 use bevy_trait_query::RegisterExt;
-use chrono::{DateTime, Utc};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::default::Default;
 
-use crate::saving::{GameSerDeRegistry, SaveId};
+use crate::saving::{
+    autosave::Autosave,
+    field_delta::{DeltaBaselines, DeltaSerialize},
+    history::SnapshotHistory,
+    journal::CommandJournal,
+    GameSerDeRegistry, RegistryError, SaveId, SimComponentId, SimCompression, SimFormat,
+};
 
 /// GameBuilder that creates a new game and sets it up correctly
 #[derive(Resource)]
@@ -29,9 +60,24 @@ where
     /// this for systems that must be run once when the game is setup and only then
     pub setup_schedule: Schedule,
     pub game_serde_registry: GameSerDeRegistry,
+    /// Holds the commands that can be encoded and sent over the network, see
+    /// [`command_registry`](crate::command_registry)
+    pub command_registry: CommandRegistry,
+    /// Moved into the sim world when the game is built, see [`SimWorld::game_commands`]
     pub commands: Option<GameCommands>,
     pub next_player_id: usize,
     pub player_list: PlayerList,
+    /// The executor used for the setup, pre, post, and runner schedules. Defaults to
+    /// [`ExecutorKind::SingleThreaded`] for determinism. Use [`ExecutorKind::MultiThreaded`] to run
+    /// the sim schedules on the shared compute task pool for throughput
+    pub executor_kind: ExecutorKind,
+    /// Autosave moved into the [`GameRuntime`] when the game is built
+    pub autosave: Option<Autosave>,
+    /// Whether the built [`SimWorld`] caches full state requests, see [`AllStateCache`]
+    pub all_state_cache: bool,
+    /// Middleware moved into the [`SimWorld`] when the game is built, see
+    /// [`middleware`](crate::requests::middleware)
+    pub request_middleware: Vec<Box<dyn RequestMiddleware>>,
 }
 
 impl<GR> GameBuilder<GR>
@@ -39,9 +85,7 @@ where
     GR: GameRunner,
 {
     pub fn new_game(game_runner: GR) -> GameBuilder<GR> {
-        let mut game_world = World::new();
-
-        game_world.insert_resource(GameCommands::default());
+        let game_world = World::new();
 
         GameBuilder {
             game_runner,
@@ -50,76 +94,221 @@ where
             game_world,
             setup_schedule: GameBuilder::<GR>::default_setup_schedule(),
             game_serde_registry: GameSerDeRegistry::default_registry(),
+            command_registry: CommandRegistry::new(),
             commands: Default::default(),
             next_player_id: 0,
             player_list: PlayerList { players: vec![] },
+            executor_kind: ExecutorKind::SingleThreaded,
+            autosave: None,
+            all_state_cache: false,
+            request_middleware: vec![],
         }
     }
     pub fn new_game_with_commands(
         commands: Vec<Box<dyn GameCommand>>,
         game_runner: GR,
     ) -> GameBuilder<GR> {
-        let mut game_command_queue: Vec<GameCommandMeta> = vec![];
-
+        let mut game_commands = GameCommands::default();
         for command in commands.into_iter() {
-            let utc: DateTime<Utc> = Utc::now();
-            game_command_queue.push(GameCommandMeta {
-                command,
-                command_time: utc,
-            })
+            game_commands
+                .queue
+                .push_meta(GameCommandMeta::new(command, false));
         }
 
-        let game_world = World::new();
-
-        GameBuilder {
-            game_runner,
-            game_pre_schedule: GameBuilder::<GR>::default_game_pre_schedule(),
-            game_post_schedule: GameBuilder::<GR>::default_game_post_schedule(),
-            game_world,
-            setup_schedule: GameBuilder::<GR>::default_setup_schedule(),
-            game_serde_registry: GameSerDeRegistry::default_registry(),
-            commands: Some(GameCommands {
-                queue: GameCommandQueue {
-                    queue: game_command_queue,
-                },
-                history: Default::default(),
-            }),
-            next_player_id: 0,
-            player_list: PlayerList { players: vec![] },
-        }
+        let mut game_builder = GameBuilder::new_game(game_runner);
+        game_builder.commands = Some(game_commands);
+        game_builder
     }
 
-    /// Removes the [`GameCommands`] from the game world and returns them. Make sure to reinsert the commands
-    /// after using them
+    /// Removes the [`GameCommands`] from the builder and returns them. Make sure to reinsert the
+    /// commands after using them
     pub fn remove_commands(&mut self) -> Option<GameCommands> {
         self.commands.take()
     }
 
-    /// Inserts the given commands into the game world
+    /// Inserts the given commands into the builder. They are moved into the sim world when the game
+    /// is built, see [`SimWorld::game_commands`]
     pub fn insert_commands(&mut self, game_commands: GameCommands) {
         self.commands = Some(game_commands);
     }
 
+    /// Records a checksum of the sim world after every executed command batch, so lockstep peers can
+    /// detect desyncs with [`GameCommands::compare_checksum`]
+    pub fn enable_command_checksums(&mut self) {
+        let commands = self.commands.get_or_insert_with(GameCommands::default);
+        commands.history.record_checksums = true;
+    }
+
+    /// Limits the number of commands each player can queue per tick and per second, see
+    /// [`CommandRateLimiter`](crate::command_rate_limit::CommandRateLimiter)
+    pub fn set_command_rate_limit(&mut self, per_tick: Option<u32>, per_second: Option<u32>) {
+        let commands = self.commands.get_or_insert_with(GameCommands::default);
+        commands.rate_limiter.per_tick = per_tick;
+        commands.rate_limiter.per_second = per_second;
+    }
+
+    /// Schedules commands issued by players the given number of ticks ahead and holds back each tick
+    /// until the command sets of every player for it have arrived, see
+    /// [`InputDelay`](crate::input_delay::InputDelay)
+    pub fn enable_input_delay(&mut self, delay: u64) {
+        let commands = self.commands.get_or_insert_with(GameCommands::default);
+        commands.input_delay = Some(InputDelay::new(delay));
+    }
+
+    /// Limits the size of the command history, compacting old commands into a snapshot baseline
+    /// once it is exceeded, see [`GameCommandsHistory::compact`](crate::command::GameCommandsHistory::compact)
+    pub fn set_command_history_limit(&mut self, limit: HistoryLimit) {
+        let commands = self.commands.get_or_insert_with(GameCommands::default);
+        commands.history.limit = limit;
+    }
+
     /// Adds the default registry which has all the basic Bevy_GGF components and resources
     pub fn add_default_registrations(&mut self) {
         self.game_world
             .register_component_as::<dyn SaveId, PlayerMarker>();
     }
 
+    /// Enables tracking state acks so that state issued to players stays pending and is resent until
+    /// the player acknowledges it with [`SimWorld::ack_state`]. At most the given number of
+    /// unacknowledged sequences are kept per player. Use this when sending state over an unreliable
+    /// transport
+    pub fn enable_state_acks(&mut self, capacity: usize) {
+        self.game_world
+            .insert_resource(PendingStateAcks::new(capacity));
+    }
+
+    /// Records what changed in each of the last given number of ticks into a [`ChangeHistory`] so
+    /// reconnecting players can be sent only what they missed with
+    /// [`StateSince`](crate::requests::state_since::StateSince)
+    pub fn enable_change_history(&mut self, capacity: usize) {
+        self.game_world
+            .insert_resource(ChangeHistory::new(capacity));
+    }
+
+    /// Caches [`AllState`](crate::requests::all_state::AllState) and
+    /// [`AllStateFor`](crate::requests::all_state::AllStateFor) results in the built [`SimWorld`] so
+    /// repeated full state requests within a tick don't serialize the whole world again, see
+    /// [`AllStateCache`]
+    pub fn enable_all_state_cache(&mut self) {
+        self.all_state_cache = true;
+    }
+
+    /// Runs the given middleware around every request made through [`SimWorld::request`], see
+    /// [`middleware`](crate::requests::middleware)
+    pub fn add_request_middleware(&mut self, middleware: impl RequestMiddleware) {
+        self.request_middleware.push(Box::new(middleware));
+    }
+
+    /// Records the count, timings, and output size of every request type made through
+    /// [`SimWorld::request`] into the [`RequestMetrics`] resource of the sim world
+    pub fn enable_request_metrics(&mut self) {
+        self.game_world.insert_resource(RequestMetrics::default());
+        self.add_request_middleware(RequestMetricsRecorder);
+    }
+
+    /// Sets the replication priority of the component C, see [`priority`](crate::saving::priority)
+    pub fn register_component_priority<C: SaveId>(&mut self, priority: i32) {
+        self.game_serde_registry
+            .register_component_priority::<C>(priority);
+    }
+
+    /// Only sends each player the entities relevant to them in [`StateDif`](crate::requests::state_dif::StateDif),
+    /// see [`interest`](crate::interest). Set the interest of each player in the
+    /// [`PlayerInterests`] resource of the sim world
+    pub fn enable_interest_management(&mut self) {
+        self.game_world.init_resource::<PlayerInterests>();
+    }
+
+    /// Marks the children and or parents of changed entities changed, see [`HierarchyPropagation`]
+    pub fn enable_hierarchy_propagation(&mut self, propagation: HierarchyPropagation) {
+        self.game_world.insert_resource(propagation);
+        self.game_post_schedule.add_systems(
+            propagate_hierarchy_changes
+                .run_if(resource_exists::<HierarchyPropagation>)
+                .in_set(PostBaseSets::Post),
+        );
+    }
+
+    /// Records every executed command into a [`ReplayRecorder`] resource, see
+    /// [`replay`](crate::replay)
+    pub fn enable_replay_recording(&mut self) {
+        self.game_world.init_resource::<ReplayRecorder>();
+    }
+
+    /// Appends every executed command to the given journal, see
+    /// [`journal`](crate::saving::journal)
+    pub fn enable_command_journal(&mut self, journal: CommandJournal) {
+        self.game_world.insert_resource(journal);
+    }
+
+    /// Records replication metrics into a [`ReplicationStats`] resource every tick, see
+    /// [`stats`](crate::stats)
+    pub fn enable_replication_stats(&mut self) {
+        self.game_world.init_resource::<ReplicationStats>();
+    }
+
+    /// Records a snapshot of the sim after every simulated tick into a [`SnapshotHistory`] holding
+    /// the given number of snapshots
+    pub fn enable_snapshot_history(&mut self, capacity: usize) {
+        self.game_world
+            .insert_resource(SnapshotHistory::new(capacity));
+    }
+
+    /// Registers [`Parent`] for serialization so that entity hierarchies are included in state and
+    /// saves, see [`GameSerDeRegistry::register_hierarchy`]. Hierarchy changes are tracked by
+    /// [`GameBuilder::default_components_track_changes`]. Returns an error without registering if the
+    /// id is already used
+    pub fn register_hierarchy(&mut self) -> Result<(), RegistryError> {
+        self.game_serde_registry.register_hierarchy()
+    }
+
     pub fn default_components_track_changes(&mut self) {
         self.register_component_track_changes::<Parent>();
         self.register_component_track_changes::<Children>();
         self.register_component_track_changes::<PlayerMarker>();
     }
 
-    /// Inserts a system into GameRunner::game_post_schedule that will track the specified Component
-    /// and insert a Changed::default() component when it detects a change
+    /// Tracks the specified Component and marks entities changed in the [`ChangeSet`] when it
+    /// detects a change. Every tracked component is run by the single
+    /// [`track_registered_components`] system, see [`ComponentChangeTrackers`]
     pub fn register_component_track_changes<C>(&mut self)
     where
         C: Component,
     {
-        self.game_post_schedule
-            .add_systems(track_component_changes::<C>.in_set(PostBaseSets::Main));
+        self.game_world
+            .get_resource_or_insert_with(ComponentChangeTrackers::default)
+            .track::<C>();
+    }
+
+    /// Only marks entities changed when the given predicate, taking the last value that marked the
+    /// entity changed and the current value, passes. See [`change_filter`](crate::change_filter)
+    pub fn register_change_threshold<C>(
+        &mut self,
+        predicate: impl Fn(&C, &C) -> bool + Send + Sync + 'static,
+    ) where
+        C: Component + Clone,
+    {
+        self.register_change_filter::<C>(ThresholdFilter::new(predicate));
+    }
+
+    /// Limits changes to the component C to marking entities changed at most every given number of
+    /// ticks, see [`change_filter`](crate::change_filter)
+    pub fn register_replication_interval<C>(&mut self, ticks: u64)
+    where
+        C: Component,
+    {
+        self.game_world
+            .insert_resource(ReplicationInterval::<C>::new(ticks));
+    }
+
+    /// Sets the strategy used to detect changes to the component C, replacing Bevy change ticks. See
+    /// [`change_filter`](crate::change_filter)
+    pub fn register_change_filter<C>(&mut self, filter: impl ComponentChangeFilter<C>)
+    where
+        C: Component,
+    {
+        self.game_world
+            .insert_resource(ChangeFilter::<C>(Box::new(filter)));
     }
 
     /// Registers a resource which will be tracked, updated, and reported in state events
@@ -132,30 +321,326 @@ where
     }
 
     /// Registers a component which will be tracked, updated, and reported in state events. Also adds
-    /// the component to change detection
-    pub fn register_component<Type>(&mut self)
+    /// the component to change detection. Returns an error without registering if the id is already
+    /// used, see [`GameSerDeRegistry::register_component`]
+    pub fn register_component<Type>(&mut self) -> Result<(), RegistryError>
     where
         Type: Component + SaveId + Serialize + DeserializeOwned,
     {
-        self.game_serde_registry.register_component::<Type>();
+        self.game_serde_registry.register_component::<Type>()?;
         self.game_world.register_component_as::<dyn SaveId, Type>();
         self.register_component_track_changes::<Type>();
+        Ok(())
+    }
+
+    /// Registers a component which stores [`Entity`] references. The references are remapped when
+    /// the component is deserialized, see [`GameSerDeRegistry::register_map_entities`]. Also adds
+    /// the component to change detection
+    pub fn register_component_map_entities<Type>(&mut self) -> Result<(), RegistryError>
+    where
+        Type: Component + SaveId + Serialize + DeserializeOwned + MapEntities + Clone,
+    {
+        self.register_component::<Type>()?;
+        self.game_serde_registry
+            .register_map_entities::<Type>(Type::save_id_const());
+        Ok(())
+    }
+
+    /// Registers a component which will be tracked, updated, and reported in state events without
+    /// requiring a [`SaveId`] implementation. The component is identified by a hash of its type path,
+    /// see [`GameSerDeRegistry::register_reflect`]. Also adds the component to change detection
+    pub fn register_reflect<Type>(&mut self) -> Result<SimComponentId, RegistryError>
+    where
+        Type: Component + TypePath + Serialize + DeserializeOwned,
+    {
+        let id = self.game_serde_registry.register_reflect::<Type>()?;
+        self.register_component_track_changes::<Type>();
+        Ok(id)
+    }
+
+    /// Registers a component which is sent in [`StateDif`](crate::requests::state_dif::StateDif) as
+    /// only its changed fields, see [`field_delta`](crate::saving::field_delta). Also registers the
+    /// component like [`GameBuilder::register_component`]
+    pub fn register_component_delta<Type>(&mut self) -> Result<(), RegistryError>
+    where
+        Type: Component + DeltaSerialize + SaveId + Serialize + DeserializeOwned,
+    {
+        self.register_component::<Type>()?;
+        self.game_serde_registry.register_component_delta::<Type>();
+        self.game_world.init_resource::<DeltaBaselines>();
+        Ok(())
+    }
+
+    /// Registers a component with the given id instead of a [`SaveId`] implementation so each
+    /// instantiation of a generic component can be registered separately, see
+    /// [`GameSerDeRegistry::register_component_with_id`]. Also adds the component to change
+    /// detection
+    pub fn register_component_with_id<Type>(
+        &mut self,
+        id: SimComponentId,
+    ) -> Result<(), RegistryError>
+    where
+        Type: Component + Serialize + DeserializeOwned,
+    {
+        self.game_serde_registry
+            .register_component_with_id::<Type>(id)?;
+        self.register_component_track_changes::<Type>();
+        Ok(())
+    }
+
+    /// Registers a component that doesn't implement [`SaveId`] or [`Serialize`], such as a component
+    /// from another crate, using the given closures to convert it to and from bytes. Also adds the
+    /// component to change detection
+    pub fn register_component_with<Type, S, D>(
+        &mut self,
+        id: SimComponentId,
+        serialize: S,
+        deserialize: D,
+    ) -> Result<(), RegistryError>
+    where
+        Type: Component,
+        S: Fn(&Type) -> Option<Vec<u8>> + Send + Sync + 'static,
+        D: Fn(&[u8]) -> Option<Type> + Send + Sync + 'static,
+    {
+        self.game_serde_registry
+            .register_component_with::<Type, S, D>(id, serialize, deserialize)?;
+        self.register_component_track_changes::<Type>();
+        Ok(())
+    }
+
+    /// Registers a [`GameCommand`] type in the game worlds [`AppTypeRegistry`] so it can be included
+    /// in saves, see [`SimWorld::save_snapshot_with_commands`]
+    pub fn register_command<Type>(&mut self)
+    where
+        Type: GameCommand + TypePath + bevy::reflect::GetTypeRegistration,
+    {
+        let type_registry = self
+            .game_world
+            .get_resource_or_insert_with(AppTypeRegistry::default);
+        let mut type_registry = type_registry.write();
+        type_registry.register::<Type>();
+        type_registry.register_type_data::<Type, ReflectGameCommand>();
+    }
+
+    /// Registers the ready made commands in [`builtin_commands`](crate::builtin_commands) along with
+    /// [`CompositeCommand`]
+    pub fn register_builtin_commands(&mut self) {
+        self.register_command::<builtin_commands::SpawnTracked>();
+        self.register_command::<builtin_commands::DespawnTracked>();
+        self.register_command::<builtin_commands::InsertComponent>();
+        self.register_command::<builtin_commands::RemoveComponent>();
+        self.register_command::<builtin_commands::SetResource>();
+        self.register_command::<CompositeCommand>();
+    }
+
+    /// Registers a [`SimCommand`] so it can be encoded and sent to other sims, see
+    /// [`command_registry`](crate::command_registry). Returns an error without registering if the id
+    /// is already used, the error is also reported by [`GameBuilder::validate`]
+    pub fn register_sim_command<Type>(&mut self) -> Result<(), RegistryError>
+    where
+        Type: SimCommand,
+    {
+        self.command_registry
+            .register::<Type>()
+            .inspect_err(|error| {
+                self.game_serde_registry
+                    .registration_errors
+                    .push(error.clone())
+            })
     }
 
     /// Registers a resource which will be tracked, updated, and reported in state events. Also adds
-    /// the resource to change detection
-    pub fn register_resource<Type>(&mut self)
+    /// the resource to change detection. Returns an error without registering if the id is already
+    /// used, see [`GameSerDeRegistry::register_resource`]
+    pub fn register_resource<Type>(&mut self) -> Result<(), RegistryError>
     where
         Type: Resource + SaveId + Serialize + DeserializeOwned,
     {
-        self.game_serde_registry.register_resource::<Type>();
+        self.game_serde_registry.register_resource::<Type>()?;
         self.register_resource_track_changes::<Type>();
+        Ok(())
     }
 
-    pub fn default_setup_schedule() -> Schedule {
-        let schedule = Schedule::default();
+    /// Reserves the given range of ids for the given owner, see [`GameSerDeRegistry::reserve_id_range`]
+    pub fn reserve_id_range(
+        &mut self,
+        owner: &'static str,
+        ids: std::ops::RangeInclusive<u16>,
+    ) -> Result<(), RegistryError> {
+        self.game_serde_registry.reserve_id_range(owner, ids)
+    }
 
-        schedule
+    /// Runs the given function with the range reserved by the given owner active. Every component
+    /// and resource registered inside it must use an id in that range, see
+    /// [`GameSerDeRegistry::in_id_range`]
+    pub fn in_id_range<T>(
+        &mut self,
+        owner: &'static str,
+        register: impl FnOnce(&mut GameBuilder<GR>) -> T,
+    ) -> T {
+        let previous = self.game_serde_registry.active_id_range.replace(owner);
+        let result = register(self);
+        self.game_serde_registry.active_id_range = previous;
+        result
+    }
+
+    /// Registers a function upgrading saved payloads of the component C from the given schema
+    /// version to the next one, see [`GameSerDeRegistry::register_component_upgrade`]
+    pub fn register_component_upgrade<C, Old, New>(
+        &mut self,
+        from_version: u16,
+        upgrade: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) where
+        C: SaveId,
+        Old: DeserializeOwned,
+        New: Serialize,
+    {
+        self.game_serde_registry
+            .register_component_upgrade::<C, Old, New>(from_version, upgrade);
+    }
+
+    /// Registers a function upgrading saved payloads of the resource R from the given schema
+    /// version to the next one, see [`GameSerDeRegistry::register_resource_upgrade`]
+    pub fn register_resource_upgrade<R, Old, New>(
+        &mut self,
+        from_version: u16,
+        upgrade: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) where
+        R: SaveId,
+        Old: DeserializeOwned,
+        New: Serialize,
+    {
+        self.game_serde_registry
+            .register_resource_upgrade::<R, Old, New>(from_version, upgrade);
+    }
+
+    /// Merges a registry built by another crate into the games registry and adds change tracking
+    /// for every merged component and resource, see [`GameSerDeRegistry::merge`]. Conflicting ids
+    /// are not merged and are reported by [`GameBuilder::validate`]
+    pub fn merge_registry(&mut self, registry: GameSerDeRegistry) {
+        let existing = self.game_serde_registry.clone();
+        // Conflicts are recorded in the registry and reported by GameBuilder::validate
+        let _ = self.game_serde_registry.merge(registry);
+
+        let mut trackers = self
+            .game_world
+            .get_resource_or_insert_with(ComponentChangeTrackers::default);
+        for (id, tracking_fn) in self.game_serde_registry.component_tracking_map.iter() {
+            if !existing.component_tracking_map.contains_key(id) {
+                tracking_fn(&mut trackers);
+            }
+        }
+        for (id, tracking_fn) in self.game_serde_registry.resource_tracking_map.iter() {
+            if !existing.resource_tracking_map.contains_key(id) {
+                tracking_fn(&mut self.game_post_schedule);
+            }
+        }
+    }
+
+    /// Returns every id collision found while registering components and resources. Registration
+    /// doesn't panic on collisions, so call this after all plugins have registered their types to
+    /// get a full report. Collisions are also logged when the game is built. With the reflection
+    /// fallback enabled, reflected components whose id is used by a registered component are also
+    /// reported, see [`GameSerDeRegistry::reflect_fallback_collisions`]
+    pub fn validate(&self) -> Result<(), Vec<RegistryError>> {
+        let mut errors = self
+            .game_serde_registry
+            .validate()
+            .err()
+            .unwrap_or_default();
+        if self.game_serde_registry.reflect_fallback {
+            if let Some(type_registry) = self.game_world.get_resource::<AppTypeRegistry>() {
+                errors.extend(
+                    self.game_serde_registry
+                        .reflect_fallback_collisions(&type_registry.read()),
+                );
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Registers every component and resource submitted with
+    /// [`auto_register_component!`](crate::auto_register_component) and
+    /// [`auto_register_resource!`](crate::auto_register_resource)
+    #[cfg(feature = "auto_register")]
+    pub fn register_auto(&mut self) {
+        for registration in inventory::iter::<crate::auto_register::AutoRegistration> {
+            (registration.register)(
+                &mut self.game_serde_registry,
+                &mut self.game_world,
+                &mut self.game_post_schedule,
+            );
+        }
+    }
+
+    /// Excludes the given component from all state output. Use this for components that implement
+    /// [`SaveId`] but should never be sent to players
+    pub fn exclude_component<C>(&mut self)
+    where
+        C: SaveId,
+    {
+        self.game_serde_registry.exclude_component::<C>();
+    }
+
+    /// Sets the format every registered component and resource is serialized with. Defaults to
+    /// [`SimFormat::Bincode`]
+    pub fn set_serialization_format(&mut self, format: SimFormat) {
+        self.game_serde_registry.format = format;
+    }
+
+    /// Appends a checksum to every component, resource, state, and save payload and verifies it on
+    /// load, see [`checksum`](crate::saving::checksum)
+    pub fn enable_payload_checksums(&mut self) {
+        self.game_serde_registry.payload_checksums = true;
+    }
+
+    /// Sets the compression applied to component and resource payloads and to whole encoded
+    /// states. Defaults to [`SimCompression::None`] for both
+    pub fn set_compression(
+        &mut self,
+        component_compression: SimCompression,
+        state_compression: SimCompression,
+    ) {
+        self.game_serde_registry.component_compression = component_compression;
+        self.game_serde_registry.state_compression = state_compression;
+    }
+
+    /// Encrypts saves with the given key so players can't trivially edit them. Saves must be loaded
+    /// with the same key, see [`SaveEncryption`](crate::saving::encryption::SaveEncryption)
+    #[cfg(feature = "encryption")]
+    pub fn set_save_encryption(&mut self, key: &[u8]) {
+        self.game_serde_registry.save_encryption =
+            Some(crate::saving::encryption::SaveEncryption::new(key));
+    }
+
+    /// Autosaves the sim from the [`GameRuntime`] using the given [`Autosave`]
+    pub fn set_autosave(&mut self, autosave: Autosave) {
+        self.autosave = Some(autosave);
+    }
+
+    /// Serializes unregistered components using reflection, see
+    /// [`GameSerDeRegistry::reflect_fallback`]. Components must be registered with
+    /// [`GameBuilder::register_type`] to be included
+    pub fn enable_reflect_fallback(&mut self) {
+        self.game_serde_registry.reflect_fallback = true;
+        self.game_world.init_resource::<AppTypeRegistry>();
+    }
+
+    /// Registers the given type in the game worlds [`AppTypeRegistry`] so it can be serialized with
+    /// the reflection fallback
+    pub fn register_type<Type: bevy::reflect::GetTypeRegistration>(&mut self) {
+        self.game_world
+            .get_resource_or_insert_with(AppTypeRegistry::default)
+            .write()
+            .register::<Type>();
+    }
+
+    pub fn default_setup_schedule() -> Schedule {
+        Schedule::default()
     }
     pub fn default_game_pre_schedule() -> Schedule {
         let mut schedule = Schedule::default();
@@ -196,11 +681,16 @@ where
             .add_systems(apply_deferred.in_set(PostBaseSets::MainCommandFlush))
             .add_systems(apply_deferred.in_set(PostBaseSets::PostCommandFlush));
 
-        schedule.add_systems(despawn_objects.in_set(PostBaseSets::Pre));
+        schedule.add_systems(
+            (assign_sim_entity_ids, despawn_objects)
+                .chain()
+                .in_set(PostBaseSets::Pre),
+        );
+        schedule.add_systems(track_registered_components.in_set(PostBaseSets::Main));
         schedule
     }
 
-    pub fn add_player(&mut self, needs_state: bool) -> (usize, EntityWorldMut) {
+    pub fn add_player(&mut self, needs_state: bool) -> (usize, EntityWorldMut<'_>) {
         let new_player_id = self.next_player_id;
         self.next_player_id += 1;
         let player_entity = self
@@ -212,37 +702,89 @@ where
         (new_player_id, player_entity)
     }
 
-    pub fn build(mut self, main_world: &mut World) {
+    /// Builds the game and inserts the [`SimWorld`] and [`GameRuntime`] into the given main world as
+    /// resources. The [`GameCommands`] of the game live in the sim world, see
+    /// [`SimWorld::game_commands`]. The result of every initial command is sent as a
+    /// [`CommandExecuted`] event if the main world has the event added
+    pub fn build(self, main_world: &mut World) {
+        let StandaloneSim {
+            sim_world,
+            game_runtime,
+            command_results,
+        } = self.build_standalone();
+
+        main_world.insert_resource::<GameRuntime<GR>>(game_runtime);
+        main_world.insert_resource::<SimWorld>(sim_world);
+        if let Some(mut events) = main_world.get_resource_mut::<Events<CommandExecuted>>() {
+            events.extend(command_results);
+        }
+    }
+
+    /// Builds the game without requiring a Bevy App or main world. Use this to embed the sim in
+    /// non-Bevy servers, CLIs, and test binaries. The results of the initial commands, see
+    /// [`GameBuilder::new_game_with_commands`], are kept in [`StandaloneSim::command_results`]
+    pub fn build_standalone(mut self) -> StandaloneSim<GR> {
+        if let Err(errors) = self.validate() {
+            for error in errors {
+                error!("GameSerDeRegistry collision: {}", error);
+            }
+        }
+
+        self.setup_schedule.set_executor_kind(self.executor_kind);
+        self.game_pre_schedule.set_executor_kind(self.executor_kind);
+        self.game_post_schedule
+            .set_executor_kind(self.executor_kind);
+        self.game_runner.set_executor_kind(self.executor_kind);
+
+        self.game_world
+            .insert_resource(self.commands.take().unwrap_or_default());
         self.setup_schedule.run(&mut self.game_world);
-        main_world.insert_resource::<GameRuntime<GR>>(GameRuntime {
+        let game_runtime = GameRuntime {
             game_runner: self.game_runner,
             game_pre_schedule: self.game_pre_schedule,
             game_post_schedule: self.game_post_schedule,
-        });
+            fixed_timestep: Default::default(),
+            autosave: self.autosave,
+        };
         self.game_world
             .insert_resource(self.game_serde_registry.clone());
+        self.command_registry.format = self.game_serde_registry.format;
+        self.game_world
+            .insert_resource(self.command_registry.clone());
+        self.game_world.insert_resource(ChangeSet::default());
         self.game_world.insert_resource(TrackedDespawns {
             despawned_objects: Default::default(),
         });
-        self.game_world.insert_resource(ResourceChangeTracking {
-            resources: Default::default(),
-        });
+        self.game_world
+            .insert_resource(ResourceChangeTracking::default());
+        self.game_world.insert_resource(StateSequences::default());
+        self.game_world.insert_resource(SentEntities::default());
+        self.game_world.insert_resource(SimTick::default());
+        self.game_world.init_resource::<SimEntityIdAllocator>();
+        self.game_world.init_resource::<SimEntityIndex>();
         self.game_world.insert_resource(self.player_list.clone());
-
-        if let Some(commands) = self.commands.as_mut() {
-            commands.execute_buffer(&mut self.game_world);
-        } else {
-            self.commands = Some(GameCommands::default());
-        }
-
-        main_world.insert_resource(self.commands.unwrap());
+        let command_results =
+            self.game_world
+                .resource_scope(|world, mut game_commands: Mut<GameCommands>| {
+                    game_commands.execute_buffer(world)
+                });
 
         self.setup_schedule.run(&mut self.game_world);
+        assign_sim_entity_ids(&mut self.game_world);
 
-        main_world.insert_resource::<SimWorld>(SimWorld {
-            world: self.game_world,
-            registry: self.game_serde_registry,
-            player_list: self.player_list,
-        });
+        let read_queries = SimReadQueries::new(&mut self.game_world);
+
+        StandaloneSim {
+            sim_world: SimWorld {
+                world: self.game_world,
+                registry: self.game_serde_registry,
+                player_list: self.player_list,
+                read_queries,
+                all_state_cache: self.all_state_cache.then(AllStateCache::default),
+                request_middleware: self.request_middleware,
+            },
+            game_runtime,
+            command_results,
+        }
     }
 }
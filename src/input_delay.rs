@@ -0,0 +1,202 @@
+//! Input delay buffering for lockstep play. With an [`InputDelay`] set on the [`GameCommands`],
+//! commands issued by players are scheduled [`InputDelay::delay`] ticks ahead so they have time to
+//! reach every peer, and a [`StandaloneSim`](crate::runner::StandaloneSim) won't simulate a tick
+//! until the command sets of every player for it have arrived. In an App the same applies to the
+//! [`simulate_game`](crate::runner::simulate_game) and [`advance_game`](crate::runner::advance_game)
+//! systems and to [`execute_game_commands_buffer`](crate::command::execute_game_commands_buffer).
+//!
+//! Each peer sends the commands of its player for a tick with [`GameCommands::add_player_inputs`],
+//! even when there are none, which marks the set of that player for the tick as arrived.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    command::{GameCommand, GameCommands},
+    player::{Player, PlayerList},
+};
+
+/// The input delay of a lockstep game and the command sets that have arrived for upcoming ticks
+#[derive(Default, Clone, Debug)]
+pub struct InputDelay {
+    /// The number of ticks commands issued by players are scheduled ahead
+    pub delay: u64,
+    /// The tick the game started at. Ticks up to `start_tick + delay` are simulated without waiting
+    /// for inputs as no player could have sent any for them
+    pub start_tick: u64,
+    /// The players whose command sets have arrived for each tick
+    pub received: BTreeMap<u64, HashSet<usize>>,
+    /// The tick of the last executed command batch
+    pub current_tick: u64,
+}
+
+impl InputDelay {
+    pub fn new(delay: u64) -> InputDelay {
+        InputDelay {
+            delay,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the tick commands issued by players now are scheduled for
+    pub fn input_tick(&self) -> u64 {
+        self.current_tick + 1 + self.delay
+    }
+
+    /// Marks the command set of the given player for the given tick as arrived
+    pub fn mark_received(&mut self, player: &Player, tick: u64) {
+        self.received.entry(tick).or_default().insert(player.id());
+    }
+
+    /// Returns true if the command sets of every player in the given list have arrived for the
+    /// given tick
+    pub fn is_ready(&self, tick: u64, player_list: &PlayerList) -> bool {
+        if tick <= self.start_tick + self.delay {
+            return true;
+        }
+        let received = self.received.get(&tick);
+        player_list
+            .players
+            .iter()
+            .all(|player| received.is_some_and(|received| received.contains(&player.id())))
+    }
+
+    /// Records that the command batch of the given tick was executed and forgets the command sets of
+    /// every tick up to it
+    pub fn executed(&mut self, tick: u64) {
+        self.current_tick = tick;
+        self.received = self.received.split_off(&(tick + 1));
+    }
+}
+
+impl GameCommands {
+    /// Queues the command set of the given player for the given tick and marks it as arrived. Call
+    /// this for every tick, with no commands if the player didn't issue any. The tick is usually
+    /// [`InputDelay::input_tick`] of the peer that issued the commands. The commands aren't checked
+    /// against the [`CommandRateLimiter`](crate::command_rate_limit::CommandRateLimiter), limit them
+    /// where the player issues them instead
+    pub fn add_player_inputs(
+        &mut self,
+        player: Player,
+        tick: u64,
+        commands: Vec<Box<dyn GameCommand>>,
+    ) {
+        for command in commands {
+            self.push_attributed(command, Some(player), Some(tick));
+        }
+        if let Some(input_delay) = self.input_delay.as_mut() {
+            input_delay.mark_received(&player, tick);
+        }
+    }
+
+    /// Returns true if every player's command set for the given tick has arrived, or if there is no
+    /// [`InputDelay`]
+    pub fn inputs_ready(&self, tick: u64, player_list: &PlayerList) -> bool {
+        self.input_delay
+            .as_ref()
+            .is_none_or(|input_delay| input_delay.is_ready(tick, player_list))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Reflect, World};
+    use std::time::Duration;
+
+    use crate::{
+        command::{CommandError, GameCommand},
+        command_rate_limit::CommandRateLimiter,
+        player::Player,
+        testing::test_game,
+    };
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Noop;
+
+    impl GameCommand for Noop {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_input_delay() {
+        let mut game = test_game();
+        game.add_player(false);
+        game.add_player(false);
+        game.enable_input_delay(1);
+        let mut sim = game.build_standalone();
+        let players = sim.sim_world.player_list.players.clone();
+
+        assert!(sim.simulate());
+        assert!(sim.simulate());
+        assert!(!sim.simulate());
+        assert_eq!(sim.sim_world.tick(), 2);
+
+        sim.sim_world
+            .game_commands_mut()
+            .add_player_inputs(players[0], 2, vec![]);
+        assert!(!sim.simulate());
+        sim.sim_world
+            .game_commands_mut()
+            .add_player_inputs(players[1], 2, vec![Box::new(Noop)]);
+        assert!(sim.simulate());
+        assert_eq!(sim.sim_world.game_commands().history.history.len(), 1);
+
+        sim.sim_world
+            .game_commands_mut()
+            .add_from_player(Noop, Player::new(players[0].id(), false));
+        assert_eq!(
+            sim.sim_world.game_commands().queue.queue[0].execute_at_tick,
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn test_input_delay_advance() {
+        let mut game = test_game();
+        game.add_player(false);
+        game.enable_input_delay(1);
+        let mut sim = game.build_standalone();
+        let players = sim.sim_world.player_list.players.clone();
+        sim.game_runtime.fixed_timestep.tick_duration = Duration::from_millis(10);
+
+        assert_eq!(sim.advance(Duration::from_millis(50)), None);
+        assert_eq!(sim.sim_world.tick(), 2);
+        assert_eq!(
+            sim.game_runtime.fixed_timestep.accumulated,
+            Duration::from_millis(30)
+        );
+
+        sim.sim_world
+            .game_commands_mut()
+            .add_player_inputs(players[0], 2, vec![Box::new(Noop)]);
+        sim.advance(Duration::ZERO);
+        assert_eq!(sim.sim_world.tick(), 3);
+        assert_eq!(sim.take_command_results().len(), 1);
+        assert!(sim.command_results.is_empty());
+    }
+
+    #[test]
+    fn test_player_inputs_skip_rate_limit() {
+        let mut game = test_game();
+        game.add_player(false);
+        game.enable_input_delay(1);
+        let mut sim = game.build_standalone();
+        let player = sim.sim_world.player_list.players[0];
+        sim.sim_world.game_commands_mut().rate_limiter = CommandRateLimiter::new(Some(1), Some(1));
+        assert!(sim.simulate());
+        assert!(sim.simulate());
+
+        sim.sim_world.game_commands_mut().add_player_inputs(
+            player,
+            2,
+            vec![Box::new(Noop), Box::new(Noop), Box::new(Noop)],
+        );
+        assert!(sim.simulate());
+        assert_eq!(sim.sim_world.game_commands().history.history.len(), 3);
+        assert!(sim
+            .take_command_results()
+            .iter()
+            .all(|executed| executed.result.is_ok()));
+    }
+}
@@ -0,0 +1,330 @@
+//! Interest management. Enabled with
+//! [`GameBuilder::enable_interest_management`](crate::game_builder::GameBuilder::enable_interest_management),
+//! every player with an [`InterestSet`] in the [`PlayerInterests`] resource only receives the
+//! entities relevant to them in [`StateDif`](crate::requests::state_dif::StateDif). Players without
+//! an interest set receive everything.
+//!
+//! An entity is relevant to a player if any of these hold:
+//! - It has no [`InterestPosition`] or [`InterestGroup`], so it is global
+//! - It is a [`Player`] entity or is marked as owned by the player with a [`PlayerMarker`]
+//! - It is in the explicit entity list of the players interest set
+//! - Its [`InterestGroup`] is one of the groups of the players interest set
+//! - Its [`InterestPosition`] is inside one of the regions of the players interest set
+//!
+//! Independently of interest sets, an entity with a [`SimVisibility`] component is never sent to the
+//! players it is hidden from, by any player specific request. Without interest management enabled
+//! entities that become visible are only sent once they change again.
+//!
+//! When an entity becomes relevant to a player it is sent in full, when it stops being relevant it
+//! is sent as despawned so the client removes it. Despawns of entities that weren't relevant to a
+//! player aren't sent to them.
+
+use bevy::{
+    math::Vec3,
+    prelude::{Component, Entity, EntityRef, Resource, Without, World},
+    reflect::Reflect,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    change_detection::{DespawnTracked, ReplicationIgnore},
+    entity_id::SimEntityId,
+    player::{Player, PlayerMarker},
+};
+
+/// The position of an entity used to check it against [`InterestRegion`]s
+#[derive(Default, Clone, Copy, Debug, PartialEq, Component, Reflect, Serialize, Deserialize)]
+pub struct InterestPosition(pub Vec3);
+
+/// The group of an entity, such as a team, used to check it against [`InterestSet::groups`]
+#[derive(
+    Default, Clone, Copy, Eq, Hash, Debug, PartialEq, Component, Reflect, Serialize, Deserialize,
+)]
+pub struct InterestGroup(pub u32);
+
+/// Restricts the players an entity is sent to, for hidden units and secret objectives
+#[derive(Clone, Eq, Debug, PartialEq, Component, Reflect, Serialize, Deserialize)]
+pub enum SimVisibility {
+    /// Only the listed players receive the entity
+    Allow(Vec<usize>),
+    /// Every player except the listed ones receives the entity
+    Deny(Vec<usize>),
+}
+
+impl SimVisibility {
+    /// Returns true if the given player is allowed to receive the entity
+    pub fn is_visible_to(&self, player_id: usize) -> bool {
+        match self {
+            SimVisibility::Allow(players) => players.contains(&player_id),
+            SimVisibility::Deny(players) => !players.contains(&player_id),
+        }
+    }
+}
+
+/// Returns true if the given entity has no [`SimVisibility`] or is visible to the given player
+pub fn is_visible_to(entity: &EntityRef, player_id: usize) -> bool {
+    entity
+        .get::<SimVisibility>()
+        .is_none_or(|visibility| visibility.is_visible_to(player_id))
+}
+
+/// Returns true if the given entity should be sent to the given player, checking its
+/// [`SimVisibility`] and the players [`InterestSet`] if interest management is enabled
+pub fn is_entity_relevant(world: &World, player_id: usize, entity: Entity) -> bool {
+    let Some(entity) = world.get_entity(entity) else {
+        return false;
+    };
+    !entity.contains::<ReplicationIgnore>()
+        && is_visible_to(&entity, player_id)
+        && world
+            .get_resource::<PlayerInterests>()
+            .and_then(|interests| interests.players.get(&player_id))
+            .is_none_or(|interest| interest.is_relevant(player_id, &entity))
+}
+
+/// An axis aligned box, inclusive on both ends
+#[derive(Default, Clone, Copy, Debug, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct InterestRegion {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl InterestRegion {
+    pub fn new(min: Vec3, max: Vec3) -> InterestRegion {
+        InterestRegion { min, max }
+    }
+
+    pub fn contains(&self, position: Vec3) -> bool {
+        position.cmpge(self.min).all() && position.cmple(self.max).all()
+    }
+}
+
+/// The entities a player is interested in
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct InterestSet {
+    pub regions: Vec<InterestRegion>,
+    pub groups: HashSet<u32>,
+    pub entities: HashSet<SimEntityId>,
+}
+
+impl InterestSet {
+    /// Returns true if the given entity is relevant to the given player under this interest set
+    pub fn is_relevant(&self, player_id: usize, entity: &EntityRef) -> bool {
+        if entity.contains::<Player>()
+            || entity
+                .get::<PlayerMarker>()
+                .is_some_and(|marker| marker.id() == player_id)
+        {
+            return true;
+        }
+        if entity
+            .get::<SimEntityId>()
+            .is_some_and(|id| self.entities.contains(id))
+        {
+            return true;
+        }
+        let group = entity.get::<InterestGroup>();
+        let position = entity.get::<InterestPosition>();
+        if group.is_none() && position.is_none() {
+            return true;
+        }
+        group.is_some_and(|group| self.groups.contains(&group.0))
+            || position.is_some_and(|position| {
+                self.regions
+                    .iter()
+                    .any(|region| region.contains(position.0))
+            })
+    }
+}
+
+/// Resource inserted into the sim world holding the [`InterestSet`] of every player, keyed by player
+/// id, and the entities each player was last sent
+#[derive(Default, Clone, Debug, PartialEq, Resource)]
+pub struct PlayerInterests {
+    pub players: HashMap<usize, InterestSet>,
+    /// The entities that were relevant to each player in the last state they were sent
+    pub in_scope: HashMap<usize, HashSet<SimEntityId>>,
+}
+
+impl PlayerInterests {
+    /// Sets the interest set of the given player
+    pub fn set(&mut self, player_id: usize, interest: InterestSet) {
+        self.players.insert(player_id, interest);
+    }
+
+    /// Removes the interest set of the given player so they receive every entity visible to them
+    /// again
+    pub fn clear(&mut self, player_id: usize) {
+        self.players.remove(&player_id);
+        self.in_scope.remove(&player_id);
+    }
+}
+
+/// The change in the entities relevant to a player since the last state they were sent
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ScopeUpdate {
+    /// Every entity relevant to the player now
+    pub relevant: HashSet<SimEntityId>,
+    /// Entities that became relevant and must be sent in full
+    pub entered: Vec<Entity>,
+    /// Entities that stopped being relevant and must be sent as despawned
+    pub left: Vec<SimEntityId>,
+    previous: Option<HashSet<SimEntityId>>,
+}
+
+impl ScopeUpdate {
+    /// Returns true if the given entity was relevant to the player in the last state they were sent.
+    /// Every entity is in scope for players that were sent state before their interest set was set
+    pub fn was_in_scope(&self, id: &SimEntityId) -> bool {
+        self.previous
+            .as_ref()
+            .is_none_or(|previous| previous.contains(id))
+    }
+
+    /// Returns true if the given entity is relevant to the player. Entities without a
+    /// [`SimEntityId`], such as player entities, are always relevant
+    pub fn is_relevant(&self, world: &World, entity: Entity) -> bool {
+        world
+            .get::<SimEntityId>(entity)
+            .is_none_or(|id| self.relevant.contains(id))
+    }
+}
+
+/// Updates the entities in scope for the given player and returns what changed. Returns None if
+/// interest management isn't enabled
+pub fn update_player_scope(world: &mut World, player_id: usize) -> Option<ScopeUpdate> {
+    world.get_resource::<PlayerInterests>()?;
+    let mut update = ScopeUpdate {
+        previous: world
+            .resource_mut::<PlayerInterests>()
+            .in_scope
+            .remove(&player_id),
+        ..Default::default()
+    };
+    let mut query = world.query_filtered::<(Entity, &SimEntityId), (Without<DespawnTracked>, Without<ReplicationIgnore>)>();
+    for (entity, id) in query.iter(world) {
+        if is_entity_relevant(world, player_id, entity) {
+            update.relevant.insert(*id);
+            if !update.was_in_scope(id) {
+                update.entered.push(entity);
+            }
+        } else if update.was_in_scope(id) {
+            update.left.push(*id);
+        }
+    }
+
+    world
+        .resource_mut::<PlayerInterests>()
+        .in_scope
+        .insert(player_id, update.relevant.clone());
+    Some(update)
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        math::Vec3,
+        prelude::{Component, DetectChangesMut},
+    };
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        entity_id::SimEntityId,
+        requests::{all_state::AllStateFor, state_dif::StateDif},
+        testing::{fixtures::bincode_save_id, test_game},
+    };
+
+    use super::{InterestPosition, InterestRegion, InterestSet, PlayerInterests, SimVisibility};
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct Unit;
+
+    bincode_save_id!(Unit, 25);
+
+    #[test]
+    fn test_interest_regions() {
+        let mut game = test_game();
+        game.register_component::<Unit>().unwrap();
+        game.enable_interest_management();
+        let mut sim = game.build_standalone();
+
+        let world = &mut sim.sim_world.world;
+        world.resource_mut::<PlayerInterests>().set(
+            0,
+            InterestSet {
+                regions: vec![InterestRegion::new(Vec3::ZERO, Vec3::splat(10.0))],
+                ..Default::default()
+            },
+        );
+        let global = world.spawn(Unit).id();
+        let unit = world
+            .spawn((Unit, InterestPosition(Vec3::splat(20.0))))
+            .id();
+        sim.simulate();
+
+        let global_id = *sim.sim_world.world.get::<SimEntityId>(global).unwrap();
+        let unit_id = *sim.sim_world.world.get::<SimEntityId>(unit).unwrap();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, global_id);
+        assert!(state.entities[0].is_new);
+
+        sim.sim_world
+            .world
+            .get_mut::<InterestPosition>(unit)
+            .unwrap()
+            .0 = Vec3::splat(5.0);
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, unit_id);
+        assert!(state.entities[0].is_new);
+
+        sim.sim_world
+            .world
+            .get_mut::<InterestPosition>(unit)
+            .unwrap()
+            .0 = Vec3::splat(-5.0);
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert!(state.entities.is_empty());
+        assert_eq!(state.despawned_objects, vec![unit_id]);
+
+        sim.sim_world
+            .world
+            .get_mut::<InterestPosition>(unit)
+            .unwrap()
+            .0 = Vec3::splat(5.0);
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert!(state.entities[0].is_new);
+        sim.sim_world
+            .world
+            .get_mut::<Unit>(unit)
+            .unwrap()
+            .set_changed();
+        sim.simulate();
+        let state = sim.request(StateDif { for_player: 0 });
+        assert!(!state.entities[0].is_new);
+    }
+
+    #[test]
+    fn test_sim_visibility() {
+        let mut game = test_game();
+        game.register_component::<Unit>().unwrap();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world
+            .world
+            .spawn((Unit, SimVisibility::Deny(vec![1])));
+        sim.simulate();
+
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+        assert!(sim.request(StateDif { for_player: 1 }).entities.is_empty());
+        assert!(sim
+            .request(AllStateFor { for_player: 1 })
+            .entities
+            .is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! Helpers for presenting state between sim ticks. A [`StateInterpolator`] keeps the last two
+//! [`SimState`]s received for each player and blends the components that have a registered [`Lerp`]
+//! implementation, so clients can render smoothly between ticks.
+
+use bevy::{prelude::Resource, utils::HashMap};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    entity_id::SimEntityId,
+    requests::{EntityState, SimState},
+    saving::{ComponentBinaryState, SaveId, SimComponentId, SimFormat, SimSerializer},
+};
+
+/// Implemented on components that can be blended between two values
+pub trait Lerp {
+    /// Returns the value between self and other at t, where t is between 0.0 and 1.0
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+pub type ComponentLerpFn = fn(format: SimFormat, from: &[u8], to: &[u8], t: f32) -> Option<Vec<u8>>;
+
+/// Deserializes both binary components, blends them, and serializes the result.
+pub fn component_lerp<C>(format: SimFormat, from: &[u8], to: &[u8], t: f32) -> Option<Vec<u8>>
+where
+    C: Lerp + Serialize + DeserializeOwned + SaveId,
+{
+    let from = format.decode::<C>(from)?;
+    let to = format.decode::<C>(to)?;
+    format.encode(&from.lerp(&to, t))
+}
+
+/// The last two states received for a single player
+#[derive(Clone, Debug, Default)]
+pub struct InterpolationBuffer {
+    pub previous: Option<SimState>,
+    pub latest: Option<SimState>,
+}
+
+impl InterpolationBuffer {
+    /// Pushes a new state into the buffer, discarding the oldest state. States older than the latest
+    /// state are ignored
+    pub fn push(&mut self, state: SimState) {
+        if let Some(latest) = self.latest.as_ref() {
+            if state.tick < latest.tick {
+                return;
+            }
+        }
+        self.previous = self.latest.take();
+        self.latest = Some(state);
+    }
+}
+
+/// Keeps the last two states per player and blends registered components between them
+#[derive(Clone, Default, Resource)]
+pub struct StateInterpolator {
+    /// The format components in received states are serialized with. Must match the sims
+    /// [`GameSerDeRegistry::format`](crate::saving::GameSerDeRegistry::format)
+    pub format: SimFormat,
+    pub lerp_map: HashMap<SimComponentId, ComponentLerpFn>,
+    pub buffers: HashMap<usize, InterpolationBuffer>,
+}
+
+impl StateInterpolator {
+    pub fn new() -> StateInterpolator {
+        StateInterpolator::default()
+    }
+
+    /// Registers a component to be blended using its [`Lerp`] implementation
+    pub fn register_lerp<C>(&mut self)
+    where
+        C: Lerp + Serialize + DeserializeOwned + SaveId,
+    {
+        self.lerp_map
+            .insert(C::save_id_const(), component_lerp::<C>);
+    }
+
+    /// Pushes a state received for the given player into its buffer
+    pub fn push(&mut self, player_id: usize, state: SimState) {
+        self.buffers.entry(player_id).or_default().push(state);
+    }
+
+    /// Returns the entities of the latest state for the given player with every registered component
+    /// blended towards it from the previous state. `tick` is the fractional tick being presented,
+    /// and is clamped between the ticks of the two buffered states.
+    ///
+    /// Components that aren't registered, or that don't exist in the previous state, are returned
+    /// as they are in the latest state.
+    pub fn interpolate(&self, player_id: usize, tick: f64) -> Vec<EntityState> {
+        let Some(buffer) = self.buffers.get(&player_id) else {
+            return vec![];
+        };
+        let Some(latest) = buffer.latest.as_ref() else {
+            return vec![];
+        };
+        let Some(previous) = buffer.previous.as_ref() else {
+            return latest.entities.clone();
+        };
+
+        let span = latest.tick.saturating_sub(previous.tick);
+        let t = if span == 0 {
+            1.0
+        } else {
+            ((tick - previous.tick as f64) / span as f64).clamp(0.0, 1.0) as f32
+        };
+
+        let previous_entities: HashMap<SimEntityId, &EntityState> = previous
+            .entities
+            .iter()
+            .map(|entity_state| (entity_state.entity, entity_state))
+            .collect();
+
+        latest
+            .entities
+            .iter()
+            .map(|entity_state| {
+                let Some(previous_state) = previous_entities.get(&entity_state.entity) else {
+                    return entity_state.clone();
+                };
+                let components = entity_state
+                    .components
+                    .iter()
+                    .map(|component| self.lerp_component(previous_state, component, t))
+                    .collect();
+                EntityState {
+                    entity: entity_state.entity,
+                    components,
+                    component_deltas: entity_state.component_deltas.clone(),
+                    changed_tick: entity_state.changed_tick,
+                    is_new: entity_state.is_new,
+                    changed_components: entity_state.changed_components.clone(),
+                }
+            })
+            .collect()
+    }
+
+    fn lerp_component(
+        &self,
+        previous_state: &EntityState,
+        component: &ComponentBinaryState,
+        t: f32,
+    ) -> ComponentBinaryState {
+        let Some(lerp_fn) = self.lerp_map.get(&component.id) else {
+            return component.clone();
+        };
+        let Some(previous_component) = previous_state
+            .components
+            .iter()
+            .find(|previous_component| previous_component.id == component.id)
+        else {
+            return component.clone();
+        };
+        match lerp_fn(
+            self.format,
+            &previous_component.component,
+            &component.component,
+            t,
+        ) {
+            Some(binary) => ComponentBinaryState {
+                id: component.id,
+                version: component.version,
+                component: binary,
+            },
+            None => component.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        entity_id::SimEntityId,
+        requests::{EntityState, SimState},
+        saving::{ComponentBinaryState, SaveId},
+        testing::fixtures::bincode_save_id,
+    };
+
+    use super::{Lerp, StateInterpolator};
+
+    #[derive(Default, Serialize, Deserialize)]
+    struct TestPosition(f32);
+
+    bincode_save_id!(TestPosition, 25);
+
+    impl Lerp for TestPosition {
+        fn lerp(&self, other: &Self, t: f32) -> Self {
+            TestPosition(self.0 + (other.0 - self.0) * t)
+        }
+    }
+
+    fn state_at(tick: u64, position: f32) -> SimState {
+        SimState {
+            entities: vec![EntityState {
+                entity: SimEntityId(0),
+                components: vec![ComponentBinaryState {
+                    id: 25,
+                    version: 0,
+                    component: TestPosition(position).to_binary().unwrap(),
+                }],
+                component_deltas: vec![],
+                changed_tick: tick,
+                is_new: false,
+                changed_components: vec![],
+            }],
+            tick,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_interpolate_between_ticks() {
+        let mut interpolator = StateInterpolator::new();
+        interpolator.register_lerp::<TestPosition>();
+        interpolator.push(0, state_at(1, 0.0));
+        interpolator.push(0, state_at(2, 10.0));
+
+        let entities = interpolator.interpolate(0, 1.5);
+        let position =
+            bincode::deserialize::<TestPosition>(&entities[0].components[0].component).unwrap();
+
+        assert_eq!(position.0, 5.0);
+    }
+}
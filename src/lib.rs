@@ -1,22 +1,61 @@
-﻿//!
+//! A library providing a separated simulation world for the Bevy Game Engine.
 
-use crate::change_detection::SimChanged;
+use crate::change_detection::ChangeSet;
 use crate::player::PlayerList;
-use bevy::ecs::system::SystemState;
 use bevy::prelude::*;
 use change_detection::{ResourceChangeTracking, TrackedDespawns};
-use requests::SimRequest;
-use saving::SimResourceId;
+use entity_id::SimEntityId;
+use memory::{trim_sim_world, SimMemoryReport};
+use requests::{
+    acks::{PendingStateAcks, ResendPending},
+    all_state::AllStateCache,
+    entity_stream::EntityStateStream,
+    middleware::{RequestMiddleware, RequestRecord},
+    SimReadQueries, SimRequest, SimRequestReadOnly, SimState, StateSequences,
+};
+use runner::SimTick;
+use saving::autosave::{autosave_paths, AutosaveSource};
+use saving::background::BackgroundSave;
+use saving::delta::SnapshotDelta;
+use saving::header::SaveHeader;
+use saving::snapshot::{SaveFilter, SimSnapshot};
+use saving::{
+    version::SchemaVersion, ComponentDeserializeFn, ComponentSerializeFn, DeserializeReport,
+    RegistryError, ResourceDeserializeFn, ResourceSerializeFn, SaveId, SimComponentId,
+    SimResourceId,
+};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use self::saving::GameSerDeRegistry;
 
+pub mod apply;
+#[cfg(feature = "auto_register")]
+pub mod auto_register;
+pub mod builtin_commands;
 pub mod change_detection;
+pub mod change_filter;
 pub mod command;
+pub mod command_rate_limit;
+pub mod command_registry;
+pub mod entity_id;
 pub mod game_builder;
+pub mod input_delay;
+pub mod interest;
+pub mod interpolation;
+pub mod memory;
 pub mod player;
+pub mod replay;
 pub mod requests;
+pub mod resim;
 pub mod runner;
 pub mod saving;
+pub mod stats;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 /// A separate world used to separate simulations
 #[derive(Resource, Component)]
@@ -27,29 +66,407 @@ pub struct SimWorld {
     pub registry: GameSerDeRegistry,
     /// List of all players in the sim. Used with state and changed
     pub player_list: PlayerList,
+    /// Cached queries used by [`SimRequestReadOnly`] requests
+    pub read_queries: SimReadQueries,
+    /// Cache of full state requests, if enabled
+    pub all_state_cache: Option<AllStateCache>,
+    /// Middleware run around every request made through [`SimWorld::request`]
+    pub request_middleware: Vec<Box<dyn RequestMiddleware>>,
 }
 
 impl SimWorld {
     /// Makes a request to the sim world and returns the results
     pub fn request<Request: SimRequest>(&mut self, mut request: Request) -> Request::Output {
-        request.request(self)
+        entity_id::assign_sim_entity_ids(&mut self.world);
+        if self.request_middleware.is_empty() {
+            return request.request(self);
+        }
+
+        let request_type = std::any::type_name::<Request>();
+        let mut middleware = std::mem::take(&mut self.request_middleware);
+        for middleware in middleware.iter_mut() {
+            middleware.before_request(&mut self.world, request_type);
+        }
+        let start = Instant::now();
+        let output = request.request(self);
+        let record = RequestRecord {
+            request_type,
+            duration: start.elapsed(),
+            output_bytes: Request::output_bytes(&output),
+        };
+        for middleware in middleware.iter_mut() {
+            middleware.after_request(&mut self.world, &record);
+        }
+        self.request_middleware = middleware;
+        output
+    }
+
+    /// Makes a read only request to the sim world and returns the results. Only requires shared
+    /// access so multiple read only requests can run at the same time
+    pub fn request_ref<Request: SimRequestReadOnly>(
+        &self,
+        mut request: Request,
+    ) -> Request::Output {
+        request.request_ref(self)
+    }
+
+    /// Returns an iterator that serializes the state of every entity, or only the entities relevant
+    /// to the given player, one at a time, see [`EntityStateStream`]
+    pub fn stream_entities(&self, for_player: Option<usize>) -> EntityStateStream<'_> {
+        EntityStateStream::new(self, for_player)
+    }
+
+    /// Returns the current sim tick, see [`SimTick`]
+    pub fn tick(&self) -> u64 {
+        self.world
+            .get_resource::<SimTick>()
+            .map(|tick| tick.0)
+            .unwrap_or_default()
+    }
+
+    /// Returns the [`GameCommands`](command::GameCommands) of the sim. Every command queued for the
+    /// sim, from the main world, a [`StandaloneSim`](runner::StandaloneSim), or the sim schedules,
+    /// goes through this single resource in the sim world
+    pub fn game_commands(&self) -> &command::GameCommands {
+        self.world.resource::<command::GameCommands>()
     }
 
-    /// Simple function that will clear all changed components that have been fully seen as well as
+    /// Returns the [`GameCommands`](command::GameCommands) of the sim mutably, see
+    /// [`SimWorld::game_commands`]
+    pub fn game_commands_mut(&mut self) -> Mut<'_, command::GameCommands> {
+        self.world.resource_mut::<command::GameCommands>()
+    }
+
+    /// Returns true if the command sets of every player for the next command batch have arrived,
+    /// see [`InputDelay`](input_delay::InputDelay)
+    pub fn inputs_ready(&self) -> bool {
+        self.world
+            .get_resource::<command::GameCommands>()
+            .is_none_or(|game_commands| game_commands.inputs_ready(self.tick(), &self.player_list))
+    }
+
+    /// Returns the latest state sequence number issued to the given player id, if any have been issued
+    pub fn latest_sequence(&self, player_id: usize) -> Option<u64> {
+        self.world
+            .get_resource::<StateSequences>()
+            .and_then(|sequences| sequences.latest(player_id))
+    }
+
+    /// Acknowledges that the given player received all state up to and including the given sequence.
+    /// Does nothing if state acks aren't enabled
+    pub fn ack_state(&mut self, player_id: usize, sequence: u64) {
+        if let Some(mut acks) = self.world.get_resource_mut::<PendingStateAcks>() {
+            acks.ack(player_id, sequence);
+        }
+    }
+
+    /// Returns all the state that the given player hasn't acknowledged yet, under a new sequence number
+    pub fn resend_pending(&mut self, player_id: usize) -> SimState {
+        self.request(ResendPending {
+            for_player: player_id,
+        })
+    }
+
+    /// Replaces the serialize and deserialize functions and the schema version of the component with
+    /// the given id in the live registry, and marks every entity holding that component as changed so
+    /// that it is sent again to all players. Use this to update script or mod defined types without
+    /// restarting the sim. Returns an error if no component is registered with the given id, see
+    /// [`GameSerDeRegistry::replace_component_fns`]
+    pub fn reload_component_registration(
+        &mut self,
+        id: SimComponentId,
+        serialize_fn: ComponentSerializeFn,
+        deserialize_fn: ComponentDeserializeFn,
+        version: SchemaVersion,
+    ) -> Result<(), RegistryError> {
+        self.registry
+            .replace_component_fns(id, serialize_fn, deserialize_fn, version)?;
+        if let Some(cache) = self.all_state_cache.as_ref() {
+            cache.invalidate();
+        }
+        self.world.insert_resource(self.registry.clone());
+
+        let mut query = self.world.query::<(Entity, Option<&dyn SaveId>)>();
+        let entities: Vec<Entity> = query
+            .iter(&self.world)
+            .filter(|(entity, saveable_components)| {
+                self.registry
+                    .save_entity(saveable_components.as_ref(), &self.world, *entity)
+                    .iter()
+                    .any(|component| component.id == id)
+            })
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in entities {
+            let tick = self.tick();
+            self.world
+                .resource_mut::<ChangeSet>()
+                .mark_changed(entity, tick);
+        }
+        Ok(())
+    }
+
+    /// Replaces the serialize and deserialize functions and the schema version of the resource with
+    /// the given id in the live registry, and marks the resource as changed so that it is sent again
+    /// to all players. Returns an error if no resource is registered with the given id, see
+    /// [`GameSerDeRegistry::replace_resource_fns`]
+    pub fn reload_resource_registration(
+        &mut self,
+        id: SimResourceId,
+        deserialize_fn: ResourceDeserializeFn,
+        serialize_fn: ResourceSerializeFn,
+        version: SchemaVersion,
+    ) -> Result<(), RegistryError> {
+        self.registry
+            .replace_resource_fns(id, deserialize_fn, serialize_fn, version)?;
+        if let Some(cache) = self.all_state_cache.as_ref() {
+            cache.invalidate();
+        }
+        self.world.insert_resource(self.registry.clone());
+
+        let tick = self.tick();
+        let Some(mut tracking) = self.world.get_resource_mut::<ResourceChangeTracking>() else {
+            return Ok(());
+        };
+        if !tracking.mark_changed(id, tick) {
+            // The resource hasn't been tracked since it was inserted, so the first time it is
+            // tracked it is reported as changed anyway
+            debug!("reloaded resource {} isn't tracked yet", id);
+        }
+        Ok(())
+    }
+
+    /// Returns an estimate of the memory used by the sim
+    pub fn memory_report(&self) -> SimMemoryReport {
+        SimMemoryReport::new(self)
+    }
+
+    /// Drops tracking kept for players that are no longer in the player list and releases unused
+    /// capacity. Call [`SimWorld::clear_changed`] first to also drop changes that every player has seen
+    pub fn trim_memory(&mut self) {
+        trim_sim_world(self);
+    }
+
+    /// Saves every registered component and resource in the sim into a single binary blob, prefixed
+    /// with a [`SaveHeader`]
+    pub fn save_snapshot(&mut self) -> Result<Vec<u8>, bincode::Error> {
+        self.save_snapshot_with("", &SaveFilter::default())
+    }
+
+    /// Saves the entities and components in the sim that pass the given filter, along with every
+    /// registered resource, into a single binary blob prefixed with a [`SaveHeader`]
+    pub fn save_snapshot_filtered(
+        &mut self,
+        filter: &SaveFilter,
+    ) -> Result<Vec<u8>, bincode::Error> {
+        self.save_snapshot_with("", filter)
+    }
+
+    /// Saves the entities and components in the sim that pass the given filter, along with every
+    /// registered resource, into a single binary blob prefixed with a [`SaveHeader`] with the
+    /// given name
+    pub fn save_snapshot_with(
+        &mut self,
+        name: &str,
+        filter: &SaveFilter,
+    ) -> Result<Vec<u8>, bincode::Error> {
+        let body = SimSnapshot::new_filtered(self, filter).to_binary()?;
+        SaveHeader::new(&self.registry, name).write(&self.registry.seal_save_body(body))
+    }
+
+    /// Saves the sim like [`SimWorld::save_snapshot_with`] and writes the save to the given writer,
+    /// such as a file, an in memory buffer, or a socket
+    pub fn save_snapshot_to(
+        &mut self,
+        writer: impl Write,
+        name: &str,
+        filter: &SaveFilter,
+    ) -> Result<(), bincode::Error> {
+        let body = SimSnapshot::new_filtered(self, filter).to_binary()?;
+        SaveHeader::new(&self.registry, name).write_to(writer, &self.registry.seal_save_body(body))
+    }
+
+    /// Reads a save written by [`SimWorld::save_snapshot_to`] from the given reader until it ends
+    /// and loads it, see [`SimWorld::load_snapshot`]
+    pub fn load_snapshot_from(
+        &mut self,
+        mut reader: impl Read,
+    ) -> Result<DeserializeReport, bincode::Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        self.load_snapshot(&data)
+    }
+
+    /// Replaces the state of the sim with the state saved in the given binary blob created with
+    /// [`SimWorld::save_snapshot`]. Fails without changing the sim if the save was written with an
+    /// incompatible registry. Returns every component and resource that failed to deserialize
+    pub fn load_snapshot(&mut self, data: &[u8]) -> Result<DeserializeReport, bincode::Error> {
+        let body = self.save_body(data)?;
+        let snapshot = SimSnapshot::from_binary(&body)?;
+        let report = snapshot.apply(&mut self.world, &self.registry);
+        self.player_list = snapshot.player_list;
+        Ok(report)
+    }
+
+    /// Saves the sim like [`SimWorld::save_snapshot_with`] and includes the queue and history of
+    /// its [`GameCommands`](command::GameCommands), so a loaded game can still roll back past the
+    /// load point. Every command type must be registered, see
+    /// [`GameBuilder::register_command`](game_builder::GameBuilder::register_command)
+    pub fn save_snapshot_with_commands(&mut self, name: &str) -> Result<Vec<u8>, bincode::Error> {
+        let type_registry = self.command_type_registry();
+        let mut snapshot = SimSnapshot::new(self);
+        snapshot.commands = self
+            .game_commands()
+            .save(&type_registry.read(), self.registry.format)
+            .map_err(|error| Box::new(bincode::ErrorKind::Custom(error)))?;
+        let body = snapshot.to_binary()?;
+        SaveHeader::new(&self.registry, name).write(&self.registry.seal_save_body(body))
+    }
+
+    /// Replaces the state of the sim with the state saved by
+    /// [`SimWorld::save_snapshot_with_commands`], and the queue and history of its
+    /// [`GameCommands`](command::GameCommands) with the saved ones. Fails without changing the sim
+    /// or the commands if a saved command can't be deserialized
+    pub fn load_snapshot_with_commands(
+        &mut self,
+        data: &[u8],
+    ) -> Result<DeserializeReport, bincode::Error> {
+        let body = self.save_body(data)?;
+        let snapshot = SimSnapshot::from_binary(&body)?;
+        let type_registry = self.command_type_registry();
+        let format = self.registry.format;
+        self.game_commands_mut()
+            .load(&snapshot.commands, &type_registry.read(), format)
+            .map_err(|error| Box::new(bincode::ErrorKind::Custom(error)))?;
+        let report = snapshot.apply(&mut self.world, &self.registry);
+        self.player_list = snapshot.player_list;
+        Ok(report)
+    }
+
+    /// Returns the type registry that commands are serialized with
+    pub(crate) fn command_type_registry(&self) -> AppTypeRegistry {
+        self.world
+            .get_resource::<AppTypeRegistry>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Extracts the state of the sim and encodes and writes it to the given path on a background
+    /// task, see [`BackgroundSave`]
+    pub fn save_in_background(&mut self, path: impl Into<PathBuf>, name: &str) -> BackgroundSave {
+        let header = SaveHeader::new(&self.registry, name);
+        BackgroundSave::start(path.into(), header, SimSnapshot::new(self), &self.registry)
+    }
+
+    /// Returns the decrypted body of the given save, or an error if its header is missing, it was
+    /// written with an incompatible registry, or it can't be decrypted
+    fn save_body(&self, data: &[u8]) -> Result<Vec<u8>, bincode::Error> {
+        let (header, body) = SaveHeader::split(data)?;
+        if !header.is_compatible(&self.registry) {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "Save {:?} was written with an incompatible registry",
+                header.name
+            ))));
+        }
+        self.registry.open_save_body(body)
+    }
+
+    /// Creates a full snapshot of the sim. Keep it as the baseline for [`SimWorld::save_delta`]
+    pub fn snapshot(&mut self) -> SimSnapshot {
+        SimSnapshot::new(self)
+    }
+
+    /// Saves only what changed since the given baseline into a single binary blob prefixed with a
+    /// [`SaveHeader`]
+    pub fn save_delta(&mut self, baseline: &SimSnapshot) -> Result<Vec<u8>, bincode::Error> {
+        let delta = SnapshotDelta::new(baseline, &SimSnapshot::new(self));
+        SaveHeader::new(&self.registry, "").write(&self.registry.seal_save_body(delta.to_binary()?))
+    }
+
+    /// Saves only what changed since the given baseline like [`SimWorld::save_delta`] and writes the
+    /// save to the given writer
+    pub fn save_delta_to(
+        &mut self,
+        writer: impl Write,
+        baseline: &SimSnapshot,
+    ) -> Result<(), bincode::Error> {
+        let delta = SnapshotDelta::new(baseline, &SimSnapshot::new(self));
+        SaveHeader::new(&self.registry, "")
+            .write_to(writer, &self.registry.seal_save_body(delta.to_binary()?))
+    }
+
+    /// Reads a delta save written by [`SimWorld::save_delta_to`] from the given reader until it
+    /// ends and loads it against the given baseline, see [`SimWorld::load_delta`]
+    pub fn load_delta_from(
+        &mut self,
+        baseline: &SimSnapshot,
+        mut reader: impl Read,
+    ) -> Result<DeserializeReport, bincode::Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        self.load_delta(baseline, &data)
+    }
+
+    /// Replaces the state of the sim with the state reassembled from the given baseline and a delta
+    /// save created with [`SimWorld::save_delta`] against that baseline
+    pub fn load_delta(
+        &mut self,
+        baseline: &SimSnapshot,
+        data: &[u8],
+    ) -> Result<DeserializeReport, bincode::Error> {
+        let body = self.save_body(data)?;
+        let Some(snapshot) = SnapshotDelta::from_binary(&body)?.apply_to(baseline) else {
+            return Err(Box::new(bincode::ErrorKind::Custom(
+                "Delta save doesn't match the given baseline".into(),
+            )));
+        };
+        let report = snapshot.apply(&mut self.world, &self.registry);
+        self.player_list = snapshot.player_list;
+        Ok(report)
+    }
+
+    /// Replaces the state of the sim with the latest autosave in the given directory, see
+    /// [`Autosave`](saving::autosave::Autosave). Uses the delta autosave if it matches the baseline,
+    /// otherwise the baseline alone, and returns which one was loaded. Returns an error without
+    /// loading anything if the delta autosave exists but can't be read or decoded
+    pub fn load_autosave(
+        &mut self,
+        directory: impl AsRef<Path>,
+    ) -> Result<(DeserializeReport, AutosaveSource), bincode::Error> {
+        let (baseline_path, delta_path) = autosave_paths(directory.as_ref());
+        let baseline_data = std::fs::read(baseline_path)?;
+        let baseline = SimSnapshot::from_save(&baseline_data, &self.registry)?;
+        let (snapshot, source) = match std::fs::read(delta_path) {
+            Ok(delta_data) => {
+                let delta = SnapshotDelta::from_binary(&self.save_body(&delta_data)?)?;
+                match delta.apply_to(&baseline) {
+                    Some(snapshot) => (snapshot, AutosaveSource::Delta),
+                    None => (baseline, AutosaveSource::StaleDelta),
+                }
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                (baseline, AutosaveSource::Baseline)
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let report = snapshot.apply(&mut self.world, &self.registry);
+        self.player_list = snapshot.player_list;
+        Ok((report, source))
+    }
+
+    /// Simple function that will clear all changed entities in the [`ChangeSet`] that have been fully seen as well as
     /// the [`TrackedDespawns`] (it despawns marked entities) resource and the [`ResourceChangeTracking`] resource.
     pub fn clear_changed(&mut self, player_list: &PlayerList) {
-        let mut system_state: SystemState<(Query<(Entity, &SimChanged)>, Commands)> =
-            SystemState::new(&mut self.world);
-        let (changed_query, mut commands) = system_state.get(&self.world);
-        for (entity, changed) in changed_query.iter() {
-            if changed.all_seen(&player_list.players) {
-                commands.entity(entity).remove::<SimChanged>();
-            }
-        }
+        self.world
+            .resource_scope(|world, mut change_set: Mut<ChangeSet>| {
+                change_set.retain(|entity, changed| {
+                    world.get_entity(entity).is_some() && !changed.all_seen(&player_list.players)
+                });
+            });
 
         self.world
             .resource_scope(|_world, mut despawned_objects: Mut<TrackedDespawns>| {
-                let mut index_to_remove: Vec<Entity> = vec![];
+                let mut index_to_remove: Vec<SimEntityId> = vec![];
                 for (id, changed) in despawned_objects.despawned_objects.iter_mut() {
                     if changed.all_seen(&player_list.players) {
                         index_to_remove.push(*id);
@@ -62,20 +479,46 @@ impl SimWorld {
 
         self.world.resource_scope(
             |_world, mut resource_change_tracking: Mut<ResourceChangeTracking>| {
-                let mut index_to_remove: Vec<SimResourceId> = vec![];
-                for (id, changed) in resource_change_tracking.resources.iter_mut() {
-                    if changed.all_seen(&player_list.players) {
-                        index_to_remove.push(*id);
-                    }
-                }
-                for id in index_to_remove {
-                    resource_change_tracking.resources.remove(&id);
-                }
+                resource_change_tracking
+                    .resources
+                    .retain(|_, changed| !changed.all_seen(&player_list.players));
             },
         );
+    }
+
+    /// Marks every tracked change as seen by the given player, for example after they were sent the
+    /// full state, and then clears everything fully seen by the players in the given list like
+    /// [`SimWorld::clear_changed`]
+    pub fn clear_changed_for_player(&mut self, player_id: usize, player_list: &PlayerList) {
+        self.world
+            .resource_mut::<ChangeSet>()
+            .entities
+            .values_mut()
+            .for_each(|changed| changed.register_seen(player_id));
+        self.world
+            .resource_mut::<TrackedDespawns>()
+            .despawned_objects
+            .values_mut()
+            .for_each(|changed| changed.register_seen(player_id));
+        self.world
+            .resource_mut::<ResourceChangeTracking>()
+            .resources
+            .values_mut()
+            .for_each(|changed| changed.register_seen(player_id));
 
-        system_state.apply(&mut self.world);
+        self.clear_changed(player_list);
     }
 
-    pub fn execute_game_commands(&mut self) {}
+    /// Executes the commands queued into the [`GameCommands`](command::GameCommands) of the sim
+    /// against the sim world, see [`SimWorld::game_commands`]. Returns the result of every executed
+    /// command
+    pub fn execute_game_commands(&mut self) -> Vec<command::CommandExecuted> {
+        if !self.world.contains_resource::<command::GameCommands>() {
+            return vec![];
+        }
+        self.world
+            .resource_scope(|world, mut game_commands: Mut<command::GameCommands>| {
+                game_commands.execute_buffer(world)
+            })
+    }
 }
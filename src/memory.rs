@@ -0,0 +1,192 @@
+//! Reporting and trimming of the memory used by a [`SimWorld`], so that long running sims can keep
+//! their memory bounded.
+
+use crate::{
+    change_detection::{ChangeSet, ResourceChangeTracking, SimChanged, TrackedDespawns},
+    command::{GameCommandMeta, GameCommands},
+    requests::{acks::PendingStateAcks, StateSequences},
+    SimWorld,
+};
+
+/// An estimate of the memory used by a [`SimWorld`]
+#[derive(Clone, Copy, Eq, Debug, PartialEq, Default)]
+pub struct SimMemoryReport {
+    /// Number of entities in the sim world
+    pub entities: usize,
+    /// Number of archetypes in the sim world
+    pub archetypes: usize,
+    /// Estimated bytes used by component data, excluding any heap allocations owned by components
+    pub component_bytes: usize,
+    /// Number of commands held in the [`GameCommands`] queue, history, and rolledback history
+    pub commands: usize,
+    /// Estimated bytes used by the commands, excluding any heap allocations owned by commands
+    pub command_bytes: usize,
+    /// Number of changed entities still tracked in [`ChangeSet`]
+    pub tracked_changes: usize,
+    /// Number of despawned entities still tracked in [`TrackedDespawns`]
+    pub tracked_despawns: usize,
+    /// Number of changed resources still tracked in [`ResourceChangeTracking`]
+    pub tracked_resources: usize,
+    /// Number of issued states still waiting on an ack in [`PendingStateAcks`]
+    pub pending_acks: usize,
+}
+
+impl SimMemoryReport {
+    /// Creates a report for the given sim world
+    pub fn new(sim_world: &SimWorld) -> SimMemoryReport {
+        let world = &sim_world.world;
+        let mut report = SimMemoryReport {
+            entities: world.entities().len() as usize,
+            archetypes: world.archetypes().len(),
+            ..Default::default()
+        };
+
+        for archetype in world.archetypes().iter() {
+            for component_id in archetype.components() {
+                if let Some(info) = world.components().get_info(component_id) {
+                    report.component_bytes += info.layout().size() * archetype.len();
+                }
+            }
+        }
+
+        if let Some(game_commands) = world.get_resource::<GameCommands>() {
+            report.commands = game_commands.queue.queue.len()
+                + game_commands.history.history.len()
+                + game_commands.history.rolledback_history.len();
+            report.command_bytes = game_commands
+                .queue
+                .queue
+                .iter()
+                .chain(game_commands.history.history.iter())
+                .chain(game_commands.history.rolledback_history.iter())
+                .map(GameCommandMeta::estimated_bytes)
+                .sum();
+        }
+
+        if let Some(change_set) = world.get_resource::<ChangeSet>() {
+            report.tracked_changes = change_set.entities.len();
+        }
+        if let Some(despawns) = world.get_resource::<TrackedDespawns>() {
+            report.tracked_despawns = despawns.despawned_objects.len();
+        }
+        if let Some(resources) = world.get_resource::<ResourceChangeTracking>() {
+            report.tracked_resources = resources.resources.len();
+        }
+        if let Some(acks) = world.get_resource::<PendingStateAcks>() {
+            report.pending_acks = acks.pending.values().map(|pending| pending.len()).sum();
+        }
+
+        report
+    }
+}
+
+/// Drops tracking kept for players that are no longer in the sim's player list and releases unused
+/// capacity held by the tracking maps and command history
+pub fn trim_sim_world(sim_world: &mut SimWorld) {
+    let player_ids: Vec<usize> = sim_world
+        .player_list
+        .players
+        .iter()
+        .map(|player| player.id())
+        .collect();
+    let retain_players = |changed: &mut SimChanged| {
+        changed
+            .players_seen
+            .retain(|player_id| player_ids.contains(&player_id));
+        changed.players_seen.shrink_to_fit();
+    };
+
+    let world = &mut sim_world.world;
+    if let Some(mut change_set) = world.get_resource_mut::<ChangeSet>() {
+        change_set.entities.values_mut().for_each(retain_players);
+        change_set.entities.shrink_to_fit();
+        change_set.components.shrink_to_fit();
+    }
+
+    if let Some(mut despawns) = world.get_resource_mut::<TrackedDespawns>() {
+        despawns
+            .despawned_objects
+            .values_mut()
+            .for_each(retain_players);
+        despawns.despawned_objects.shrink_to_fit();
+    }
+    if let Some(mut resources) = world.get_resource_mut::<ResourceChangeTracking>() {
+        resources.resources.values_mut().for_each(retain_players);
+        resources.resources.shrink_to_fit();
+    }
+    if let Some(mut acks) = world.get_resource_mut::<PendingStateAcks>() {
+        acks.pending
+            .retain(|player_id, _| player_ids.contains(player_id));
+        acks.pending.shrink_to_fit();
+    }
+    if let Some(mut sequences) = world.get_resource_mut::<StateSequences>() {
+        sequences
+            .sequences
+            .retain(|player_id, _| player_ids.contains(player_id));
+        sequences.sequences.shrink_to_fit();
+    }
+    if let Some(mut game_commands) = world.get_resource_mut::<GameCommands>() {
+        game_commands.queue.queue.shrink_to_fit();
+        game_commands.history.history.shrink_to_fit();
+        game_commands.history.rolledback_history.shrink_to_fit();
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Reflect, World};
+
+    use crate::{
+        change_detection::ChangeSet,
+        command::{CommandError, GameCommand},
+        testing::{fixtures::TestComponent, mock_players, test_game},
+    };
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Noop;
+
+    impl GameCommand for Noop {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_memory_report_and_trim() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let players = mock_players(&mut game, 2);
+        let mut sim = game.build_standalone();
+        let unit = sim.sim_world.world.spawn(TestComponent(0)).id();
+        sim.sim_world.game_commands_mut().add(Noop);
+        sim.sim_world.game_commands_mut().add(Noop);
+        sim.simulate();
+        sim.sim_world.game_commands_mut().add(Noop);
+
+        let report = sim.sim_world.memory_report();
+        assert!(report.entities >= 1);
+        assert!(report.component_bytes > 0);
+        assert_eq!(report.tracked_changes, 1);
+        assert_eq!(report.commands, 3);
+        assert!(report.command_bytes > 0);
+
+        let mut change_set = sim.sim_world.world.resource_mut::<ChangeSet>();
+        let changed = change_set.entities.get_mut(&unit).unwrap();
+        changed.players_seen.insert(players[0]);
+        changed.players_seen.insert(players[1]);
+        sim.sim_world
+            .player_list
+            .players
+            .retain(|player| player.id() != players[1]);
+        sim.sim_world.trim_memory();
+
+        let players_seen =
+            &sim.sim_world.world.resource::<ChangeSet>().entities[&unit].players_seen;
+        assert!(players_seen.contains(players[0]));
+        assert!(!players_seen.contains(players[1]));
+        let report = sim.sim_world.memory_report();
+        assert_eq!(report.tracked_changes, 1);
+        assert_eq!(report.commands, 3);
+        assert_eq!(sim.sim_world.game_commands().history.history.capacity(), 2);
+    }
+}
@@ -0,0 +1,198 @@
+//! Recording and replaying of the commands executed by a sim. Enable recording with
+//! [`GameBuilder::enable_replay_recording`](crate::game_builder::GameBuilder::enable_replay_recording)
+//! and every successfully executed [`GameCommandMeta`] is saved with its tick into the [`ReplayLog`]
+//! of the [`ReplayRecorder`] resource. Feed the log into a freshly built sim with a [`ReplayRunner`]
+//! to deterministically reproduce the recorded game. Rollbacks aren't recorded, so replays of sims
+//! that roll commands back diverge from them.
+//!
+//! Every recorded command type must be registered with
+//! [`GameBuilder::register_command`](crate::game_builder::GameBuilder::register_command).
+
+use bevy::{
+    ecs::reflect::AppTypeRegistry,
+    log::warn,
+    prelude::{Resource, World},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::{GameCommandMeta, SavedCommand},
+    runner::{GameRunner, StandaloneSim},
+    saving::{
+        journal::{CommandJournal, JournalEntry},
+        GameSerDeRegistry,
+    },
+};
+
+/// Every command executed by a sim, in execution order
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayLog {
+    pub commands: Vec<SavedCommand>,
+    /// The last tick commands were executed at. Replays simulate up to and including it
+    pub end_tick: u64,
+}
+
+impl ReplayLog {
+    /// Serializes the log into binary
+    pub fn to_binary(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserializes a log serialized with [`ReplayLog::to_binary`]
+    pub fn from_binary(data: &[u8]) -> Result<ReplayLog, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
+
+/// Resource inserted into the sim world that records every executed command into its [`ReplayLog`]
+#[derive(Clone, Default, Debug, Resource)]
+pub struct ReplayRecorder {
+    pub log: ReplayLog,
+}
+
+/// Sets the end tick of the [`ReplayRecorder`] of the given world to the given tick and journals the
+/// tick into its [`CommandJournal`], if it has them. Called by
+/// [`GameCommands::execute_buffer`](crate::command::GameCommands::execute_buffer)
+pub(crate) fn record_tick(world: &mut World, tick: u64) {
+    if let Some(mut recorder) = world.get_resource_mut::<ReplayRecorder>() {
+        recorder.log.end_tick = tick;
+    }
+    if let Some(mut journal) = world.get_resource_mut::<CommandJournal>() {
+        if let Err(error) = journal.append(&JournalEntry::Tick(tick)) {
+            warn!("Tick not journaled: {}", error);
+        }
+    }
+}
+
+/// Saves the given executed command into the [`ReplayRecorder`] and [`CommandJournal`] of the given
+/// world, if it has them. Called by
+/// [`GameCommands::execute_buffer`](crate::command::GameCommands::execute_buffer)
+pub(crate) fn record_command(world: &mut World, command: &GameCommandMeta) {
+    if !world.contains_resource::<ReplayRecorder>() && !world.contains_resource::<CommandJournal>()
+    {
+        return;
+    }
+    let Some(saved) = save_command(world, command) else {
+        return;
+    };
+    if let Some(mut journal) = world.get_resource_mut::<CommandJournal>() {
+        if let Err(error) = journal.append(&JournalEntry::Command(saved.clone())) {
+            warn!("Command not journaled: {}", error);
+        }
+    }
+    if let Some(mut recorder) = world.get_resource_mut::<ReplayRecorder>() {
+        recorder.log.commands.push(saved);
+    }
+}
+
+/// Replaces the recordings of the given merged command and the command merged into it in the
+/// [`ReplayRecorder`] of the given world with the merged command, see
+/// [`GameCommand::merge`](crate::command::GameCommand::merge). The [`CommandJournal`] keeps every
+/// command as it was executed
+pub(crate) fn record_merged(world: &mut World, merged: &GameCommandMeta, next: &GameCommandMeta) {
+    if !world.contains_resource::<ReplayRecorder>() {
+        return;
+    }
+    let Some(saved) = save_command(world, merged) else {
+        return;
+    };
+    let mut recorder = world.resource_mut::<ReplayRecorder>();
+    while recorder.log.commands.last().is_some_and(|last| {
+        last.tick == merged.tick
+            && (last.sequence == merged.sequence || last.sequence == next.sequence)
+    }) {
+        recorder.log.commands.pop();
+    }
+    recorder.log.commands.push(saved);
+}
+
+/// Saves the given command for recording with the formats of the given world
+fn save_command(world: &World, command: &GameCommandMeta) -> Option<SavedCommand> {
+    let format = world
+        .get_resource::<GameSerDeRegistry>()
+        .map(|registry| registry.format)
+        .unwrap_or_default();
+    let type_registry = world
+        .get_resource::<AppTypeRegistry>()
+        .cloned()
+        .unwrap_or_default();
+    let saved = command.save(&type_registry.read(), format);
+    match saved {
+        Ok(saved) => Some(saved),
+        Err(error) => {
+            warn!("Command not recorded: {}", error);
+            None
+        }
+    }
+}
+
+/// Feeds the commands of a [`ReplayLog`] back into a sim, queueing each command at the tick it was
+/// originally executed at
+pub struct ReplayRunner {
+    pub log: ReplayLog,
+    next: usize,
+}
+
+impl ReplayRunner {
+    pub fn new(log: ReplayLog) -> ReplayRunner {
+        ReplayRunner { log, next: 0 }
+    }
+
+    /// Returns true once the given sim has simulated past the end tick of the log
+    pub fn is_finished<T: GameRunner>(&self, sim: &StandaloneSim<T>) -> bool {
+        sim.sim_world.tick() > self.log.end_tick
+    }
+
+    /// Queues the commands recorded at the current tick of the sim and simulates it once. Returns
+    /// false without simulating if the replay is finished
+    pub fn step<T: GameRunner>(&mut self, sim: &mut StandaloneSim<T>) -> Result<bool, String> {
+        if self.is_finished(sim) {
+            return Ok(false);
+        }
+        let tick = sim.sim_world.tick();
+        let type_registry = sim.sim_world.command_type_registry();
+        let format = sim.sim_world.registry.format;
+        while let Some(saved) = self.log.commands.get(self.next) {
+            if saved.tick > tick {
+                break;
+            }
+            let command = GameCommandMeta::load(saved, &type_registry.read(), format)?;
+            sim.sim_world.game_commands_mut().queue.queue.push(command);
+            self.next += 1;
+        }
+        sim.simulate();
+        Ok(true)
+    }
+
+    /// Steps the sim until the replay is finished
+    pub fn run<T: GameRunner>(&mut self, sim: &mut StandaloneSim<T>) -> Result<(), String> {
+        while self.step(sim)? {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{ReplayLog, ReplayRecorder, ReplayRunner};
+    use crate::testing::fixtures::{build_sim, unit_values, SpawnUnit};
+
+    #[test]
+    fn test_replay() {
+        let mut sim = build_sim(|game| game.enable_replay_recording());
+        sim.sim_world.game_commands_mut().add(SpawnUnit(1));
+        sim.simulate();
+        sim.simulate();
+        sim.sim_world.game_commands_mut().add(SpawnUnit(2));
+        sim.sim_world.game_commands_mut().add(SpawnUnit(3));
+        sim.simulate();
+
+        let log = sim.sim_world.world.resource::<ReplayRecorder>().log.clone();
+        assert_eq!(log.commands.len(), 3);
+        let log = ReplayLog::from_binary(&log.to_binary().unwrap()).unwrap();
+
+        let mut replay = build_sim(|_| {});
+        ReplayRunner::new(log).run(&mut replay).unwrap();
+        assert_eq!(replay.sim_world.tick(), sim.sim_world.tick());
+        assert_eq!(unit_values(&mut replay), unit_values(&mut sim));
+    }
+}
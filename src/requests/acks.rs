@@ -0,0 +1,251 @@
+//! Support for sending state over unreliable transports. When enabled through
+//! [`GameBuilder::enable_state_acks`](crate::game_builder::GameBuilder::enable_state_acks) every
+//! player specific state request records what it included under its sequence number. Until the
+//! player acknowledges that sequence with [`SimWorld::ack_state`], the recorded state stays pending
+//! and is included again in the next state issued to that player.
+
+use bevy::{
+    prelude::{Entity, Resource},
+    utils::{HashMap, HashSet},
+};
+use std::{collections::BTreeMap, hash::Hash};
+
+use crate::{
+    entity_id::SimEntityId, interest::is_entity_relevant, saving::SimResourceId, SimWorld,
+};
+
+use super::{push_entity_state, SimRequest, SimState, StateSequences};
+
+/// The state included in a single issued [`SimState`]
+#[derive(Clone, Eq, Debug, PartialEq, Default)]
+pub struct PendingState {
+    /// Entities in the sim world, including player entities, whose state was included
+    pub entities: Vec<Entity>,
+    pub despawned_objects: Vec<SimEntityId>,
+    pub resources: Vec<SimResourceId>,
+}
+
+impl PendingState {
+    /// Adds all the state from other that isn't already in self
+    pub fn extend(&mut self, other: &PendingState) {
+        self.entities.extend_from_slice(&other.entities);
+        self.despawned_objects
+            .extend_from_slice(&other.despawned_objects);
+        self.resources.extend_from_slice(&other.resources);
+        self.dedup();
+    }
+
+    /// Removes every repeated entry, keeping the first one
+    fn dedup(&mut self) {
+        dedup(&mut self.entities);
+        dedup(&mut self.despawned_objects);
+        dedup(&mut self.resources);
+    }
+}
+
+/// Removes every repeated item from the given vec, keeping the first one
+fn dedup<T: Copy + Eq + Hash>(items: &mut Vec<T>) {
+    let mut seen = HashSet::with_capacity(items.len());
+    items.retain(|item| seen.insert(*item));
+}
+
+/// Resource inserted into the sim world that holds all issued state that players haven't
+/// acknowledged yet, keyed by player id and then by sequence number
+#[derive(Clone, Eq, Debug, PartialEq, Resource)]
+pub struct PendingStateAcks {
+    /// The maximum number of unacknowledged sequences kept per player
+    pub capacity: usize,
+    pub pending: HashMap<usize, BTreeMap<u64, PendingState>>,
+}
+
+impl PendingStateAcks {
+    pub fn new(capacity: usize) -> PendingStateAcks {
+        PendingStateAcks {
+            capacity,
+            pending: HashMap::default(),
+        }
+    }
+
+    /// Records the state issued to the given player under the given sequence. Drops the oldest
+    /// sequences of the player once it is over capacity. Every issued state includes the still
+    /// relevant state of the older pending ones, so this only forgets entities that stopped being
+    /// relevant to the player
+    pub fn record(&mut self, player_id: usize, sequence: u64, state: PendingState) {
+        let pending = self.pending.entry(player_id).or_default();
+        pending.insert(sequence, state);
+        while pending.len() > self.capacity {
+            pending.pop_first();
+        }
+    }
+
+    /// Acknowledges every sequence up to and including the given sequence for the given player
+    pub fn ack(&mut self, player_id: usize, sequence: u64) {
+        if let Some(pending) = self.pending.get_mut(&player_id) {
+            pending.retain(|pending_sequence, _| *pending_sequence > sequence);
+        }
+    }
+
+    /// Returns all the state that is still waiting on an ack from the given player
+    pub fn pending_for(&self, player_id: usize) -> PendingState {
+        let mut state = PendingState::default();
+        if let Some(pending) = self.pending.get(&player_id) {
+            for pending_state in pending.values() {
+                state.entities.extend_from_slice(&pending_state.entities);
+                state
+                    .despawned_objects
+                    .extend_from_slice(&pending_state.despawned_objects);
+                state.resources.extend_from_slice(&pending_state.resources);
+            }
+        }
+        state.dedup();
+        state
+    }
+}
+
+/// Finishes a player specific [`SimState`]. Includes any state still pending an ack from the player
+/// that is still relevant to them, issues the next sequence number, and records the state under
+/// that sequence if acks are enabled.
+///
+/// `included` must contain everything already included in the given state.
+pub fn finish_player_state(
+    sim_world: &mut SimWorld,
+    for_player: usize,
+    state: &mut SimState,
+    mut included: PendingState,
+) {
+    let pending = sim_world
+        .world
+        .get_resource::<PendingStateAcks>()
+        .map(|acks| acks.pending_for(for_player));
+
+    if let Some(pending) = pending.as_ref() {
+        let included_entities: HashSet<Entity> = included.entities.iter().copied().collect();
+        for entity in pending.entities.iter() {
+            if !included_entities.contains(entity)
+                && is_entity_relevant(&sim_world.world, for_player, *entity)
+                && push_entity_state(&mut sim_world.world, &sim_world.registry, *entity, state)
+            {
+                included.entities.push(*entity);
+            }
+        }
+        let included_despawns: HashSet<SimEntityId> =
+            included.despawned_objects.iter().copied().collect();
+        for entity in pending.despawned_objects.iter() {
+            if !included_despawns.contains(entity) {
+                state.despawned_objects.push(*entity);
+                included.despawned_objects.push(*entity);
+            }
+        }
+        let included_resources: HashSet<SimResourceId> =
+            included.resources.iter().copied().collect();
+        for id in pending.resources.iter() {
+            if included_resources.contains(id) {
+                continue;
+            }
+            if let Some(resource_state) =
+                sim_world.registry.serialize_resource(id, &sim_world.world)
+            {
+                state.resources.push(resource_state);
+                included.resources.push(*id);
+            }
+        }
+    }
+
+    state.stamp_despawns(&sim_world.world);
+    state.stamp_new_entities(&mut sim_world.world, for_player);
+
+    let sequence = sim_world
+        .world
+        .resource_mut::<StateSequences>()
+        .issue(for_player);
+    state.sequence = Some(sequence);
+
+    if let Some(mut acks) = sim_world.world.get_resource_mut::<PendingStateAcks>() {
+        acks.record(for_player, sequence, included);
+    }
+}
+
+/// Returns all the state that hasn't been acknowledged by the given player under a new sequence
+/// number.
+pub struct ResendPending {
+    pub for_player: usize,
+}
+
+impl SimRequest for ResendPending {
+    type Output = SimState;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+        finish_player_state(
+            sim_world,
+            self.for_player,
+            &mut state,
+            PendingState::default(),
+        );
+        state
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::{PendingState, PendingStateAcks};
+    use crate::{
+        requests::state_dif::StateDif,
+        saving::SimResourceId,
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    #[test]
+    fn test_unacked_state_is_resent() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_state_acks(8);
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(TestComponent(0));
+        sim.simulate();
+
+        let first_state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(first_state.entities.len(), 1);
+
+        sim.simulate();
+        let second_state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(second_state.entities.len(), 1);
+
+        let resent_state = sim.sim_world.resend_pending(0);
+        assert_eq!(resent_state.entities.len(), 1);
+        assert_eq!(resent_state.sequence, Some(2));
+
+        sim.sim_world.ack_state(0, 2);
+        let third_state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(third_state.entities.len(), 0);
+    }
+
+    #[test]
+    fn test_pending_sequences_are_capped() {
+        let mut acks = PendingStateAcks::new(2);
+        for sequence in 0..4 {
+            acks.record(
+                0,
+                sequence,
+                PendingState {
+                    resources: vec![sequence as SimResourceId, 0],
+                    ..Default::default()
+                },
+            );
+        }
+        assert_eq!(
+            acks.pending[&0].keys().copied().collect::<Vec<u64>>(),
+            vec![2, 3]
+        );
+        assert_eq!(acks.pending_for(0).resources, vec![2, 0, 3]);
+    }
+}
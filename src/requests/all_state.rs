@@ -1,83 +1,533 @@
-use bevy::prelude::{Entity, Mut, Without};
+use std::sync::RwLock;
+
+use bevy::{
+    ecs::component::Tick,
+    prelude::{Resource, World},
+    utils::HashMap,
+};
 
 use crate::{
-    change_detection::{DespawnTracked, ResourceChangeTracking, TrackedDespawns},
-    player::Player,
-    saving::{ComponentBinaryState, SaveId},
+    change_detection::{ChangeSet, ResourceChangeTracking, TrackedDespawns},
+    entity_id::SimEntityId,
+    interest::is_entity_relevant,
+    saving::SimResourceId,
+    SimWorld,
 };
 
-use super::{EntityState, PlayerState, SimRequest, SimState};
+use super::{push_entity_ref_state, SimRequest, SimRequestReadOnly, SimState};
 
-/// Returns all the state regardless of its changed status
+/// Returns all the state regardless of its changed status. This is the whole authoritative world, use
+/// [`AllStateFor`] when sending it to a player
 pub struct AllState;
 
+/// Returns all the state relevant to the given player regardless of its changed status. Entities
+/// hidden from the player by [`SimVisibility`](crate::interest::SimVisibility) or outside their
+/// [`InterestSet`](crate::interest::InterestSet) are left out
+pub struct AllStateFor {
+    pub for_player: usize,
+}
+
 impl SimRequest for AllState {
     type Output = SimState;
 
-    fn request(&mut self, sim_world: &mut crate::SimWorld) -> Self::Output {
-        let mut state: SimState = SimState {
-            players: vec![],
-            resources: vec![],
-            entities: vec![],
-            despawned_objects: vec![],
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for AllState {
+    type Output = SimState;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        all_state(sim_world, None)
+    }
+}
+
+impl SimRequest for AllStateFor {
+    type Output = SimState;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for AllStateFor {
+    type Output = SimState;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        all_state(sim_world, Some(self.for_player))
+    }
+}
+
+/// The sim tick, world change tick, and change ticks of the change tracking resources a cached state
+/// was made at
+type AllStateCacheKey = (u64, Tick, [Option<Tick>; 3]);
+
+/// Cache of the last [`AllState`] and [`AllStateFor`] results of a [`SimWorld`], enabled with
+/// [`GameBuilder::enable_all_state_cache`](crate::game_builder::GameBuilder::enable_all_state_cache).
+/// A cached state is reused until the sim tick changes, a system runs in the sim world, or one of
+/// the change tracking resources is marked, so repeated full state requests within a tick only
+/// serialize the world once. Call [`AllStateCache::invalidate`] after changing the world directly
+/// outside of a system
+#[derive(Default)]
+pub struct AllStateCache {
+    cached: RwLock<HashMap<Option<usize>, (AllStateCacheKey, SimState)>>,
+}
+
+impl AllStateCache {
+    /// Drops every cached state
+    pub fn invalidate(&self) {
+        self.cached
+            .write()
+            .expect("AllStateCache lock was poisoned")
+            .clear();
+    }
+
+    fn key(sim_world: &SimWorld) -> AllStateCacheKey {
+        let world = &sim_world.world;
+        (
+            sim_world.tick(),
+            world.read_change_tick(),
+            [
+                resource_changed_tick::<ChangeSet>(world),
+                resource_changed_tick::<TrackedDespawns>(world),
+                resource_changed_tick::<ResourceChangeTracking>(world),
+            ],
+        )
+    }
+}
+
+fn resource_changed_tick<R: Resource>(world: &World) -> Option<Tick> {
+    world
+        .get_resource_change_ticks::<R>()
+        .map(|ticks| ticks.last_changed_tick())
+}
+
+/// Returns all the state for the given player, from the [`AllStateCache`] of the sim world if it
+/// has one and the cached state is still valid
+fn all_state(sim_world: &SimWorld, for_player: Option<usize>) -> SimState {
+    let Some(cache) = sim_world.all_state_cache.as_ref() else {
+        return build_all_state(sim_world, for_player);
+    };
+    let key = AllStateCache::key(sim_world);
+    if let Some((cached_key, state)) = cache
+        .cached
+        .read()
+        .expect("AllStateCache lock was poisoned")
+        .get(&for_player)
+    {
+        if *cached_key == key {
+            return state.clone();
+        }
+    }
+    let state = build_all_state(sim_world, for_player);
+    cache
+        .cached
+        .write()
+        .expect("AllStateCache lock was poisoned")
+        .insert(for_player, (key, state.clone()));
+    state
+}
+
+/// Returns all the state, only including the entities relevant to the given player if there is one.
+/// Tracked despawns are left out of player states since despawned entities can't be checked for
+/// relevance
+fn build_all_state(sim_world: &SimWorld, for_player: Option<usize>) -> SimState {
+    let mut state: SimState = SimState {
+        players: vec![],
+        resources: vec![],
+        entities: vec![],
+        despawned_objects: vec![],
+        despawn_ticks: vec![],
+        removed_components: vec![],
+        sequence: None,
+        tick: sim_world.tick(),
+    };
+
+    push_entities(sim_world, for_player, &mut state);
+    push_despawns(sim_world, for_player, &mut state);
+    push_resources(sim_world, &mut state);
+
+    state
+}
+
+/// Pushes every entity, or only the entities relevant to the given player if there is one, into the
+/// given state
+fn push_entities(sim_world: &SimWorld, for_player: Option<usize>, state: &mut SimState) {
+    let query = sim_world.read_queries.saveable_entities(&sim_world.world);
+
+    for (entity, saveable_components) in query.iter_manual(&sim_world.world) {
+        if for_player.is_some_and(|player| !is_entity_relevant(&sim_world.world, player, entity)) {
+            continue;
+        }
+        push_entity_ref_state(
+            &sim_world.registry,
+            &sim_world.world,
+            entity,
+            saveable_components.as_ref(),
+            state,
+        );
+    }
+}
+
+/// Pushes the tracked despawns into the given state if it isn't for a player
+fn push_despawns(sim_world: &SimWorld, for_player: Option<usize>, state: &mut SimState) {
+    if for_player.is_none() {
+        let despawned_objects = sim_world.world.resource::<TrackedDespawns>();
+        for (id, _) in despawned_objects.despawned_objects.iter() {
+            state.despawned_objects.push(*id);
+        }
+    }
+    state.stamp_despawns(&sim_world.world);
+}
+
+/// Pushes every registered resource into the given state
+fn push_resources(sim_world: &SimWorld, state: &mut SimState) {
+    let resource_change_tracking = sim_world.world.resource::<ResourceChangeTracking>();
+    let mut resources: Vec<SimResourceId> = vec![];
+    for (id, _) in resource_change_tracking.changed() {
+        if resources.contains(&id) {
+            continue;
+        }
+        resources.push(id);
+        if let Some(resource_state) = sim_world.registry.serialize_resource(&id, &sim_world.world) {
+            state.resources.push(resource_state);
+        }
+    }
+}
+
+/// Returns only the resources, or only the resources with the given ids if they are set, without
+/// walking any entities
+pub struct ResourcesState {
+    pub resources: Option<Vec<SimResourceId>>,
+}
+
+impl SimRequest for ResourcesState {
+    type Output = SimState;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for ResourcesState {
+    type Output = SimState;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+        let Some(resources) = self.resources.as_ref() else {
+            push_resources(sim_world, &mut state);
+            return state;
         };
+        state.resources = resources
+            .iter()
+            .filter_map(|id| sim_world.registry.serialize_resource(id, &sim_world.world))
+            .collect();
+        state
+    }
+}
 
-        let mut query = sim_world
-            .world
-            .query_filtered::<(&dyn SaveId, Entity, Option<&Player>), Without<DespawnTracked>>();
-
-        for (saveable_components, entity, opt_player) in query.iter_mut(&mut sim_world.world) {
-            let mut components: Vec<ComponentBinaryState> = vec![];
-            if opt_player.is_some() {
-                for component in saveable_components.iter() {
-                    if let Some((id, binary)) = component.save() {
-                        components.push(ComponentBinaryState {
-                            id,
-                            component: binary,
-                        });
-                    }
-                }
-
-                if let Some(player) = opt_player {
-                    state.players.push(PlayerState {
-                        components,
-                        player_id: *player,
-                    });
-                }
-            } else {
-                for component in saveable_components.iter() {
-                    if let Some((id, binary)) = component.save() {
-                        components.push(ComponentBinaryState {
-                            id,
-                            component: binary,
-                        });
-                    }
-                }
-                state.entities.push(EntityState {
-                    components,
-                    entity: entity,
-                });
+/// Returns the same state as [`AllState`], or [`AllStateFor`] if `for_player` is set, without any
+/// resources
+pub struct EntitiesOnlyState {
+    pub for_player: Option<usize>,
+}
+
+impl SimRequest for EntitiesOnlyState {
+    type Output = SimState;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for EntitiesOnlyState {
+    type Output = SimState;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+        push_entities(sim_world, self.for_player, &mut state);
+        push_despawns(sim_world, self.for_player, &mut state);
+        state
+    }
+}
+
+/// Where an [`AllStateChunked`] request left off. Pass it back in the next request to get the next
+/// chunk
+#[derive(Clone, Copy, Eq, Debug, PartialEq)]
+pub struct StateContinuation {
+    /// The id of the last entity sent. Entities are sent in id order so the continuation stays valid
+    /// while the world changes between chunks
+    pub after: SimEntityId,
+}
+
+/// A chunk of the state returned by [`AllStateChunked`]
+#[derive(Clone, Debug)]
+pub struct StateChunk {
+    pub state: SimState,
+    /// Where to continue from to get the next chunk. None if this was the last chunk
+    pub continuation: Option<StateContinuation>,
+}
+
+/// Returns the same state as [`AllState`], or [`AllStateFor`] if `for_player` is set, split into
+/// bounded chunks so large worlds don't have to be serialized all at once. Every chunk holds at
+/// least one entity. Resources and despawns are only sent in the first chunk
+pub struct AllStateChunked {
+    pub for_player: Option<usize>,
+    /// The maximum number of entities in a chunk
+    pub max_entities: Option<usize>,
+    /// The maximum size of the serialized components in a chunk, in bytes
+    pub max_bytes: Option<usize>,
+    /// Where the previous chunk left off, None to get the first chunk
+    pub continuation: Option<StateContinuation>,
+}
+
+impl SimRequest for AllStateChunked {
+    type Output = StateChunk;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.state.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for AllStateChunked {
+    type Output = StateChunk;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+        let after = self.continuation.map(|continuation| continuation.after);
+
+        let query = sim_world.read_queries.saveable_entities(&sim_world.world);
+        let mut entities: Vec<(SimEntityId, _)> = query
+            .iter_manual(&sim_world.world)
+            .filter_map(|(entity, saveable_components)| {
+                let id = *sim_world.world.get::<SimEntityId>(entity)?;
+                after
+                    .is_none_or(|after| id > after)
+                    .then_some((id, (entity, saveable_components)))
+            })
+            .collect();
+        entities.sort_unstable_by_key(|(id, _)| *id);
+
+        let mut sent = 0;
+        let mut bytes = 0;
+        let mut continuation = None;
+        for (id, (entity, saveable_components)) in entities.iter() {
+            if sent > 0
+                && (self.max_entities.is_some_and(|max| sent >= max)
+                    || self.max_bytes.is_some_and(|max| bytes >= max))
+            {
+                break;
+            }
+            continuation = Some(StateContinuation { after: *id });
+            if self
+                .for_player
+                .is_some_and(|player| !is_entity_relevant(&sim_world.world, player, *entity))
+            {
+                continue;
+            }
+            let counts = (state.players.len(), state.entities.len());
+            if !push_entity_ref_state(
+                &sim_world.registry,
+                &sim_world.world,
+                *entity,
+                saveable_components.as_ref(),
+                &mut state,
+            ) {
+                continue;
             }
+            sent += 1;
+            bytes += state.players[counts.0..]
+                .iter()
+                .flat_map(|player| player.components.iter())
+                .chain(
+                    state.entities[counts.1..]
+                        .iter()
+                        .flat_map(|entity| entity.components.iter()),
+                )
+                .map(|component| component.component.len())
+                .sum::<usize>();
         }
+        if continuation.is_some_and(|continuation| {
+            entities
+                .last()
+                .is_some_and(|(id, _)| *id == continuation.after)
+        }) {
+            continuation = None;
+        }
+
+        if self.continuation.is_none() {
+            push_despawns(sim_world, self.for_player, &mut state);
+            push_resources(sim_world, &mut state);
+        }
+
+        StateChunk {
+            state,
+            continuation,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Component, Reflect, Resource};
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        requests::state_dif::StateDif,
+        testing::{
+            fixtures::{bincode_save_id, TestComponent},
+            test_game,
+        },
+    };
+
+    use super::{AllState, AllStateChunked, EntitiesOnlyState, ResourcesState};
+
+    #[derive(Default, Resource, Reflect, Serialize, Deserialize)]
+    struct TurnTimer(u32);
 
-        sim_world
+    bincode_save_id!(TurnTimer, 26);
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct Bookkeeping(u32);
+
+    bincode_save_id!(Bookkeeping, 27);
+
+    #[test]
+    fn test_excluded_components() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.register_component::<Bookkeeping>().unwrap();
+        game.exclude_component::<Bookkeeping>();
+        let mut sim = game.build_standalone();
+        sim.sim_world
             .world
-            .resource_scope(|_, mut despawned_objects: Mut<TrackedDespawns>| {
-                for (id, _) in despawned_objects.despawned_objects.iter_mut() {
-                    state.despawned_objects.push(*id);
-                }
+            .spawn((TestComponent(0), Bookkeeping(1)));
+        sim.simulate();
+
+        for state in [
+            sim.request(AllState),
+            sim.request(StateDif { for_player: 0 }),
+        ] {
+            assert_eq!(state.entities.len(), 1);
+            let ids: Vec<_> = state.entities[0]
+                .components
+                .iter()
+                .map(|component| component.id)
+                .collect();
+            assert_eq!(ids, vec![25]);
+        }
+    }
+
+    #[test]
+    fn test_resources_and_entities_only_state() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.register_resource::<TurnTimer>().unwrap();
+        let mut sim = game.build_standalone();
+        sim.sim_world.world.spawn(TestComponent(0));
+        sim.sim_world.world.insert_resource(TurnTimer(30));
+        sim.simulate();
+
+        let resources = sim.request(ResourcesState { resources: None });
+        assert_eq!(resources.resources.len(), 1);
+        assert!(resources.entities.is_empty());
+        let resources = sim.request(ResourcesState {
+            resources: Some(vec![26]),
+        });
+        assert_eq!(resources.resources.len(), 1);
+
+        let entities = sim.request(EntitiesOnlyState { for_player: None });
+        assert_eq!(entities.entities.len(), 1);
+        assert!(entities.resources.is_empty());
+    }
+
+    #[test]
+    fn test_all_state_cache() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_all_state_cache();
+        let mut sim = game.build_standalone();
+        sim.sim_world.world.spawn(TestComponent(0));
+        sim.simulate();
+
+        assert_eq!(sim.request(AllState).entities.len(), 1);
+        sim.sim_world.world.spawn(TestComponent(1));
+        assert_eq!(sim.request(AllState).entities.len(), 1);
+        sim.simulate();
+        assert_eq!(sim.request(AllState).entities.len(), 2);
+
+        sim.sim_world.world.spawn(TestComponent(2));
+        sim.sim_world.all_state_cache.as_ref().unwrap().invalidate();
+        assert_eq!(sim.request(AllState).entities.len(), 3);
+    }
+
+    #[test]
+    fn test_chunked_state() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+        for index in 0..5 {
+            sim.sim_world.world.spawn(TestComponent(index));
+        }
+        sim.simulate();
+
+        let mut chunks = 0;
+        let mut entities = vec![];
+        let mut continuation = None;
+        loop {
+            let chunk = sim.request(AllStateChunked {
+                for_player: None,
+                max_entities: Some(2),
+                max_bytes: None,
+                continuation,
             });
-        sim_world.world.resource_scope(
-            |world, mut resource_change_tracking: Mut<ResourceChangeTracking>| {
-                for (id, _) in resource_change_tracking.resources.iter_mut() {
-                    if let Some(resource_state) = sim_world.registry.serialize_resource(id, &world)
-                    {
-                        state.resources.push(resource_state);
-                    }
-                }
-            },
-        );
+            chunks += 1;
+            assert!(chunk.state.entities.len() <= 2);
+            entities.extend(chunk.state.entities.iter().map(|entity| entity.entity));
+            continuation = chunk.continuation;
+            if continuation.is_none() {
+                break;
+            }
+        }
 
-        state
+        let mut all: Vec<_> = sim
+            .request(AllState)
+            .entities
+            .iter()
+            .map(|entity| entity.entity)
+            .collect();
+        all.sort();
+        assert_eq!(entities, all);
+        assert_eq!(chunks, 3);
     }
 }
@@ -0,0 +1,192 @@
+use bevy::reflect::TypeRegistry;
+
+use crate::{
+    command::{CommandHistoryFilter, GameCommandMeta, GameCommands},
+    player::Player,
+    saving::SimFormat,
+    SimWorld,
+};
+
+use super::{SimRequest, SimRequestReadOnly};
+
+/// A single executed command from the [`GameCommands`] history
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandHistoryEntry {
+    /// The reflected type path of the command
+    pub type_name: String,
+    /// The player that issued the command, if any
+    pub issued_by: Option<Player>,
+    /// The sim tick the command was executed at
+    pub tick: u64,
+    /// The order the command was executed in within its tick
+    pub sequence: u32,
+    /// The command encoded with [`GameCommandMeta::save`] in the format of the sim, or the reason it
+    /// couldn't be encoded. Commands must be registered to be encoded, see
+    /// [`GameBuilder::register_command`](crate::game_builder::GameBuilder::register_command)
+    pub payload: Result<Vec<u8>, String>,
+}
+
+impl CommandHistoryEntry {
+    pub fn new(
+        command: &GameCommandMeta,
+        type_registry: &TypeRegistry,
+        format: SimFormat,
+    ) -> CommandHistoryEntry {
+        CommandHistoryEntry {
+            type_name: command.command.reflect_type_path().to_string(),
+            issued_by: command.issued_by,
+            tick: command.tick,
+            sequence: command.sequence,
+            payload: command
+                .save(type_registry, format)
+                .map(|saved| saved.command),
+        }
+    }
+}
+
+/// Returns every command in the [`GameCommands`] history of the sim that was executed at or after
+/// the given tick, in execution order
+pub struct CommandHistoryRequest {
+    pub since_tick: u64,
+}
+
+impl SimRequest for CommandHistoryRequest {
+    type Output = Vec<CommandHistoryEntry>;
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for CommandHistoryRequest {
+    type Output = Vec<CommandHistoryEntry>;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        history_entries(sim_world, |game_commands| {
+            game_commands.history.since_tick(self.since_tick).collect()
+        })
+    }
+}
+
+/// Returns every command in the [`GameCommands`] history of the sim that matches the given filter,
+/// in execution order. Useful for battle logs and debugging
+pub struct FilteredCommandHistory {
+    pub filter: CommandHistoryFilter,
+}
+
+impl SimRequest for FilteredCommandHistory {
+    type Output = Vec<CommandHistoryEntry>;
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for FilteredCommandHistory {
+    type Output = Vec<CommandHistoryEntry>;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        history_entries(sim_world, |game_commands| {
+            game_commands.history.query(&self.filter).collect()
+        })
+    }
+}
+
+/// Returns an entry for every command the given function selects from the history of the sim
+fn history_entries<'a>(
+    sim_world: &'a SimWorld,
+    select: impl FnOnce(&'a GameCommands) -> Vec<&'a GameCommandMeta>,
+) -> Vec<CommandHistoryEntry> {
+    let Some(game_commands) = sim_world.world.get_resource::<GameCommands>() else {
+        return vec![];
+    };
+    let type_registry = sim_world.command_type_registry();
+    let type_registry = type_registry.read();
+    select(game_commands)
+        .into_iter()
+        .map(|command| CommandHistoryEntry::new(command, &type_registry, sim_world.registry.format))
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::{Reflect, World},
+        reflect::TypePath,
+    };
+
+    use crate::{
+        command::{CommandError, CommandHistoryFilter, GameCommand},
+        player::Player,
+        testing::test_game,
+    };
+
+    use super::{CommandHistoryRequest, FilteredCommandHistory};
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Move(u32);
+
+    impl GameCommand for Move {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Unregistered;
+
+    impl GameCommand for Unregistered {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_command_history_request() {
+        let mut game = test_game();
+        game.register_command::<Move>();
+        let mut sim = game.build_standalone();
+        assert!(sim
+            .request(CommandHistoryRequest { since_tick: 0 })
+            .is_empty());
+
+        let player = Player::new(1, false);
+        sim.sim_world.game_commands_mut().add(Move(1));
+        sim.simulate();
+        sim.sim_world
+            .game_commands_mut()
+            .add_from_player(Move(2), player);
+        sim.sim_world.game_commands_mut().add(Unregistered);
+        sim.simulate();
+
+        let history = sim.request(CommandHistoryRequest { since_tick: 0 });
+        assert_eq!(history.len(), 3);
+        let moved = history
+            .iter()
+            .find(|entry| entry.issued_by == Some(player))
+            .unwrap();
+        let unregistered = history
+            .iter()
+            .find(|entry| entry.type_name == Unregistered::type_path())
+            .unwrap();
+        assert_eq!(history[0].type_name, Move::type_path());
+        assert_eq!(history[0].issued_by, None);
+        assert!(history[0].tick < moved.tick);
+        assert_eq!(moved.tick, unregistered.tick);
+        assert_ne!(moved.sequence, unregistered.sequence);
+        let first = history[0].payload.as_ref().unwrap();
+        assert!(!first.is_empty());
+        assert_ne!(first, moved.payload.as_ref().unwrap());
+        assert!(unregistered.payload.is_err());
+
+        let since = sim.request(CommandHistoryRequest {
+            since_tick: moved.tick,
+        });
+        assert_eq!(since, history[1..].to_vec());
+
+        let filtered = sim.request(FilteredCommandHistory {
+            filter: CommandHistoryFilter::new().issued_by(1),
+        });
+        assert_eq!(filtered, vec![moved.clone()]);
+    }
+}
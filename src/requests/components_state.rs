@@ -0,0 +1,128 @@
+use bevy::prelude::Entity;
+
+use crate::{
+    change_detection::{ChangeSet, ReplicationIgnore},
+    entity_id::SimEntityId,
+    interest::is_entity_relevant,
+    player::Player,
+    saving::SimComponentId,
+    SimWorld,
+};
+
+use super::{EntityState, SimRequest, SimRequestReadOnly, SimState};
+
+/// Returns the state of only the given components on every entity that has at least one of them,
+/// regardless of their changed status. Use this for consumers that only need part of the state,
+/// such as a minimap that only needs positions. Player entities, and entities that aren't relevant
+/// to `for_player` if it is set, are left out
+pub struct ComponentsState {
+    pub components: Vec<SimComponentId>,
+    pub for_player: Option<usize>,
+}
+
+impl SimRequest for ComponentsState {
+    type Output = SimState;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for ComponentsState {
+    type Output = SimState;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+        let world = &sim_world.world;
+        let change_set = world.get_resource::<ChangeSet>();
+
+        let query = sim_world.read_queries.saveable_entities(world);
+
+        for (entity, saveable_components) in query.iter_manual(world) {
+            if !self.is_included(sim_world, entity) {
+                continue;
+            }
+            let Some(id) = world.get::<SimEntityId>(entity) else {
+                continue;
+            };
+            let components = sim_world.registry.serialize_entity_components(
+                saveable_components.as_ref(),
+                world,
+                entity,
+                &self.components,
+            );
+            if components.is_empty() {
+                continue;
+            }
+            state.entities.push(EntityState {
+                entity: *id,
+                components,
+                component_deltas: vec![],
+                changed_tick: change_set
+                    .and_then(|change_set| change_set.get(entity))
+                    .map(|changed| changed.tick)
+                    .unwrap_or_default(),
+                is_new: false,
+                changed_components: vec![],
+            });
+        }
+
+        state
+    }
+}
+
+impl ComponentsState {
+    fn is_included(&self, sim_world: &SimWorld, entity: Entity) -> bool {
+        let world = &sim_world.world;
+        world.get::<ReplicationIgnore>(entity).is_none()
+            && world.get::<Player>(entity).is_none()
+            && self
+                .for_player
+                .is_none_or(|player| is_entity_relevant(world, player, entity))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::testing::{
+        fixtures::{bincode_save_id, TestComponent},
+        test_game,
+    };
+
+    use super::ComponentsState;
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct Health(u32);
+
+    bincode_save_id!(Health, 26);
+
+    #[test]
+    fn test_components_state() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.register_component::<Health>().unwrap();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn((TestComponent(0), Health(5)));
+        sim.sim_world.world.spawn(Health(5));
+        sim.simulate();
+
+        let state = sim.request(ComponentsState {
+            components: vec![25],
+            for_player: None,
+        });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].components.len(), 1);
+        assert_eq!(state.entities[0].components[0].id, 25);
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::Entity;
+
+use crate::{interest::is_entity_relevant, SimWorld};
+
+use super::{push_entity_ref_state, SimRequest, SimRequestReadOnly, SimState};
+
+/// Returns the full state of just the given entities regardless of their changed status, for
+/// targeted refreshes such as after a failed prediction. Entities that don't exist, are being
+/// despawned, or aren't relevant to `for_player` if it is set are left out
+pub struct EntitiesState {
+    pub entities: Vec<Entity>,
+    pub for_player: Option<usize>,
+}
+
+impl SimRequest for EntitiesState {
+    type Output = SimState;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for EntitiesState {
+    type Output = SimState;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+
+        let query = sim_world.read_queries.saveable_entities(&sim_world.world);
+
+        for entity in self.entities.iter() {
+            let Ok((entity, saveable_components)) = query.get_manual(&sim_world.world, *entity)
+            else {
+                continue;
+            };
+            if self
+                .for_player
+                .is_some_and(|player| !is_entity_relevant(&sim_world.world, player, entity))
+            {
+                continue;
+            }
+            push_entity_ref_state(
+                &sim_world.registry,
+                &sim_world.world,
+                entity,
+                saveable_components.as_ref(),
+                &mut state,
+            );
+        }
+
+        state
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::{
+        entity_id::SimEntityId,
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    use super::EntitiesState;
+
+    #[test]
+    fn test_entities_state() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(TestComponent(0));
+        let target = sim.sim_world.world.spawn(TestComponent(1)).id();
+        sim.simulate();
+        sim.simulate();
+        let target_id = *sim.sim_world.world.get::<SimEntityId>(target).unwrap();
+
+        let state = sim.request(EntitiesState {
+            entities: vec![target],
+            for_player: None,
+        });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, target_id);
+        assert_eq!(state.entities[0].components.len(), 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! Streaming state extraction. [`SimWorld::stream_entities`] returns an [`EntityStateStream`]
+//! that serializes one entity each time it is advanced, so a network layer can start writing
+//! packets before the whole state has been extracted.
+
+use std::sync::RwLockReadGuard;
+
+use bevy::prelude::Entity;
+
+use crate::{interest::is_entity_relevant, player::Player, SimWorld};
+
+use super::{push_entity_ref_state, EntityState, SaveableEntitiesQuery, SimState};
+
+/// Iterator over the [`EntityState`] of every entity in the sim world, or only the entities relevant
+/// to a player, serialized lazily. Player entities and resources aren't included, request them
+/// separately with [`AllState`](super::all_state::AllState) or
+/// [`ResourcesState`](super::all_state::ResourcesState). Holds shared access to the cached saveable
+/// entities query of the sim world until it is dropped
+pub struct EntityStateStream<'w> {
+    sim_world: &'w SimWorld,
+    query: RwLockReadGuard<'w, SaveableEntitiesQuery>,
+    entities: std::vec::IntoIter<Entity>,
+}
+
+impl<'w> EntityStateStream<'w> {
+    pub fn new(sim_world: &'w SimWorld, for_player: Option<usize>) -> EntityStateStream<'w> {
+        let world = &sim_world.world;
+        let query = sim_world.read_queries.saveable_entities(world);
+        let entities: Vec<Entity> = query
+            .iter_manual(world)
+            .map(|(entity, _)| entity)
+            .filter(|entity| {
+                world.get::<Player>(*entity).is_none()
+                    && for_player.is_none_or(|player| is_entity_relevant(world, player, *entity))
+            })
+            .collect();
+        EntityStateStream {
+            sim_world,
+            query,
+            entities: entities.into_iter(),
+        }
+    }
+}
+
+impl Iterator for EntityStateStream<'_> {
+    type Item = EntityState;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entity = self.entities.next()?;
+            let Ok((entity, saveable_components)) =
+                self.query.get_manual(&self.sim_world.world, entity)
+            else {
+                continue;
+            };
+            let mut state = SimState::default();
+            push_entity_ref_state(
+                &self.sim_world.registry,
+                &self.sim_world.world,
+                entity,
+                saveable_components.as_ref(),
+                &mut state,
+            );
+            if let Some(entity_state) = state.entities.pop() {
+                return Some(entity_state);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.entities.len()))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::{
+        requests::all_state::AllState,
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    #[test]
+    fn test_stream_entities() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.add_player(false);
+        let mut sim = game.build_standalone();
+        for index in 0..3 {
+            sim.sim_world.world.spawn(TestComponent(index));
+        }
+        sim.simulate();
+
+        let mut streamed: Vec<_> = sim
+            .sim_world
+            .stream_entities(None)
+            .map(|entity_state| entity_state.entity)
+            .collect();
+        let mut all: Vec<_> = sim
+            .request(AllState)
+            .entities
+            .iter()
+            .map(|entity_state| entity_state.entity)
+            .collect();
+        streamed.sort();
+        all.sort();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed, all);
+    }
+
+    #[test]
+    fn test_concurrent_read_requests() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+        sim.sim_world.world.spawn(TestComponent(0));
+        sim.simulate();
+
+        let mut stream = sim.sim_world.stream_entities(None);
+        assert_eq!(sim.sim_world.request_ref(AllState).entities.len(), 1);
+        assert!(stream.next().is_some());
+        let entities = std::thread::scope(|scope| {
+            scope
+                .spawn(|| sim.sim_world.request_ref(AllState).entities.len())
+                .join()
+                .unwrap()
+        });
+        assert_eq!(entities, 1);
+        assert!(stream.next().is_none());
+    }
+}
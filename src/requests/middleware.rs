@@ -0,0 +1,133 @@
+//! Request instrumentation. Middleware added with
+//! [`GameBuilder::add_request_middleware`](crate::game_builder::GameBuilder::add_request_middleware)
+//! sees every request made through [`SimWorld::request`](crate::SimWorld::request), along with how
+//! long it took and the size of its output. Requests made with
+//! [`SimWorld::request_ref`](crate::SimWorld::request_ref) aren't seen as middleware needs exclusive
+//! access to the sim world.
+//!
+//! [`RequestMetricsRecorder`] is a middleware that records per request type timings into the
+//! [`RequestMetrics`] resource of the sim world, enabled with
+//! [`GameBuilder::enable_request_metrics`](crate::game_builder::GameBuilder::enable_request_metrics).
+
+use std::time::Duration;
+
+use bevy::{
+    prelude::{Resource, World},
+    utils::HashMap,
+};
+
+/// A request made through [`SimWorld::request`](crate::SimWorld::request)
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestRecord {
+    /// The type name of the request
+    pub request_type: &'static str,
+    /// How long the request took
+    pub duration: Duration,
+    /// The size of the output in bytes, see [`SimRequest::output_bytes`](super::SimRequest::output_bytes)
+    pub output_bytes: Option<usize>,
+}
+
+/// Middleware run around every request made through [`SimWorld::request`](crate::SimWorld::request)
+pub trait RequestMiddleware: Send + Sync + 'static {
+    /// Called before the request with the given type name is made
+    fn before_request(&mut self, _world: &mut World, _request_type: &'static str) {}
+
+    /// Called after a request was made
+    fn after_request(&mut self, world: &mut World, record: &RequestRecord);
+}
+
+/// The metrics of every request of a single type
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RequestTypeMetrics {
+    /// The number of requests made
+    pub count: u64,
+    /// The time spent on all requests
+    pub total_duration: Duration,
+    /// The time spent on the slowest request
+    pub max_duration: Duration,
+    /// The size of every output with a size, in bytes
+    pub total_bytes: usize,
+}
+
+impl RequestTypeMetrics {
+    /// Returns the average time spent on a request
+    pub fn average_duration(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let nanos = self.total_duration.as_nanos() / u128::from(self.count);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+}
+
+/// Resource holding the metrics of every request type made since it was inserted, recorded by
+/// [`RequestMetricsRecorder`]
+#[derive(Default, Clone, Debug, PartialEq, Resource)]
+pub struct RequestMetrics {
+    pub requests: HashMap<&'static str, RequestTypeMetrics>,
+}
+
+impl RequestMetrics {
+    /// Returns the metrics of the request type R
+    pub fn get<R>(&self) -> Option<&RequestTypeMetrics> {
+        self.requests.get(std::any::type_name::<R>())
+    }
+}
+
+/// [`RequestMiddleware`] that records every request into the [`RequestMetrics`] resource of the sim
+/// world, if it has one
+#[derive(Default, Clone, Copy, Debug)]
+pub struct RequestMetricsRecorder;
+
+impl RequestMiddleware for RequestMetricsRecorder {
+    fn after_request(&mut self, world: &mut World, record: &RequestRecord) {
+        let Some(mut metrics) = world.get_resource_mut::<RequestMetrics>() else {
+            return;
+        };
+        let metrics = metrics.requests.entry(record.request_type).or_default();
+        metrics.count += 1;
+        metrics.total_duration += record.duration;
+        metrics.max_duration = metrics.max_duration.max(record.duration);
+        metrics.total_bytes += record.output_bytes.unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        requests::{all_state::AllState, state_dif::StateDif},
+        testing::test_game,
+    };
+
+    use std::time::Duration;
+
+    use super::{RequestMetrics, RequestTypeMetrics};
+
+    #[test]
+    fn test_request_metrics() {
+        let mut game = test_game();
+        game.add_player(true);
+        game.enable_request_metrics();
+        let mut sim = game.build_standalone();
+        sim.simulate();
+
+        sim.request(AllState);
+        sim.request(AllState);
+        sim.request(StateDif { for_player: 0 });
+
+        let metrics = sim.sim_world.world.resource::<RequestMetrics>();
+        assert_eq!(metrics.get::<AllState>().unwrap().count, 2);
+        assert_eq!(metrics.get::<StateDif>().unwrap().count, 1);
+        assert_eq!(metrics.requests.len(), 2);
+    }
+
+    #[test]
+    fn test_average_duration_over_u32_requests() {
+        let metrics = RequestTypeMetrics {
+            count: u64::from(u32::MAX) + 1,
+            total_duration: Duration::from_secs(1 << 32),
+            ..Default::default()
+        };
+        assert_eq!(metrics.average_duration(), Duration::from_secs(1));
+    }
+}
@@ -1,46 +1,498 @@
-use bevy::prelude::Entity;
+use bevy::{
+    ecs::query::QueryState,
+    prelude::{Entity, Reflect, Resource, Without, World},
+    utils::HashMap,
+};
+use bevy_trait_query::ReadTraits;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock, RwLockReadGuard,
+    },
+};
 
 use crate::{
+    change_detection::{
+        ChangeSet, DespawnTracked, PlayerMask, ReplicationIgnore, SimRemovedComponents,
+        TrackedDespawns,
+    },
+    entity_id::SimEntityId,
     player::Player,
-    saving::{ComponentBinaryState, SimResourceId},
+    saving::{
+        ComponentBinaryState, GameSerDeRegistry, SaveId, SimComponentId, SimResourceId,
+        SimSerializer,
+    },
     SimWorld,
 };
 
+pub mod acks;
 pub mod all_state;
+pub mod command_history;
+pub mod components_state;
+pub mod entities_state;
+pub mod entity_stream;
+pub mod middleware;
 pub mod state_dif;
+pub mod state_since;
+pub mod text_state;
+pub mod world_hash;
 
 /// Trait used to make requests into the game world
 pub trait SimRequest {
     type Output;
     fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output;
+
+    /// Returns the size of the given output in bytes, recorded by
+    /// [`RequestMiddleware`](middleware::RequestMiddleware). None if the output has no meaningful
+    /// size
+    fn output_bytes(_output: &Self::Output) -> Option<usize> {
+        None
+    }
+}
+
+/// Trait used to make requests into the game world that only read from it. Because they only need
+/// shared access to the [`SimWorld`], multiple read only requests can run concurrently.
+pub trait SimRequestReadOnly {
+    type Output;
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output;
+}
+
+/// Query over every entity that isn't being despawned, along with its [`SaveId`] components
+pub type SaveableEntitiesQuery =
+    QueryState<(Entity, Option<&'static dyn SaveId>), Without<DespawnTracked>>;
+
+/// Queries cached on the [`SimWorld`] that are used by [`SimRequestReadOnly`] requests. Each query
+/// is kept behind a lock that is only held exclusively while updating its archetypes after new
+/// archetypes were added to the world, so read only requests run concurrently.
+pub struct SimReadQueries {
+    saveable_entities: RwLock<SaveableEntitiesQuery>,
+    /// The number of archetypes in the world when the queries were last updated
+    archetypes: AtomicUsize,
+}
+
+impl SimReadQueries {
+    pub fn new(world: &mut World) -> SimReadQueries {
+        SimReadQueries {
+            saveable_entities: RwLock::new(SaveableEntitiesQuery::new(world)),
+            archetypes: AtomicUsize::new(world.archetypes().len()),
+        }
+    }
+
+    /// Updates and returns the query over every saveable entity. Iterate it with
+    /// [`QueryState::iter_manual`]. The query is only locked exclusively if archetypes were added
+    /// to the world since it was last updated
+    pub fn saveable_entities(&self, world: &World) -> RwLockReadGuard<'_, SaveableEntitiesQuery> {
+        let archetypes = world.archetypes().len();
+        if self.archetypes.load(Ordering::Acquire) != archetypes {
+            self.saveable_entities
+                .write()
+                .expect("SimReadQueries lock was poisoned")
+                .update_archetypes(world);
+            self.archetypes.store(archetypes, Ordering::Release);
+        }
+        self.saveable_entities
+            .read()
+            .expect("SimReadQueries lock was poisoned")
+    }
 }
 
 /// Contains the state of a player, identified by a [`Player`] component
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerState {
     pub player_id: Player,
     pub components: Vec<ComponentBinaryState>,
+    /// The sim tick the player entity last changed at
+    pub changed_tick: u64,
 }
 
 /// Contains the state of a [`Resource`]
-#[derive(Debug)]
+#[derive(Debug, Clone, Reflect, Serialize, Deserialize)]
 pub struct ResourceState {
     pub resource_id: SimResourceId,
+    /// The schema version the resource was serialized with, see [`version`](crate::saving::version)
+    pub version: u16,
     pub resource: Vec<u8>,
+    /// The sim tick the resource last changed at
+    pub changed_tick: u64,
 }
 
 /// Contains an entities state, identified via its [`Entity`] component
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityState {
-    pub entity: Entity,
+    pub entity: SimEntityId,
     pub components: Vec<ComponentBinaryState>,
+    /// Components sent as only their changed fields, applied onto the components the entity
+    /// already has, see [`field_delta`](crate::saving::field_delta)
+    pub component_deltas: Vec<ComponentBinaryState>,
+    /// The sim tick the entity last changed at
+    pub changed_tick: u64,
+    /// True if the player this state was made for hasn't been sent the entity before, or it was
+    /// despawned for them since. Always false in states that aren't made for a player
+    pub is_new: bool,
+    /// The registered components that changed since the entity was last fully seen. Components of
+    /// the entity not listed are only being resent
+    pub changed_components: Vec<SimComponentId>,
 }
 
 /// A list of state
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct SimState {
     pub players: Vec<PlayerState>,
     pub resources: Vec<ResourceState>,
     pub entities: Vec<EntityState>,
-    pub despawned_objects: Vec<Entity>,
+    pub despawned_objects: Vec<SimEntityId>,
+    /// The sim tick each entity in `despawned_objects` was despawned at, or stopped being relevant
+    /// to the player at
+    pub despawn_ticks: Vec<(SimEntityId, u64)>,
+    /// Registered components removed from entities that are still alive. Each entity stays listed
+    /// until the component is added back
+    pub removed_components: Vec<(SimEntityId, SimComponentId)>,
+    /// The sequence number this state was issued with. Only set for requests made for a specific
+    /// player, see [`StateSequences`]
+    pub sequence: Option<u64>,
+    /// The sim tick this state was produced at, see [`SimTick`](crate::runner::SimTick)
+    pub tick: u64,
+}
+
+impl SimState {
+    /// Returns the number of bytes of component and resource payloads in the state
+    pub fn payload_bytes(&self) -> usize {
+        let components = |components: &[ComponentBinaryState]| -> usize {
+            components
+                .iter()
+                .map(|component| component.component.len())
+                .sum()
+        };
+        self.players
+            .iter()
+            .map(|player| components(&player.components))
+            .sum::<usize>()
+            + self
+                .entities
+                .iter()
+                .map(|entity| components(&entity.components) + components(&entity.component_deltas))
+                .sum::<usize>()
+            + self
+                .resources
+                .iter()
+                .map(|resource| resource.resource.len())
+                .sum::<usize>()
+    }
+
+    /// Returns the component of type T of the given entity in the state. Components are matched by
+    /// [`SaveId::save_id_const`] and decoded with bincode, the encoding used by
+    /// [`SaveId::to_binary`] implementations. Payloads of registries with compression or payload
+    /// checksums need to be deserialized with the [`GameSerDeRegistry`] instead
+    pub fn component<T: SaveId + DeserializeOwned>(&self, entity: SimEntityId) -> Option<T> {
+        self.entities
+            .iter()
+            .find(|entity_state| entity_state.entity == entity)
+            .and_then(|entity_state| decode_component(&entity_state.components))
+    }
+
+    /// Returns every entity in the state with a component of type T, along with the component. See
+    /// [`SimState::component`]
+    pub fn components<T: SaveId + DeserializeOwned>(
+        &self,
+    ) -> impl Iterator<Item = (SimEntityId, T)> + '_ {
+        self.entities.iter().filter_map(|entity_state| {
+            decode_component(&entity_state.components)
+                .map(|component| (entity_state.entity, component))
+        })
+    }
+
+    /// Returns the component of type T of the given player in the state. See
+    /// [`SimState::component`]
+    pub fn player_component<T: SaveId + DeserializeOwned>(&self, player_id: usize) -> Option<T> {
+        self.players
+            .iter()
+            .find(|player_state| player_state.player_id.id() == player_id)
+            .and_then(|player_state| decode_component(&player_state.components))
+    }
+
+    /// Fills [`SimState::despawn_ticks`] for every despawned entity with the tick it was despawned
+    /// at, falling back to the tick of the state for entities that aren't tracked despawns
+    pub fn stamp_despawns(&mut self, world: &World) {
+        let despawns = world.get_resource::<TrackedDespawns>();
+        self.despawn_ticks = self
+            .despawned_objects
+            .iter()
+            .map(|id| {
+                let tick = despawns
+                    .and_then(|despawns| despawns.despawned_objects.get(id))
+                    .map_or(self.tick, |changed| changed.tick);
+                (*id, tick)
+            })
+            .collect();
+    }
+
+    /// Sets [`EntityState::is_new`] for every entity in the state and records them and the despawns
+    /// in the [`SentEntities`] of the world
+    pub fn stamp_new_entities(&mut self, world: &mut World, for_player: usize) {
+        let Some(mut sent) = world.get_resource_mut::<SentEntities>() else {
+            return;
+        };
+        for id in self.despawned_objects.iter() {
+            sent.forget(*id, for_player);
+        }
+        for entity_state in self.entities.iter_mut() {
+            entity_state.is_new = sent.mark_sent(entity_state.entity, for_player);
+        }
+    }
+
+    /// Encodes the whole state into a single payload using the registries format and
+    /// [`state_compression`](GameSerDeRegistry::state_compression), followed by a checksum if
+    /// [`payload_checksums`](GameSerDeRegistry::payload_checksums) is set
+    pub fn encode(&self, registry: &GameSerDeRegistry) -> Option<Vec<u8>> {
+        let data = registry.format.encode(self)?;
+        Some(registry.append_checksum(registry.state_compression.compress(data)))
+    }
+
+    /// Decodes a state encoded with [`SimState::encode`] using the same registry settings. Returns
+    /// None if the payload fails its checksum
+    pub fn decode(data: &[u8], registry: &GameSerDeRegistry) -> Option<SimState> {
+        let data = registry.verify_checksum(data).ok()?;
+        let data = registry.state_compression.decompress(data)?;
+        registry.format.decode(&data)
+    }
+
+    /// Writes the state to the given writer as a single frame, the length of the encoded state as a
+    /// little endian u32 followed by the state encoded with [`SimState::encode`]. Frames can be
+    /// written back to back onto a stream and read with [`SimState::read_framed`]
+    pub fn write_framed(
+        &self,
+        mut writer: impl Write,
+        registry: &GameSerDeRegistry,
+    ) -> io::Result<()> {
+        let data = self
+            .encode(registry)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "failed to encode state"))?;
+        let length = u32::try_from(data.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "state is too large to frame")
+        })?;
+        writer.write_all(&length.to_le_bytes())?;
+        writer.write_all(&data)
+    }
+
+    /// Reads a single frame written with [`SimState::write_framed`] from the given reader, leaving
+    /// the reader at the start of the next frame. Frames longer than `max_length` bytes return
+    /// [`io::ErrorKind::InvalidData`] without reading their body
+    pub fn read_framed(
+        mut reader: impl Read,
+        registry: &GameSerDeRegistry,
+        max_length: usize,
+    ) -> io::Result<SimState> {
+        let mut length = [0u8; 4];
+        reader.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length > max_length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame is longer than the maximum length",
+            ));
+        }
+        let mut data = vec![0u8; length];
+        reader.read_exact(&mut data)?;
+        SimState::decode(&data, registry)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "failed to decode state"))
+    }
+
+    /// Decodes the first frame in the given buffer and returns it along with the rest of the buffer.
+    /// Returns None if the buffer doesn't hold a whole frame yet or the frame fails to decode
+    pub fn split_framed<'a>(
+        data: &'a [u8],
+        registry: &GameSerDeRegistry,
+    ) -> Option<(SimState, &'a [u8])> {
+        let length = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        let end = length.checked_add(4)?;
+        let frame = data.get(4..end)?;
+        Some((SimState::decode(frame, registry)?, &data[end..]))
+    }
+}
+
+/// Resource inserted into the sim world that tracks which players have been sent each entity, used to
+/// set [`EntityState::is_new`]
+#[derive(Default, Clone, Eq, Debug, PartialEq, Resource)]
+pub struct SentEntities {
+    pub entities: HashMap<SimEntityId, PlayerMask>,
+}
+
+impl SentEntities {
+    /// Records that the given entity was sent to the given player and returns true if it wasn't
+    /// sent to them before
+    pub fn mark_sent(&mut self, entity: SimEntityId, player_id: usize) -> bool {
+        let players = self.entities.entry(entity).or_default();
+        let is_new = !players.contains(player_id);
+        players.insert(player_id);
+        is_new
+    }
+
+    /// Forgets that the given entity was sent to the given player, so it is new to them if sent again
+    pub fn forget(&mut self, entity: SimEntityId, player_id: usize) {
+        if let Some(players) = self.entities.get_mut(&entity) {
+            players.remove(player_id);
+            if players.is_empty() {
+                self.entities.remove(&entity);
+            }
+        }
+    }
+}
+
+/// Resource inserted into the sim world that tracks the sequence numbers issued to each player.
+/// Every player specific state request issues the next sequence for that player, allowing clients
+/// to apply state in order and to detect missed state
+#[derive(Clone, Eq, Debug, PartialEq, Default, Resource)]
+pub struct StateSequences {
+    pub sequences: HashMap<usize, u64>,
+}
+
+impl StateSequences {
+    /// Issues the next sequence number for the given player id and returns it. The first sequence
+    /// issued to a player is 0
+    pub fn issue(&mut self, player_id: usize) -> u64 {
+        let sequence = self
+            .sequences
+            .entry(player_id)
+            .and_modify(|sequence| *sequence += 1)
+            .or_insert(0);
+        *sequence
+    }
+
+    /// Returns the latest sequence number issued to the given player id, if any have been issued
+    pub fn latest(&self, player_id: usize) -> Option<u64> {
+        self.sequences.get(&player_id).copied()
+    }
+}
+
+/// Decodes the component of type T from the given components, see [`SimState::component`]
+fn decode_component<T: SaveId + DeserializeOwned>(
+    components: &[ComponentBinaryState],
+) -> Option<T> {
+    components
+        .iter()
+        .find(|component| component.id == T::save_id_const())
+        .and_then(|component| bincode::deserialize(&component.component).ok())
+}
+
+/// Serializes the given entity and pushes it into the given state, as a [`PlayerState`] if the entity
+/// is a [`Player`] and an [`EntityState`] otherwise. Returns false if the entity doesn't exist, has
+/// no saveable components, is marked [`ReplicationIgnore`], or hasn't been assigned a [`SimEntityId`] yet. Components removed from
+/// the entity are pushed into [`SimState::removed_components`]
+pub fn push_entity_state(
+    world: &mut World,
+    registry: &GameSerDeRegistry,
+    entity: Entity,
+    state: &mut SimState,
+) -> bool {
+    let mut query = world.query::<Option<&dyn SaveId>>();
+    let world: &World = world;
+    let Ok(saveable_components) = query.get(world, entity) else {
+        return false;
+    };
+    push_entity_ref_state(registry, world, entity, saveable_components.as_ref(), state)
+}
+
+/// Serializes the given entity and pushes it into the given state, see [`push_entity_state`]. Use
+/// this when the entities [`SaveId`] components have already been queried
+pub fn push_entity_ref_state(
+    registry: &GameSerDeRegistry,
+    world: &World,
+    entity: Entity,
+    saveable_components: Option<&ReadTraits<'_, dyn SaveId>>,
+    state: &mut SimState,
+) -> bool {
+    if world.get::<ReplicationIgnore>(entity).is_some() {
+        return false;
+    }
+    let components = registry.serialize_entity(saveable_components, world, entity);
+    let removed_count = state.removed_components.len();
+    if let (Some(removed), Some(id)) = (
+        world.get::<SimRemovedComponents>(entity),
+        world.get::<SimEntityId>(entity),
+    ) {
+        for type_id in removed.components.iter() {
+            if let Some(component_id) = registry.component_type_ids.get(type_id) {
+                state.removed_components.push((*id, *component_id));
+            }
+        }
+    }
+    if components.is_empty() {
+        return state.removed_components.len() > removed_count;
+    }
+
+    let change_set = world.get_resource::<ChangeSet>();
+    let changed_tick = change_set
+        .and_then(|change_set| change_set.get(entity))
+        .map(|changed| changed.tick)
+        .unwrap_or_default();
+    if let Some(player) = world.get::<Player>(entity) {
+        state.players.push(PlayerState {
+            player_id: *player,
+            components,
+            changed_tick,
+        });
+    } else {
+        let Some(id) = world.get::<SimEntityId>(entity) else {
+            return false;
+        };
+        state.entities.push(EntityState {
+            entity: *id,
+            components,
+            component_deltas: vec![],
+            changed_tick,
+            is_new: false,
+            changed_components: change_set
+                .map(|change_set| change_set.changed_components(entity))
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|type_id| registry.component_type_ids.get(type_id).copied())
+                .collect(),
+        });
+    }
+    true
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{entity_id::SimEntityId, saving::GameSerDeRegistry};
+
+    use super::SimState;
+
+    #[test]
+    fn test_framed_states() {
+        let registry = GameSerDeRegistry::new();
+        let mut first = SimState {
+            tick: 1,
+            ..Default::default()
+        };
+        first.removed_components.push((SimEntityId(3), 25));
+        let second = SimState {
+            tick: 2,
+            ..Default::default()
+        };
+
+        let mut stream = vec![];
+        first.write_framed(&mut stream, &registry).unwrap();
+        second.write_framed(&mut stream, &registry).unwrap();
+
+        let (decoded, rest) = SimState::split_framed(&stream, &registry).unwrap();
+        assert_eq!(decoded.tick, 1);
+        assert_eq!(decoded.removed_components, first.removed_components);
+        assert!(SimState::split_framed(&rest[..rest.len() - 1], &registry).is_none());
+
+        let mut reader = rest;
+        assert_eq!(
+            SimState::read_framed(&mut reader, &registry, 1024)
+                .unwrap()
+                .tick,
+            2
+        );
+        assert!(reader.is_empty());
+
+        let error = SimState::read_framed(&stream[..], &registry, 4).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(SimState::split_framed(&u32::MAX.to_le_bytes(), &registry).is_none());
+    }
 }
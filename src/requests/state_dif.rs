@@ -1,14 +1,23 @@
-use bevy::prelude::{Entity, Mut, With, Without};
+use bevy::prelude::{Entity, Mut};
+use std::time::Instant;
 
 use crate::{
-    change_detection::{DespawnTracked, ResourceChangeTracking, SimChanged, TrackedDespawns},
-    player::Player,
-    saving::{ComponentBinaryState, SaveId},
+    change_detection::{ChangeSet, DespawnTracked, ResourceChangeTracking, TrackedDespawns},
+    entity_id::SimEntityId,
+    interest::{is_visible_to, update_player_scope, PlayerInterests, ScopeUpdate},
+    saving::{field_delta::DeltaBaselines, priority::ReplicationBudget},
+    stats::ReplicationStats,
+    SimWorld,
 };
 
-use super::{EntityState, PlayerState, SimRequest, SimState};
+use super::{
+    acks::{finish_player_state, PendingState, PendingStateAcks},
+    push_entity_state, SimRequest, SimState,
+};
 
-/// Returns only the state that has changed.
+/// Returns only the state that has changed. When interest management is enabled only the entities
+/// relevant to the player are included, see [`interest`](crate::interest). Entities hidden from the
+/// player by [`SimVisibility`](crate::interest::SimVisibility) are never included
 pub struct StateDif {
     pub for_player: usize,
 }
@@ -16,54 +25,96 @@ pub struct StateDif {
 impl SimRequest for StateDif {
     type Output = SimState;
 
-    fn request(&mut self, sim_world: &mut crate::SimWorld) -> Self::Output {
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        Some(output.payload_bytes())
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        let started = Instant::now();
         let mut state: SimState = SimState {
             players: vec![],
             resources: vec![],
             entities: vec![],
             despawned_objects: vec![],
+            despawn_ticks: vec![],
+            removed_components: vec![],
+            sequence: None,
+            tick: sim_world.tick(),
         };
+        let mut included = PendingState::default();
 
-        let mut query = sim_world
+        let mut changed_entities: Vec<Entity> = vec![];
+        sim_world
             .world
-            .query_filtered::<(&dyn SaveId, Entity, Option<&Player>, &mut SimChanged), (With<SimChanged>, Without<DespawnTracked>)>();
-
-        for (saveable_components, entity, opt_player, mut changed) in
-            query.iter_mut(&mut sim_world.world)
-        {
-            if changed.check_and_register_seen(self.for_player) {
-                continue;
-            }
-            let mut components: Vec<ComponentBinaryState> = vec![];
-
-            if let Some(player) = opt_player {
-                for component in saveable_components.iter() {
-                    if let Some((id, binary)) = component.save() {
-                        components.push(ComponentBinaryState {
-                            id,
-                            component: binary,
-                        });
+            .resource_scope(|world, mut change_set: Mut<ChangeSet>| {
+                for entity in change_set.sorted_entities() {
+                    if world
+                        .get_entity(entity)
+                        .is_none_or(|entity| entity.contains::<DespawnTracked>())
+                    {
+                        continue;
+                    }
+                    if let Some(changed) = change_set.get_mut(entity) {
+                        if !changed.check_and_register_seen(self.for_player) {
+                            changed_entities.push(entity);
+                        }
                     }
                 }
+            });
 
-                state.players.push(PlayerState {
-                    player_id: *player,
-                    components,
-                })
-            } else {
-                for component in saveable_components.iter() {
-                    if let Some((id, binary)) = component.save() {
-                        components.push(ComponentBinaryState {
-                            id,
-                            component: binary,
-                        });
-                    }
+        let scope = update_player_scope(&mut sim_world.world, self.for_player);
+        changed_entities.retain(|entity| {
+            is_visible_to(&sim_world.world.entity(*entity), self.for_player)
+                && scope
+                    .as_ref()
+                    .is_none_or(|scope| scope.is_relevant(&sim_world.world, *entity))
+        });
+        if let Some(scope) = scope.as_ref() {
+            for entity in scope.entered.iter() {
+                if !changed_entities.contains(entity) {
+                    changed_entities.push(*entity);
                 }
+            }
+        }
 
-                state.entities.push(EntityState {
-                    entity: entity,
-                    components,
-                })
+        sim_world
+            .registry
+            .sort_by_priority(&mut sim_world.world, &mut changed_entities);
+        if let Some(budget) = sim_world.world.get_resource::<ReplicationBudget>().copied() {
+            if changed_entities.len() > budget.max_entities {
+                let deferred = changed_entities.split_off(budget.max_entities);
+                defer_entities(sim_world, self.for_player, &deferred, scope.as_ref());
+            }
+        }
+
+        let pending_entities = sim_world
+            .world
+            .get_resource::<PendingStateAcks>()
+            .map(|acks| acks.pending_for(self.for_player).entities)
+            .unwrap_or_default();
+        let mut delta_baselines = sim_world.world.remove_resource::<DeltaBaselines>();
+        for entity in changed_entities {
+            let entity_count = state.entities.len();
+            if push_entity_state(
+                &mut sim_world.world,
+                &sim_world.registry,
+                entity,
+                &mut state,
+            ) {
+                included.entities.push(entity);
+                if let (Some(delta_baselines), Some(entity_state)) = (
+                    delta_baselines.as_mut(),
+                    state.entities.get_mut(entity_count),
+                ) {
+                    sim_world.registry.encode_entity_deltas(
+                        delta_baselines.players.entry(self.for_player).or_default(),
+                        entity_state,
+                        pending_entities.contains(&entity)
+                            || scope
+                                .as_ref()
+                                .is_some_and(|scope| scope.entered.contains(&entity)),
+                    );
+                }
             }
         }
 
@@ -71,26 +122,80 @@ impl SimRequest for StateDif {
             .world
             .resource_scope(|_, mut despawned_objects: Mut<TrackedDespawns>| {
                 for (id, changed) in despawned_objects.despawned_objects.iter_mut() {
-                    if !changed.check_and_register_seen(self.for_player) {
+                    if !changed.check_and_register_seen(self.for_player)
+                        && scope.as_ref().is_none_or(|scope| scope.was_in_scope(id))
+                    {
                         state.despawned_objects.push(*id);
+                        included.despawned_objects.push(*id);
+                        if let Some(delta_baselines) = delta_baselines.as_mut() {
+                            delta_baselines.remove_entity(*id);
+                        }
                     }
                 }
             });
+        for id in scope.iter().flat_map(|scope| scope.left.iter()) {
+            state.despawned_objects.push(*id);
+            included.despawned_objects.push(*id);
+            if let Some(delta_baselines) = delta_baselines.as_mut() {
+                delta_baselines.remove_entity(*id);
+            }
+        }
+        if let Some(delta_baselines) = delta_baselines {
+            sim_world.world.insert_resource(delta_baselines);
+        }
 
         sim_world.world.resource_scope(
             |world, mut resource_change_tracking: Mut<ResourceChangeTracking>| {
-                for (id, changed) in resource_change_tracking.resources.iter_mut() {
-                    if !changed.check_and_register_seen(self.for_player) {
-                        if let Some(resource_state) =
-                            sim_world.registry.serialize_resource(id, &world)
-                        {
-                            state.resources.push(resource_state);
-                        }
+                let tracking = &mut *resource_change_tracking;
+                for (component_id, changed) in tracking.resources.iter_mut() {
+                    let Some(id) = tracking.resource_ids.get_save_id(*component_id) else {
+                        continue;
+                    };
+                    if changed.check_and_register_seen(self.for_player)
+                        || included.resources.contains(id)
+                    {
+                        continue;
+                    }
+                    if let Some(resource_state) = sim_world.registry.serialize_resource(id, world) {
+                        state.resources.push(resource_state);
+                        included.resources.push(*id);
                     }
                 }
             },
         );
 
+        finish_player_state(sim_world, self.for_player, &mut state, included);
+
+        if let Some(mut stats) = sim_world.world.get_resource_mut::<ReplicationStats>() {
+            stats.record_state(self.for_player, state.payload_bytes(), started.elapsed());
+        }
+
         state
     }
 }
+
+/// Marks the given changed entities as unseen by the player, and the given entities that entered
+/// their scope as out of scope, so they are included in the next state
+fn defer_entities(
+    sim_world: &mut SimWorld,
+    for_player: usize,
+    entities: &[Entity],
+    scope: Option<&ScopeUpdate>,
+) {
+    for entity in entities.iter() {
+        if let Some(changed) = sim_world.world.resource_mut::<ChangeSet>().get_mut(*entity) {
+            changed.unregister_seen(for_player);
+        }
+        if !scope.is_some_and(|scope| scope.entered.contains(entity)) {
+            continue;
+        }
+        let Some(id) = sim_world.world.get::<SimEntityId>(*entity).copied() else {
+            continue;
+        };
+        if let Some(mut interests) = sim_world.world.get_resource_mut::<PlayerInterests>() {
+            if let Some(in_scope) = interests.in_scope.get_mut(&for_player) {
+                in_scope.remove(&id);
+            }
+        }
+    }
+}
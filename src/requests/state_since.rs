@@ -0,0 +1,210 @@
+//! Catching up reconnecting players. With
+//! [`GameBuilder::enable_change_history`](crate::game_builder::GameBuilder::enable_change_history)
+//! the sim keeps a bounded [`ChangeHistory`] of what changed in each tick, and [`StateSince`] serves
+//! a player everything that changed after the last tick they received instead of a full
+//! [`AllState`](super::all_state::AllState).
+
+use bevy::utils::HashSet;
+
+use crate::{
+    change_detection::ChangeHistory,
+    entity_id::sim_entity_map,
+    interest::{is_entity_relevant, update_player_scope},
+    SimWorld,
+};
+
+use super::{
+    acks::{finish_player_state, PendingState},
+    push_entity_state, SimRequest, SimState,
+};
+
+/// Returns the current state of everything that changed after the given tick for the given player.
+/// Returns None if change history isn't enabled or no longer reaches back to the given tick, in which
+/// case the player needs a full state
+pub struct StateSince {
+    pub since_tick: u64,
+    pub for_player: usize,
+}
+
+impl SimRequest for StateSince {
+    type Output = Option<SimState>;
+
+    fn output_bytes(output: &Self::Output) -> Option<usize> {
+        output.as_ref().map(SimState::payload_bytes)
+    }
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        let history = sim_world.world.get_resource::<ChangeHistory>()?;
+        if !history.covers(self.since_tick) {
+            return None;
+        }
+        let mut entities: Vec<_> = vec![];
+        let mut despawned_objects: Vec<_> = vec![];
+        let mut resources: HashSet<_> = HashSet::default();
+        for changes in history.since(self.since_tick) {
+            entities.extend(changes.entities.iter().copied());
+            despawned_objects.extend(changes.despawned_objects.iter().copied());
+            resources.extend(changes.resources.iter().copied());
+        }
+        entities.sort();
+        entities.dedup();
+        despawned_objects.sort();
+        despawned_objects.dedup();
+
+        let mut state = SimState {
+            tick: sim_world.tick(),
+            ..Default::default()
+        };
+        let mut included = PendingState::default();
+
+        let entity_map = sim_entity_map(&mut sim_world.world);
+        for id in entities {
+            let Some(entity) = entity_map.get(&id).copied() else {
+                continue;
+            };
+            if is_entity_relevant(&sim_world.world, self.for_player, entity)
+                && push_entity_state(
+                    &mut sim_world.world,
+                    &sim_world.registry,
+                    entity,
+                    &mut state,
+                )
+            {
+                included.entities.push(entity);
+            }
+        }
+        let scope = update_player_scope(&mut sim_world.world, self.for_player);
+        for entity in scope.iter().flat_map(|scope| scope.entered.iter()) {
+            if !included.entities.contains(entity)
+                && push_entity_state(
+                    &mut sim_world.world,
+                    &sim_world.registry,
+                    *entity,
+                    &mut state,
+                )
+            {
+                included.entities.push(*entity);
+            }
+        }
+        for id in despawned_objects {
+            if !entity_map.contains_key(&id)
+                && scope.as_ref().is_none_or(|scope| scope.was_in_scope(&id))
+            {
+                state.despawned_objects.push(id);
+                included.despawned_objects.push(id);
+            }
+        }
+        for id in scope.iter().flat_map(|scope| scope.left.iter()) {
+            if !state.despawned_objects.contains(id) {
+                state.despawned_objects.push(*id);
+                included.despawned_objects.push(*id);
+            }
+        }
+        for id in resources {
+            if let Some(resource_state) =
+                sim_world.registry.serialize_resource(&id, &sim_world.world)
+            {
+                state.resources.push(resource_state);
+                included.resources.push(id);
+            }
+        }
+
+        finish_player_state(sim_world, self.for_player, &mut state, included);
+
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use bevy::math::Vec3;
+
+    use crate::{
+        change_detection::DespawnTracked,
+        entity_id::SimEntityId,
+        interest::{InterestPosition, InterestRegion, InterestSet, PlayerInterests},
+        requests::state_dif::StateDif,
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    use super::StateSince;
+
+    #[test]
+    fn test_state_since() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_change_history(2);
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(TestComponent(0));
+        sim.simulate();
+        let moved = sim.sim_world.world.spawn(TestComponent(0)).id();
+        sim.simulate();
+        let moved_id = *sim.sim_world.world.get::<SimEntityId>(moved).unwrap();
+
+        let state = sim
+            .request(StateSince {
+                since_tick: 1,
+                for_player: 0,
+            })
+            .unwrap();
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, moved_id);
+        assert_eq!(
+            sim.request(StateSince {
+                since_tick: 0,
+                for_player: 0,
+            })
+            .map(|state| state.entities.len()),
+            Some(2)
+        );
+
+        sim.simulate();
+        assert!(sim
+            .request(StateSince {
+                since_tick: 0,
+                for_player: 0,
+            })
+            .is_none());
+    }
+
+    #[test]
+    fn test_state_since_skips_despawns_out_of_scope() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_change_history(4);
+        game.enable_interest_management();
+        let mut sim = game.build_standalone();
+
+        let world = &mut sim.sim_world.world;
+        world.resource_mut::<PlayerInterests>().set(
+            0,
+            InterestSet {
+                regions: vec![InterestRegion::new(Vec3::ZERO, Vec3::splat(10.0))],
+                ..Default::default()
+            },
+        );
+        let near = world
+            .spawn((TestComponent(0), InterestPosition(Vec3::splat(5.0))))
+            .id();
+        let far = world
+            .spawn((TestComponent(0), InterestPosition(Vec3::splat(20.0))))
+            .id();
+        sim.simulate();
+        let near_id = *sim.sim_world.world.get::<SimEntityId>(near).unwrap();
+        assert_eq!(sim.request(StateDif { for_player: 0 }).entities.len(), 1);
+
+        sim.sim_world.world.entity_mut(near).insert(DespawnTracked);
+        sim.sim_world.world.entity_mut(far).insert(DespawnTracked);
+        sim.simulate();
+
+        let state = sim
+            .request(StateSince {
+                since_tick: 1,
+                for_player: 0,
+            })
+            .unwrap();
+        assert_eq!(state.despawned_objects, vec![near_id]);
+    }
+}
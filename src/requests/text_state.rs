@@ -0,0 +1,188 @@
+//! Human readable export of the whole sim world. [`TextState`] returns a [`WorldText`] that can be
+//! written as RON or JSON for bug reports, diffing in git, and hand editing test fixtures.
+//!
+//! Registered components and resources are written as their serialized fields. Components without
+//! a text decode function, such as unregistered [`SaveId`](crate::saving::SaveId) components, are
+//! written as their raw bytes. Entities are sorted by id and components by type name so exports of
+//! the same world are identical.
+
+use bevy::utils::HashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{
+    saving::{ComponentBinaryState, GameSerDeRegistry, SimComponentId, TextDecodeFn},
+    SimWorld,
+};
+
+use super::{all_state::AllState, SimRequest, SimRequestReadOnly};
+
+/// A component or resource decoded for text export
+pub type TextValue = Box<dyn erased_serde::Serialize>;
+
+/// The components of an entity or player keyed by type name
+#[derive(Serialize)]
+pub struct EntityText {
+    /// The [`SimEntityId`](crate::entity_id::SimEntityId) of the entity or the id of the player
+    pub id: u64,
+    pub components: BTreeMap<String, TextValue>,
+}
+
+/// The whole sim world in a human readable form
+#[derive(Serialize)]
+pub struct WorldText {
+    pub tick: u64,
+    pub players: Vec<EntityText>,
+    pub entities: Vec<EntityText>,
+    pub resources: BTreeMap<String, TextValue>,
+}
+
+impl WorldText {
+    /// Writes the world as a pretty printed RON document
+    #[cfg(feature = "ron")]
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// Writes the world as a pretty printed JSON document
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// Returns the whole sim world in a human readable form, see [`WorldText`]
+pub struct TextState;
+
+impl SimRequest for TextState {
+    type Output = WorldText;
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        self.request_ref(sim_world)
+    }
+}
+
+impl SimRequestReadOnly for TextState {
+    type Output = WorldText;
+
+    fn request_ref(&mut self, sim_world: &SimWorld) -> Self::Output {
+        let state = AllState.request_ref(sim_world);
+        let registry = &sim_world.registry;
+
+        let mut players: Vec<EntityText> = state
+            .players
+            .iter()
+            .map(|player| EntityText {
+                id: player.player_id.id() as u64,
+                components: components_text(registry, &player.components),
+            })
+            .collect();
+        players.sort_by_key(|player| player.id);
+
+        let mut entities: Vec<EntityText> = state
+            .entities
+            .iter()
+            .map(|entity| EntityText {
+                id: entity.entity.0,
+                components: components_text(registry, &entity.components),
+            })
+            .collect();
+        entities.sort_by_key(|entity| entity.id);
+
+        let resources = state
+            .resources
+            .iter()
+            .map(|resource| {
+                text_entry(
+                    registry,
+                    &registry.resource_text_map,
+                    &registry.resource_type_names,
+                    resource.resource_id,
+                    &resource.resource,
+                )
+            })
+            .collect();
+
+        WorldText {
+            tick: state.tick,
+            players,
+            entities,
+            resources,
+        }
+    }
+}
+
+fn components_text(
+    registry: &GameSerDeRegistry,
+    components: &[ComponentBinaryState],
+) -> BTreeMap<String, TextValue> {
+    components
+        .iter()
+        .map(|component| {
+            text_entry(
+                registry,
+                &registry.component_text_map,
+                &registry.component_type_names,
+                component.id,
+                &component.component,
+            )
+        })
+        .collect()
+}
+
+/// Returns the type name and decoded value of the given data, falling back to the id and the raw
+/// bytes if it can't be decoded
+fn text_entry(
+    registry: &GameSerDeRegistry,
+    text_map: &HashMap<SimComponentId, TextDecodeFn>,
+    type_names: &HashMap<SimComponentId, &'static str>,
+    id: SimComponentId,
+    data: &[u8],
+) -> (String, TextValue) {
+    let name = type_names
+        .get(&id)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| id.to_string());
+    let value = registry
+        .verify_checksum(data)
+        .ok()
+        .and_then(|data| registry.component_compression.decompress(data))
+        .and_then(|data| {
+            text_map
+                .get(&id)
+                .and_then(|text_fn| text_fn(registry.format, &data))
+        })
+        .unwrap_or_else(|| Box::new(data.to_vec()));
+    (name, value)
+}
+
+#[cfg(all(test, feature = "ron"))]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        requests::text_state::TextState,
+        testing::{fixtures::bincode_save_id, test_game},
+    };
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct Health {
+        current: u32,
+    }
+
+    bincode_save_id!(Health, 25);
+
+    #[test]
+    fn test_text_state_ron() {
+        let mut game = test_game();
+        game.register_component::<Health>().unwrap();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(Health { current: 7 });
+        sim.simulate();
+
+        let text = sim.request(TextState).to_ron().unwrap();
+        assert!(text.contains("current: 7"));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{saving::checksum::world_hash, SimWorld};
+
+use super::SimRequest;
+
+/// Returns a 64 bit FNV-1a hash of the same sorted snapshot of every registered component and
+/// resource that [`world_checksum`](crate::saving::checksum::world_checksum) checksums. Entities are
+/// sorted by their [`SimEntityId`](crate::entity_id::SimEntityId), so two worlds with the same state
+/// and ids hash the same no matter the order their entities were spawned in. Compare the hashes of
+/// peers at the same tick to detect desyncs, see [`world_hash`]
+pub struct WorldHash;
+
+impl SimRequest for WorldHash {
+    type Output = u64;
+
+    fn request(&mut self, sim_world: &mut SimWorld) -> Self::Output {
+        // Every built sim world has a registry
+        world_hash(&mut sim_world.world).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::{
+        entity_id::SimEntityId,
+        runner::{StandaloneSim, TurnBasedGameRunner},
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    use super::WorldHash;
+
+    fn sim() -> StandaloneSim<TurnBasedGameRunner> {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.build_standalone()
+    }
+
+    #[test]
+    fn test_world_hash() {
+        let mut first = sim();
+        let mut second = sim();
+        for index in 0..3 {
+            first.sim_world.world.spawn(TestComponent(index));
+            second.sim_world.world.spawn(TestComponent(index));
+        }
+        first.simulate();
+        second.simulate();
+        assert_eq!(first.request(WorldHash), second.request(WorldHash));
+
+        second.sim_world.world.spawn(TestComponent(3));
+        assert_ne!(first.request(WorldHash), second.request(WorldHash));
+    }
+
+    #[test]
+    fn test_world_hash_ignores_spawn_order() {
+        let mut first = sim();
+        let mut second = sim();
+        for index in 0..3 {
+            first
+                .sim_world
+                .world
+                .spawn((TestComponent(index), SimEntityId(index as u64)));
+        }
+        for index in (0..3).rev() {
+            second
+                .sim_world
+                .world
+                .spawn((TestComponent(index), SimEntityId(index as u64)));
+        }
+        assert_eq!(first.request(WorldHash), second.request(WorldHash));
+    }
+}
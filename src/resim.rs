@@ -0,0 +1,148 @@
+//! Rollback and resimulation for real time networked games. When a late or corrected command
+//! arrives for a tick the sim has already simulated, [`StandaloneSim::resimulate_with`] restores the
+//! sim world from its [`SnapshotHistory`] to that tick, inserts the command, and simulates forward
+//! again to the current tick, executing every command from the history at its original tick.
+//!
+//! Requires [`GameBuilder::enable_snapshot_history`](crate::game_builder::GameBuilder::enable_snapshot_history)
+//! with a capacity covering the furthest a command can arrive late. Restoring a snapshot respawns
+//! every entity, so all of them are sent to players again by the next
+//! [`StateDif`](crate::requests::state_dif::StateDif).
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use bevy::prelude::Mut;
+
+use crate::{
+    command::{GameCommand, GameCommandMeta},
+    player::Player,
+    runner::{GameRunner, StandaloneSim},
+    saving::{history::SnapshotHistory, GameSerDeRegistry},
+};
+
+/// The reasons a sim can't be resimulated
+#[derive(Clone, Copy, Eq, Debug, PartialEq)]
+pub enum ResimError {
+    /// The sim world has no [`SnapshotHistory`]
+    NoSnapshotHistory,
+    /// No snapshot is stored for the given tick, it is older than the history keeps
+    SnapshotUnavailable(u64),
+    /// The given tick hasn't been simulated yet, so the command can be queued normally
+    FutureTick(u64),
+}
+
+impl Display for ResimError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResimError::NoSnapshotHistory => write!(f, "the sim world has no snapshot history"),
+            ResimError::SnapshotUnavailable(tick) => {
+                write!(f, "no snapshot is stored for tick {}", tick)
+            }
+            ResimError::FutureTick(tick) => write!(f, "tick {} hasn't been simulated yet", tick),
+        }
+    }
+}
+
+impl Error for ResimError {}
+
+impl<T> StandaloneSim<T>
+where
+    T: GameRunner,
+{
+    /// Rolls the sim back to the given tick, inserts the given command into the command batch of
+    /// that tick, and resimulates forward to the current tick. Commands in the history executed at
+    /// or after the tick are executed again at their original ticks. The result of every command
+    /// executed while resimulating is kept, see [`StandaloneSim::take_command_results`]
+    pub fn resimulate_with(
+        &mut self,
+        command: Box<dyn GameCommand>,
+        tick: u64,
+        issued_by: Option<Player>,
+    ) -> Result<(), ResimError> {
+        let current_tick = self.sim_world.tick();
+        if tick >= current_tick {
+            return Err(ResimError::FutureTick(tick));
+        }
+        let world = &mut self.sim_world.world;
+        let snapshot = world
+            .get_resource::<SnapshotHistory>()
+            .ok_or(ResimError::NoSnapshotHistory)?
+            .get(tick)
+            .ok_or(ResimError::SnapshotUnavailable(tick))?;
+
+        world.resource_mut::<SnapshotHistory>().truncate_after(tick);
+        world.resource_scope(|world, registry: Mut<GameSerDeRegistry>| {
+            snapshot.apply(world, &registry);
+        });
+
+        let mut game_commands = self.sim_world.game_commands_mut();
+        for mut executed in game_commands.history.take_since_tick(tick) {
+            executed.execute_at_tick = Some(executed.tick);
+            game_commands.queue.queue.push(executed);
+        }
+        let mut meta = GameCommandMeta::new(command, game_commands.queue.record_wall_clock);
+        meta.issued_by = issued_by;
+        meta.execute_at_tick = Some(tick);
+        game_commands.queue.push_meta(meta);
+
+        while self.sim_world.tick() < current_tick {
+            let results = self.step();
+            self.command_results.extend(results);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Reflect, World};
+
+    use super::ResimError;
+    use crate::{
+        command::{CommandError, GameCommand},
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    #[derive(Clone, Debug, Reflect)]
+    struct AddValue(u32);
+
+    impl GameCommand for AddValue {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            let mut query = world.query::<&mut TestComponent>();
+            for mut value in query.iter_mut(world) {
+                value.0 += self.0;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_resimulate_with_late_command() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_snapshot_history(8);
+        let mut sim = game.build_standalone();
+        sim.sim_world.world.spawn(TestComponent(0));
+        sim.simulate();
+        sim.sim_world.game_commands_mut().add(AddValue(1));
+        sim.simulate();
+        sim.simulate();
+        assert_eq!(sim.sim_world.tick(), 3);
+        sim.take_command_results();
+
+        sim.resimulate_with(Box::new(AddValue(10)), 1, None)
+            .unwrap();
+        let results = sim.take_command_results();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|executed| executed.result.is_ok()));
+        assert_eq!(sim.sim_world.tick(), 3);
+        let mut query = sim.sim_world.world.query::<&TestComponent>();
+        assert_eq!(query.single(&sim.sim_world.world).0, 11);
+        assert_eq!(sim.sim_world.game_commands().history.history.len(), 2);
+
+        assert_eq!(
+            sim.resimulate_with(Box::new(AddValue(1)), 3, None),
+            Err(ResimError::FutureTick(3))
+        );
+    }
+}
@@ -1,4 +1,17 @@
-use bevy::prelude::{Resource, Schedule, SystemSet, World};
+use bevy::{
+    ecs::schedule::ExecutorKind,
+    prelude::{Event, Events, Mut, Resource, Schedule, SystemSet, Time, World},
+};
+use std::time::Duration;
+
+use crate::{
+    change_detection::record_change_history,
+    command::{execute_game_commands_buffer, CommandExecuted},
+    requests::SimRequest,
+    saving::{autosave::Autosave, history::record_snapshot_history, DeserializeReport},
+    stats::record_replication_stats,
+    SimWorld,
+};
 
 /// Runtime that is used to drive the game. Users can implement whatever the want onto the GameRunner
 /// and then call [GameRuntime::simulate()] in order to drive their game forward.
@@ -10,16 +23,293 @@ where
     pub game_runner: T,
     pub game_pre_schedule: Schedule,
     pub game_post_schedule: Schedule,
+    /// Controls how [`GameRuntime::advance`] turns elapsed real time into ticks
+    pub fixed_timestep: FixedTimestep,
+    /// Checked after every simulated tick and saves the sim when due, see [`Autosave`]
+    pub autosave: Option<Autosave>,
 }
 
 impl<T> GameRuntime<T>
 where
     T: GameRunner,
 {
-    pub fn simulate(&mut self, mut world: &mut World) {
-        self.game_pre_schedule.run(&mut world);
-        self.game_runner.simulate_game(&mut world);
-        self.game_post_schedule.run(&mut world);
+    pub fn simulate(&mut self, world: &mut World) {
+        world.get_resource_or_insert_with(SimTick::default).0 += 1;
+        self.game_pre_schedule.run(world);
+        self.game_runner.simulate_game(world);
+        self.game_post_schedule.run(world);
+        record_snapshot_history(world);
+        record_change_history(world);
+        record_replication_stats(world);
+        if let Some(autosave) = self.autosave.as_mut() {
+            autosave.update(world);
+        }
+    }
+
+    /// Advances the game by the given elapsed real time, simulating once for every fixed tick that
+    /// is due. If more ticks are due than the [`CatchUpPolicy`] allows, returns a [`SimFallingBehind`]
+    /// describing the ticks that weren't simulated so the host can react.
+    ///
+    /// NOTE: This doesn't execute any [`GameCommands`](crate::command::GameCommands) or wait for
+    /// inputs. Use [`StandaloneSim::advance`] or the [`advance_game`] system to execute commands
+    /// before every tick
+    pub fn advance(&mut self, world: &mut World, elapsed: Duration) -> Option<SimFallingBehind> {
+        let (ticks, missed_ticks) = self.fixed_timestep.accumulate(elapsed);
+        for _ in 0..ticks {
+            self.simulate(world);
+        }
+        SimFallingBehind::new(missed_ticks)
+    }
+}
+
+/// Exclusive system that executes the [`GameCommands`](crate::command::GameCommands) of the
+/// [`SimWorld`] in the main world and simulates the game once, see
+/// [`execute_game_commands_buffer`]. Does nothing while the command sets of some players haven't
+/// arrived yet, see [`InputDelay`](crate::input_delay::InputDelay)
+pub fn simulate_game<T: GameRunner + 'static>(world: &mut World) {
+    world.resource_scope(|world, mut game_runtime: Mut<GameRuntime<T>>| {
+        step_game(world, &mut game_runtime);
+    });
+}
+
+/// Exclusive system that advances the game by the [`Time`] elapsed since the last update, executing
+/// the [`GameCommands`](crate::command::GameCommands) of the [`SimWorld`] in the main world before
+/// every simulated tick. Stops early while the command sets of some players haven't arrived yet,
+/// keeping the remaining ticks due. Sends a [`SimFallingBehind`] event into the main world if the
+/// sim couldn't keep up and the app has the event added
+pub fn advance_game<T: GameRunner + 'static>(world: &mut World) {
+    let elapsed = world
+        .get_resource::<Time>()
+        .map(|time| time.delta())
+        .unwrap_or_default();
+    let falling_behind = world.resource_scope(|world, mut game_runtime: Mut<GameRuntime<T>>| {
+        let (ticks, missed_ticks) = game_runtime.fixed_timestep.accumulate(elapsed);
+        for tick in 0..ticks {
+            if !step_game(world, &mut game_runtime) {
+                game_runtime.fixed_timestep.defer(ticks - tick);
+                break;
+            }
+        }
+        SimFallingBehind::new(missed_ticks)
+    });
+    if let (Some(falling_behind), Some(mut events)) = (
+        falling_behind,
+        world.get_resource_mut::<Events<SimFallingBehind>>(),
+    ) {
+        events.send(falling_behind);
+    }
+}
+
+/// Executes the [`GameCommands`](crate::command::GameCommands) of the [`SimWorld`] in the main world
+/// and simulates the game once if every input for the tick has arrived. Returns false without doing
+/// anything otherwise
+fn step_game<T: GameRunner>(world: &mut World, game_runtime: &mut GameRuntime<T>) -> bool {
+    if !game_inputs_ready(world) {
+        return false;
+    }
+    execute_game_commands_buffer(world);
+    world.resource_scope(|_world, mut sim_world: Mut<SimWorld>| {
+        game_runtime.simulate(&mut sim_world.world);
+    });
+    true
+}
+
+/// Returns true if the command sets of every player for the next tick of the [`SimWorld`] in the
+/// given main world have arrived
+pub(crate) fn game_inputs_ready(world: &World) -> bool {
+    world
+        .get_resource::<SimWorld>()
+        .is_none_or(|sim_world| sim_world.inputs_ready())
+}
+
+/// What the runtime does when more fixed ticks are due than it can simulate in one update
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq)]
+pub enum CatchUpPolicy {
+    /// Simulates a single tick and drops every other due tick, so the sim stays in step with real
+    /// time but skips the missed time entirely
+    DropTicks,
+    /// Simulates a single tick and keeps every other due tick, so the sim runs slower than real time
+    /// while behind and catches up once it can
+    SlowTime,
+    /// Simulates up to the given number of ticks and drops the rest
+    Burst(u32),
+}
+
+/// Event returned by [`GameRuntime::advance`] when the sim couldn't keep up with real time. Sent
+/// into the main world by the [`advance_game`] system
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq, Event)]
+pub struct SimFallingBehind {
+    /// The number of due ticks that weren't simulated in this update. For
+    /// [`CatchUpPolicy::SlowTime`] these are still pending, for the other policies they were dropped
+    pub missed_ticks: u32,
+}
+
+impl SimFallingBehind {
+    /// Returns the event for the given number of missed ticks, or None if no tick was missed
+    pub fn new(missed_ticks: u32) -> Option<SimFallingBehind> {
+        (missed_ticks > 0).then_some(SimFallingBehind { missed_ticks })
+    }
+}
+
+/// Accumulates elapsed real time into fixed length ticks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedTimestep {
+    pub tick_duration: Duration,
+    pub policy: CatchUpPolicy,
+    /// Elapsed time that hasn't been simulated yet
+    pub accumulated: Duration,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        FixedTimestep {
+            tick_duration: Duration::from_secs_f64(1.0 / 60.0),
+            policy: CatchUpPolicy::Burst(5),
+            accumulated: Duration::ZERO,
+        }
+    }
+}
+
+impl FixedTimestep {
+    /// Adds the elapsed time and returns the number of ticks to simulate and the number of due ticks
+    /// that won't be simulated according to the policy
+    pub fn accumulate(&mut self, elapsed: Duration) -> (u32, u32) {
+        self.accumulated += elapsed;
+        if self.tick_duration.is_zero() {
+            return (0, 0);
+        }
+
+        let due = (self.accumulated.as_nanos() / self.tick_duration.as_nanos()) as u32;
+        let remainder = self.accumulated - self.tick_duration * due;
+        let max_ticks = match self.policy {
+            CatchUpPolicy::DropTicks | CatchUpPolicy::SlowTime => 1,
+            CatchUpPolicy::Burst(max_ticks) => max_ticks,
+        };
+        let ticks = due.min(max_ticks);
+        let missed_ticks = due - ticks;
+
+        self.accumulated = match self.policy {
+            CatchUpPolicy::SlowTime => remainder + self.tick_duration * missed_ticks,
+            CatchUpPolicy::DropTicks | CatchUpPolicy::Burst(_) => remainder,
+        };
+
+        (ticks, missed_ticks)
+    }
+
+    /// Returns the given number of ticks to the accumulated time so they are due again on the next
+    /// update, for ticks that were due but couldn't be simulated yet
+    pub fn defer(&mut self, ticks: u32) {
+        self.accumulated += self.tick_duration * ticks;
+    }
+}
+
+/// Resource inserted into the sim world that holds the current sim tick. Incremented at the start of
+/// every [`GameRuntime::simulate`] call, so it is 0 until the game has been simulated once
+#[derive(Default, Clone, Copy, Eq, Hash, Debug, PartialEq, PartialOrd, Ord, Resource)]
+pub struct SimTick(pub u64);
+
+/// A fully built sim that lives outside of any Bevy App or main world. Created through
+/// [`GameBuilder::build_standalone`](crate::game_builder::GameBuilder::build_standalone) and driven
+/// by calling [`StandaloneSim::simulate`] directly.
+pub struct StandaloneSim<T>
+where
+    T: GameRunner,
+{
+    /// The sim world, holding the commands of the sim, see [`SimWorld::game_commands`]
+    pub sim_world: SimWorld,
+    pub game_runtime: GameRuntime<T>,
+    /// The result of every command executed since the results were last taken, see
+    /// [`StandaloneSim::take_command_results`]
+    pub command_results: Vec<CommandExecuted>,
+}
+
+impl<T> StandaloneSim<T>
+where
+    T: GameRunner,
+{
+    /// Executes all queued commands, see [`SimWorld::execute_game_commands`], and then simulates the
+    /// game once. Returns false without simulating if the command sets of some players haven't
+    /// arrived yet, see [`InputDelay`](crate::input_delay::InputDelay)
+    pub fn simulate(&mut self) -> bool {
+        if !self.inputs_ready() {
+            return false;
+        }
+        let results = self.step();
+        self.command_results.extend(results);
+        true
+    }
+
+    /// Executes all queued commands and simulates the game once without checking for inputs.
+    /// Returns the result of every executed command
+    pub(crate) fn step(&mut self) -> Vec<CommandExecuted> {
+        let results = self.sim_world.execute_game_commands();
+        self.game_runtime.simulate(&mut self.sim_world.world);
+        results
+    }
+
+    /// Takes the result of every command executed by [`StandaloneSim::simulate`] and
+    /// [`StandaloneSim::advance`] since the last call
+    pub fn take_command_results(&mut self) -> Vec<CommandExecuted> {
+        std::mem::take(&mut self.command_results)
+    }
+
+    /// Returns true if the command sets of every player for the next command batch have arrived
+    pub fn inputs_ready(&self) -> bool {
+        self.sim_world.inputs_ready()
+    }
+
+    /// Advances the game by the given elapsed real time, executing all queued commands before
+    /// every simulated tick, see [`GameRuntime::advance`]. Stops early while the command sets
+    /// of some players haven't arrived yet, keeping the remaining ticks due
+    pub fn advance(&mut self, elapsed: Duration) -> Option<SimFallingBehind> {
+        let (ticks, missed_ticks) = self.game_runtime.fixed_timestep.accumulate(elapsed);
+        for tick in 0..ticks {
+            if !self.simulate() {
+                self.game_runtime.fixed_timestep.defer(ticks - tick);
+                break;
+            }
+        }
+        SimFallingBehind::new(missed_ticks)
+    }
+
+    /// Makes a request to the sim world and returns the results
+    pub fn request<Request: SimRequest>(&mut self, request: Request) -> Request::Output {
+        self.sim_world.request(request)
+    }
+
+    /// Saves the sim along with the queue and history of its commands, see
+    /// [`SimWorld::save_snapshot_with_commands`]
+    pub fn save_snapshot(&mut self, name: &str) -> Result<Vec<u8>, bincode::Error> {
+        self.sim_world.save_snapshot_with_commands(name)
+    }
+
+    /// Saves the sim along with its commands and writes the save to the given writer, see
+    /// [`StandaloneSim::save_snapshot`]
+    pub fn save_snapshot_to(
+        &mut self,
+        mut writer: impl std::io::Write,
+        name: &str,
+    ) -> Result<(), bincode::Error> {
+        writer.write_all(&self.save_snapshot(name)?)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a save written by [`StandaloneSim::save_snapshot_to`] from the given reader until it
+    /// ends and loads it, see [`StandaloneSim::load_snapshot`]
+    pub fn load_snapshot_from(
+        &mut self,
+        mut reader: impl std::io::Read,
+    ) -> Result<DeserializeReport, bincode::Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        self.load_snapshot(&data)
+    }
+
+    /// Loads a save created with [`StandaloneSim::save_snapshot`], see
+    /// [`SimWorld::load_snapshot_with_commands`]
+    pub fn load_snapshot(&mut self, data: &[u8]) -> Result<DeserializeReport, bincode::Error> {
+        self.sim_world.load_snapshot_with_commands(data)
     }
 }
 
@@ -50,6 +340,13 @@ pub enum PreBaseSets {
 /// of calling this directly in order to utilize automate change detection
 pub trait GameRunner: Send + Sync {
     fn simulate_game(&mut self, world: &mut World);
+
+    /// Sets the executor used by any schedules owned by the runner. Called by the
+    /// [`GameBuilder`](crate::game_builder::GameBuilder) when the game is built.
+    ///
+    /// NOTE: This has a default implementation that does nothing so runners without schedules
+    /// aren't required to implement it.
+    fn set_executor_kind(&mut self, _executor_kind: ExecutorKind) {}
 }
 
 /// A simple example game runner for a turn based game
@@ -61,6 +358,10 @@ impl GameRunner for TurnBasedGameRunner {
     fn simulate_game(&mut self, world: &mut World) {
         self.turn_schedule.run(world);
     }
+
+    fn set_executor_kind(&mut self, executor_kind: ExecutorKind) {
+        self.turn_schedule.set_executor_kind(executor_kind);
+    }
 }
 
 /// A simple example game runner for a real time based game
@@ -74,4 +375,147 @@ impl GameRunner for RealTimeGameRunner {
         self.ticks = self.ticks.saturating_add(1);
         self.tick_schedule.run(world);
     }
+
+    fn set_executor_kind(&mut self, executor_kind: ExecutorKind) {
+        self.tick_schedule.set_executor_kind(executor_kind);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        ecs::schedule::ExecutorKind,
+        prelude::{Events, Reflect, Time, World},
+    };
+
+    use std::time::Duration;
+
+    use crate::{
+        command::{CommandError, CommandExecuted, CompositeCommand, GameCommand},
+        game_builder::GameBuilder,
+        requests::all_state::AllState,
+        runner::{
+            advance_game, CatchUpPolicy, FixedTimestep, GameRuntime, SimFallingBehind,
+            TurnBasedGameRunner,
+        },
+        testing::{fixtures::TestComponent, test_game},
+        SimWorld,
+    };
+
+    #[test]
+    fn test_catch_up_policy() {
+        let mut timestep = FixedTimestep {
+            tick_duration: Duration::from_millis(10),
+            policy: CatchUpPolicy::Burst(2),
+            accumulated: Duration::ZERO,
+        };
+        assert_eq!(timestep.accumulate(Duration::from_millis(55)), (2, 3));
+        assert_eq!(timestep.accumulated, Duration::from_millis(5));
+
+        timestep.policy = CatchUpPolicy::SlowTime;
+        assert_eq!(timestep.accumulate(Duration::from_millis(25)), (1, 2));
+        assert_eq!(timestep.accumulated, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_standalone_sim() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(TestComponent(3));
+        sim.sim_world
+            .game_commands_mut()
+            .add(CompositeCommand::new());
+        sim.simulate();
+
+        let state = sim.request(AllState);
+        assert_eq!(state.entities.len(), 1);
+        let results = sim.take_command_results();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].result.is_ok());
+    }
+
+    #[test]
+    fn test_executor_kind() {
+        let mut game = test_game();
+        game.executor_kind = ExecutorKind::MultiThreaded;
+        let mut sim = game.build_standalone();
+
+        let runtime = &sim.game_runtime;
+        assert_eq!(
+            runtime.game_pre_schedule.get_executor_kind(),
+            ExecutorKind::MultiThreaded
+        );
+        assert_eq!(
+            runtime.game_post_schedule.get_executor_kind(),
+            ExecutorKind::MultiThreaded
+        );
+        assert_eq!(
+            runtime.game_runner.turn_schedule.get_executor_kind(),
+            ExecutorKind::MultiThreaded
+        );
+        sim.simulate();
+        assert_eq!(sim.sim_world.tick(), 1);
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct Fail;
+
+    impl GameCommand for Fail {
+        fn execute(&mut self, _world: &mut World) -> Result<(), CommandError> {
+            Err(CommandError::ValidationFailed(String::from("fail")))
+        }
+    }
+
+    fn game_with_commands() -> GameBuilder<TurnBasedGameRunner> {
+        GameBuilder::new_game_with_commands(
+            vec![Box::new(CompositeCommand::new()), Box::new(Fail)],
+            TurnBasedGameRunner {
+                turn_schedule: Default::default(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_initial_command_results() {
+        let mut sim = game_with_commands().build_standalone();
+        let results = sim.take_command_results();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results
+                .iter()
+                .filter(|executed| executed.result.is_err())
+                .count(),
+            1
+        );
+
+        let mut main_world = World::new();
+        main_world.init_resource::<Events<CommandExecuted>>();
+        game_with_commands().build(&mut main_world);
+        assert_eq!(main_world.resource::<Events<CommandExecuted>>().len(), 2);
+    }
+
+    #[test]
+    fn test_advance_game() {
+        let game = test_game();
+        let mut main_world = World::new();
+        game.build(&mut main_world);
+        main_world.init_resource::<Events<SimFallingBehind>>();
+        main_world
+            .resource_mut::<GameRuntime<TurnBasedGameRunner>>()
+            .fixed_timestep
+            .tick_duration = Duration::from_millis(10);
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(100));
+        main_world.insert_resource(time);
+
+        advance_game::<TurnBasedGameRunner>(&mut main_world);
+        assert_eq!(main_world.resource::<SimWorld>().tick(), 5);
+        let events = main_world.resource::<Events<SimFallingBehind>>();
+        assert_eq!(
+            events.iter_current_update_events().next(),
+            Some(&SimFallingBehind { missed_ticks: 5 })
+        );
+    }
 }
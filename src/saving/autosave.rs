@@ -0,0 +1,187 @@
+//! Autosaving driven by the [`GameRuntime`](crate::runner::GameRuntime). Set an [`Autosave`] on the
+//! runtime with [`GameBuilder::set_autosave`](crate::game_builder::GameBuilder::set_autosave) and it
+//! is checked after every simulated tick, so every autosave is a consistent snapshot of a finished
+//! tick.
+//!
+//! The first autosave writes a full baseline, the following ones write a [`SnapshotDelta`] against
+//! that baseline until [`Autosave::deltas_per_baseline`] is reached and a new baseline is written.
+//! Encoding happens on the calling thread, writing to disk on the
+//! [`IoTaskPool`](bevy::tasks::IoTaskPool). The [`AutosaveStatus`] resource in the sim world exposes
+//! the result of the last autosave. Load the latest autosave with
+//! [`SimWorld::load_autosave`](crate::SimWorld::load_autosave).
+
+use bevy::prelude::{Mut, Resource, World};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{player::PlayerList, runner::SimTick};
+
+use super::{
+    background::BackgroundSave,
+    delta::SnapshotDelta,
+    header::SaveHeader,
+    snapshot::{SaveFilter, SimSnapshot},
+    GameSerDeRegistry,
+};
+
+/// The file name of the baseline autosave
+pub const AUTOSAVE_BASELINE_FILE: &str = "autosave_baseline.sav";
+
+/// The file name of the delta autosave
+pub const AUTOSAVE_DELTA_FILE: &str = "autosave_delta.sav";
+
+/// Which autosave [`SimWorld::load_autosave`](crate::SimWorld::load_autosave) loaded
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq)]
+pub enum AutosaveSource {
+    /// The baseline with the delta autosave applied
+    Delta,
+    /// The baseline alone, as there is no delta autosave
+    Baseline,
+    /// The baseline alone, as the delta autosave was written against an older baseline. Happens
+    /// when the sim stopped after a new baseline was written but before its first delta
+    StaleDelta,
+}
+
+/// How often an [`Autosave`] saves
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq)]
+pub enum AutosaveInterval {
+    /// Saves every given number of ticks
+    Ticks(u64),
+    /// Saves once the given real time has passed since the last autosave
+    Duration(Duration),
+}
+
+/// Resource inserted into the sim world holding the status of the last autosave
+#[derive(Resource, Clone, Default, Debug, PartialEq)]
+pub struct AutosaveStatus {
+    /// The tick of the last autosave that was started
+    pub last_tick: Option<u64>,
+    /// The path of the last autosave that was started
+    pub last_path: Option<PathBuf>,
+    /// The result of the last finished autosave
+    pub last_result: Option<Result<(), String>>,
+    /// Whether an autosave is still being written
+    pub saving: bool,
+}
+
+/// Autosave configuration and state kept in the [`GameRuntime`](crate::runner::GameRuntime)
+pub struct Autosave {
+    pub interval: AutosaveInterval,
+    /// The directory the autosave files are written to
+    pub directory: PathBuf,
+    /// The number of delta autosaves written against a baseline before a new baseline is written
+    pub deltas_per_baseline: u32,
+    baseline: Option<SimSnapshot>,
+    deltas_since_baseline: u32,
+    last_tick: u64,
+    last_time: Instant,
+    pending: Option<BackgroundSave>,
+}
+
+impl Autosave {
+    pub fn new(directory: impl Into<PathBuf>, interval: AutosaveInterval) -> Autosave {
+        Autosave {
+            interval,
+            directory: directory.into(),
+            deltas_per_baseline: 10,
+            baseline: None,
+            deltas_since_baseline: 0,
+            last_tick: 0,
+            last_time: Instant::now(),
+            pending: None,
+        }
+    }
+
+    /// Returns whether an autosave is due at the given tick
+    pub fn is_due(&self, tick: u64) -> bool {
+        match self.interval {
+            AutosaveInterval::Ticks(ticks) => tick >= self.last_tick + ticks,
+            AutosaveInterval::Duration(duration) => self.last_time.elapsed() >= duration,
+        }
+    }
+
+    /// Updates the [`AutosaveStatus`] with the result of a finished autosave and starts a new
+    /// autosave if one is due and the previous one has finished
+    pub fn update(&mut self, world: &mut World) {
+        if let Some(result) = self.pending.as_mut().and_then(BackgroundSave::poll) {
+            self.pending = None;
+            Self::finish_status(world, result);
+        }
+
+        let tick = world
+            .get_resource::<SimTick>()
+            .map(|tick| tick.0)
+            .unwrap_or_default();
+        if self.pending.is_some() || !self.is_due(tick) {
+            return;
+        }
+        self.last_tick = tick;
+        self.last_time = Instant::now();
+
+        match self.encode(world) {
+            Ok((path, data)) => {
+                let mut status = world.get_resource_or_insert_with(AutosaveStatus::default);
+                status.last_tick = Some(tick);
+                status.last_path = Some(path.clone());
+                status.saving = true;
+                self.pending = Some(BackgroundSave::write(path, data));
+            }
+            Err(error) => Self::finish_status(world, Err(error.to_string())),
+        }
+    }
+
+    /// Blocks until the pending autosave is written and updates the [`AutosaveStatus`]
+    pub fn wait(&mut self, world: &mut World) {
+        if let Some(save) = self.pending.take() {
+            Self::finish_status(world, save.wait());
+        }
+    }
+
+    /// Encodes the next autosave, either a new baseline or a delta against the current one
+    fn encode(&mut self, world: &mut World) -> Result<(PathBuf, Vec<u8>), bincode::Error> {
+        let player_list = world
+            .get_resource::<PlayerList>()
+            .cloned()
+            .unwrap_or(PlayerList { players: vec![] });
+        world.resource_scope(|world, registry: Mut<GameSerDeRegistry>| {
+            let snapshot =
+                SimSnapshot::from_world(world, &registry, &player_list, &SaveFilter::default());
+            let header = SaveHeader::new(&registry, "autosave");
+            match &self.baseline {
+                Some(baseline) if self.deltas_since_baseline < self.deltas_per_baseline => {
+                    let body = SnapshotDelta::new(baseline, &snapshot).to_binary()?;
+                    self.deltas_since_baseline += 1;
+                    Ok((
+                        self.directory.join(AUTOSAVE_DELTA_FILE),
+                        header.write(&registry.seal_save_body(body))?,
+                    ))
+                }
+                _ => {
+                    let body = snapshot.to_binary()?;
+                    self.baseline = Some(snapshot);
+                    self.deltas_since_baseline = 0;
+                    Ok((
+                        self.directory.join(AUTOSAVE_BASELINE_FILE),
+                        header.write(&registry.seal_save_body(body))?,
+                    ))
+                }
+            }
+        })
+    }
+
+    fn finish_status(world: &mut World, result: Result<(), String>) {
+        let mut status = world.get_resource_or_insert_with(AutosaveStatus::default);
+        status.last_result = Some(result);
+        status.saving = false;
+    }
+}
+
+/// Returns the paths of the baseline and delta autosaves in the given directory
+pub fn autosave_paths(directory: &Path) -> (PathBuf, PathBuf) {
+    (
+        directory.join(AUTOSAVE_BASELINE_FILE),
+        directory.join(AUTOSAVE_DELTA_FILE),
+    )
+}
@@ -0,0 +1,110 @@
+//! Saving without stalling the frame. [`SimWorld::save_in_background`](crate::SimWorld::save_in_background)
+//! only extracts the state of the sim on the calling thread, encoding the save and writing it to
+//! disk happens on the [`IoTaskPool`]. Push the returned [`BackgroundSave`] into the
+//! [`PendingSaves`] resource and add [`poll_background_saves`] to your app to receive a
+//! [`SaveCompleted`] event once the save is written.
+//!
+//! ```rust,ignore
+//! app.init_resource::<PendingSaves>()
+//!     .add_event::<SaveCompleted>()
+//!     .add_systems(Update, poll_background_saves);
+//! ```
+
+use bevy::{
+    prelude::{Event, EventWriter, ResMut, Resource},
+    tasks::{block_on, poll_once, IoTaskPool, Task, TaskPool},
+};
+use std::path::PathBuf;
+
+use super::{header::SaveHeader, snapshot::SimSnapshot, GameSerDeRegistry};
+
+/// A save that is being encoded and written on a background task
+pub struct BackgroundSave {
+    pub path: PathBuf,
+    pub task: Task<Result<(), String>>,
+}
+
+impl BackgroundSave {
+    /// Starts encoding the given snapshot and writing it to the given path on the [`IoTaskPool`].
+    /// The body is encrypted if the given registry has
+    /// [`save_encryption`](GameSerDeRegistry::save_encryption) set
+    pub fn start(
+        path: PathBuf,
+        header: SaveHeader,
+        snapshot: SimSnapshot,
+        registry: &GameSerDeRegistry,
+    ) -> BackgroundSave {
+        let task_path = path.clone();
+        let registry = registry.clone();
+        let task = IoTaskPool::get_or_init(TaskPool::new).spawn(async move {
+            let body = snapshot.to_binary().map_err(|error| error.to_string())?;
+            let body = registry.seal_save_body(body);
+            let data = header.write(&body).map_err(|error| error.to_string())?;
+            std::fs::write(task_path, data).map_err(|error| error.to_string())
+        });
+        BackgroundSave { path, task }
+    }
+
+    /// Starts writing the given already encoded save to the given path on the [`IoTaskPool`],
+    /// creating its parent directories
+    pub fn write(path: PathBuf, data: Vec<u8>) -> BackgroundSave {
+        let task_path = path.clone();
+        let task = IoTaskPool::get_or_init(TaskPool::new).spawn(async move {
+            if let Some(parent) = task_path.parent() {
+                std::fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+            }
+            std::fs::write(task_path, data).map_err(|error| error.to_string())
+        });
+        BackgroundSave { path, task }
+    }
+
+    /// Returns the result of the save if it has finished, without blocking
+    pub fn poll(&mut self) -> Option<Result<(), String>> {
+        block_on(poll_once(&mut self.task))
+    }
+
+    /// Blocks until the save is written and returns the result
+    pub fn wait(self) -> Result<(), String> {
+        block_on(self.task)
+    }
+}
+
+/// Resource holding every [`BackgroundSave`] that hasn't finished yet
+#[derive(Default, Resource)]
+pub struct PendingSaves {
+    pub saves: Vec<BackgroundSave>,
+}
+
+impl PendingSaves {
+    pub fn push(&mut self, save: BackgroundSave) {
+        self.saves.push(save);
+    }
+}
+
+/// Event sent by [`poll_background_saves`] when a [`BackgroundSave`] finishes
+#[derive(Clone, Eq, Hash, Debug, PartialEq, Event)]
+pub struct SaveCompleted {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+}
+
+/// Sends a [`SaveCompleted`] event for every finished save in [`PendingSaves`]
+pub fn poll_background_saves(
+    mut pending_saves: ResMut<PendingSaves>,
+    mut save_completed: EventWriter<SaveCompleted>,
+) {
+    let mut index = 0;
+    while index < pending_saves.saves.len() {
+        let save = &mut pending_saves.saves[index];
+        match save.poll() {
+            Some(result) => {
+                let save = pending_saves.saves.swap_remove(index);
+                save_completed.send(SaveCompleted {
+                    path: save.path,
+                    result,
+                });
+            }
+            None => index += 1,
+        }
+    }
+}
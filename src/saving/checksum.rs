@@ -0,0 +1,202 @@
+//! Integrity checksums. When [`GameSerDeRegistry::payload_checksums`] is enabled a CRC-32 of every
+//! serialized component, resource, state, and save body is appended to it and verified before it
+//! is deserialized, so truncated or corrupted payloads are rejected instead of being half applied.
+//!
+//! Checksums are appended after compression and encryption and are part of the
+//! [`registry_hash`](GameSerDeRegistry::registry_hash), so both sides must enable them.
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    hash::Hasher,
+};
+
+use bevy::prelude::World;
+
+use super::{history::snapshot_world, GameSerDeRegistry};
+
+/// The number of bytes a checksum adds to a payload
+pub const CHECKSUM_LEN: usize = 4;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// Returns the CRC-32 (IEEE) of the given data
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// A 64 bit FNV-1a [`Hasher`]. Unlike the std hashers its output is the same across builds and
+/// machines, so it is used for every hash that is stored or compared between peers
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Returns the 64 bit FNV-1a hash of the given data, see [`Fnv1a`]
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Returns the full state of the given sim world serialized with its entities, components, and
+/// resources sorted by id, so it only depends on the state of the world
+fn sorted_world_state(world: &mut World) -> Option<Vec<u8>> {
+    let mut snapshot = snapshot_world(world)?;
+    snapshot.entities.sort_by_key(|entity| entity.entity);
+    for entity in snapshot.entities.iter_mut() {
+        entity.components.sort_by_key(|component| component.id);
+    }
+    snapshot
+        .resources
+        .sort_by_key(|resource| resource.resource_id);
+    snapshot.to_binary().ok()
+}
+
+/// Returns a CRC-32 of the full state of the given sim world, see
+/// [`snapshot_world`](super::history::snapshot_world). Entities, components, and resources are
+/// sorted by id first so the checksum only depends on the state of the world. Used to detect
+/// desyncs between lockstep peers, see [`GameCommands::compare_checksum`](crate::command::GameCommands::compare_checksum).
+/// Returns None if the world has no [`GameSerDeRegistry`]
+pub fn world_checksum(world: &mut World) -> Option<u32> {
+    sorted_world_state(world).map(|state| crc32(&state))
+}
+
+/// Returns a 64 bit [`Fnv1a`] hash of the same state as [`world_checksum`]. Returns None if the
+/// world has no [`GameSerDeRegistry`]
+pub fn world_hash(world: &mut World) -> Option<u64> {
+    sorted_world_state(world).map(|state| fnv1a(&state))
+}
+
+/// A payload that failed its checksum
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq)]
+pub enum ChecksumError {
+    /// The payload is too short to contain a checksum
+    Missing,
+    /// The checksum stored in the payload doesn't match its data
+    Mismatch { stored: u32, computed: u32 },
+}
+
+impl Display for ChecksumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChecksumError::Missing => write!(f, "payload is missing its checksum"),
+            ChecksumError::Mismatch { stored, computed } => write!(
+                f,
+                "payload checksum {:08x} doesn't match its data {:08x}",
+                stored, computed
+            ),
+        }
+    }
+}
+
+impl Error for ChecksumError {}
+
+impl GameSerDeRegistry {
+    /// Appends a checksum to the given payload if [`GameSerDeRegistry::payload_checksums`] is set
+    pub fn append_checksum(&self, mut data: Vec<u8>) -> Vec<u8> {
+        if self.payload_checksums {
+            let checksum = crc32(&data);
+            data.extend_from_slice(&checksum.to_le_bytes());
+        }
+        data
+    }
+
+    /// Verifies and strips the checksum of the given payload if
+    /// [`GameSerDeRegistry::payload_checksums`] is set
+    pub fn verify_checksum<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], ChecksumError> {
+        if !self.payload_checksums {
+            return Ok(data);
+        }
+        if data.len() < CHECKSUM_LEN {
+            return Err(ChecksumError::Missing);
+        }
+        let (data, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+        let stored = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        let computed = crc32(data);
+        if stored != computed {
+            return Err(ChecksumError::Mismatch { stored, computed });
+        }
+        Ok(data)
+    }
+
+    /// Compresses the given serialized component or resource and appends its checksum
+    pub(crate) fn pack_payload(&self, binary: Vec<u8>) -> Vec<u8> {
+        self.append_checksum(self.component_compression.compress(binary))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::World;
+
+    use crate::saving::{
+        checksum::{crc32, ChecksumError},
+        ComponentBinaryState, DeserializeError, GameSerDeRegistry,
+    };
+
+    #[test]
+    fn test_payload_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+
+        let mut registry = GameSerDeRegistry::new();
+        registry.payload_checksums = true;
+        let data = registry.append_checksum(vec![1, 2, 3]);
+        assert_eq!(registry.verify_checksum(&data), Ok(&[1u8, 2, 3][..]));
+        assert!(matches!(
+            registry.verify_checksum(&data[..data.len() - 1]),
+            Err(ChecksumError::Mismatch { .. })
+        ));
+        assert_eq!(registry.verify_checksum(&[0]), Err(ChecksumError::Missing));
+
+        let mut world = World::new();
+        let truncated = ComponentBinaryState {
+            id: 1,
+            version: 0,
+            component: data[..data.len() - 1].to_vec(),
+        };
+        assert!(matches!(
+            registry.deserialize_component_onto(&truncated, &mut world.spawn_empty()),
+            Err(DeserializeError::ComponentChecksum { id: 1, .. })
+        ));
+    }
+}
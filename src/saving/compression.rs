@@ -0,0 +1,68 @@
+//! Optional compression of serialized payloads. Lz4 is available behind the `lz4` feature and zstd
+//! behind the `zstd` feature. Configure it on the [`GameSerDeRegistry`](super::GameSerDeRegistry)
+//! with [`component_compression`](super::GameSerDeRegistry::component_compression) for individual
+//! component and resource payloads and
+//! [`state_compression`](super::GameSerDeRegistry::state_compression) for whole encoded states.
+
+use serde::{Deserialize, Serialize};
+
+/// The compression applied to serialized payloads
+#[derive(Clone, Copy, Default, Eq, Hash, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SimCompression {
+    #[default]
+    None,
+    /// Fast compression using lz4
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Compression using zstd at the given level. Higher levels compress better but slower
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl SimCompression {
+    /// Compresses the given data
+    pub fn compress(&self, data: Vec<u8>) -> Vec<u8> {
+        match self {
+            SimCompression::None => data,
+            #[cfg(feature = "lz4")]
+            SimCompression::Lz4 => lz4_flex::compress_prepend_size(&data),
+            #[cfg(feature = "zstd")]
+            SimCompression::Zstd(level) => {
+                zstd::encode_all(data.as_slice(), *level).unwrap_or(data)
+            }
+        }
+    }
+
+    /// Decompresses data compressed with [`SimCompression::compress`]. Returns None if the data is
+    /// invalid
+    pub fn decompress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            SimCompression::None => Some(data.to_vec()),
+            #[cfg(feature = "lz4")]
+            SimCompression::Lz4 => lz4_flex::decompress_size_prepended(data).ok(),
+            #[cfg(feature = "zstd")]
+            SimCompression::Zstd(_) => zstd::decode_all(data).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::SimCompression;
+
+    #[test]
+    fn test_compression_round_trip() {
+        let data = vec![7u8; 512];
+        let compressions = [
+            SimCompression::None,
+            #[cfg(feature = "lz4")]
+            SimCompression::Lz4,
+            #[cfg(feature = "zstd")]
+            SimCompression::Zstd(3),
+        ];
+        for compression in compressions {
+            let compressed = compression.compress(data.clone());
+            assert_eq!(compression.decompress(&compressed), Some(data.clone()));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Serialization of components that can't implement [`SaveId`](super::SaveId) or [`Serialize`](serde::Serialize),
+//! such as components from third party crates. Registered with
+//! [`GameSerDeRegistry::register_component_with`] using closures that convert the component to and
+//! from bytes.
+
+use std::{any::TypeId, sync::Arc};
+
+use bevy::prelude::{Component, Entity, EntityWorldMut, World};
+
+use super::{
+    add_component_tracking, DecodeError, GameSerDeRegistry, RegistryError, SimComponentId,
+};
+
+pub type CustomSerializeFn = Arc<dyn Fn(&World, Entity) -> Option<Vec<u8>> + Send + Sync>;
+
+pub type CustomDeserializeFn =
+    Arc<dyn Fn(&[u8], &mut EntityWorldMut) -> Result<(), DecodeError> + Send + Sync>;
+
+/// The type erased closures of a component registered with
+/// [`GameSerDeRegistry::register_component_with`]
+#[derive(Clone)]
+pub struct CustomComponentFns {
+    pub serialize: CustomSerializeFn,
+    pub deserialize: CustomDeserializeFn,
+}
+
+impl GameSerDeRegistry {
+    /// Registers a component into the [`GameSerDeRegistry`] using the given closures to serialize
+    /// and deserialize it. The bytes produced by the closures are used as is, the registries
+    /// [`SimFormat`](super::SimFormat) isn't applied to them. Returns an error without registering
+    /// if the id is already used
+    pub fn register_component_with<C, S, D>(
+        &mut self,
+        id: SimComponentId,
+        serialize: S,
+        deserialize: D,
+    ) -> Result<(), RegistryError>
+    where
+        C: Component,
+        S: Fn(&C) -> Option<Vec<u8>> + Send + Sync + 'static,
+        D: Fn(&[u8]) -> Option<C> + Send + Sync + 'static,
+    {
+        self.check_id_range(id, std::any::type_name::<C>())?;
+        self.check_component_id(id, std::any::type_name::<C>())?;
+        self.component_custom_map.insert(
+            id,
+            CustomComponentFns {
+                serialize: Arc::new(move |world, entity| serialize(world.get::<C>(entity)?)),
+                deserialize: Arc::new(move |data, entity| {
+                    let component = deserialize(data)
+                        .ok_or_else(|| DecodeError::new(0, "custom deserializer failed"))?;
+                    entity.insert(component);
+                    Ok(())
+                }),
+            },
+        );
+        self.component_type_names
+            .insert(id, std::any::type_name::<C>());
+        self.component_type_ids.insert(TypeId::of::<C>(), id);
+        self.component_tracking_map
+            .insert(id, add_component_tracking::<C>);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Component, World};
+
+    use crate::saving::GameSerDeRegistry;
+
+    #[derive(Component, Debug, PartialEq)]
+    struct ForeignComponent(u32);
+
+    #[test]
+    fn test_register_component_with_closures() {
+        let mut registry = GameSerDeRegistry::new();
+        registry
+            .register_component_with::<ForeignComponent, _, _>(
+                40,
+                |component| Some(component.0.to_le_bytes().to_vec()),
+                |data| Some(ForeignComponent(u32::from_le_bytes(data.try_into().ok()?))),
+            )
+            .unwrap();
+
+        let mut world = World::new();
+        let entity = world.spawn(ForeignComponent(7)).id();
+        let components = registry.save_entity(None, &world, entity);
+        assert_eq!(components.len(), 1);
+
+        let mut new_entity = world.spawn_empty();
+        registry
+            .deserialize_component_onto(&components[0], &mut new_entity)
+            .unwrap();
+        assert_eq!(
+            new_entity.get::<ForeignComponent>(),
+            Some(&ForeignComponent(7))
+        );
+    }
+}
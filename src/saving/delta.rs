@@ -0,0 +1,146 @@
+//! Incremental saves. A [`SnapshotDelta`] only contains what changed between a baseline
+//! [`SimSnapshot`] and the current state, and is reassembled into a full snapshot on load by
+//! applying it to the same baseline.
+
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{command::SavedCommands, entity_id::SimEntityId, player::PlayerList};
+
+use super::{
+    snapshot::{EntitySnapshot, ResourceSnapshot, SimSnapshot},
+    SimResourceId,
+};
+
+/// The changes between a baseline [`SimSnapshot`] and a newer one
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotDelta {
+    /// The tick of the baseline this delta must be applied to
+    pub baseline_tick: u64,
+    pub tick: u64,
+    pub next_entity_id: u64,
+    pub player_list: PlayerList,
+    /// Entities that were spawned or changed since the baseline, with all their components
+    pub changed_entities: Vec<EntitySnapshot>,
+    /// Entities in the baseline that no longer exist
+    pub removed_entities: Vec<SimEntityId>,
+    pub changed_resources: Vec<ResourceSnapshot>,
+    /// Resources in the baseline that no longer exist
+    pub removed_resources: Vec<SimResourceId>,
+    /// The full command queue and history of the newer snapshot
+    pub commands: SavedCommands,
+}
+
+impl SnapshotDelta {
+    /// Creates the delta that turns the baseline into the given snapshot
+    pub fn new(baseline: &SimSnapshot, snapshot: &SimSnapshot) -> SnapshotDelta {
+        let baseline_entities: HashMap<SimEntityId, &EntitySnapshot> = baseline
+            .entities
+            .iter()
+            .map(|entity| (entity.entity, entity))
+            .collect();
+        let entities: HashMap<SimEntityId, &EntitySnapshot> = snapshot
+            .entities
+            .iter()
+            .map(|entity| (entity.entity, entity))
+            .collect();
+        let baseline_resources: HashMap<SimResourceId, &ResourceSnapshot> = baseline
+            .resources
+            .iter()
+            .map(|resource| (resource.resource_id, resource))
+            .collect();
+
+        SnapshotDelta {
+            baseline_tick: baseline.tick,
+            tick: snapshot.tick,
+            next_entity_id: snapshot.next_entity_id,
+            player_list: snapshot.player_list.clone(),
+            changed_entities: snapshot
+                .entities
+                .iter()
+                .filter(|entity| baseline_entities.get(&entity.entity) != Some(entity))
+                .cloned()
+                .collect(),
+            removed_entities: baseline
+                .entities
+                .iter()
+                .filter(|entity| !entities.contains_key(&entity.entity))
+                .map(|entity| entity.entity)
+                .collect(),
+            changed_resources: snapshot
+                .resources
+                .iter()
+                .filter(|resource| baseline_resources.get(&resource.resource_id) != Some(resource))
+                .cloned()
+                .collect(),
+            removed_resources: baseline
+                .resources
+                .iter()
+                .filter(|resource| {
+                    !snapshot
+                        .resources
+                        .iter()
+                        .any(|current| current.resource_id == resource.resource_id)
+                })
+                .map(|resource| resource.resource_id)
+                .collect(),
+            commands: snapshot.commands.clone(),
+        }
+    }
+
+    /// Reassembles the full snapshot by applying the delta to the given baseline. Returns None if
+    /// the baseline isn't the one the delta was created from
+    pub fn apply_to(&self, baseline: &SimSnapshot) -> Option<SimSnapshot> {
+        if baseline.tick != self.baseline_tick {
+            return None;
+        }
+
+        let mut entities: Vec<EntitySnapshot> = baseline
+            .entities
+            .iter()
+            .filter(|entity| {
+                !self.removed_entities.contains(&entity.entity)
+                    && !self
+                        .changed_entities
+                        .iter()
+                        .any(|changed| changed.entity == entity.entity)
+            })
+            .cloned()
+            .collect();
+        entities.extend(self.changed_entities.iter().cloned());
+        entities.sort_by_key(|entity| entity.entity);
+
+        let mut resources: Vec<ResourceSnapshot> = baseline
+            .resources
+            .iter()
+            .filter(|resource| {
+                !self.removed_resources.contains(&resource.resource_id)
+                    && !self
+                        .changed_resources
+                        .iter()
+                        .any(|changed| changed.resource_id == resource.resource_id)
+            })
+            .cloned()
+            .collect();
+        resources.extend(self.changed_resources.iter().cloned());
+
+        Some(SimSnapshot {
+            tick: self.tick,
+            next_entity_id: self.next_entity_id,
+            player_list: self.player_list.clone(),
+            entities,
+            resources,
+            commands: self.commands.clone(),
+        })
+    }
+
+    /// Serializes the delta into a single binary blob
+    pub fn to_binary(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserializes a delta from a binary blob created with [`SnapshotDelta::to_binary`]
+    pub fn from_binary(data: &[u8]) -> Result<SnapshotDelta, bincode::Error> {
+        bincode::deserialize(data)
+    }
+}
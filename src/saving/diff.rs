@@ -0,0 +1,242 @@
+//! Comparing snapshots. [`SimSnapshot::diff`] returns a [`SnapshotDiff`] listing every entity,
+//! component, and resource that was added, removed, or changed between two snapshots. Use it to
+//! assert rollback correctness in tests or to find where two sims desynced.
+
+use bevy::utils::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::entity_id::SimEntityId;
+
+use super::{
+    snapshot::{EntitySnapshot, SimSnapshot},
+    SimComponentId, SimResourceId,
+};
+
+/// The component changes of an entity that exists in both snapshots
+#[derive(Clone, Eq, Hash, Debug, PartialEq)]
+pub struct EntityDiff {
+    pub entity: SimEntityId,
+    pub added_components: Vec<SimComponentId>,
+    pub removed_components: Vec<SimComponentId>,
+    pub changed_components: Vec<SimComponentId>,
+}
+
+/// Everything that differs between two snapshots. Every list is sorted by id
+#[derive(Clone, Default, Eq, Hash, Debug, PartialEq)]
+pub struct SnapshotDiff {
+    pub added_entities: Vec<SimEntityId>,
+    pub removed_entities: Vec<SimEntityId>,
+    pub changed_entities: Vec<EntityDiff>,
+    pub added_resources: Vec<SimResourceId>,
+    pub removed_resources: Vec<SimResourceId>,
+    pub changed_resources: Vec<SimResourceId>,
+}
+
+impl SnapshotDiff {
+    /// Returns true if the snapshots hold the same entities, components, and resources
+    pub fn is_empty(&self) -> bool {
+        self.added_entities.is_empty()
+            && self.removed_entities.is_empty()
+            && self.changed_entities.is_empty()
+            && self.added_resources.is_empty()
+            && self.removed_resources.is_empty()
+            && self.changed_resources.is_empty()
+    }
+}
+
+impl Display for SnapshotDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for entity in self.added_entities.iter() {
+            writeln!(f, "+ entity {}", entity.0)?;
+        }
+        for entity in self.removed_entities.iter() {
+            writeln!(f, "- entity {}", entity.0)?;
+        }
+        for entity in self.changed_entities.iter() {
+            writeln!(f, "~ entity {}", entity.entity.0)?;
+            for id in entity.added_components.iter() {
+                writeln!(f, "  + component {}", id)?;
+            }
+            for id in entity.removed_components.iter() {
+                writeln!(f, "  - component {}", id)?;
+            }
+            for id in entity.changed_components.iter() {
+                writeln!(f, "  ~ component {}", id)?;
+            }
+        }
+        for id in self.added_resources.iter() {
+            writeln!(f, "+ resource {}", id)?;
+        }
+        for id in self.removed_resources.iter() {
+            writeln!(f, "- resource {}", id)?;
+        }
+        for id in self.changed_resources.iter() {
+            writeln!(f, "~ resource {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl SimSnapshot {
+    /// Compares this snapshot to the given newer snapshot. Components and resources are compared
+    /// by their serialized bytes
+    pub fn diff(&self, other: &SimSnapshot) -> SnapshotDiff {
+        let entities: HashMap<SimEntityId, &EntitySnapshot> = self
+            .entities
+            .iter()
+            .map(|entity| (entity.entity, entity))
+            .collect();
+        let other_entities: HashMap<SimEntityId, &EntitySnapshot> = other
+            .entities
+            .iter()
+            .map(|entity| (entity.entity, entity))
+            .collect();
+
+        let mut diff = SnapshotDiff::default();
+        for (id, entity) in entities.iter() {
+            match other_entities.get(id) {
+                Some(other_entity) => {
+                    let entity_diff = diff_components(*id, entity, other_entity);
+                    if !entity_diff.added_components.is_empty()
+                        || !entity_diff.removed_components.is_empty()
+                        || !entity_diff.changed_components.is_empty()
+                    {
+                        diff.changed_entities.push(entity_diff);
+                    }
+                }
+                None => diff.removed_entities.push(*id),
+            }
+        }
+        diff.added_entities = other_entities
+            .keys()
+            .filter(|id| !entities.contains_key(*id))
+            .copied()
+            .collect();
+
+        let resources: HashMap<SimResourceId, &Vec<u8>> = self
+            .resources
+            .iter()
+            .map(|resource| (resource.resource_id, &resource.resource))
+            .collect();
+        let other_resources: HashMap<SimResourceId, &Vec<u8>> = other
+            .resources
+            .iter()
+            .map(|resource| (resource.resource_id, &resource.resource))
+            .collect();
+        (
+            diff.added_resources,
+            diff.removed_resources,
+            diff.changed_resources,
+        ) = diff_maps(&resources, &other_resources);
+
+        diff.added_entities.sort();
+        diff.removed_entities.sort();
+        diff.changed_entities.sort_by_key(|entity| entity.entity);
+        diff
+    }
+}
+
+fn diff_components(
+    entity: SimEntityId,
+    snapshot: &EntitySnapshot,
+    other: &EntitySnapshot,
+) -> EntityDiff {
+    let components: HashMap<SimComponentId, &Vec<u8>> = snapshot
+        .components
+        .iter()
+        .map(|component| (component.id, &component.component))
+        .collect();
+    let other_components: HashMap<SimComponentId, &Vec<u8>> = other
+        .components
+        .iter()
+        .map(|component| (component.id, &component.component))
+        .collect();
+    let (added_components, removed_components, changed_components) =
+        diff_maps(&components, &other_components);
+    EntityDiff {
+        entity,
+        added_components,
+        removed_components,
+        changed_components,
+    }
+}
+
+/// Returns the sorted ids that were added, removed, and changed between the two maps
+fn diff_maps(
+    map: &HashMap<u16, &Vec<u8>>,
+    other: &HashMap<u16, &Vec<u8>>,
+) -> (Vec<u16>, Vec<u16>, Vec<u16>) {
+    let mut added: Vec<u16> = other
+        .keys()
+        .filter(|id| !map.contains_key(*id))
+        .copied()
+        .collect();
+    let mut removed = vec![];
+    let mut changed = vec![];
+    for (id, data) in map.iter() {
+        match other.get(id) {
+            Some(other_data) if other_data != data => changed.push(*id),
+            Some(_) => {}
+            None => removed.push(*id),
+        }
+    }
+    added.sort();
+    removed.sort();
+    changed.sort();
+    (added, removed, changed)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        entity_id::SimEntityId,
+        player::PlayerList,
+        saving::{
+            snapshot::{EntitySnapshot, ResourceSnapshot, SimSnapshot},
+            ComponentBinaryState,
+        },
+    };
+
+    fn entity(id: u64, components: &[(u16, u8)]) -> EntitySnapshot {
+        EntitySnapshot {
+            entity: SimEntityId(id),
+            components: components
+                .iter()
+                .map(|(id, value)| ComponentBinaryState {
+                    id: *id,
+                    version: 0,
+                    component: vec![*value],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let snapshot = SimSnapshot {
+            tick: 1,
+            next_entity_id: 3,
+            player_list: PlayerList { players: vec![] },
+            entities: vec![entity(0, &[(1, 0), (2, 0)]), entity(1, &[(1, 0)])],
+            resources: vec![ResourceSnapshot {
+                resource_id: 5,
+                version: 0,
+                resource: vec![0],
+            }],
+            commands: Default::default(),
+        };
+        let mut other = snapshot.clone();
+        other.entities = vec![entity(0, &[(1, 1), (3, 0)]), entity(2, &[(1, 0)])];
+        other.resources[0].resource = vec![1];
+
+        let diff = snapshot.diff(&other);
+        assert_eq!(diff.added_entities, vec![SimEntityId(2)]);
+        assert_eq!(diff.removed_entities, vec![SimEntityId(1)]);
+        assert_eq!(diff.changed_entities.len(), 1);
+        assert_eq!(diff.changed_entities[0].added_components, vec![3]);
+        assert_eq!(diff.changed_entities[0].removed_components, vec![2]);
+        assert_eq!(diff.changed_entities[0].changed_components, vec![1]);
+        assert_eq!(diff.changed_resources, vec![5]);
+        assert!(snapshot.diff(&snapshot).is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! Optional encryption of save bodies, available behind the `encryption` feature. Configure it on
+//! the [`GameSerDeRegistry`] with [`GameSerDeRegistry::save_encryption`] using a key provided by the
+//! game. The [`SaveHeader`](super::header::SaveHeader) stays readable so saves can still be listed
+//! without the key.
+//!
+//! Encryption is applied to the whole save body after it is serialized, so component and state
+//! compression are applied first. Bodies are encrypted with XChaCha20-Poly1305, so saves that
+//! were edited or encrypted with another key fail to load. The key ships with the game, so this
+//! keeps players from editing saves but can't hide their contents from someone who extracts it.
+
+use super::GameSerDeRegistry;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+
+#[cfg(feature = "encryption")]
+const ENCRYPTION_CONTEXT: &str = "bevy_sim_world 2024-01-01 save encryption";
+
+/// Length of the random nonce prefixed to every encrypted save body
+#[cfg(feature = "encryption")]
+pub const NONCE_LEN: usize = 24;
+
+/// A XChaCha20-Poly1305 cipher keyed from a game provided key that encrypts and authenticates
+/// save bodies. Each body is encrypted with a random nonce which is prefixed to it
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+pub struct SaveEncryption {
+    cipher: XChaCha20Poly1305,
+}
+
+#[cfg(feature = "encryption")]
+impl SaveEncryption {
+    pub fn new(key: &[u8]) -> SaveEncryption {
+        let key = blake3::derive_key(ENCRYPTION_CONTEXT, key);
+        SaveEncryption {
+            cipher: XChaCha20Poly1305::new(&key.into()),
+        }
+    }
+
+    /// Encrypts the given data with a new random nonce
+    pub fn encrypt(&self, data: Vec<u8>) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, data.as_slice())
+            .expect("Save body too large to encrypt");
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(ciphertext);
+        encrypted
+    }
+
+    /// Decrypts data encrypted with [`SaveEncryption::encrypt`]. Returns None if the data was
+    /// changed or encrypted with a different key
+    pub fn decrypt(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, body) = data.split_at(NONCE_LEN);
+        self.cipher.decrypt(XNonce::from_slice(nonce), body).ok()
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl std::fmt::Debug for SaveEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaveEncryption").finish_non_exhaustive()
+    }
+}
+
+impl GameSerDeRegistry {
+    /// Encrypts the given save body if [`GameSerDeRegistry::save_encryption`] is set and appends
+    /// its checksum if [`GameSerDeRegistry::payload_checksums`] is set
+    pub fn seal_save_body(&self, body: Vec<u8>) -> Vec<u8> {
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.save_encryption {
+            return self.append_checksum(encryption.encrypt(body));
+        }
+        self.append_checksum(body)
+    }
+
+    /// Verifies the checksum of and decrypts the given save body. Returns an error if it fails its
+    /// checksum, wrapping a [`ChecksumError`](super::checksum::ChecksumError) in an
+    /// [`std::io::Error`], or if it can't be decrypted
+    pub fn open_save_body(&self, body: &[u8]) -> Result<Vec<u8>, bincode::Error> {
+        let body = self
+            .verify_checksum(body)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.save_encryption {
+            return encryption.decrypt(body).ok_or_else(|| {
+                Box::new(bincode::ErrorKind::Custom(
+                    "Save was changed or encrypted with a different key".into(),
+                ))
+            });
+        }
+        Ok(body.to_vec())
+    }
+}
+
+#[cfg(all(test, feature = "encryption"))]
+pub mod test {
+    use super::{SaveEncryption, NONCE_LEN};
+
+    #[test]
+    fn test_encryption_round_trip() {
+        let encryption = SaveEncryption::new(b"game key");
+        let data = b"a save body that is longer than one keystream block of 64 bytes".to_vec();
+
+        let encrypted = encryption.encrypt(data.clone());
+        assert_ne!(encrypted[NONCE_LEN..data.len() + NONCE_LEN], data[..]);
+        assert_ne!(encryption.encrypt(data.clone()), encrypted);
+        assert_eq!(encryption.decrypt(&encrypted), Some(data));
+
+        let mut edited = encrypted.clone();
+        edited[40] ^= 1;
+        assert_eq!(encryption.decrypt(&edited), None);
+        assert_eq!(SaveEncryption::new(b"other key").decrypt(&encrypted), None);
+    }
+}
@@ -0,0 +1,279 @@
+//! Field level deltas for large components that change a little every tick. A component that
+//! implements [`DeltaSerialize`] and is registered with
+//! [`GameBuilder::register_component_delta`](crate::game_builder::GameBuilder::register_component_delta)
+//! is sent in [`StateDif`](crate::requests::state_dif::StateDif) as only its changed fields, in
+//! [`EntityState::component_deltas`], once the player has been sent the component in full.
+//! Components that didn't change at all are left out. Apply received deltas with
+//! [`GameSerDeRegistry::deserialize_component_delta_onto`].
+//!
+//! Deltas are computed against the last value sent to each player, kept in the [`DeltaBaselines`]
+//! resource, so state must be delivered reliably and in order. When state acks are enabled
+//! entities waiting on an ack are always sent in full.
+
+use bevy::{
+    prelude::{Component, EntityWorldMut, Resource},
+    utils::HashMap,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{entity_id::SimEntityId, requests::EntityState};
+
+use super::{
+    ComponentBinaryState, DecodeError, DeserializeError, GameSerDeRegistry, SaveId, SimComponentId,
+    SimFormat, SimSerializer,
+};
+
+/// A component that can be serialized as only the fields that changed
+pub trait DeltaSerialize: Sized {
+    /// The changed fields, usually a struct of the components fields wrapped in [`Option`]
+    type Delta: Serialize + DeserializeOwned;
+
+    /// Returns the fields of self that differ from previous, or None if nothing changed
+    fn delta_from(&self, previous: &Self) -> Option<Self::Delta>;
+
+    /// Applies the given changed fields to self
+    fn apply_delta(&mut self, delta: Self::Delta);
+}
+
+/// Encodes the delta between two serialized values of a component. Returns None if they're equal
+pub type ComponentDeltaEncodeFn =
+    fn(format: SimFormat, previous: &[u8], current: &[u8]) -> Result<Option<Vec<u8>>, DecodeError>;
+
+/// Applies a serialized delta to the component on the given entity
+pub type ComponentDeltaApplyFn =
+    fn(format: SimFormat, delta: &[u8], entity: &mut EntityWorldMut) -> Result<(), DecodeError>;
+
+/// The delta functions of a component registered with
+/// [`GameSerDeRegistry::register_component_delta`]
+#[derive(Clone, Copy, Debug)]
+pub struct ComponentDeltaFns {
+    pub encode: ComponentDeltaEncodeFn,
+    pub apply: ComponentDeltaApplyFn,
+}
+
+/// Resource inserted into the sim world holding the last serialized value of every delta component
+/// sent to each player, keyed by player id
+#[derive(Clone, Eq, Debug, PartialEq, Default, Resource)]
+pub struct DeltaBaselines {
+    pub players: HashMap<usize, HashMap<(SimEntityId, SimComponentId), Vec<u8>>>,
+}
+
+impl DeltaBaselines {
+    /// Removes the baselines of the given despawned entity for every player
+    pub fn remove_entity(&mut self, entity: SimEntityId) {
+        for baselines in self.players.values_mut() {
+            baselines.retain(|(baseline_entity, _), _| *baseline_entity != entity);
+        }
+    }
+}
+
+impl GameSerDeRegistry {
+    /// Registers the delta functions of an already registered component so it is sent as only its
+    /// changed fields, see [`field_delta`](crate::saving::field_delta)
+    pub fn register_component_delta<C>(&mut self)
+    where
+        C: Component + DeltaSerialize + Serialize + DeserializeOwned + SaveId,
+    {
+        self.component_delta_map.insert(
+            C::save_id_const(),
+            ComponentDeltaFns {
+                encode: component_delta_encode::<C>,
+                apply: component_delta_apply::<C>,
+            },
+        );
+    }
+
+    /// Applies the given component delta to the component already on the given entity
+    pub fn deserialize_component_delta_onto(
+        &self,
+        data: &ComponentBinaryState,
+        entity: &mut EntityWorldMut,
+    ) -> Result<(), DeserializeError> {
+        let Some(delta_fns) = self.component_delta_map.get(&data.id) else {
+            return Err(DeserializeError::UnregisteredComponent { id: data.id });
+        };
+        let payload = self
+            .verify_checksum(&data.component)
+            .map_err(|error| DeserializeError::ComponentChecksum { id: data.id, error })?;
+        self.component_compression
+            .decompress(payload)
+            .ok_or_else(|| DecodeError::new(0, "failed to decompress"))
+            .and_then(|delta| (delta_fns.apply)(self.format, &delta, entity))
+            .map_err(|error| DeserializeError::Component { id: data.id, error })
+    }
+
+    /// Replaces the delta components of the given entity state with their deltas against the
+    /// given baselines and updates the baselines. Components sent in full are kept when full is set
+    pub(crate) fn encode_entity_deltas(
+        &self,
+        baselines: &mut HashMap<(SimEntityId, SimComponentId), Vec<u8>>,
+        entity_state: &mut EntityState,
+        full: bool,
+    ) {
+        let components = std::mem::take(&mut entity_state.components);
+        for component in components {
+            let Some(delta_fns) = self.component_delta_map.get(&component.id) else {
+                entity_state.components.push(component);
+                continue;
+            };
+            let key = (entity_state.entity, component.id);
+            let previous = baselines.insert(key, component.component.clone());
+            let delta = match previous {
+                Some(previous) if !full => self
+                    .unpack_payload(&previous)
+                    .zip(self.unpack_payload(&component.component))
+                    .and_then(|(previous, current)| {
+                        (delta_fns.encode)(self.format, &previous, &current).ok()
+                    }),
+                _ => None,
+            };
+            match delta {
+                // Unchanged since the last value sent
+                Some(None) => {}
+                Some(Some(delta)) => entity_state.component_deltas.push(ComponentBinaryState {
+                    id: component.id,
+                    version: component.version,
+                    component: self.pack_payload(delta),
+                }),
+                None => entity_state.components.push(component),
+            }
+        }
+    }
+
+    /// Verifies and decompresses a payload packed with [`GameSerDeRegistry::pack_payload`]
+    fn unpack_payload(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let data = self.verify_checksum(data).ok()?;
+        self.component_compression.decompress(data)
+    }
+}
+
+/// Encodes the delta between the two serialized values of the component C
+pub fn component_delta_encode<C>(
+    format: SimFormat,
+    previous: &[u8],
+    current: &[u8],
+) -> Result<Option<Vec<u8>>, DecodeError>
+where
+    C: DeltaSerialize + DeserializeOwned,
+{
+    let previous = format.try_decode::<C>(previous)?;
+    let current = format.try_decode::<C>(current)?;
+    let Some(delta) = current.delta_from(&previous) else {
+        return Ok(None);
+    };
+    format
+        .encode(&delta)
+        .map(Some)
+        .ok_or_else(|| DecodeError::new(0, "failed to encode delta"))
+}
+
+/// Applies a serialized delta of the component C to the component on the given entity
+pub fn component_delta_apply<C>(
+    format: SimFormat,
+    delta: &[u8],
+    entity: &mut EntityWorldMut,
+) -> Result<(), DecodeError>
+where
+    C: Component + DeltaSerialize,
+{
+    let delta = format.try_decode::<C::Delta>(delta)?;
+    let Some(mut component) = entity.get_mut::<C>() else {
+        return Err(DecodeError::new(0, "entity doesn't have the component yet"));
+    };
+    component.apply_delta(delta);
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Component, World};
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        requests::state_dif::StateDif,
+        testing::{fixtures::bincode_save_id, test_game},
+    };
+
+    use super::DeltaSerialize;
+
+    #[derive(Clone, Debug, PartialEq, Component, Serialize, Deserialize)]
+    struct Unit {
+        position: (i32, i32),
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct UnitDelta {
+        position: Option<(i32, i32)>,
+        name: Option<String>,
+    }
+
+    impl DeltaSerialize for Unit {
+        type Delta = UnitDelta;
+
+        fn delta_from(&self, previous: &Self) -> Option<UnitDelta> {
+            let delta = UnitDelta {
+                position: (self.position != previous.position).then_some(self.position),
+                name: (self.name != previous.name).then(|| self.name.clone()),
+            };
+            (delta.position.is_some() || delta.name.is_some()).then_some(delta)
+        }
+
+        fn apply_delta(&mut self, delta: UnitDelta) {
+            if let Some(position) = delta.position {
+                self.position = position;
+            }
+            if let Some(name) = delta.name {
+                self.name = name;
+            }
+        }
+    }
+
+    bincode_save_id!(Unit, 25);
+
+    #[test]
+    fn test_field_deltas() {
+        let mut game = test_game();
+        game.register_component_delta::<Unit>().unwrap();
+        let mut sim = game.build_standalone();
+
+        let unit = sim
+            .sim_world
+            .world
+            .spawn(Unit {
+                position: (0, 0),
+                name: "knight".into(),
+            })
+            .id();
+        sim.simulate();
+        let full_state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(full_state.entities[0].components.len(), 1);
+        assert!(full_state.entities[0].component_deltas.is_empty());
+
+        sim.sim_world.world.get_mut::<Unit>(unit).unwrap().position = (1, 0);
+        sim.simulate();
+        let delta_state = sim.request(StateDif { for_player: 0 });
+        assert!(delta_state.entities[0].components.is_empty());
+        assert_eq!(delta_state.entities[0].component_deltas.len(), 1);
+
+        let mut client = World::new();
+        let mut client_unit = client.spawn_empty();
+        let registry = &sim.sim_world.registry;
+        registry
+            .deserialize_component_onto(&full_state.entities[0].components[0], &mut client_unit)
+            .unwrap();
+        registry
+            .deserialize_component_delta_onto(
+                &delta_state.entities[0].component_deltas[0],
+                &mut client_unit,
+            )
+            .unwrap();
+        assert_eq!(
+            client_unit.get::<Unit>(),
+            Some(&Unit {
+                position: (1, 0),
+                name: "knight".into(),
+            })
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! The header written in front of every save created with
+//! [`SimWorld::save_snapshot`](crate::SimWorld::save_snapshot). The header can be read without
+//! deserializing the rest of the save, for save slot UIs and to reject incompatible saves early.
+//!
+//! A save is laid out as the length of the header as a little endian u32, the bincode encoded
+//! [`SaveHeader`], and then the body.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    hash::Hasher,
+    io::{Read, Write},
+};
+
+use super::{checksum::Fnv1a, GameSerDeRegistry};
+
+/// Metadata written in front of a save
+#[derive(Clone, Eq, Hash, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SaveHeader {
+    /// The version of this crate that wrote the save
+    pub crate_version: String,
+    pub saved_at: DateTime<Utc>,
+    /// The [`GameSerDeRegistry::registry_hash`] of the registry that wrote the save
+    pub registry_hash: u64,
+    /// A user defined name for the save
+    pub name: String,
+}
+
+impl SaveHeader {
+    /// Creates a header for a save made now with the given registry
+    pub fn new(registry: &GameSerDeRegistry, name: impl Into<String>) -> SaveHeader {
+        SaveHeader {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            saved_at: Utc::now(),
+            registry_hash: registry.registry_hash(),
+            name: name.into(),
+        }
+    }
+
+    /// Returns true if the save was written with a registry matching the given one
+    pub fn is_compatible(&self, registry: &GameSerDeRegistry) -> bool {
+        self.registry_hash == registry.registry_hash()
+    }
+
+    /// Writes the header followed by the given body
+    pub fn write(&self, body: &[u8]) -> Result<Vec<u8>, bincode::Error> {
+        let mut data = Vec::with_capacity(body.len() + 64);
+        self.write_to(&mut data, body)?;
+        Ok(data)
+    }
+
+    /// Writes the header followed by the given body to the given writer
+    pub fn write_to(&self, mut writer: impl Write, body: &[u8]) -> Result<(), bincode::Error> {
+        let header = bincode::serialize(self)?;
+        writer.write_all(&(header.len() as u32).to_le_bytes())?;
+        writer.write_all(&header)?;
+        writer.write_all(body)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads only the header from the given reader, leaving the reader at the start of the body
+    pub fn read_from(mut reader: impl Read) -> Result<SaveHeader, bincode::Error> {
+        let mut length = [0u8; 4];
+        reader.read_exact(&mut length)?;
+        let mut header = vec![0u8; u32::from_le_bytes(length) as usize];
+        reader.read_exact(&mut header)?;
+        bincode::deserialize(&header)
+    }
+
+    /// Reads only the header of the given save
+    pub fn read(data: &[u8]) -> Result<SaveHeader, bincode::Error> {
+        Ok(SaveHeader::split(data)?.0)
+    }
+
+    /// Reads the header of the given save and returns it along with the body
+    pub fn split(data: &[u8]) -> Result<(SaveHeader, &[u8]), bincode::Error> {
+        let invalid = || {
+            Box::new(bincode::ErrorKind::Custom(
+                "Save is missing its header".into(),
+            ))
+        };
+        let length: [u8; 4] = data
+            .get(..4)
+            .ok_or_else(invalid)?
+            .try_into()
+            .map_err(|_| invalid())?;
+        let length = u32::from_le_bytes(length) as usize;
+        let header = data.get(4..4 + length).ok_or_else(invalid)?;
+        Ok((bincode::deserialize(header)?, &data[4 + length..]))
+    }
+}
+
+impl GameSerDeRegistry {
+    /// Returns a hash of the id and type name of every registered component and resource, and of
+    /// the registries format. Two registries with the same hash read and write the same saves
+    pub fn registry_hash(&self) -> u64 {
+        let mut components: Vec<_> = self.component_type_names.iter().collect();
+        components.sort();
+        let mut resources: Vec<_> = self.resource_type_names.iter().collect();
+        resources.sort();
+
+        let mut hasher = Fnv1a::default();
+        hasher.write(format!("{:?}", self.format).as_bytes());
+        if self.payload_checksums {
+            hasher.write(b"checksums");
+        }
+        for (id, type_name) in components {
+            hasher.write(&id.to_le_bytes());
+            hasher.write(type_name.as_bytes());
+        }
+        hasher.write(b"resources");
+        for (id, type_name) in resources {
+            hasher.write(&id.to_le_bytes());
+            hasher.write(type_name.as_bytes());
+        }
+        hasher.finish()
+    }
+}
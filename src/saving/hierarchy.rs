@@ -0,0 +1,85 @@
+//! Serialization of entity hierarchies. Registered with
+//! [`GameSerDeRegistry::register_hierarchy`], every entity with a [`Parent`] is saved with a
+//! [`SimParent`] holding the [`SimEntityId`] of its parent. [`Children`](bevy::prelude::Children)
+//! aren't saved, they are rebuilt from the parents when the state is deserialized with
+//! [`GameSerDeRegistry::deserialize_component_onto_mapped`].
+
+use bevy::{
+    prelude::{BuildWorldChildren, Component, Entity, EntityWorldMut, Parent, World},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+
+use crate::entity_id::SimEntityId;
+
+use super::{
+    DecodeError, GameSerDeRegistry, RegistryError, SimComponentId, SimFormat, SimSerializer,
+};
+
+/// The [`SimComponentId`] reserved for [`SimParent`]
+pub const HIERARCHY_COMPONENT_ID: SimComponentId = 2;
+
+/// The saved parent of an entity. Stays on the entity if it was deserialized without a map, and is
+/// replaced by a [`Parent`] when it was deserialized with one
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq, Component, Serialize, Deserialize)]
+pub struct SimParent(pub SimEntityId);
+
+impl GameSerDeRegistry {
+    /// Registers [`Parent`] for serialization so entity hierarchies are included in state and saves.
+    /// Returns an error if [`HIERARCHY_COMPONENT_ID`] is already used
+    pub fn register_hierarchy(&mut self) -> Result<(), RegistryError> {
+        self.check_component_id(HIERARCHY_COMPONENT_ID, std::any::type_name::<SimParent>())?;
+        self.component_de_map
+            .insert(HIERARCHY_COMPONENT_ID, sim_parent_deserialize_onto);
+        self.component_se_map
+            .insert(HIERARCHY_COMPONENT_ID, sim_parent_serialize_from);
+        self.component_map_entities_map
+            .insert(HIERARCHY_COMPONENT_ID, sim_parent_map_entities);
+        self.component_type_names
+            .insert(HIERARCHY_COMPONENT_ID, std::any::type_name::<SimParent>());
+        self.component_type_ids
+            .insert(TypeId::of::<Parent>(), HIERARCHY_COMPONENT_ID);
+        Ok(())
+    }
+}
+
+/// Serializes the [`SimEntityId`] of the given entities parent, if it has one.
+pub fn sim_parent_serialize_from(
+    format: SimFormat,
+    world: &World,
+    entity: Entity,
+) -> Option<Vec<u8>> {
+    let parent = world.get::<Parent>(entity)?;
+    let parent_id = world.get::<SimEntityId>(parent.get())?;
+    format.encode(&SimParent(*parent_id))
+}
+
+/// Deserializes a [`SimParent`] onto the given entity.
+#[allow(clippy::ptr_arg)]
+pub fn sim_parent_deserialize_onto(
+    format: SimFormat,
+    data: &Vec<u8>,
+    entity: &mut EntityWorldMut,
+) -> Result<(), DecodeError> {
+    entity.insert(format.try_decode::<SimParent>(data)?);
+    Ok(())
+}
+
+/// Replaces the [`SimParent`] on the given entity with a [`Parent`] pointing at the mapped entity.
+pub fn sim_parent_map_entities(
+    entity: &mut EntityWorldMut,
+    entity_map: &HashMap<SimEntityId, Entity>,
+) {
+    let Some(sim_parent) = entity.take::<SimParent>() else {
+        return;
+    };
+    match entity_map.get(&sim_parent.0) {
+        Some(parent) => {
+            entity.set_parent(*parent);
+        }
+        None => {
+            entity.insert(sim_parent);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! A ring buffer of recent snapshots for rollback and resimulation. Enable it with
+//! [`GameBuilder::enable_snapshot_history`](crate::game_builder::GameBuilder::enable_snapshot_history)
+//! and the [`GameRuntime`](crate::runner::GameRuntime) records a snapshot into the
+//! [`SnapshotHistory`] resource after every simulated tick.
+//!
+//! Every [`SnapshotHistory::keyframe_interval`] ticks a full keyframe is stored, the ticks in
+//! between are stored as a [`SnapshotDelta`] against the latest keyframe. Once the history holds
+//! more than [`SnapshotHistory::capacity`] snapshots the oldest are pruned.
+
+use bevy::prelude::{Mut, Resource, World};
+use std::collections::VecDeque;
+
+use crate::player::PlayerList;
+
+use super::{
+    delta::SnapshotDelta,
+    snapshot::{SaveFilter, SimSnapshot},
+    GameSerDeRegistry,
+};
+
+/// A snapshot stored in the [`SnapshotHistory`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum HistoryEntry {
+    Keyframe(SimSnapshot),
+    /// A delta against the closest older keyframe
+    Delta(SnapshotDelta),
+}
+
+impl HistoryEntry {
+    pub fn tick(&self) -> u64 {
+        match self {
+            HistoryEntry::Keyframe(snapshot) => snapshot.tick,
+            HistoryEntry::Delta(delta) => delta.tick,
+        }
+    }
+}
+
+/// Resource holding the last [`SnapshotHistory::capacity`] snapshots of the sim, oldest first
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct SnapshotHistory {
+    /// The maximum number of snapshots kept
+    pub capacity: usize,
+    /// The number of ticks between full keyframes
+    pub keyframe_interval: u32,
+    entries: VecDeque<HistoryEntry>,
+    deltas_since_keyframe: u32,
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> SnapshotHistory {
+        SnapshotHistory {
+            capacity,
+            keyframe_interval: 10,
+            entries: VecDeque::with_capacity(capacity),
+            deltas_since_keyframe: 0,
+        }
+    }
+
+    /// Returns the history with the given number of ticks between keyframes
+    pub fn with_keyframe_interval(mut self, keyframe_interval: u32) -> SnapshotHistory {
+        self.keyframe_interval = keyframe_interval.max(1);
+        self
+    }
+
+    /// Stores the given snapshot, replacing any stored snapshots at or after its tick, and prunes
+    /// the oldest snapshots if the history is over capacity
+    pub fn push(&mut self, snapshot: SimSnapshot) {
+        self.truncate_after(snapshot.tick.saturating_sub(1));
+        let keyframe = self.latest_keyframe();
+        match keyframe {
+            Some(keyframe) if self.deltas_since_keyframe + 1 < self.keyframe_interval => {
+                let delta = SnapshotDelta::new(keyframe, &snapshot);
+                self.entries.push_back(HistoryEntry::Delta(delta));
+                self.deltas_since_keyframe += 1;
+            }
+            _ => {
+                self.entries.push_back(HistoryEntry::Keyframe(snapshot));
+                self.deltas_since_keyframe = 0;
+            }
+        }
+        while self.entries.len() > self.capacity {
+            self.pop_oldest();
+        }
+    }
+
+    /// Returns the full snapshot stored for the given tick
+    pub fn get(&self, tick: u64) -> Option<SimSnapshot> {
+        let index = self.entries.iter().position(|entry| entry.tick() == tick)?;
+        match &self.entries[index] {
+            HistoryEntry::Keyframe(snapshot) => Some(snapshot.clone()),
+            HistoryEntry::Delta(delta) => {
+                let keyframe = self
+                    .entries
+                    .range(..index)
+                    .rev()
+                    .find_map(|entry| match entry {
+                        HistoryEntry::Keyframe(snapshot) => Some(snapshot),
+                        HistoryEntry::Delta(_) => None,
+                    })?;
+                delta.apply_to(keyframe)
+            }
+        }
+    }
+
+    /// Returns the full snapshot of the newest stored tick at or before the given tick
+    pub fn get_at_or_before(&self, tick: u64) -> Option<SimSnapshot> {
+        let stored_tick = self
+            .entries
+            .iter()
+            .rev()
+            .map(HistoryEntry::tick)
+            .find(|stored_tick| *stored_tick <= tick)?;
+        self.get(stored_tick)
+    }
+
+    /// Returns the ticks of every stored snapshot, oldest first
+    pub fn ticks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.entries.iter().map(HistoryEntry::tick)
+    }
+
+    pub fn oldest_tick(&self) -> Option<u64> {
+        self.entries.front().map(HistoryEntry::tick)
+    }
+
+    pub fn latest_tick(&self) -> Option<u64> {
+        self.entries.back().map(HistoryEntry::tick)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes every snapshot after the given tick, such as after rolling back to it
+    pub fn truncate_after(&mut self, tick: u64) {
+        while self.latest_tick().is_some_and(|latest| latest > tick) {
+            self.entries.pop_back();
+        }
+        self.count_deltas_since_keyframe();
+    }
+
+    /// Removes every snapshot before the given tick
+    pub fn prune_before(&mut self, tick: u64) {
+        while self.oldest_tick().is_some_and(|oldest| oldest < tick) {
+            self.pop_oldest();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.deltas_since_keyframe = 0;
+    }
+
+    fn latest_keyframe(&self) -> Option<&SimSnapshot> {
+        self.entries.iter().rev().find_map(|entry| match entry {
+            HistoryEntry::Keyframe(snapshot) => Some(snapshot),
+            HistoryEntry::Delta(_) => None,
+        })
+    }
+
+    /// Removes the oldest snapshot. Deltas against it are turned into keyframes or rebased onto the
+    /// next oldest snapshot so every remaining snapshot can still be reassembled
+    fn pop_oldest(&mut self) {
+        let Some(HistoryEntry::Keyframe(keyframe)) = self.entries.pop_front() else {
+            return;
+        };
+        let Some(HistoryEntry::Delta(delta)) = self.entries.front() else {
+            return;
+        };
+        let Some(new_keyframe) = delta.apply_to(&keyframe) else {
+            return;
+        };
+        for entry in self.entries.iter_mut().skip(1) {
+            let HistoryEntry::Delta(delta) = entry else {
+                break;
+            };
+            if let Some(snapshot) = delta.apply_to(&keyframe) {
+                *delta = SnapshotDelta::new(&new_keyframe, &snapshot);
+            }
+        }
+        self.entries[0] = HistoryEntry::Keyframe(new_keyframe);
+        self.count_deltas_since_keyframe();
+    }
+
+    fn count_deltas_since_keyframe(&mut self) {
+        self.deltas_since_keyframe = self
+            .entries
+            .iter()
+            .rev()
+            .take_while(|entry| matches!(entry, HistoryEntry::Delta(_)))
+            .count() as u32;
+    }
+}
+
+/// Records a snapshot of the given world into its [`SnapshotHistory`] if it has one. Called by the
+/// [`GameRuntime`](crate::runner::GameRuntime) after every simulated tick
+pub fn record_snapshot_history(world: &mut World) {
+    if !world.contains_resource::<SnapshotHistory>() {
+        return;
+    }
+    if let Some(snapshot) = snapshot_world(world) {
+        world.resource_mut::<SnapshotHistory>().push(snapshot);
+    }
+}
+
+/// Creates a snapshot of the given sim world using its [`GameSerDeRegistry`] and [`PlayerList`]
+/// resources. Returns None if the world has no registry
+pub fn snapshot_world(world: &mut World) -> Option<SimSnapshot> {
+    if !world.contains_resource::<GameSerDeRegistry>() {
+        return None;
+    }
+    let player_list = world
+        .get_resource::<PlayerList>()
+        .cloned()
+        .unwrap_or(PlayerList { players: vec![] });
+    Some(
+        world.resource_scope(|world, registry: Mut<GameSerDeRegistry>| {
+            SimSnapshot::from_world(world, &registry, &player_list, &SaveFilter::default())
+        }),
+    )
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        entity_id::SimEntityId,
+        player::PlayerList,
+        saving::{
+            history::{HistoryEntry, SnapshotHistory},
+            snapshot::{EntitySnapshot, SimSnapshot},
+            ComponentBinaryState,
+        },
+    };
+
+    fn snapshot(tick: u64) -> SimSnapshot {
+        SimSnapshot {
+            tick,
+            next_entity_id: 1,
+            player_list: PlayerList { players: vec![] },
+            entities: vec![EntitySnapshot {
+                entity: SimEntityId(0),
+                components: vec![ComponentBinaryState {
+                    id: 1,
+                    version: 0,
+                    component: vec![tick as u8],
+                }],
+            }],
+            resources: vec![],
+            commands: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_history() {
+        let mut history = SnapshotHistory::new(5).with_keyframe_interval(3);
+        for tick in 1..=8 {
+            history.push(snapshot(tick));
+        }
+        assert_eq!(history.ticks().collect::<Vec<u64>>(), vec![4, 5, 6, 7, 8]);
+        assert!(matches!(
+            history.entries.front(),
+            Some(HistoryEntry::Keyframe(_))
+        ));
+        for tick in 4..=8 {
+            assert_eq!(history.get(tick), Some(snapshot(tick)));
+        }
+        assert_eq!(history.get(3), None);
+
+        history.push(snapshot(6));
+        assert_eq!(history.latest_tick(), Some(6));
+        assert_eq!(history.get_at_or_before(10), Some(snapshot(6)));
+    }
+}
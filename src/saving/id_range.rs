@@ -0,0 +1,138 @@
+//! Id ranges reserved by plugins. Independent content crates reserve a range of ids with
+//! [`GameSerDeRegistry::reserve_id_range`] and register their types inside it with
+//! [`GameSerDeRegistry::in_id_range`]. Registering an id outside the active range, or an id inside a
+//! range reserved by someone else, fails so collisions between crates are caught at registration.
+//!
+//! Ranges apply to both component and resource ids. Ids assigned by
+//! [`GameSerDeRegistry::register_reflect`] are hashed and aren't checked.
+
+use std::ops::RangeInclusive;
+
+use super::{GameSerDeRegistry, RegistryError};
+
+/// A range of ids reserved by a plugin
+#[derive(Clone, Eq, Hash, Debug, PartialEq)]
+pub struct IdRange {
+    pub owner: &'static str,
+    pub ids: RangeInclusive<u16>,
+}
+
+impl IdRange {
+    /// Returns true if the ranges share any id
+    pub fn overlaps(&self, other: &IdRange) -> bool {
+        self.ids.start() <= other.ids.end() && other.ids.start() <= self.ids.end()
+    }
+}
+
+impl GameSerDeRegistry {
+    /// Reserves the given range of ids for the given owner. Returns an error if it overlaps a range
+    /// reserved by another owner, the error is also recorded for [`GameSerDeRegistry::validate`]
+    pub fn reserve_id_range(
+        &mut self,
+        owner: &'static str,
+        ids: RangeInclusive<u16>,
+    ) -> Result<(), RegistryError> {
+        self.insert_id_range(IdRange { owner, ids })
+            .inspect_err(|error| self.registration_errors.push(error.clone()))
+    }
+
+    /// Adds the given range unless it overlaps a range reserved by another owner
+    pub(crate) fn insert_id_range(&mut self, range: IdRange) -> Result<(), RegistryError> {
+        if self.id_ranges.contains(&range) {
+            return Ok(());
+        }
+        if let Some(existing) = self
+            .id_ranges
+            .iter()
+            .find(|existing| existing.overlaps(&range))
+        {
+            return Err(RegistryError::OverlappingIdRange {
+                owner: range.owner,
+                existing: existing.owner,
+            });
+        }
+        self.id_ranges.push(range);
+        Ok(())
+    }
+
+    /// Runs the given function with the range reserved by the given owner active, so every id
+    /// registered inside it must be in that range
+    pub fn in_id_range<T>(
+        &mut self,
+        owner: &'static str,
+        register: impl FnOnce(&mut GameSerDeRegistry) -> T,
+    ) -> T {
+        let previous = self.active_id_range.replace(owner);
+        let result = register(self);
+        self.active_id_range = previous;
+        result
+    }
+
+    /// Returns an error for the given id if it is outside the active range or inside a range
+    /// reserved by another owner and records it
+    pub(crate) fn check_id_range(
+        &mut self,
+        id: u16,
+        type_name: &'static str,
+    ) -> Result<(), RegistryError> {
+        let owner = self
+            .id_ranges
+            .iter()
+            .find(|range| range.ids.contains(&id))
+            .map(|range| range.owner);
+        let error = match (self.active_id_range, owner) {
+            (Some(active), owner) if owner != Some(active) => RegistryError::IdOutsideRange {
+                id,
+                owner: active,
+                type_name,
+            },
+            (None, Some(owner)) => RegistryError::IdInReservedRange {
+                id,
+                owner,
+                type_name,
+            },
+            _ => return Ok(()),
+        };
+        self.registration_errors.push(error.clone());
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        saving::{GameSerDeRegistry, RegistryError},
+        testing::fixtures::bincode_save_id,
+    };
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct TestComponent(u32);
+
+    bincode_save_id!(TestComponent, 1000);
+
+    #[test]
+    fn test_id_ranges() {
+        let mut registry = GameSerDeRegistry::new();
+        registry.reserve_id_range("plugin", 1000..=1999).unwrap();
+        assert!(registry.reserve_id_range("other", 1500..=2499).is_err());
+        registry.reserve_id_range("other", 2000..=2999).unwrap();
+
+        assert!(matches!(
+            registry.register_component::<TestComponent>(),
+            Err(RegistryError::IdInReservedRange { .. })
+        ));
+        assert!(matches!(
+            registry.in_id_range("other", |registry| registry
+                .register_component::<TestComponent>()),
+            Err(RegistryError::IdOutsideRange { .. })
+        ));
+        registry
+            .in_id_range("plugin", |registry| {
+                registry.register_component::<TestComponent>()
+            })
+            .unwrap();
+    }
+}
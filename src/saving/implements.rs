@@ -1,6 +1,6 @@
 use crate::player::{Player, PlayerMarker};
 
-use super::{SimComponentId, SaveId};
+use super::{SaveId, SimComponentId};
 
 impl SaveId for PlayerMarker {
     fn save_id(&self) -> SimComponentId {
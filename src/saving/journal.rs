@@ -0,0 +1,144 @@
+//! Crash recovery for long running sims. A [`CommandJournal`] inserted into the sim world with
+//! [`GameBuilder::enable_command_journal`](crate::game_builder::GameBuilder::enable_command_journal)
+//! appends every executed command to an append-only file on disk. After a crash, load the last
+//! snapshot saved with [`StandaloneSim::save_snapshot`] and replay the journal on top of it with
+//! [`StandaloneSim::recover`].
+//!
+//! Truncate the journal with [`CommandJournal::truncate`] after saving a snapshot to keep it short.
+//! Commands already included in the snapshot are skipped when recovering, so truncating is optional.
+
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    command::SavedCommand,
+    replay::{ReplayLog, ReplayRunner},
+    runner::{GameRunner, StandaloneSim},
+};
+
+/// A single entry of a [`CommandJournal`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum JournalEntry {
+    /// The sim started executing the commands of the given tick
+    Tick(u64),
+    /// A command that executed successfully
+    Command(SavedCommand),
+}
+
+/// Resource inserted into the sim world that appends every executed command to a file
+#[derive(Resource)]
+pub struct CommandJournal {
+    pub path: PathBuf,
+    file: File,
+}
+
+impl CommandJournal {
+    /// Opens the journal at the given path, creating it if it doesn't exist. New entries are
+    /// appended after the existing ones
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<CommandJournal> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(CommandJournal { path, file })
+    }
+
+    /// Appends the given entry to the journal and flushes it to disk. Each entry is written as its
+    /// length followed by the entry
+    pub fn append(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        let data = bincode::serialize(entry).map_err(io::Error::other)?;
+        let mut record = Vec::with_capacity(data.len() + 4);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(&data);
+        self.file.write_all(&record)?;
+        self.file.sync_data()
+    }
+
+    /// Removes every entry from the journal
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)
+    }
+}
+
+/// Reads the journal at the given path into a [`ReplayLog`]. An incomplete last entry, left by a
+/// crash while it was written, is ignored
+pub fn read_journal(path: &Path) -> io::Result<ReplayLog> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut log = ReplayLog::default();
+    loop {
+        let mut length = [0; 4];
+        if reader.read_exact(&mut length).is_err() {
+            break;
+        }
+        // Read through `take` so a corrupted length can't allocate more than the file holds
+        let length = u32::from_le_bytes(length) as usize;
+        let mut data = Vec::new();
+        if (&mut reader)
+            .take(length as u64)
+            .read_to_end(&mut data)
+            .is_err()
+            || data.len() != length
+        {
+            break;
+        }
+        match bincode::deserialize::<JournalEntry>(&data) {
+            Ok(JournalEntry::Tick(tick)) => log.end_tick = tick,
+            Ok(JournalEntry::Command(command)) => log.commands.push(command),
+            Err(_) => break,
+        }
+    }
+    Ok(log)
+}
+
+impl<T> StandaloneSim<T>
+where
+    T: GameRunner,
+{
+    /// Loads the given snapshot and replays every command in the journal at the given path executed
+    /// at or after the tick of the snapshot. Recover into a sim without a [`CommandJournal`] so the
+    /// replayed commands aren't journaled twice
+    pub fn recover(&mut self, snapshot: &[u8], journal_path: &Path) -> Result<(), String> {
+        self.load_snapshot(snapshot)
+            .map_err(|error| error.to_string())?;
+        let mut log = read_journal(journal_path).map_err(|error| error.to_string())?;
+        let tick = self.sim_world.tick();
+        log.commands.retain(|command| command.tick >= tick);
+        ReplayRunner::new(log).run(self)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::CommandJournal;
+    use crate::testing::fixtures::{build_sim, unit_values, SpawnUnit};
+
+    #[test]
+    fn test_journal_recovery() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_sim_world_test_journal_{}.log",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let journal = CommandJournal::open(&path).unwrap();
+        let mut sim = build_sim(|game| game.enable_command_journal(journal));
+        sim.sim_world.game_commands_mut().add(SpawnUnit(1));
+        sim.simulate();
+        let snapshot = sim.save_snapshot("journal").unwrap();
+        sim.sim_world.game_commands_mut().add(SpawnUnit(2));
+        sim.simulate();
+        sim.simulate();
+        sim.sim_world.game_commands_mut().add(SpawnUnit(3));
+        sim.simulate();
+
+        let mut recovered = build_sim(|_| {});
+        recovered.recover(&snapshot, &path).unwrap();
+        assert_eq!(recovered.sim_world.tick(), sim.sim_world.tick());
+        assert_eq!(unit_values(&mut recovered), vec![1, 2, 3]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,170 @@
+//! Combining registries. Library crates can build and ship their own [`GameSerDeRegistry`] so that
+//! games can merge it into theirs with
+//! [`GameBuilder::merge_registry`](crate::game_builder::GameBuilder::merge_registry) without the
+//! library exposing every type it registers.
+
+use super::{GameSerDeRegistry, RegistryError, SimComponentId, SimResourceId};
+
+impl GameSerDeRegistry {
+    /// Merges every component and resource registered in other into self. Ids registered in both
+    /// registries by the same type are skipped, ids registered by different types are reported as
+    /// errors, recorded for [`GameSerDeRegistry::validate`], and not merged.
+    ///
+    /// Returns the ids of the components and resources that were added
+    pub fn merge(
+        &mut self,
+        other: GameSerDeRegistry,
+    ) -> Result<(Vec<SimComponentId>, Vec<SimResourceId>), Vec<RegistryError>> {
+        let mut errors = vec![];
+        let mut components = vec![];
+        let mut resources = vec![];
+
+        for (id, type_name) in other.component_type_names.iter() {
+            if let Some(existing) = self.component_type_names.get(id) {
+                if existing != type_name {
+                    errors.push(RegistryError::DuplicateComponentId {
+                        id: *id,
+                        existing,
+                        new: type_name,
+                    });
+                }
+                continue;
+            }
+            self.component_type_names.insert(*id, type_name);
+            if let Some(deserialize_fn) = other.component_de_map.get(id) {
+                self.component_de_map.insert(*id, *deserialize_fn);
+            }
+            if let Some(serialize_fn) = other.component_se_map.get(id) {
+                self.component_se_map.insert(*id, *serialize_fn);
+            }
+            if let Some(version) = other.component_versions.get(id) {
+                self.component_versions.insert(*id, *version);
+            }
+            if let Some(delta_fns) = other.component_delta_map.get(id) {
+                self.component_delta_map.insert(*id, *delta_fns);
+            }
+            if let Some(text_fn) = other.component_text_map.get(id) {
+                self.component_text_map.insert(*id, *text_fn);
+            }
+            if let Some(custom_fns) = other.component_custom_map.get(id) {
+                self.component_custom_map.insert(*id, custom_fns.clone());
+            }
+            if let Some(map_entities_fn) = other.component_map_entities_map.get(id) {
+                self.component_map_entities_map
+                    .insert(*id, *map_entities_fn);
+            }
+            if let Some(tracking_fn) = other.component_tracking_map.get(id) {
+                self.component_tracking_map.insert(*id, *tracking_fn);
+            }
+            if let Some(remove_fn) = other.component_remove_map.get(id) {
+                self.component_remove_map.insert(*id, *remove_fn);
+            }
+            components.push(*id);
+        }
+
+        for (id, type_name) in other.resource_type_names.iter() {
+            if let Some(existing) = self.resource_type_names.get(id) {
+                if existing != type_name {
+                    errors.push(RegistryError::DuplicateResourceId {
+                        id: *id,
+                        existing,
+                        new: type_name,
+                    });
+                }
+                continue;
+            }
+            self.resource_type_names.insert(*id, type_name);
+            if let Some(deserialize_fn) = other.resource_de_map.get(id) {
+                self.resource_de_map.insert(*id, *deserialize_fn);
+            }
+            if let Some(serialize_fn) = other.resource_se_map.get(id) {
+                self.resource_se_map.insert(*id, *serialize_fn);
+            }
+            if let Some(version) = other.resource_versions.get(id) {
+                self.resource_versions.insert(*id, *version);
+            }
+            if let Some(text_fn) = other.resource_text_map.get(id) {
+                self.resource_text_map.insert(*id, *text_fn);
+            }
+            if let Some(tracking_fn) = other.resource_tracking_map.get(id) {
+                self.resource_tracking_map.insert(*id, *tracking_fn);
+            }
+            if let Some(remove_fn) = other.resource_remove_map.get(id) {
+                self.resource_remove_map.insert(*id, *remove_fn);
+            }
+            resources.push(*id);
+        }
+
+        for range in other.id_ranges {
+            if let Err(error) = self.insert_id_range(range) {
+                errors.push(error);
+            }
+        }
+        for (id, priority) in other.component_priorities {
+            if components.contains(&id) {
+                self.component_priorities.insert(id, priority);
+            }
+        }
+        for (type_id, id) in other.component_type_ids {
+            if components.contains(&id) {
+                self.component_type_ids.insert(type_id, id);
+            }
+        }
+        for ((id, version), upgrade_fn) in other.component_upgrades {
+            if components.contains(&id) {
+                self.component_upgrades.insert((id, version), upgrade_fn);
+            }
+        }
+        for ((id, version), upgrade_fn) in other.resource_upgrades {
+            if resources.contains(&id) {
+                self.resource_upgrades.insert((id, version), upgrade_fn);
+            }
+        }
+        self.excluded_components
+            .extend(other.excluded_components.iter());
+        errors.extend(other.registration_errors);
+        self.registration_errors.extend(errors.iter().cloned());
+
+        if errors.is_empty() {
+            Ok((components, resources))
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        saving::GameSerDeRegistry,
+        testing::fixtures::{bincode_save_id, TestComponent},
+    };
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct OtherComponent(u32);
+
+    bincode_save_id!(OtherComponent, 25);
+
+    #[test]
+    fn test_merge_detects_conflicts() {
+        let mut plugin_registry = GameSerDeRegistry::new();
+        plugin_registry
+            .register_component::<TestComponent>()
+            .unwrap();
+
+        let mut registry = GameSerDeRegistry::new();
+        assert_eq!(registry.merge(plugin_registry.clone()).unwrap().0, vec![25]);
+        assert!(registry.component_de_map.contains_key(&25));
+        assert!(registry.merge(plugin_registry).is_ok());
+
+        let mut conflicting_registry = GameSerDeRegistry::new();
+        conflicting_registry
+            .register_component::<OtherComponent>()
+            .unwrap();
+        assert!(registry.merge(conflicting_registry).is_err());
+        assert!(registry.validate().is_err());
+    }
+}
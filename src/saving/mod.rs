@@ -1,17 +1,56 @@
 use bevy::{
+    ecs::entity::MapEntities,
     ecs::{
         component::{Component, ComponentId},
         system::Resource,
         world::World,
     },
-    prelude::EntityWorldMut,
-    utils::HashMap,
+    prelude::{Entity, EntityWorldMut, IntoSystemConfigs, Schedule},
+    reflect::{Reflect, TypePath},
+    utils::{HashMap, HashSet},
+};
+use bevy_trait_query::ReadTraits;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    any::TypeId,
+    error::Error,
+    fmt::{Display, Formatter},
 };
-use serde::{de::DeserializeOwned, Serialize};
 
-use crate::requests::ResourceState;
+use crate::{
+    change_detection::{track_resource_changes, ComponentChangeTrackers, ResourceChangeTracking},
+    command_registry::SimCommandId,
+    entity_id::{PortableEntityMapper, SimEntityId, SimEntityIdMapper},
+    requests::ResourceState,
+    runner::PostBaseSets,
+};
 
+pub mod autosave;
+pub mod background;
+pub mod checksum;
+pub mod compression;
+pub mod custom;
+pub mod delta;
+pub mod diff;
+pub mod encryption;
+pub mod field_delta;
+pub mod header;
+pub mod hierarchy;
+pub mod history;
+pub mod id_range;
 pub mod implements;
+pub mod journal;
+pub mod merge;
+pub mod priority;
+pub mod reflect;
+pub mod schema;
+pub mod serializer;
+pub mod snapshot;
+pub mod version;
+
+pub use compression::SimCompression;
+pub use custom::CustomComponentFns;
+pub use serializer::{DecodeError, SimFormat, SimSerializer};
 
 /// An id hand assigned to components using the [`SaveId`] trait that identifies each component
 ///
@@ -23,19 +62,259 @@ pub type SimComponentId = u16;
 /// Is simply a u16 under the type
 pub type SimResourceId = u16;
 
-#[derive(Debug)]
+/// Errors returned when registering types into a [`GameSerDeRegistry`]
+#[derive(Clone, Eq, Hash, Debug, PartialEq)]
+pub enum RegistryError {
+    /// A component was registered with an id that is already used by another component
+    DuplicateComponentId {
+        id: SimComponentId,
+        existing: &'static str,
+        new: &'static str,
+    },
+    /// A resource was registered with an id that is already used by another resource
+    DuplicateResourceId {
+        id: SimResourceId,
+        existing: &'static str,
+        new: &'static str,
+    },
+    /// A command was registered with an id that is already used by another command
+    DuplicateCommandId {
+        id: SimCommandId,
+        existing: &'static str,
+        new: &'static str,
+    },
+    /// An id range was reserved that overlaps a range reserved by another owner
+    OverlappingIdRange {
+        owner: &'static str,
+        existing: &'static str,
+    },
+    /// A type was registered inside an id range with an id outside of it
+    IdOutsideRange {
+        id: u16,
+        owner: &'static str,
+        type_name: &'static str,
+    },
+    /// A type was registered outside of any id range with an id reserved by an owner
+    IdInReservedRange {
+        id: u16,
+        owner: &'static str,
+        type_name: &'static str,
+    },
+    /// The functions of a component were replaced but no component is registered with its id
+    UnknownComponentId { id: SimComponentId },
+    /// The functions of a resource were replaced but no resource is registered with its id
+    UnknownResourceId { id: SimResourceId },
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::DuplicateComponentId { id, existing, new } => write!(
+                f,
+                "component id {} of {} is already used by {}",
+                id, new, existing
+            ),
+            RegistryError::DuplicateResourceId { id, existing, new } => write!(
+                f,
+                "resource id {} of {} is already used by {}",
+                id, new, existing
+            ),
+            RegistryError::DuplicateCommandId { id, existing, new } => write!(
+                f,
+                "command id {} of {} is already used by {}",
+                id, new, existing
+            ),
+            RegistryError::OverlappingIdRange { owner, existing } => write!(
+                f,
+                "id range of {} overlaps the id range of {}",
+                owner, existing
+            ),
+            RegistryError::IdOutsideRange {
+                id,
+                owner,
+                type_name,
+            } => write!(
+                f,
+                "id {} of {} is outside the id range of {}",
+                id, type_name, owner
+            ),
+            RegistryError::IdInReservedRange {
+                id,
+                owner,
+                type_name,
+            } => write!(
+                f,
+                "id {} of {} is in the id range reserved by {}",
+                id, type_name, owner
+            ),
+            RegistryError::UnknownComponentId { id } => {
+                write!(f, "no component is registered with id {}", id)
+            }
+            RegistryError::UnknownResourceId { id } => {
+                write!(f, "no resource is registered with id {}", id)
+            }
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+/// Errors returned when deserializing components and resources with a [`GameSerDeRegistry`]
+#[derive(Clone, Eq, Debug, PartialEq)]
+pub enum DeserializeError {
+    /// No component is registered with the id
+    UnregisteredComponent { id: SimComponentId },
+    /// No resource is registered with the id
+    UnregisteredResource { id: SimResourceId },
+    /// The component with the id is registered but its data couldn't be deserialized
+    Component {
+        id: SimComponentId,
+        error: DecodeError,
+    },
+    /// The resource with the id is registered but its data couldn't be deserialized
+    Resource {
+        id: SimResourceId,
+        error: DecodeError,
+    },
+    /// The data of the component with the id failed its checksum, see [`checksum`]
+    ComponentChecksum {
+        id: SimComponentId,
+        error: checksum::ChecksumError,
+    },
+    /// The data of the resource with the id failed its checksum
+    ResourceChecksum {
+        id: SimResourceId,
+        error: checksum::ChecksumError,
+    },
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializeError::UnregisteredComponent { id } => {
+                write!(f, "component id {} is not registered", id)
+            }
+            DeserializeError::UnregisteredResource { id } => {
+                write!(f, "resource id {} is not registered", id)
+            }
+            DeserializeError::Component { id, error } => {
+                write!(f, "component id {} failed to deserialize: {}", id, error)
+            }
+            DeserializeError::Resource { id, error } => {
+                write!(f, "resource id {} failed to deserialize: {}", id, error)
+            }
+            DeserializeError::ComponentChecksum { id, error } => {
+                write!(f, "component id {}: {}", id, error)
+            }
+            DeserializeError::ResourceChecksum { id, error } => {
+                write!(f, "resource id {}: {}", id, error)
+            }
+        }
+    }
+}
+
+impl Error for DeserializeError {}
+
+/// Every failure found while deserializing a state or snapshot. Failing components and resources
+/// are skipped, the rest are still deserialized
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct DeserializeReport {
+    /// Components that failed to deserialize and the entity they belong to
+    pub entities: Vec<(SimEntityId, DeserializeError)>,
+    /// Resources that failed to deserialize
+    pub resources: Vec<DeserializeError>,
+    /// Player components that failed to deserialize and the id of the player they belong to
+    pub players: Vec<(usize, DeserializeError)>,
+}
+
+impl DeserializeReport {
+    /// Returns true if everything deserialized
+    pub fn is_ok(&self) -> bool {
+        self.entities.is_empty() && self.resources.is_empty() && self.players.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
 pub struct ComponentBinaryState {
     pub id: SimComponentId,
+    /// The schema version the component was serialized with, see [`version`]
+    pub version: version::SchemaVersion,
     pub component: Vec<u8>,
 }
 
 /// A registry that contains deserialization functions for game components
 #[derive(Resource, Clone, Default)]
 pub struct GameSerDeRegistry {
+    /// The format every registered component and resource is serialized with
+    pub format: SimFormat,
+    /// The compression applied to every serialized component and resource payload
+    pub component_compression: SimCompression,
+    /// The compression applied to whole states encoded with
+    /// [`SimState::encode`](crate::requests::SimState::encode)
+    pub state_compression: SimCompression,
+    /// The encryption applied to save bodies, see [`encryption`]
+    #[cfg(feature = "encryption")]
+    pub save_encryption: Option<encryption::SaveEncryption>,
     pub component_de_map: HashMap<SimComponentId, ComponentDeserializeFn>,
+    /// Serialize functions for registered components. Components with a [`SaveId`] implementation
+    /// that aren't registered are serialized through [`SaveId::save`] instead
+    pub component_se_map: HashMap<SimComponentId, ComponentSerializeFn>,
+    /// Closures for components registered with [`GameSerDeRegistry::register_component_with`]
+    pub component_custom_map: HashMap<SimComponentId, CustomComponentFns>,
     pub resource_de_map: HashMap<SimResourceId, ResourceDeserializeFn>,
     pub resource_se_map: HashMap<SimResourceId, ResourceSerializeFn>,
-    pub resource_id_map: ResourceSaveComponentIdMap,
+    /// Functions that remap the [`Entity`] references of registered components after they are
+    /// deserialized, see [`GameSerDeRegistry::register_map_entities`]
+    pub component_map_entities_map: HashMap<SimComponentId, ComponentMapEntitiesFn>,
+    /// Functions that add change tracking for registered components to the
+    /// [`ComponentChangeTrackers`]. Used to track components registered in a merged registry, see
+    /// [`GameSerDeRegistry::merge`]
+    pub component_tracking_map: HashMap<SimComponentId, TrackComponentChangesFn>,
+    /// Functions that remove registered components from an entity
+    pub component_remove_map: HashMap<SimComponentId, ComponentRemoveFn>,
+    /// Functions that remove registered resources from a world
+    pub resource_remove_map: HashMap<SimResourceId, ResourceRemoveFn>,
+    /// Functions that add change tracking for registered resources to a schedule
+    pub resource_tracking_map: HashMap<SimResourceId, TrackChangesFn>,
+    /// Delta functions of components sent as only their changed fields, see [`field_delta`]
+    pub component_delta_map: HashMap<SimComponentId, field_delta::ComponentDeltaFns>,
+    /// Functions that decode registered components for text export, see
+    /// [`TextState`](crate::requests::text_state::TextState)
+    pub component_text_map: HashMap<SimComponentId, TextDecodeFn>,
+    /// Functions that decode registered resources for text export
+    pub resource_text_map: HashMap<SimResourceId, TextDecodeFn>,
+    /// Replication priorities of components, see [`priority`]
+    pub component_priorities: HashMap<SimComponentId, i32>,
+    /// The ids of registered components keyed by their Rust type, used to report removed components
+    /// in [`SimState::removed_components`](crate::requests::SimState::removed_components)
+    pub component_type_ids: HashMap<TypeId, SimComponentId>,
+    /// The type names of registered components, used by [`GameSerDeRegistry::export_schema`]
+    pub component_type_names: HashMap<SimComponentId, &'static str>,
+    /// The type names of registered resources, used by [`GameSerDeRegistry::export_schema`]
+    pub resource_type_names: HashMap<SimResourceId, &'static str>,
+    /// Every collision found while registering, see [`GameSerDeRegistry::validate`]
+    pub registration_errors: Vec<RegistryError>,
+    /// Components that are never included in state output, even though they implement [`SaveId`].
+    /// Use this for bookkeeping components that should never be sent to players
+    pub excluded_components: HashSet<SimComponentId>,
+    /// Whether unregistered components that are registered in the worlds
+    /// [`AppTypeRegistry`](bevy::ecs::reflect::AppTypeRegistry) are serialized using reflection,
+    /// see [`reflect`]
+    pub reflect_fallback: bool,
+    /// Whether a checksum is appended to every payload and verified on load, see [`checksum`]
+    pub payload_checksums: bool,
+    /// The current schema versions of registered components, see [`version`]
+    pub component_versions: HashMap<SimComponentId, version::SchemaVersion>,
+    /// The current schema versions of registered resources
+    pub resource_versions: HashMap<SimResourceId, version::SchemaVersion>,
+    /// Functions upgrading component payloads from a version to the next one
+    pub component_upgrades: HashMap<(SimComponentId, version::SchemaVersion), version::UpgradeFn>,
+    /// Functions upgrading resource payloads from a version to the next one
+    pub resource_upgrades: HashMap<(SimResourceId, version::SchemaVersion), version::UpgradeFn>,
+    /// Id ranges reserved by plugins, see [`id_range`]
+    pub id_ranges: Vec<id_range::IdRange>,
+    /// The owner of the id range registrations must currently be in
+    pub active_id_range: Option<&'static str>,
 }
 
 impl GameSerDeRegistry {
@@ -43,54 +322,429 @@ impl GameSerDeRegistry {
         GameSerDeRegistry::default()
     }
 
-    /// Registers a component into the [`GameSerDeRegistry`] for automatic serialization and deserialization
-    pub fn register_component<C>(&mut self)
+    /// Registers a component into the [`GameSerDeRegistry`] for automatic serialization and
+    /// deserialization. Returns an error without registering if the id is already used, the error
+    /// is also recorded for [`GameSerDeRegistry::validate`]
+    pub fn register_component<C>(&mut self) -> Result<(), RegistryError>
     where
         C: Component + Serialize + DeserializeOwned + SaveId,
     {
-        if self.component_de_map.contains_key(&C::save_id_const()) {
-            panic!(
-                "SavingMap component_de_map already contains key {}",
-                C::save_id_const(),
-            )
-        }
+        self.check_id_range(C::save_id_const(), std::any::type_name::<C>())?;
+        self.check_component_id(C::save_id_const(), std::any::type_name::<C>())?;
         self.component_de_map
             .insert(C::save_id_const(), component_deserialize_onto::<C>);
+        self.component_se_map
+            .insert(C::save_id_const(), component_serialize_from::<C>);
+        self.component_text_map
+            .insert(C::save_id_const(), decode_text::<C>);
+        self.component_versions
+            .insert(C::save_id_const(), C::save_version());
+        self.component_type_names
+            .insert(C::save_id_const(), std::any::type_name::<C>());
+        self.component_type_ids
+            .insert(TypeId::of::<C>(), C::save_id_const());
+        self.component_tracking_map
+            .insert(C::save_id_const(), add_component_tracking::<C>);
+        self.component_remove_map
+            .insert(C::save_id_const(), component_remove::<C>);
+        Ok(())
+    }
+
+    /// Registers a component into the [`GameSerDeRegistry`] with the given id instead of a [`SaveId`]
+    /// implementation. Use this for generic components, where every instantiation such as
+    /// `Inventory<Weapon>` and `Inventory<Armor>` needs its own id. Returns an error without
+    /// registering if the id is already used
+    pub fn register_component_with_id<C>(&mut self, id: SimComponentId) -> Result<(), RegistryError>
+    where
+        C: Component + Serialize + DeserializeOwned,
+    {
+        let type_name = std::any::type_name::<C>();
+        self.check_id_range(id, type_name)?;
+        self.check_component_id(id, type_name)?;
+        self.component_de_map
+            .insert(id, component_deserialize_onto::<C>);
+        self.component_se_map
+            .insert(id, component_serialize_from::<C>);
+        self.component_text_map.insert(id, decode_text::<C>);
+        self.component_type_names.insert(id, type_name);
+        self.component_type_ids.insert(TypeId::of::<C>(), id);
+        self.component_tracking_map
+            .insert(id, add_component_tracking::<C>);
+        self.component_remove_map.insert(id, component_remove::<C>);
+        Ok(())
     }
 
-    /// Registers a component into the [`GameSerDeRegistry`] for automatic serialization and deserialization
-    pub fn register_resource<R>(&mut self)
+    /// Registers a component into the [`GameSerDeRegistry`] for automatic serialization and
+    /// deserialization without requiring a hand assigned [`SaveId`]. The component is identified by
+    /// a hash of its [`TypePath`], see [`type_path_id`], so its id never changes when other types are
+    /// registered. Returns the id that was assigned, or an error without registering if the id is
+    /// already used. The error is also recorded for [`GameSerDeRegistry::validate`]
+    pub fn register_reflect<C>(&mut self) -> Result<SimComponentId, RegistryError>
+    where
+        C: Component + TypePath + Serialize + DeserializeOwned,
+    {
+        let id = type_path_id(C::type_path());
+        self.check_component_id(id, C::type_path())?;
+        self.component_de_map
+            .insert(id, component_deserialize_onto::<C>);
+        self.component_se_map
+            .insert(id, component_serialize_from::<C>);
+        self.component_text_map.insert(id, decode_text::<C>);
+        self.component_type_names.insert(id, C::type_path());
+        self.component_type_ids.insert(TypeId::of::<C>(), id);
+        self.component_tracking_map
+            .insert(id, add_component_tracking::<C>);
+        self.component_remove_map.insert(id, component_remove::<C>);
+        Ok(id)
+    }
+
+    /// Registers the [`Entity`] references of an already registered component for remapping. The
+    /// references are serialized as portable entities, see [`SimEntityId::to_portable`], and mapped
+    /// back onto the matching entities by [`GameSerDeRegistry::deserialize_component_onto_mapped`]
+    pub fn register_map_entities<C>(&mut self, id: SimComponentId)
+    where
+        C: Component + MapEntities + Clone + Serialize,
+    {
+        self.component_se_map
+            .insert(id, component_serialize_portable::<C>);
+        self.component_map_entities_map
+            .insert(id, component_map_entities::<C>);
+    }
+
+    /// Registers a resource into the [`GameSerDeRegistry`] for automatic serialization and
+    /// deserialization. Returns an error without registering if the id is already used, the error
+    /// is also recorded for [`GameSerDeRegistry::validate`]
+    pub fn register_resource<R>(&mut self) -> Result<(), RegistryError>
     where
         R: Resource + Serialize + DeserializeOwned + SaveId,
     {
-        if self.resource_de_map.contains_key(&R::save_id_const()) {
-            panic!(
-                "SavingMap component_de_map already contains key {}",
-                R::save_id_const(),
-            )
+        let type_name = std::any::type_name::<R>();
+        self.check_id_range(R::save_id_const(), type_name)?;
+        if let Some(existing) = self.resource_type_names.get(&R::save_id_const()) {
+            let error = RegistryError::DuplicateResourceId {
+                id: R::save_id_const(),
+                existing,
+                new: type_name,
+            };
+            self.registration_errors.push(error.clone());
+            return Err(error);
         }
         self.resource_de_map
             .insert(R::save_id_const(), resource_deserialize_into_world::<R>);
         self.resource_se_map
             .insert(R::save_id_const(), serialize_resource_from_world::<R>);
+        self.resource_text_map
+            .insert(R::save_id_const(), decode_text::<R>);
+        self.resource_versions
+            .insert(R::save_id_const(), R::save_version());
+        self.resource_type_names
+            .insert(R::save_id_const(), type_name);
+        self.resource_tracking_map
+            .insert(R::save_id_const(), add_resource_tracking::<R>);
+        self.resource_remove_map
+            .insert(R::save_id_const(), resource_remove::<R>);
+        Ok(())
+    }
+
+    /// Returns an error for the given component id if it is already registered and records it
+    pub(crate) fn check_component_id(
+        &mut self,
+        id: SimComponentId,
+        type_name: &'static str,
+    ) -> Result<(), RegistryError> {
+        let Some(existing) = self.component_type_names.get(&id) else {
+            return Ok(());
+        };
+        let error = RegistryError::DuplicateComponentId {
+            id,
+            existing,
+            new: type_name,
+        };
+        self.registration_errors.push(error.clone());
+        Err(error)
+    }
+
+    /// Returns every id collision found while registering, so they can all be fixed at once
+    pub fn validate(&self) -> Result<(), Vec<RegistryError>> {
+        if self.registration_errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.registration_errors.clone())
+        }
     }
 
-    /// Deserializes the given component onto the given entity.
+    /// Replaces the serialize and deserialize functions and the schema version of an already
+    /// registered component. Returns the previous functions if there were any, or an error if no
+    /// component is registered with the given id
+    pub fn replace_component_fns(
+        &mut self,
+        id: SimComponentId,
+        serialize_fn: ComponentSerializeFn,
+        deserialize_fn: ComponentDeserializeFn,
+        version: version::SchemaVersion,
+    ) -> Result<(Option<ComponentSerializeFn>, Option<ComponentDeserializeFn>), RegistryError> {
+        if !self.component_type_names.contains_key(&id) {
+            return Err(RegistryError::UnknownComponentId { id });
+        }
+        // Closures from register_component_with would otherwise still serialize the component
+        self.component_custom_map.remove(&id);
+        self.component_versions.insert(id, version);
+        Ok((
+            self.component_se_map.insert(id, serialize_fn),
+            self.component_de_map.insert(id, deserialize_fn),
+        ))
+    }
+
+    /// Returns the registry with the given serialization format
+    pub fn with_format(mut self, format: SimFormat) -> GameSerDeRegistry {
+        self.format = format;
+        self
+    }
+
+    /// Replaces the serialize and deserialize functions and the schema version of an already
+    /// registered resource. Returns the previous functions if there were any, or an error if no
+    /// resource is registered with the given id
+    pub fn replace_resource_fns(
+        &mut self,
+        id: SimResourceId,
+        deserialize_fn: ResourceDeserializeFn,
+        serialize_fn: ResourceSerializeFn,
+        version: version::SchemaVersion,
+    ) -> Result<(Option<ResourceDeserializeFn>, Option<ResourceSerializeFn>), RegistryError> {
+        if !self.resource_type_names.contains_key(&id) {
+            return Err(RegistryError::UnknownResourceId { id });
+        }
+        self.resource_versions.insert(id, version);
+        Ok((
+            self.resource_de_map.insert(id, deserialize_fn),
+            self.resource_se_map.insert(id, serialize_fn),
+        ))
+    }
+
+    /// Excludes the given component from all state output
+    pub fn exclude_component<C>(&mut self)
+    where
+        C: SaveId,
+    {
+        self.exclude_component_id(C::save_id_const());
+    }
+
+    /// Excludes the component with the given id from all state output
+    pub fn exclude_component_id(&mut self, id: SimComponentId) {
+        self.excluded_components.insert(id);
+    }
+
+    /// Returns true if the component with the given id is excluded from state output
+    pub fn is_excluded(&self, id: SimComponentId) -> bool {
+        self.excluded_components.contains(&id)
+    }
+
+    /// Saves every saveable component on the given entity, both its registered components and any
+    /// unregistered [`SaveId`] components. Components are sorted by id so the same entity always
+    /// saves to the same bytes
+    pub fn save_entity(
+        &self,
+        saveable_components: Option<&ReadTraits<'_, dyn SaveId>>,
+        world: &World,
+        entity: Entity,
+    ) -> Vec<ComponentBinaryState> {
+        let mut components: Vec<ComponentBinaryState> = vec![];
+        if let Some(saveable_components) = saveable_components {
+            for component in saveable_components.iter() {
+                if self.component_se_map.contains_key(&component.save_id()) {
+                    continue;
+                }
+                if let Some((id, binary)) = component.save() {
+                    components.push(ComponentBinaryState {
+                        id,
+                        version: 0,
+                        component: self.pack_payload(binary),
+                    });
+                }
+            }
+        }
+        for (id, serialize_fn) in self.component_se_map.iter() {
+            if let Some(binary) = serialize_fn(self.format, world, entity) {
+                components.push(ComponentBinaryState {
+                    id: *id,
+                    version: self.component_versions.get(id).copied().unwrap_or(0),
+                    component: self.pack_payload(binary),
+                });
+            }
+        }
+        for (id, custom_fns) in self.component_custom_map.iter() {
+            if let Some(binary) = (custom_fns.serialize)(world, entity) {
+                components.push(ComponentBinaryState {
+                    id: *id,
+                    version: 0,
+                    component: self.pack_payload(binary),
+                });
+            }
+        }
+        if self.reflect_fallback {
+            self.save_reflected(world, entity, &mut components);
+        }
+        components.sort_by_key(|component| component.id);
+        components
+    }
+
+    /// Serializes the registered component with the given id from the given entity. Returns None if
+    /// the component isn't registered or the entity doesn't have it
+    pub fn serialize_component(
+        &self,
+        id: SimComponentId,
+        world: &World,
+        entity: Entity,
+    ) -> Option<ComponentBinaryState> {
+        let (binary, version) = if let Some(serialize_fn) = self.component_se_map.get(&id) {
+            (
+                serialize_fn(self.format, world, entity)?,
+                self.component_versions.get(&id).copied().unwrap_or(0),
+            )
+        } else {
+            (
+                (self.component_custom_map.get(&id)?.serialize)(world, entity)?,
+                0,
+            )
+        };
+        Some(ComponentBinaryState {
+            id,
+            version,
+            component: self.pack_payload(binary),
+        })
+    }
+
+    /// Removes the registered component with the given id from the given entity. Returns false if
+    /// the component isn't registered
+    pub fn remove_component(&self, id: SimComponentId, entity: &mut EntityWorldMut) -> bool {
+        let Some(remove_fn) = self.component_remove_map.get(&id) else {
+            return false;
+        };
+        remove_fn(entity);
+        true
+    }
+
+    /// Removes the registered resource with the given id from the given world. Returns false if the
+    /// resource isn't registered
+    pub fn remove_resource(&self, id: SimResourceId, world: &mut World) -> bool {
+        let Some(remove_fn) = self.resource_remove_map.get(&id) else {
+            return false;
+        };
+        remove_fn(world);
+        true
+    }
+
+    /// Serializes every saveable component on the given entity that isn't excluded from state output
+    pub fn serialize_entity(
+        &self,
+        saveable_components: Option<&ReadTraits<'_, dyn SaveId>>,
+        world: &World,
+        entity: Entity,
+    ) -> Vec<ComponentBinaryState> {
+        let mut components = self.save_entity(saveable_components, world, entity);
+        components.retain(|component| !self.is_excluded(component.id));
+        components
+    }
+
+    /// Serializes only the components with the given ids from the given entity, skipping components
+    /// excluded from state. Components the entity doesn't have are left out
+    pub fn serialize_entity_components(
+        &self,
+        saveable_components: Option<&ReadTraits<'_, dyn SaveId>>,
+        world: &World,
+        entity: Entity,
+        ids: &[SimComponentId],
+    ) -> Vec<ComponentBinaryState> {
+        let mut components: Vec<ComponentBinaryState> = vec![];
+        for id in ids.iter().filter(|id| !self.is_excluded(**id)) {
+            if self.component_se_map.contains_key(id) || self.component_custom_map.contains_key(id)
+            {
+                components.extend(self.serialize_component(*id, world, entity));
+                continue;
+            }
+            let Some(component) = saveable_components.and_then(|components| {
+                components
+                    .iter()
+                    .find(|component| component.save_id() == *id)
+            }) else {
+                continue;
+            };
+            if let Some((id, binary)) = component.save() {
+                components.push(ComponentBinaryState {
+                    id,
+                    version: 0,
+                    component: self.pack_payload(binary),
+                });
+            }
+        }
+        components
+    }
+
+    /// Deserializes the given component onto the given entity. Returns an error with the id of the
+    /// component if it isn't registered or its data is invalid
     pub fn deserialize_component_onto(
         &self,
         data: &ComponentBinaryState,
         entity: &mut EntityWorldMut,
-    ) {
-        if let Some(deserialize_fn) = self.component_de_map.get(&data.id) {
-            deserialize_fn(&data.component, entity);
-        }
+    ) -> Result<(), DeserializeError> {
+        let payload = self
+            .verify_checksum(&data.component)
+            .map_err(|error| DeserializeError::ComponentChecksum { id: data.id, error })?;
+        let decompress = || {
+            self.component_compression
+                .decompress(payload)
+                .ok_or_else(|| DecodeError::new(0, "failed to decompress"))
+                .and_then(|component| self.upgrade_component(data.id, data.version, component))
+        };
+        let result = if let Some(deserialize_fn) = self.component_de_map.get(&data.id) {
+            decompress().and_then(|component| deserialize_fn(self.format, &component, entity))
+        } else if let Some(custom_fns) = self.component_custom_map.get(&data.id) {
+            decompress().and_then(|component| (custom_fns.deserialize)(&component, entity))
+        } else if self.reflect_fallback {
+            decompress().and_then(|component| self.deserialize_reflected(&component, entity))
+        } else {
+            return Err(DeserializeError::UnregisteredComponent { id: data.id });
+        };
+        result.map_err(|error| DeserializeError::Component { id: data.id, error })
     }
 
-    /// Deserializes the given [`ResourceState`] into the given world.
-    pub fn deserialize_resource(&self, resource_state: ResourceState, world: &mut World) {
-        if let Some(deserialize_fn) = self.resource_de_map.get(&resource_state.resource_id) {
-            deserialize_fn(&resource_state.resource, world);
+    /// Deserializes the given component onto the given entity and remaps its [`Entity`] references
+    /// using the given map from [`SimEntityId`]s to entities, see
+    /// [`sim_entity_map`](crate::entity_id::sim_entity_map). Entities that
+    /// are referenced by the component should be spawned with their [`SimEntityId`] beforehand
+    pub fn deserialize_component_onto_mapped(
+        &self,
+        data: &ComponentBinaryState,
+        entity: &mut EntityWorldMut,
+        entity_map: &HashMap<SimEntityId, Entity>,
+    ) -> Result<(), DeserializeError> {
+        self.deserialize_component_onto(data, entity)?;
+        if let Some(map_entities_fn) = self.component_map_entities_map.get(&data.id) {
+            map_entities_fn(entity, entity_map);
         }
+        Ok(())
+    }
+
+    /// Deserializes the given [`ResourceState`] into the given world. Returns an error with the id
+    /// of the resource if it isn't registered or its data is invalid
+    pub fn deserialize_resource(
+        &self,
+        resource_state: ResourceState,
+        world: &mut World,
+    ) -> Result<(), DeserializeError> {
+        let id = resource_state.resource_id;
+        let Some(deserialize_fn) = self.resource_de_map.get(&id) else {
+            return Err(DeserializeError::UnregisteredResource { id });
+        };
+        let payload = self
+            .verify_checksum(&resource_state.resource)
+            .map_err(|error| DeserializeError::ResourceChecksum { id, error })?;
+        self.component_compression
+            .decompress(payload)
+            .ok_or_else(|| DecodeError::new(0, "failed to decompress"))
+            .and_then(|resource| self.upgrade_resource(id, resource_state.version, resource))
+            .and_then(|resource| deserialize_fn(self.format, &resource, world))
+            .map_err(|error| DeserializeError::Resource { id, error })
     }
 
     /// Serializes the given resource from the given world.
@@ -100,7 +754,19 @@ impl GameSerDeRegistry {
         world: &World,
     ) -> Option<ResourceState> {
         if let Some(serialize_fn) = self.resource_se_map.get(resource_id) {
-            serialize_fn(world)
+            let mut resource_state = serialize_fn(self.format, world)?;
+            resource_state.version = self
+                .resource_versions
+                .get(resource_id)
+                .copied()
+                .unwrap_or(0);
+            resource_state.resource = self.pack_payload(resource_state.resource);
+            resource_state.changed_tick = world
+                .get_resource::<ResourceChangeTracking>()
+                .and_then(|tracking| tracking.get_by_save_id(*resource_id))
+                .map(|changed| changed.tick)
+                .unwrap_or_default();
+            Some(resource_state)
         } else {
             None
         }
@@ -108,58 +774,157 @@ impl GameSerDeRegistry {
 
     /// Adds the default registry which has all the basic Bevy_GGF components and resources
     pub fn default_registry() -> GameSerDeRegistry {
-        let game_registry = GameSerDeRegistry::new();
-        game_registry
+        GameSerDeRegistry::new()
     }
 }
 
-pub type ComponentDeserializeFn = fn(data: &Vec<u8>, entity: &mut EntityWorldMut);
+pub type ComponentDeserializeFn =
+    fn(format: SimFormat, data: &Vec<u8>, entity: &mut EntityWorldMut) -> Result<(), DecodeError>;
+
+pub type ComponentSerializeFn =
+    fn(format: SimFormat, world: &World, entity: Entity) -> Option<Vec<u8>>;
+
+pub type TrackChangesFn = fn(schedule: &mut Schedule);
+
+pub type ComponentRemoveFn = fn(entity: &mut EntityWorldMut);
+
+pub type ResourceRemoveFn = fn(world: &mut World);
+
+/// Removes the given component from the given entity
+pub fn component_remove<C: Component>(entity: &mut EntityWorldMut) {
+    entity.remove::<C>();
+}
+
+/// Removes the given resource from the given world
+pub fn resource_remove<R: Resource>(world: &mut World) {
+    world.remove_resource::<R>();
+}
+
+pub type TrackComponentChangesFn = fn(trackers: &mut ComponentChangeTrackers);
+
+/// Adds change tracking for the given component to the given [`ComponentChangeTrackers`].
+pub fn add_component_tracking<C: Component>(trackers: &mut ComponentChangeTrackers) {
+    trackers.track::<C>();
+}
+
+/// Adds change tracking for the given resource to the given game post schedule.
+pub fn add_resource_tracking<R: Resource + SaveId>(schedule: &mut Schedule) {
+    schedule.add_systems(track_resource_changes::<R>.in_set(PostBaseSets::Main));
+}
+
+pub type ComponentMapEntitiesFn =
+    fn(entity: &mut EntityWorldMut, entity_map: &HashMap<SimEntityId, Entity>);
+
+/// Serializes the given component from the given entity, if the entity has it.
+pub fn component_serialize_from<T>(
+    format: SimFormat,
+    world: &World,
+    entity: Entity,
+) -> Option<Vec<u8>>
+where
+    T: Serialize + Component,
+{
+    let component = world.get::<T>(entity)?;
+    format.encode(component)
+}
+
+/// Serializes the given component from the given entity, if the entity has it, with every
+/// [`Entity`] reference turned into a portable entity.
+pub fn component_serialize_portable<T>(
+    format: SimFormat,
+    world: &World,
+    entity: Entity,
+) -> Option<Vec<u8>>
+where
+    T: Serialize + Component + MapEntities + Clone,
+{
+    let mut component = world.get::<T>(entity)?.clone();
+    component.map_entities(&mut PortableEntityMapper { world });
+    format.encode(&component)
+}
+
+/// Maps the portable [`Entity`] references of the given component on the given entity back onto
+/// entities using the given map.
+pub fn component_map_entities<T>(
+    entity: &mut EntityWorldMut,
+    entity_map: &HashMap<SimEntityId, Entity>,
+) where
+    T: Component + MapEntities,
+{
+    if let Some(mut component) = entity.get_mut::<T>() {
+        component.map_entities(&mut SimEntityIdMapper { map: entity_map });
+    }
+}
+
+/// Returns the [`SimComponentId`] for the given type path. This is a stable FNV-1a hash of the type
+/// path folded into a [`SimComponentId`], so it is the same across builds and machines. Different
+/// type paths can share an id, see [`GameSerDeRegistry::reflect_fallback_collisions`]
+pub fn type_path_id(type_path: &str) -> SimComponentId {
+    let hash = checksum::fnv1a(type_path.as_bytes());
+    (hash ^ (hash >> 16) ^ (hash >> 32) ^ (hash >> 48)) as SimComponentId
+}
 
 /// Deserializes a binary component onto the given entity.
-pub fn component_deserialize_onto<T>(data: &Vec<u8>, entity: &mut EntityWorldMut)
+#[allow(clippy::ptr_arg)]
+pub fn component_deserialize_onto<T>(
+    format: SimFormat,
+    data: &Vec<u8>,
+    entity: &mut EntityWorldMut,
+) -> Result<(), DecodeError>
 where
-    T: Serialize + DeserializeOwned + Component + SaveId,
+    T: Serialize + DeserializeOwned + Component,
 {
-    let Some(keyframe) = bincode::deserialize::<T>(data).ok() else {
-        return;
-    };
-    entity.insert(keyframe);
+    entity.insert(format.try_decode::<T>(data)?);
+    Ok(())
 }
 
-pub type ResourceDeserializeFn = fn(data: &Vec<u8>, world: &mut World);
+pub type TextDecodeFn =
+    fn(format: SimFormat, data: &[u8]) -> Option<Box<dyn erased_serde::Serialize>>;
 
-pub type ResourceSerializeFn = fn(world: &World) -> Option<ResourceState>;
+/// Decodes the given data into a type erased value so it can be written in a text format
+pub fn decode_text<T>(format: SimFormat, data: &[u8]) -> Option<Box<dyn erased_serde::Serialize>>
+where
+    T: Serialize + DeserializeOwned + 'static,
+{
+    Some(Box::new(format.decode::<T>(data)?))
+}
+
+pub type ResourceDeserializeFn =
+    fn(format: SimFormat, data: &Vec<u8>, world: &mut World) -> Result<(), DecodeError>;
+
+pub type ResourceSerializeFn = fn(format: SimFormat, world: &World) -> Option<ResourceState>;
 
 /// Deserializes a binary component onto the given entity.
-pub fn resource_deserialize_into_world<T>(data: &Vec<u8>, world: &mut World)
+#[allow(clippy::ptr_arg)]
+pub fn resource_deserialize_into_world<T>(
+    format: SimFormat,
+    data: &Vec<u8>,
+    world: &mut World,
+) -> Result<(), DecodeError>
 where
     T: Serialize + DeserializeOwned + Resource + SaveId,
 {
-    let Some(resource) = bincode::deserialize::<T>(data).ok() else {
-        return;
-    };
-    world.insert_resource(resource);
+    world.insert_resource(format.try_decode::<T>(data)?);
+    Ok(())
 }
 
 /// Deserializes a binary component onto the given entity.
-pub fn serialize_resource_from_world<R>(world: &World) -> Option<ResourceState>
+pub fn serialize_resource_from_world<R>(format: SimFormat, world: &World) -> Option<ResourceState>
 where
     R: Serialize + DeserializeOwned + Resource + SaveId,
 {
-    let Some(resource) = world.get_resource::<R>() else {
-        return None;
-    };
-    let Some((id, binary)) = resource.save() else {
-        return None;
-    };
+    let resource = world.get_resource::<R>()?;
 
     Some(ResourceState {
-        resource_id: id,
-        resource: binary,
+        resource_id: resource.save_id(),
+        version: R::save_version(),
+        resource: format.encode(resource)?,
+        changed_tick: 0,
     })
 }
 
-#[derive(Clone, Default)]
+/// Maps the [`ComponentId`]s of resources in a world to their [`SimResourceId`]s
+#[derive(Clone, Default, Eq, Debug, PartialEq)]
 pub struct ResourceSaveComponentIdMap {
     pub component_to_id: HashMap<ComponentId, SimResourceId>,
     pub id_to_component: HashMap<SimResourceId, ComponentId>,
@@ -227,14 +992,22 @@ pub trait SaveId {
     where
         Self: Sized;
 
+    /// The schema version written next to every payload of this type. Bump it and register an
+    /// upgrade with [`GameSerDeRegistry::register_component_upgrade`] when the serialized form
+    /// changes, see [`version`]
+    fn save_version() -> version::SchemaVersion
+    where
+        Self: Sized,
+    {
+        0
+    }
+
     /// Serializes the object into binary
     fn to_binary(&self) -> Option<Vec<u8>>;
 
     /// Saves self according to the implementation given in to_binary
     fn save(&self) -> Option<(SimComponentId, Vec<u8>)> {
-        let Some(data) = self.to_binary() else {
-            return None;
-        };
+        let data = self.to_binary()?;
         Some((self.save_id(), data))
     }
 }
@@ -0,0 +1,114 @@
+//! Replication priorities. Components registered with
+//! [`GameSerDeRegistry::register_component_priority`] give every entity holding them that priority,
+//! the highest of its components, and [`StateDif`](crate::requests::state_dif::StateDif) sends
+//! changed entities highest priority first. Insert a [`ReplicationBudget`] into the sim world to
+//! cap how many entities a single state includes, entities over the budget are deferred to the
+//! next state.
+
+use bevy::prelude::{Entity, Resource, World};
+use bevy_trait_query::ReadTraits;
+
+use super::{GameSerDeRegistry, SaveId, SimComponentId};
+
+/// The priority of components that weren't given one
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+/// Resource inserted into the sim world that caps the number of changed entities included in a
+/// single [`StateDif`](crate::requests::state_dif::StateDif)
+#[derive(Clone, Copy, Eq, Hash, Debug, PartialEq, Resource)]
+pub struct ReplicationBudget {
+    pub max_entities: usize,
+}
+
+impl GameSerDeRegistry {
+    /// Sets the replication priority of the component C. Higher priorities are sent first
+    pub fn register_component_priority<C: SaveId>(&mut self, priority: i32) {
+        self.component_priorities
+            .insert(C::save_id_const(), priority);
+    }
+
+    /// Returns the replication priority of the component with the given id
+    pub fn component_priority(&self, id: SimComponentId) -> i32 {
+        self.component_priorities
+            .get(&id)
+            .copied()
+            .unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    /// Returns the replication priority of an entity, the highest priority of its [`SaveId`]
+    /// components
+    pub fn entity_priority(&self, saveable_components: Option<&ReadTraits<'_, dyn SaveId>>) -> i32 {
+        saveable_components
+            .into_iter()
+            .flat_map(|components| components.iter())
+            .map(|component| self.component_priority(component.save_id()))
+            .max()
+            .unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    /// Sorts the given entities highest replication priority first, keeping the order of entities
+    /// with equal priority
+    pub fn sort_by_priority(&self, world: &mut World, entities: &mut [Entity]) {
+        if self.component_priorities.is_empty() {
+            return;
+        }
+        let mut query = world.query::<Option<&dyn SaveId>>();
+        entities.sort_by_cached_key(|entity| {
+            let priority = query
+                .get(world, *entity)
+                .map(|components| self.entity_priority(components.as_ref()))
+                .unwrap_or(DEFAULT_PRIORITY);
+            std::cmp::Reverse(priority)
+        });
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::Component;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        entity_id::SimEntityId,
+        requests::state_dif::StateDif,
+        testing::{fixtures::bincode_save_id, test_game},
+    };
+
+    use super::ReplicationBudget;
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct Position(i32);
+
+    bincode_save_id!(Position, 25);
+
+    #[derive(Default, Component, Serialize, Deserialize)]
+    struct Cosmetic(u32);
+
+    bincode_save_id!(Cosmetic, 26);
+
+    #[test]
+    fn test_replication_priority() {
+        let mut game = test_game();
+        game.register_component::<Position>().unwrap();
+        game.register_component::<Cosmetic>().unwrap();
+        game.register_component_priority::<Position>(10);
+        let mut sim = game.build_standalone();
+        sim.sim_world
+            .world
+            .insert_resource(ReplicationBudget { max_entities: 1 });
+
+        let cosmetic = sim.sim_world.world.spawn(Cosmetic(0)).id();
+        let unit = sim.sim_world.world.spawn(Position(0)).id();
+        sim.simulate();
+        let cosmetic_id = *sim.sim_world.world.get::<SimEntityId>(cosmetic).unwrap();
+        let unit_id = *sim.sim_world.world.get::<SimEntityId>(unit).unwrap();
+
+        let state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, unit_id);
+
+        let state = sim.request(StateDif { for_player: 0 });
+        assert_eq!(state.entities.len(), 1);
+        assert_eq!(state.entities[0].entity, cosmetic_id);
+    }
+}
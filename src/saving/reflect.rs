@@ -0,0 +1,251 @@
+//! Reflection based serialization for components that aren't registered. Enabled with
+//! [`GameSerDeRegistry::reflect_fallback`], every component on a saved entity that is registered in
+//! the worlds [`AppTypeRegistry`] with [`ReflectComponent`] is serialized the same way Bevy scenes
+//! are, so components can be prototyped without registering each one up front.
+//!
+//! Reflected components are identified by [`type_path_id`] and are included whenever an entity is
+//! saved, but changes to them aren't tracked. Register them once they need to be sent in change
+//! based state.
+//!
+//! A reflected component whose id is already used by a registered component can't be saved and is
+//! left out. These collisions are returned by [`GameSerDeRegistry::reflect_fallback_collisions`]
+//! and reported by [`GameBuilder::validate`](crate::game_builder::GameBuilder::validate).
+
+use bevy::{
+    ecs::reflect::{AppTypeRegistry, ReflectComponent},
+    prelude::{Entity, EntityWorldMut, World},
+    reflect::{
+        serde::{ReflectSerializer, UntypedReflectDeserializer},
+        TypeRegistry,
+    },
+};
+
+use super::{
+    type_path_id, ComponentBinaryState, DecodeError, GameSerDeRegistry, RegistryError,
+    SimSerializer,
+};
+
+impl GameSerDeRegistry {
+    /// Returns a [`RegistryError::DuplicateComponentId`] for every unregistered component in the
+    /// given type registry whose [`type_path_id`] is used by a registered component. These
+    /// components are left out of saves by the reflection fallback
+    pub fn reflect_fallback_collisions(&self, type_registry: &TypeRegistry) -> Vec<RegistryError> {
+        let mut errors: Vec<RegistryError> = type_registry
+            .iter()
+            .filter(|registration| registration.data::<ReflectComponent>().is_some())
+            .filter(|registration| {
+                !self
+                    .component_type_ids
+                    .contains_key(&registration.type_id())
+            })
+            .filter_map(|registration| {
+                let type_path = registration.type_info().type_path();
+                let id = type_path_id(type_path);
+                self.component_type_names.get(&id).map(|existing| {
+                    RegistryError::DuplicateComponentId {
+                        id,
+                        existing,
+                        new: type_path,
+                    }
+                })
+            })
+            .collect();
+        errors.sort_by_key(|error| error.to_string());
+        errors
+    }
+
+    /// Serializes every unregistered component on the given entity that is registered in the worlds
+    /// [`AppTypeRegistry`] with [`ReflectComponent`]
+    pub(crate) fn save_reflected(
+        &self,
+        world: &World,
+        entity: Entity,
+        components: &mut Vec<ComponentBinaryState>,
+    ) {
+        let Some(type_registry) = world.get_resource::<AppTypeRegistry>() else {
+            return;
+        };
+        let type_registry = type_registry.read();
+        let Some(entity_ref) = world.get_entity(entity) else {
+            return;
+        };
+        for component_id in entity_ref.archetype().components() {
+            let Some(type_id) = world
+                .components()
+                .get_info(component_id)
+                .and_then(|info| info.type_id())
+            else {
+                continue;
+            };
+            let Some(registration) = type_registry.get(type_id) else {
+                continue;
+            };
+            let Some(reflect_component) = registration.data::<ReflectComponent>() else {
+                continue;
+            };
+            let id = type_path_id(registration.type_info().type_path());
+            if self.component_type_names.contains_key(&id) {
+                continue;
+            }
+            let Some(component) = reflect_component.reflect(entity_ref) else {
+                continue;
+            };
+            let Some(binary) = self
+                .format
+                .encode(&ReflectSerializer::new(component, &type_registry))
+            else {
+                continue;
+            };
+            components.push(ComponentBinaryState {
+                id,
+                version: 0,
+                component: self.pack_payload(binary),
+            });
+        }
+    }
+
+    /// Deserializes a component saved by [`GameSerDeRegistry::save_reflected`] onto the given
+    /// entity
+    pub(crate) fn deserialize_reflected(
+        &self,
+        data: &[u8],
+        entity: &mut EntityWorldMut,
+    ) -> Result<(), DecodeError> {
+        let Some(type_registry) = entity.world().get_resource::<AppTypeRegistry>().cloned() else {
+            return Err(DecodeError::new(0, "world has no AppTypeRegistry"));
+        };
+        let type_registry = type_registry.read();
+        let component = self
+            .format
+            .decode_seed(UntypedReflectDeserializer::new(&type_registry), data)?;
+        let Some(reflect_component) = component
+            .get_represented_type_info()
+            .and_then(|type_info| type_registry.get(type_info.type_id()))
+            .and_then(|registration| registration.data::<ReflectComponent>())
+        else {
+            return Err(DecodeError::new(0, "type is not a reflected component"));
+        };
+        reflect_component.insert(entity, component.as_reflect(), &type_registry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        ecs::reflect::{AppTypeRegistry, ReflectComponent},
+        prelude::{Component, World},
+        reflect::{Reflect, TypePath},
+    };
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::saving::{type_path_id, GameSerDeRegistry, RegistryError};
+
+    #[derive(Component, Reflect, Default, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct PrototypeComponent {
+        health: u32,
+        name: String,
+    }
+
+    #[test]
+    fn test_reflect_fallback_round_trip() {
+        let mut registry = GameSerDeRegistry::new();
+        registry.reflect_fallback = true;
+
+        let mut world = World::new();
+        world.init_resource::<AppTypeRegistry>();
+        world
+            .resource::<AppTypeRegistry>()
+            .write()
+            .register::<PrototypeComponent>();
+        let component = PrototypeComponent {
+            health: 5,
+            name: "test".into(),
+        };
+        let entity = world.spawn(component).id();
+        let components = registry.save_entity(None, &world, entity);
+        assert_eq!(components.len(), 1);
+
+        let mut new_entity = world.spawn_empty();
+        registry
+            .deserialize_component_onto(&components[0], &mut new_entity)
+            .unwrap();
+        assert_eq!(
+            new_entity.get::<PrototypeComponent>(),
+            Some(&PrototypeComponent {
+                health: 5,
+                name: "test".into(),
+            })
+        );
+    }
+
+    #[derive(Component, Reflect, Serialize, Deserialize)]
+    struct Armor(u32);
+
+    #[derive(Component, Reflect, Serialize, Deserialize)]
+    struct Speed(u32);
+
+    #[test]
+    fn test_reflect_ids_are_stable() {
+        let mut registry = GameSerDeRegistry::new();
+        let speed = registry.register_reflect::<Speed>().unwrap();
+        let armor = registry.register_reflect::<Armor>().unwrap();
+
+        let mut other = GameSerDeRegistry::new();
+        assert_eq!(other.register_reflect::<Armor>(), Ok(armor));
+        assert_eq!(other.register_reflect::<Speed>(), Ok(speed));
+        assert_eq!(armor, type_path_id(Armor::type_path()));
+    }
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct HandAssigned;
+
+    #[test]
+    fn test_reflect_id_collision() {
+        let mut registry = GameSerDeRegistry::new();
+        let id = type_path_id(Armor::type_path());
+        registry
+            .register_component_with_id::<HandAssigned>(id)
+            .unwrap();
+
+        assert!(matches!(
+            registry.register_reflect::<Armor>(),
+            Err(RegistryError::DuplicateComponentId { id: collided, .. }) if collided == id
+        ));
+        assert!(registry.validate().is_err());
+        assert_eq!(
+            registry.component_type_names.get(&id),
+            Some(&std::any::type_name::<HandAssigned>())
+        );
+    }
+
+    #[test]
+    fn test_reflect_fallback_collision() {
+        let mut registry = GameSerDeRegistry::new();
+        registry.reflect_fallback = true;
+        let id = type_path_id(PrototypeComponent::type_path());
+        registry
+            .register_component_with_id::<HandAssigned>(id)
+            .unwrap();
+
+        let mut world = World::new();
+        world.init_resource::<AppTypeRegistry>();
+        world
+            .resource::<AppTypeRegistry>()
+            .write()
+            .register::<PrototypeComponent>();
+        assert_eq!(
+            registry.reflect_fallback_collisions(&world.resource::<AppTypeRegistry>().read()),
+            vec![RegistryError::DuplicateComponentId {
+                id,
+                existing: std::any::type_name::<HandAssigned>(),
+                new: PrototypeComponent::type_path(),
+            }]
+        );
+
+        let entity = world.spawn(PrototypeComponent::default()).id();
+        assert!(registry.save_entity(None, &world, entity).is_empty());
+    }
+}
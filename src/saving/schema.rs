@@ -0,0 +1,171 @@
+//! Machine readable descriptions of everything registered in a [`GameSerDeRegistry`]. External
+//! tools like replay viewers and server dashboards can use the exported [`RegistrySchema`] to know
+//! which type each [`SimComponentId`] and [`SimResourceId`] in a state belongs to.
+
+use bevy::reflect::{TypeInfo, TypeRegistry};
+use serde::{Deserialize, Serialize};
+
+use super::{GameSerDeRegistry, SimComponentId, SimFormat, SimResourceId};
+
+/// A single field of a registered type
+#[derive(Clone, Eq, Hash, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    /// The field name, or its index for tuple structs
+    pub name: String,
+    pub type_name: String,
+}
+
+/// A single registered component or resource
+#[derive(Clone, Eq, Hash, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TypeSchema {
+    pub id: u16,
+    pub type_name: String,
+    /// The fields of the type. Only filled in when the schema is exported with a [`TypeRegistry`]
+    /// containing the type
+    pub fields: Vec<FieldSchema>,
+    /// The variants of the type if it is an enum. Only filled in when the schema is exported with a
+    /// [`TypeRegistry`] containing the type
+    pub variants: Vec<String>,
+}
+
+/// A description of every component and resource registered in a [`GameSerDeRegistry`]
+#[derive(Clone, Eq, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RegistrySchema {
+    pub format: SimFormat,
+    pub components: Vec<TypeSchema>,
+    pub resources: Vec<TypeSchema>,
+}
+
+impl GameSerDeRegistry {
+    /// Exports the id and type name of every registered component and resource
+    pub fn export_schema(&self) -> RegistrySchema {
+        self.build_schema(None)
+    }
+
+    /// Exports the id, type name, and fields of every registered component and resource. Fields
+    /// are only included for types registered in the given [`TypeRegistry`]
+    pub fn export_schema_with_types(&self, type_registry: &TypeRegistry) -> RegistrySchema {
+        self.build_schema(Some(type_registry))
+    }
+
+    fn build_schema(&self, type_registry: Option<&TypeRegistry>) -> RegistrySchema {
+        let mut components: Vec<TypeSchema> = self
+            .component_type_names
+            .keys()
+            .map(|id| type_schema(*id, self.component_type_name(*id), type_registry))
+            .collect();
+        components.sort_by_key(|schema| schema.id);
+
+        let mut resources: Vec<TypeSchema> = self
+            .resource_de_map
+            .keys()
+            .map(|id| type_schema(*id, self.resource_type_name(*id), type_registry))
+            .collect();
+        resources.sort_by_key(|schema| schema.id);
+
+        RegistrySchema {
+            format: self.format,
+            components,
+            resources,
+        }
+    }
+
+    /// Returns the type name of the registered component with the given id
+    pub fn component_type_name(&self, id: SimComponentId) -> Option<&'static str> {
+        self.component_type_names.get(&id).copied()
+    }
+
+    /// Returns the type name of the registered resource with the given id
+    pub fn resource_type_name(&self, id: SimResourceId) -> Option<&'static str> {
+        self.resource_type_names.get(&id).copied()
+    }
+}
+
+fn type_schema(
+    id: u16,
+    type_name: Option<&'static str>,
+    type_registry: Option<&TypeRegistry>,
+) -> TypeSchema {
+    let mut schema = TypeSchema {
+        id,
+        type_name: type_name.unwrap_or_default().to_string(),
+        fields: vec![],
+        variants: vec![],
+    };
+
+    let Some(registration) = type_name.and_then(|name| type_registry?.get_with_type_path(name))
+    else {
+        return schema;
+    };
+    match registration.type_info() {
+        TypeInfo::Struct(info) => {
+            schema.fields = info
+                .iter()
+                .map(|field| FieldSchema {
+                    name: field.name().to_string(),
+                    type_name: field.type_path().to_string(),
+                })
+                .collect();
+        }
+        TypeInfo::TupleStruct(info) => {
+            schema.fields = info
+                .iter()
+                .map(|field| FieldSchema {
+                    name: field.index().to_string(),
+                    type_name: field.type_path().to_string(),
+                })
+                .collect();
+        }
+        TypeInfo::Enum(info) => {
+            schema.variants = info
+                .iter()
+                .map(|variant| variant.name().to_string())
+                .collect();
+        }
+        _ => {}
+    }
+    schema
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        prelude::Component,
+        reflect::{Reflect, TypeRegistry},
+    };
+    use serde::{Deserialize, Serialize};
+
+    use crate::{saving::GameSerDeRegistry, testing::fixtures::bincode_save_id};
+
+    #[derive(Default, Component, Reflect, Serialize, Deserialize)]
+    struct TestComponent {
+        value: u32,
+    }
+
+    bincode_save_id!(TestComponent, 25);
+
+    #[test]
+    fn test_export_schema_with_fields() {
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        let mut type_registry = TypeRegistry::new();
+        type_registry.register::<TestComponent>();
+
+        let schema = registry.export_schema_with_types(&type_registry);
+
+        assert_eq!(schema.components.len(), 1);
+        assert_eq!(schema.components[0].id, 25);
+        assert_eq!(schema.components[0].fields[0].name, "value");
+        assert_eq!(schema.components[0].fields[0].type_name, "u32");
+    }
+
+    #[test]
+    fn test_validate_reports_all_collisions() {
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<TestComponent>().unwrap();
+        assert!(registry.register_component::<TestComponent>().is_err());
+        assert!(registry.register_component::<TestComponent>().is_err());
+
+        assert_eq!(registry.validate().unwrap_err().len(), 2);
+    }
+}
@@ -0,0 +1,301 @@
+//! Serialization backends used by the [`GameSerDeRegistry`](super::GameSerDeRegistry) to turn
+//! registered components and resources into bytes. Bincode is always available, JSON, RON, and
+//! MessagePack are available behind the `json`, `ron`, and `msgpack` features.
+//!
+//! Registrations don't depend on the backend, so the same registration code can produce human
+//! readable saves in development and compact binary in production by changing
+//! [`GameSerDeRegistry::format`](super::GameSerDeRegistry::format).
+
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed},
+    Deserialize, Serialize,
+};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    io::Cursor,
+};
+
+/// An error deserializing a value, with the byte offset into the data where it failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl DecodeError {
+    pub fn new(offset: usize, message: impl Into<String>) -> DecodeError {
+        DecodeError {
+            offset,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A format that registered components and resources can be serialized with
+pub trait SimSerializer {
+    /// Serializes the given value. Returns None if it fails
+    fn encode<T: Serialize>(&self, value: &T) -> Option<Vec<u8>>;
+
+    /// Deserializes a value from the given data
+    fn try_decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DecodeError>;
+
+    /// Deserializes a value from the given data using the given seed
+    fn decode_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        data: &'de [u8],
+    ) -> Result<S::Value, DecodeError>;
+
+    /// Deserializes a value from the given data. Returns None if it fails
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Option<T> {
+        self.try_decode(data).ok()
+    }
+}
+
+/// Returns the byte offset of the given one based line and column in the given data
+#[cfg(any(feature = "json", feature = "ron"))]
+fn line_column_offset(data: &[u8], line: usize, column: usize) -> usize {
+    let line_start = data
+        .split_inclusive(|byte| *byte == b'\n')
+        .take(line.saturating_sub(1))
+        .map(|line| line.len())
+        .sum::<usize>();
+    (line_start + column.saturating_sub(1)).min(data.len())
+}
+
+/// Compact binary serialization using bincode
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BincodeSerializer;
+
+impl SimSerializer for BincodeSerializer {
+    fn encode<T: Serialize>(&self, value: &T) -> Option<Vec<u8>> {
+        bincode::serialize(value).ok()
+    }
+
+    fn try_decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DecodeError> {
+        let mut cursor = Cursor::new(data);
+        bincode::deserialize_from(&mut cursor)
+            .map_err(|error| DecodeError::new(cursor.position() as usize, error.to_string()))
+    }
+
+    fn decode_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        data: &'de [u8],
+    ) -> Result<S::Value, DecodeError> {
+        use bincode::Options;
+        let mut cursor = Cursor::new(data);
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .deserialize_from_seed(seed, &mut cursor)
+            .map_err(|error| DecodeError::new(cursor.position() as usize, error.to_string()))
+    }
+}
+
+/// Human readable serialization using JSON
+#[cfg(feature = "json")]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct JsonSerializer;
+
+#[cfg(feature = "json")]
+impl SimSerializer for JsonSerializer {
+    fn encode<T: Serialize>(&self, value: &T) -> Option<Vec<u8>> {
+        serde_json::to_vec(value).ok()
+    }
+
+    fn try_decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DecodeError> {
+        serde_json::from_slice(data).map_err(|error| json_error(data, error))
+    }
+
+    fn decode_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        data: &'de [u8],
+    ) -> Result<S::Value, DecodeError> {
+        seed.deserialize(&mut serde_json::Deserializer::from_slice(data))
+            .map_err(|error| json_error(data, error))
+    }
+}
+
+#[cfg(feature = "json")]
+fn json_error(data: &[u8], error: serde_json::Error) -> DecodeError {
+    DecodeError::new(
+        line_column_offset(data, error.line(), error.column()),
+        error.to_string(),
+    )
+}
+
+/// Human readable serialization using RON
+#[cfg(feature = "ron")]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RonSerializer;
+
+#[cfg(feature = "ron")]
+impl SimSerializer for RonSerializer {
+    fn encode<T: Serialize>(&self, value: &T) -> Option<Vec<u8>> {
+        ron::to_string(value).ok().map(String::into_bytes)
+    }
+
+    fn try_decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DecodeError> {
+        self.decode_seed(std::marker::PhantomData::<T>, data)
+    }
+
+    fn decode_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        data: &'de [u8],
+    ) -> Result<S::Value, DecodeError> {
+        let mut deserializer =
+            ron::Deserializer::from_bytes(data).map_err(|error| ron_error(data, error))?;
+        let value = seed
+            .deserialize(&mut deserializer)
+            .map_err(|error| ron_error(data, deserializer.span_error(error)))?;
+        deserializer
+            .end()
+            .map_err(|error| ron_error(data, deserializer.span_error(error)))?;
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "ron")]
+fn ron_error(data: &[u8], error: ron::error::SpannedError) -> DecodeError {
+    DecodeError::new(
+        line_column_offset(data, error.position.line, error.position.col),
+        error.code.to_string(),
+    )
+}
+
+/// Compact binary serialization using MessagePack
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MessagePackSerializer;
+
+#[cfg(feature = "msgpack")]
+impl SimSerializer for MessagePackSerializer {
+    fn encode<T: Serialize>(&self, value: &T) -> Option<Vec<u8>> {
+        rmp_serde::to_vec(value).ok()
+    }
+
+    fn try_decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DecodeError> {
+        let mut cursor = Cursor::new(data);
+        rmp_serde::from_read(&mut cursor)
+            .map_err(|error| DecodeError::new(cursor.position() as usize, error.to_string()))
+    }
+
+    fn decode_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        data: &'de [u8],
+    ) -> Result<S::Value, DecodeError> {
+        let mut cursor = Cursor::new(data);
+        seed.deserialize(&mut rmp_serde::Deserializer::new(&mut cursor))
+            .map_err(|error| DecodeError::new(cursor.position() as usize, error.to_string()))
+    }
+}
+
+/// The backend a [`GameSerDeRegistry`](super::GameSerDeRegistry) serializes with. Dispatches to
+/// the matching [`SimSerializer`]
+#[derive(Clone, Copy, Default, Eq, Hash, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SimFormat {
+    #[default]
+    Bincode,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "ron")]
+    Ron,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl SimSerializer for SimFormat {
+    fn encode<T: Serialize>(&self, value: &T) -> Option<Vec<u8>> {
+        match self {
+            SimFormat::Bincode => BincodeSerializer.encode(value),
+            #[cfg(feature = "json")]
+            SimFormat::Json => JsonSerializer.encode(value),
+            #[cfg(feature = "ron")]
+            SimFormat::Ron => RonSerializer.encode(value),
+            #[cfg(feature = "msgpack")]
+            SimFormat::MessagePack => MessagePackSerializer.encode(value),
+        }
+    }
+
+    fn try_decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DecodeError> {
+        match self {
+            SimFormat::Bincode => BincodeSerializer.try_decode(data),
+            #[cfg(feature = "json")]
+            SimFormat::Json => JsonSerializer.try_decode(data),
+            #[cfg(feature = "ron")]
+            SimFormat::Ron => RonSerializer.try_decode(data),
+            #[cfg(feature = "msgpack")]
+            SimFormat::MessagePack => MessagePackSerializer.try_decode(data),
+        }
+    }
+
+    fn decode_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        data: &'de [u8],
+    ) -> Result<S::Value, DecodeError> {
+        match self {
+            SimFormat::Bincode => BincodeSerializer.decode_seed(seed, data),
+            #[cfg(feature = "json")]
+            SimFormat::Json => JsonSerializer.decode_seed(seed, data),
+            #[cfg(feature = "ron")]
+            SimFormat::Ron => RonSerializer.decode_seed(seed, data),
+            #[cfg(feature = "msgpack")]
+            SimFormat::MessagePack => MessagePackSerializer.decode_seed(seed, data),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::{SimFormat, SimSerializer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestComponent(u32, String);
+
+    #[test]
+    fn test_formats_round_trip() {
+        let formats = [
+            SimFormat::Bincode,
+            #[cfg(feature = "json")]
+            SimFormat::Json,
+            #[cfg(feature = "ron")]
+            SimFormat::Ron,
+            #[cfg(feature = "msgpack")]
+            SimFormat::MessagePack,
+        ];
+        for format in formats {
+            let data = format.encode(&TestComponent(3, "test".into())).unwrap();
+            assert_eq!(
+                format.decode::<TestComponent>(&data),
+                Some(TestComponent(3, "test".into()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_error_offset() {
+        let data = SimFormat::Bincode
+            .encode(&TestComponent(3, "test".into()))
+            .unwrap();
+        let error = SimFormat::Bincode
+            .try_decode::<TestComponent>(&data[..6])
+            .unwrap_err();
+        assert_eq!(error.offset, 6);
+    }
+}
@@ -0,0 +1,719 @@
+//! Full snapshots of a [`SimWorld`]. A [`SimSnapshot`] contains every registered component on every
+//! entity, every registered resource, the player list, and the sim tick, and can be turned into a
+//! single binary blob and loaded back into a sim world. Entities are identified by their
+//! [`SimEntityId`], so loading a snapshot spawns new bevy entities that keep their sim ids.
+
+use bevy::{
+    prelude::{Component, Entity, EntityRef, Without, World},
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+
+use crate::{
+    change_detection::DespawnTracked,
+    command::SavedCommands,
+    entity_id::{assign_sim_entity_ids, sim_entity_map, SimEntityId, SimEntityIdAllocator},
+    player::{Player, PlayerList},
+    requests::ResourceState,
+    runner::SimTick,
+    SimWorld,
+};
+
+use super::{
+    header::SaveHeader, ComponentBinaryState, DeserializeReport, GameSerDeRegistry, SaveId,
+    SimComponentId, SimResourceId,
+};
+
+/// The saved state of a single entity
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub entity: SimEntityId,
+    pub components: Vec<ComponentBinaryState>,
+}
+
+/// The saved state of a single resource
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResourceSnapshot {
+    pub resource_id: SimResourceId,
+    pub version: u16,
+    pub resource: Vec<u8>,
+}
+
+/// Filters which entities and components are included in a [`SimSnapshot`]. Use this to persist
+/// part of a sim world, eg campaign state without the scratch entities of the current battle
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct SaveFilter {
+    /// Only entities that have every one of these components are saved
+    pub required: Vec<TypeId>,
+    /// Entities that have any of these components aren't saved
+    pub excluded_entities: Vec<TypeId>,
+    /// Components that aren't saved on any entity
+    pub excluded_components: HashSet<SimComponentId>,
+}
+
+impl SaveFilter {
+    pub fn new() -> SaveFilter {
+        SaveFilter::default()
+    }
+
+    /// Only saves entities that have the given component, eg a marker component
+    pub fn with<C: Component>(mut self) -> SaveFilter {
+        self.required.push(TypeId::of::<C>());
+        self
+    }
+
+    /// Doesn't save entities that have the given component
+    pub fn without<C: Component>(mut self) -> SaveFilter {
+        self.excluded_entities.push(TypeId::of::<C>());
+        self
+    }
+
+    /// Doesn't save the component with the given id on any entity
+    pub fn exclude_component(mut self, id: SimComponentId) -> SaveFilter {
+        self.excluded_components.insert(id);
+        self
+    }
+
+    /// Returns true if the given entity should be saved
+    pub fn matches(&self, entity: &EntityRef) -> bool {
+        self.required
+            .iter()
+            .all(|type_id| entity.contains_type_id(*type_id))
+            && !self
+                .excluded_entities
+                .iter()
+                .any(|type_id| entity.contains_type_id(*type_id))
+    }
+}
+
+/// A full snapshot of a [`SimWorld`]. Player entities aren't saved as entities, they are respawned
+/// from the player list when the snapshot is applied
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimSnapshot {
+    pub tick: u64,
+    /// The next [`SimEntityId`] that will be assigned
+    pub next_entity_id: u64,
+    pub player_list: PlayerList,
+    pub entities: Vec<EntitySnapshot>,
+    pub resources: Vec<ResourceSnapshot>,
+    /// The command queue and history, only included by
+    /// [`SimWorld::save_snapshot_with_commands`](crate::SimWorld::save_snapshot_with_commands)
+    pub commands: SavedCommands,
+}
+
+impl SimSnapshot {
+    /// Creates a snapshot of the given sim world
+    pub fn new(sim_world: &mut SimWorld) -> SimSnapshot {
+        SimSnapshot::new_filtered(sim_world, &SaveFilter::default())
+    }
+
+    /// Creates a snapshot of the entities and components in the given sim world that pass the given
+    /// filter. Resources, the player list, and the tick are always saved
+    pub fn new_filtered(sim_world: &mut SimWorld, filter: &SaveFilter) -> SimSnapshot {
+        SimSnapshot::from_world(
+            &mut sim_world.world,
+            &sim_world.registry,
+            &sim_world.player_list,
+            filter,
+        )
+    }
+
+    /// Creates a snapshot of the entities and components in the given world that pass the given
+    /// filter using the given registry. Use this when only the world of a sim is available, such
+    /// as from within the [`GameRuntime`](crate::runner::GameRuntime)
+    ///
+    /// Entities, components, and resources are sorted by id so the same world always snapshots to
+    /// the same bytes
+    pub fn from_world(
+        world: &mut World,
+        registry: &GameSerDeRegistry,
+        player_list: &PlayerList,
+        filter: &SaveFilter,
+    ) -> SimSnapshot {
+        assign_sim_entity_ids(world);
+        let mut entities = vec![];
+        let mut query = world
+            .query_filtered::<(Entity, &SimEntityId, Option<&dyn SaveId>), (Without<DespawnTracked>, Without<Player>)>();
+        for (entity, id, saveable_components) in query.iter(world) {
+            if !filter.matches(&world.entity(entity)) {
+                continue;
+            }
+            let mut components = registry.save_entity(saveable_components.as_ref(), world, entity);
+            components.retain(|component| !filter.excluded_components.contains(&component.id));
+            if !components.is_empty() {
+                entities.push(EntitySnapshot {
+                    entity: *id,
+                    components,
+                });
+            }
+        }
+
+        let mut resources = vec![];
+        for id in registry.resource_se_map.keys() {
+            if let Some(resource_state) = registry.serialize_resource(id, world) {
+                resources.push(ResourceSnapshot {
+                    resource_id: resource_state.resource_id,
+                    version: resource_state.version,
+                    resource: resource_state.resource,
+                });
+            }
+        }
+        entities.sort_by_key(|entity| entity.entity);
+        resources.sort_by_key(|resource| resource.resource_id);
+
+        SimSnapshot {
+            tick: world
+                .get_resource::<SimTick>()
+                .map(|tick| tick.0)
+                .unwrap_or_default(),
+            next_entity_id: world
+                .get_resource::<SimEntityIdAllocator>()
+                .map(|allocator| allocator.next)
+                .unwrap_or_default(),
+            player_list: player_list.clone(),
+            entities,
+            resources,
+            commands: SavedCommands::default(),
+        }
+    }
+
+    /// Serializes the snapshot into a single binary blob
+    pub fn to_binary(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    /// Deserializes a snapshot from a binary blob created with [`SimSnapshot::to_binary`]
+    pub fn from_binary(data: &[u8]) -> Result<SimSnapshot, bincode::Error> {
+        bincode::deserialize(data)
+    }
+
+    /// Deserializes a snapshot from a save created with
+    /// [`SimWorld::save_snapshot`](crate::SimWorld::save_snapshot), skipping its header. Use this to
+    /// load the baseline of a delta save. The body is decrypted with the given registry if it has
+    /// [`save_encryption`](GameSerDeRegistry::save_encryption) set
+    pub fn from_save(
+        data: &[u8],
+        registry: &GameSerDeRegistry,
+    ) -> Result<SimSnapshot, bincode::Error> {
+        let (_, body) = SaveHeader::split(data)?;
+        SimSnapshot::from_binary(&registry.open_save_body(body)?)
+    }
+
+    /// Replaces every entity in the given world with the entities in the snapshot, keeping their
+    /// [`SimEntityId`]s, and inserts every resource in the snapshot. Player entities are respawned
+    /// from the snapshots player list. Returns every component and resource that failed to
+    /// deserialize
+    pub fn apply(&self, world: &mut World, registry: &GameSerDeRegistry) -> DeserializeReport {
+        let mut report = DeserializeReport::default();
+        world.clear_entities();
+
+        for entity_snapshot in self.entities.iter() {
+            world.spawn(entity_snapshot.entity);
+        }
+        let entity_map = sim_entity_map(world);
+        for entity_snapshot in self.entities.iter() {
+            let mut entity = world.entity_mut(entity_map[&entity_snapshot.entity]);
+            for component in entity_snapshot.components.iter() {
+                if let Err(error) =
+                    registry.deserialize_component_onto_mapped(component, &mut entity, &entity_map)
+                {
+                    report.entities.push((entity_snapshot.entity, error));
+                }
+            }
+        }
+
+        for player in self.player_list.players.iter() {
+            world.spawn(Player::new(player.id(), player.needs_state));
+        }
+
+        for resource in self.resources.iter() {
+            if let Err(error) = registry.deserialize_resource(
+                ResourceState {
+                    resource_id: resource.resource_id,
+                    version: resource.version,
+                    resource: resource.resource.clone(),
+                    changed_tick: 0,
+                },
+                world,
+            ) {
+                report.resources.push(error);
+            }
+        }
+
+        world.insert_resource(self.player_list.clone());
+        world.insert_resource(SimTick(self.tick));
+        world.insert_resource(SimEntityIdAllocator {
+            next: self.next_entity_id,
+        });
+        assign_sim_entity_ids(world);
+        report
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::{
+        ecs::entity::{EntityMapper, MapEntities},
+        prelude::{BuildWorldChildren, Children, Component, Entity, Parent, Resource, World},
+        reflect::{Reflect, TypePath},
+    };
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        command::{CommandError, GameCommand},
+        entity_id::{sim_entity_map, SimEntityId},
+        game_builder::GameBuilder,
+        requests::all_state::AllState,
+        runner::TurnBasedGameRunner,
+        saving::{
+            autosave::{
+                autosave_paths, Autosave, AutosaveInterval, AutosaveSource, AutosaveStatus,
+            },
+            header::SaveHeader,
+            snapshot::{SaveFilter, SimSnapshot},
+        },
+        testing::{
+            fixtures::{bincode_save_id, TestComponent},
+            TestSim,
+        },
+    };
+
+    #[derive(Default, Debug, PartialEq, Resource, Serialize, Deserialize)]
+    struct TestResource(u32);
+
+    bincode_save_id!(TestResource, 25);
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+            builder.register_resource::<TestResource>().unwrap();
+        });
+        let entity = sim.world().spawn(TestComponent(7)).id();
+        sim.world().insert_resource(TestResource(3));
+        sim.simulate();
+        let id = *sim.world().get::<SimEntityId>(entity).unwrap();
+
+        let snapshot = sim.sim.sim_world.save_snapshot().unwrap();
+
+        sim.world().entity_mut(entity).insert(TestComponent(1));
+        sim.world().insert_resource(TestResource(1));
+        sim.sim.sim_world.load_snapshot(&snapshot).unwrap();
+
+        let entity = sim_entity_map(sim.world())[&id];
+        assert_eq!(
+            sim.world().get::<TestComponent>(entity),
+            Some(&TestComponent(7))
+        );
+        assert_eq!(sim.world().resource::<TestResource>(), &TestResource(3));
+        assert_eq!(sim.sim.sim_world.tick(), 1);
+    }
+
+    #[derive(Default, Debug, PartialEq, Resource, Serialize, Deserialize)]
+    struct OtherResource(u32);
+
+    bincode_save_id!(OtherResource, 26);
+
+    #[derive(Default, Debug, PartialEq, Component, Serialize, Deserialize)]
+    struct OtherComponent(u32);
+
+    bincode_save_id!(OtherComponent, 26);
+
+    #[test]
+    fn test_snapshot_is_deterministic() {
+        let snapshot = |reverse: bool| {
+            let mut sim = TestSim::with_builder(|builder| {
+                if reverse {
+                    builder.register_component::<OtherComponent>().unwrap();
+                    builder.register_component::<TestComponent>().unwrap();
+                    builder.register_resource::<OtherResource>().unwrap();
+                    builder.register_resource::<TestResource>().unwrap();
+                } else {
+                    builder.register_component::<TestComponent>().unwrap();
+                    builder.register_component::<OtherComponent>().unwrap();
+                    builder.register_resource::<TestResource>().unwrap();
+                    builder.register_resource::<OtherResource>().unwrap();
+                }
+            });
+            sim.world().spawn((TestComponent(1), OtherComponent(2)));
+            sim.world().spawn((TestComponent(3), OtherComponent(4)));
+            sim.world().insert_resource(TestResource(5));
+            sim.world().insert_resource(OtherResource(6));
+            sim.simulate();
+            SimSnapshot::new(&mut sim.sim.sim_world)
+        };
+
+        let forward = snapshot(false);
+        let reverse = snapshot(true);
+        for entity in forward.entities.iter() {
+            assert!(entity
+                .components
+                .windows(2)
+                .all(|pair| pair[0].id < pair[1].id));
+        }
+        assert!(forward
+            .resources
+            .windows(2)
+            .all(|pair| pair[0].resource_id < pair[1].resource_id));
+        assert_eq!(forward.to_binary().unwrap(), reverse.to_binary().unwrap());
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        let changed = sim.world().spawn(TestComponent(1)).id();
+        let removed = sim.world().spawn(TestComponent(2)).id();
+        sim.simulate();
+        let changed_id = *sim.world().get::<SimEntityId>(changed).unwrap();
+        let baseline = sim.sim.sim_world.snapshot();
+
+        sim.world().entity_mut(changed).insert(TestComponent(3));
+        sim.world().despawn(removed);
+        sim.world().spawn(TestComponent(4));
+        sim.simulate();
+        let delta = sim.sim.sim_world.save_delta(&baseline).unwrap();
+        sim.sim.sim_world.load_delta(&baseline, &delta).unwrap();
+
+        let entity_map = sim_entity_map(sim.world());
+        assert_eq!(entity_map.len(), 2);
+        assert_eq!(
+            sim.world().get::<TestComponent>(entity_map[&changed_id]),
+            Some(&TestComponent(3))
+        );
+    }
+
+    #[derive(Clone, Debug, Reflect)]
+    struct SpawnCommand {
+        value: u32,
+    }
+
+    impl GameCommand for SpawnCommand {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.spawn(TestComponent(self.value));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_save_commands() {
+        let setup = |builder: &mut GameBuilder<TurnBasedGameRunner>| {
+            builder.register_component::<TestComponent>().unwrap();
+            builder.register_command::<SpawnCommand>();
+        };
+        let mut sim = TestSim::with_builder(setup);
+        sim.sim
+            .sim_world
+            .game_commands_mut()
+            .add(SpawnCommand { value: 4 });
+        sim.simulate();
+        let save = sim.sim.save_snapshot("commands").unwrap();
+
+        let mut loaded = TestSim::with_builder(setup);
+        loaded.sim.load_snapshot(&save).unwrap();
+        let history = &loaded.sim.sim_world.game_commands().history.history;
+        assert_eq!(history.len(), 1);
+        let command = history[0]
+            .command
+            .as_reflect()
+            .downcast_ref::<SpawnCommand>()
+            .unwrap();
+        assert_eq!(command.value, 4);
+    }
+
+    #[test]
+    fn test_autosave() {
+        let directory = std::env::temp_dir().join("bevy_sim_world_test_autosave");
+        let autosave_directory = directory.clone();
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+            builder.set_autosave(Autosave::new(
+                autosave_directory,
+                AutosaveInterval::Ticks(2),
+            ));
+        });
+        sim.world().spawn(TestComponent(1));
+        sim.simulate();
+        sim.simulate();
+        let autosave = sim.sim.game_runtime.autosave.as_mut().unwrap();
+        autosave.wait(&mut sim.sim.sim_world.world);
+
+        sim.world().spawn(TestComponent(2));
+        sim.simulate();
+        sim.simulate();
+        let autosave = sim.sim.game_runtime.autosave.as_mut().unwrap();
+        autosave.wait(&mut sim.sim.sim_world.world);
+        let status = sim.world().resource::<AutosaveStatus>().clone();
+        assert_eq!(status.last_tick, Some(4));
+        assert_eq!(status.last_result, Some(Ok(())));
+
+        let mut loaded = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        let (_, source) = loaded.sim.sim_world.load_autosave(&directory).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(source, AutosaveSource::Delta);
+        assert_eq!(loaded.sim.sim_world.tick(), 4);
+        assert_eq!(loaded.request(AllState).entities.len(), 2);
+    }
+
+    #[test]
+    fn test_autosave_delta_errors() {
+        let directory = std::env::temp_dir().join("bevy_sim_world_test_autosave_delta_errors");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let (baseline_path, delta_path) = autosave_paths(&directory);
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        sim.world().spawn(TestComponent(1));
+        sim.simulate();
+        let old_baseline = sim.sim.sim_world.snapshot();
+        sim.simulate();
+        let baseline = sim.sim.sim_world.save_snapshot().unwrap();
+        std::fs::write(&baseline_path, baseline).unwrap();
+
+        let mut loaded = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        let (_, source) = loaded.sim.sim_world.load_autosave(&directory).unwrap();
+        assert_eq!(source, AutosaveSource::Baseline);
+
+        let stale_delta = sim.sim.sim_world.save_delta(&old_baseline).unwrap();
+        std::fs::write(&delta_path, stale_delta).unwrap();
+        let (_, source) = loaded.sim.sim_world.load_autosave(&directory).unwrap();
+        assert_eq!(source, AutosaveSource::StaleDelta);
+
+        std::fs::write(&delta_path, [0u8; 4]).unwrap();
+        let result = loaded.sim.sim_world.load_autosave(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_background_save() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        sim.world().spawn(TestComponent(7));
+        sim.simulate();
+
+        let path = std::env::temp_dir().join("bevy_sim_world_test_background_save");
+        let save = sim.sim.sim_world.save_in_background(&path, "background");
+        save.wait().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(SaveHeader::read(&data).unwrap().name, "background");
+        assert_eq!(
+            SimSnapshot::from_save(&data, &sim.sim.sim_world.registry)
+                .unwrap()
+                .entities
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_save_header() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        let save = sim
+            .sim
+            .sim_world
+            .save_snapshot_with("slot one", &SaveFilter::new())
+            .unwrap();
+
+        let header = SaveHeader::read(&save).unwrap();
+        assert_eq!(header.name, "slot one");
+        assert!(header.is_compatible(&sim.sim.sim_world.registry));
+
+        let mut other = TestSim::new();
+        assert!(other.sim.sim_world.load_snapshot(&save).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_streams() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        sim.sim.sim_world.world.spawn(TestComponent(3));
+
+        let mut buffer = vec![];
+        sim.sim
+            .sim_world
+            .save_snapshot_to(&mut buffer, "stream", &SaveFilter::new())
+            .unwrap();
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(SaveHeader::read_from(&mut reader).unwrap().name, "stream");
+
+        let mut other = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        let report = other
+            .sim
+            .sim_world
+            .load_snapshot_from(std::io::Cursor::new(buffer))
+            .unwrap();
+        assert!(report.is_ok());
+        assert_eq!(
+            other
+                .sim
+                .sim_world
+                .world
+                .query::<&TestComponent>()
+                .iter(&other.sim.sim_world.world)
+                .len(),
+            1
+        );
+    }
+
+    #[derive(Default, Debug, PartialEq, Component, Serialize, Deserialize)]
+    struct TestInventory<T: Send + Sync + 'static> {
+        items: Vec<T>,
+    }
+
+    #[test]
+    fn test_generic_components() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder
+                .register_component_with_id::<TestInventory<u32>>(40)
+                .unwrap();
+            builder
+                .register_component_with_id::<TestInventory<String>>(41)
+                .unwrap();
+            assert!(builder
+                .register_component_with_id::<TestInventory<u8>>(41)
+                .is_err());
+        });
+        sim.sim.sim_world.world.spawn((
+            TestInventory { items: vec![1u32] },
+            TestInventory {
+                items: vec![String::from("sword")],
+            },
+        ));
+        let save = sim.sim.sim_world.save_snapshot().unwrap();
+
+        let world = &mut sim.sim.sim_world.world;
+        world.clear_entities();
+        sim.sim.sim_world.load_snapshot(&save).unwrap();
+        let world = &mut sim.sim.sim_world.world;
+        let mut query = world.query::<(&TestInventory<u32>, &TestInventory<String>)>();
+        let (numbers, names) = query.single(world);
+        assert_eq!(numbers.items, vec![1]);
+        assert_eq!(names.items, vec![String::from("sword")]);
+    }
+
+    #[derive(Default, Debug, PartialEq, Component, TypePath, Serialize, Deserialize)]
+    struct TestReflectComponent(u32);
+
+    #[derive(Clone, Debug, PartialEq, Component, Serialize, Deserialize)]
+    struct TestTarget(Entity);
+
+    impl MapEntities for TestTarget {
+        fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+            self.0 = entity_mapper.map_entity(self.0);
+        }
+    }
+
+    bincode_save_id!(TestTarget, 26);
+
+    #[test]
+    fn test_entity_references_remapped() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+            builder
+                .register_component_map_entities::<TestTarget>()
+                .unwrap();
+        });
+        let target = sim.world().spawn(TestComponent(7)).id();
+        let entity = sim.world().spawn(TestTarget(target)).id();
+        sim.simulate();
+        let target_id = *sim.world().get::<SimEntityId>(target).unwrap();
+        let id = *sim.world().get::<SimEntityId>(entity).unwrap();
+
+        let snapshot = sim.sim.sim_world.save_snapshot().unwrap();
+        sim.sim.sim_world.load_snapshot(&snapshot).unwrap();
+
+        let entity_map = sim_entity_map(sim.world());
+        assert_eq!(
+            sim.world().get::<TestTarget>(entity_map[&id]),
+            Some(&TestTarget(entity_map[&target_id]))
+        );
+    }
+
+    #[test]
+    fn test_reflect_registration() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_reflect::<TestReflectComponent>().unwrap();
+        });
+        let entity = sim.world().spawn(TestReflectComponent(7)).id();
+        sim.simulate();
+        let id = *sim.world().get::<SimEntityId>(entity).unwrap();
+
+        let state = sim.request(AllState);
+        assert_eq!(state.entities.len(), 1);
+
+        let snapshot = sim.sim.sim_world.save_snapshot().unwrap();
+        sim.world()
+            .entity_mut(entity)
+            .insert(TestReflectComponent(1));
+        sim.sim.sim_world.load_snapshot(&snapshot).unwrap();
+
+        let entity = sim_entity_map(sim.world())[&id];
+        assert_eq!(
+            sim.world().get::<TestReflectComponent>(entity),
+            Some(&TestReflectComponent(7))
+        );
+    }
+
+    #[derive(Component)]
+    struct TestScratch;
+
+    #[test]
+    fn test_filtered_snapshot() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+        });
+        sim.world().spawn(TestComponent(1));
+        sim.world().spawn((TestComponent(2), TestScratch));
+        sim.simulate();
+
+        let filter = SaveFilter::new().without::<TestScratch>();
+        let snapshot = SimSnapshot::new_filtered(&mut sim.sim.sim_world, &filter);
+
+        assert_eq!(snapshot.entities.len(), 1);
+    }
+
+    #[test]
+    fn test_hierarchy_round_trip() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+            builder.register_hierarchy().unwrap();
+        });
+        let parent = sim.world().spawn(TestComponent(1)).id();
+        let child = sim.world().spawn(TestComponent(2)).set_parent(parent).id();
+        sim.simulate();
+        let parent_id = *sim.world().get::<SimEntityId>(parent).unwrap();
+        let child_id = *sim.world().get::<SimEntityId>(child).unwrap();
+
+        let snapshot = sim.sim.sim_world.save_snapshot().unwrap();
+        sim.sim.sim_world.load_snapshot(&snapshot).unwrap();
+
+        let entity_map = sim_entity_map(sim.world());
+        let parent = entity_map[&parent_id];
+        let child = entity_map[&child_id];
+        assert_eq!(
+            sim.world().get::<Parent>(child).map(|p| p.get()),
+            Some(parent)
+        );
+        assert_eq!(
+            sim.world().get::<Children>(parent).map(|c| c.to_vec()),
+            Some(vec![child])
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Per type schema versions. A [`SaveId`] type declares its current version with
+//! [`SaveId::save_version`], which is written next to every payload of it. When a payload with an
+//! older version is deserialized it is passed through the upgrade functions registered with
+//! [`GameSerDeRegistry::register_component_upgrade`] one version at a time until it reaches the
+//! current version, so only the types that changed need migrations.
+//!
+//! Versions aren't part of the [`registry_hash`](GameSerDeRegistry::registry_hash) so saves written
+//! before a version bump still load.
+
+use bevy::utils::HashMap;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+
+use super::{DecodeError, GameSerDeRegistry, SaveId, SimFormat, SimSerializer};
+
+/// The schema version of a serialized component or resource
+pub type SchemaVersion = u16;
+
+/// Upgrades a decompressed payload from one version to the next
+pub type UpgradeFn = Arc<dyn Fn(SimFormat, &[u8]) -> Result<Vec<u8>, DecodeError> + Send + Sync>;
+
+impl GameSerDeRegistry {
+    /// Registers a function upgrading payloads of the component C from the given version to the
+    /// next one by decoding them as Old and encoding the result as New
+    pub fn register_component_upgrade<C, Old, New>(
+        &mut self,
+        from_version: SchemaVersion,
+        upgrade: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) where
+        C: SaveId,
+        Old: DeserializeOwned,
+        New: Serialize,
+    {
+        self.component_upgrades.insert(
+            (C::save_id_const(), from_version),
+            typed_upgrade_fn(upgrade),
+        );
+    }
+
+    /// Registers a function upgrading payloads of the resource R from the given version to the
+    /// next one by decoding them as Old and encoding the result as New
+    pub fn register_resource_upgrade<R, Old, New>(
+        &mut self,
+        from_version: SchemaVersion,
+        upgrade: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) where
+        R: SaveId,
+        Old: DeserializeOwned,
+        New: Serialize,
+    {
+        self.resource_upgrades.insert(
+            (R::save_id_const(), from_version),
+            typed_upgrade_fn(upgrade),
+        );
+    }
+
+    /// Upgrades the given component payload from the given version to the current version of the
+    /// component
+    pub(crate) fn upgrade_component(
+        &self,
+        id: u16,
+        version: SchemaVersion,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, DecodeError> {
+        let current = self.component_versions.get(&id).copied().unwrap_or(0);
+        upgrade(
+            &self.component_upgrades,
+            self.format,
+            id,
+            version,
+            current,
+            data,
+        )
+    }
+
+    /// Upgrades the given resource payload from the given version to the current version of the
+    /// resource
+    pub(crate) fn upgrade_resource(
+        &self,
+        id: u16,
+        version: SchemaVersion,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, DecodeError> {
+        let current = self.resource_versions.get(&id).copied().unwrap_or(0);
+        upgrade(
+            &self.resource_upgrades,
+            self.format,
+            id,
+            version,
+            current,
+            data,
+        )
+    }
+}
+
+fn typed_upgrade_fn<Old, New>(upgrade: impl Fn(Old) -> New + Send + Sync + 'static) -> UpgradeFn
+where
+    Old: DeserializeOwned,
+    New: Serialize,
+{
+    Arc::new(move |format: SimFormat, data: &[u8]| {
+        let old = format.try_decode::<Old>(data)?;
+        format
+            .encode(&upgrade(old))
+            .ok_or_else(|| DecodeError::new(0, "failed to encode upgraded data"))
+    })
+}
+
+fn upgrade(
+    upgrades: &HashMap<(u16, SchemaVersion), UpgradeFn>,
+    format: SimFormat,
+    id: u16,
+    mut version: SchemaVersion,
+    current: SchemaVersion,
+    mut data: Vec<u8>,
+) -> Result<Vec<u8>, DecodeError> {
+    if version > current {
+        return Err(DecodeError::new(
+            0,
+            format!(
+                "version {} is newer than the current version {}",
+                version, current
+            ),
+        ));
+    }
+    while version < current {
+        let Some(upgrade_fn) = upgrades.get(&(id, version)) else {
+            return Err(DecodeError::new(
+                0,
+                format!("no upgrade registered from version {}", version),
+            ));
+        };
+        data = upgrade_fn(format, &data)?;
+        version += 1;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod test {
+    use bevy::prelude::{Component, Resource, World};
+    use serde::{Deserialize, Serialize};
+
+    use crate::saving::{
+        component_deserialize_onto, component_serialize_from, resource_deserialize_into_world,
+        serialize_resource_from_world, version::SchemaVersion, ComponentBinaryState,
+        GameSerDeRegistry, RegistryError, SaveId, SimComponentId,
+    };
+    use crate::testing::fixtures::bincode_save_id;
+
+    #[derive(Serialize, Deserialize)]
+    struct HealthV0(u32);
+
+    #[derive(Debug, PartialEq, Component, Serialize, Deserialize)]
+    struct Health {
+        current: u32,
+        max: u32,
+    }
+
+    impl SaveId for Health {
+        fn save_id(&self) -> SimComponentId {
+            25
+        }
+
+        fn save_id_const() -> SimComponentId
+        where
+            Self: Sized,
+        {
+            25
+        }
+
+        fn save_version() -> SchemaVersion
+        where
+            Self: Sized,
+        {
+            1
+        }
+
+        fn to_binary(&self) -> Option<Vec<u8>> {
+            bincode::serialize(self).ok()
+        }
+    }
+
+    #[test]
+    fn test_component_upgrade() {
+        let mut registry = GameSerDeRegistry::new();
+        registry.register_component::<Health>().unwrap();
+        registry.register_component_upgrade::<Health, HealthV0, Health>(0, |old| Health {
+            current: old.0,
+            max: old.0,
+        });
+
+        let mut world = World::new();
+        let mut entity = world.spawn_empty();
+        let old = ComponentBinaryState {
+            id: 25,
+            version: 0,
+            component: bincode::serialize(&HealthV0(5)).unwrap(),
+        };
+        registry
+            .deserialize_component_onto(&old, &mut entity)
+            .unwrap();
+        assert_eq!(entity.get::<Health>(), Some(&Health { current: 5, max: 5 }));
+
+        let newer = ComponentBinaryState { version: 2, ..old };
+        assert!(registry
+            .deserialize_component_onto(&newer, &mut entity)
+            .is_err());
+    }
+
+    #[test]
+    fn test_replace_component_fns() {
+        let mut registry = GameSerDeRegistry::new();
+        assert_eq!(
+            registry.replace_component_fns(
+                25,
+                component_serialize_from::<Health>,
+                component_deserialize_onto::<Health>,
+                2,
+            ),
+            Err(RegistryError::UnknownComponentId { id: 25 })
+        );
+
+        registry.register_component::<Health>().unwrap();
+        let (serialize_fn, deserialize_fn) = registry
+            .replace_component_fns(
+                25,
+                component_serialize_from::<Health>,
+                component_deserialize_onto::<Health>,
+                2,
+            )
+            .unwrap();
+        assert!(serialize_fn.is_some() && deserialize_fn.is_some());
+        assert_eq!(registry.component_versions[&25], 2);
+    }
+
+    #[derive(Resource, Serialize, Deserialize)]
+    struct Weather(u32);
+
+    bincode_save_id!(Weather, 4);
+
+    #[test]
+    fn test_replace_resource_fns() {
+        let mut registry = GameSerDeRegistry::new();
+        assert_eq!(
+            registry.replace_resource_fns(
+                4,
+                resource_deserialize_into_world::<Weather>,
+                serialize_resource_from_world::<Weather>,
+                1,
+            ),
+            Err(RegistryError::UnknownResourceId { id: 4 })
+        );
+        assert!(!registry.resource_de_map.contains_key(&4));
+
+        registry.register_resource::<Weather>().unwrap();
+        let (deserialize_fn, serialize_fn) = registry
+            .replace_resource_fns(
+                4,
+                resource_deserialize_into_world::<Weather>,
+                serialize_resource_from_world::<Weather>,
+                1,
+            )
+            .unwrap();
+        assert!(deserialize_fn.is_some() && serialize_fn.is_some());
+        assert_eq!(registry.resource_versions[&4], 1);
+    }
+}
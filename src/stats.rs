@@ -0,0 +1,110 @@
+//! Per tick replication metrics, used to find replication hotspots. Enable with
+//! [`GameBuilder::enable_replication_stats`](crate::game_builder::GameBuilder::enable_replication_stats)
+//! and read the [`ReplicationStats`] resource of the sim world after requesting states.
+
+use std::time::Duration;
+
+use bevy::{
+    prelude::{Resource, World},
+    utils::HashMap,
+};
+
+use crate::{
+    change_detection::{ChangeSet, TrackedDespawns},
+    runner::SimTick,
+};
+
+/// Resource holding the replication metrics of the current tick. Reset after every simulated tick
+#[derive(Default, Clone, Debug, PartialEq, Resource)]
+pub struct ReplicationStats {
+    /// The sim tick the stats are for
+    pub tick: u64,
+    /// Number of entities marked changed in the tick
+    pub entities_changed: usize,
+    /// Number of despawns not yet seen by every player
+    pub despawns_pending: usize,
+    /// Bytes of component and resource payloads serialized for each player since the tick was
+    /// simulated
+    pub bytes_per_player: HashMap<usize, usize>,
+    /// Time spent building [`StateDif`](crate::requests::state_dif::StateDif)s since the tick was
+    /// simulated
+    pub diff_time: Duration,
+}
+
+impl ReplicationStats {
+    /// Records a state of the given size made for the given player in the given time
+    pub fn record_state(&mut self, player_id: usize, bytes: usize, elapsed: Duration) {
+        *self.bytes_per_player.entry(player_id).or_default() += bytes;
+        self.diff_time += elapsed;
+    }
+
+    /// Returns the total bytes serialized for every player
+    pub fn total_bytes(&self) -> usize {
+        self.bytes_per_player.values().sum()
+    }
+}
+
+/// Resets the [`ReplicationStats`] of the given world if it has one and fills in the changes of the
+/// current tick. Called by the [`GameRuntime`](crate::runner::GameRuntime) after every simulated tick
+pub fn record_replication_stats(world: &mut World) {
+    if !world.contains_resource::<ReplicationStats>() {
+        return;
+    }
+    let tick = world
+        .get_resource::<SimTick>()
+        .map(|tick| tick.0)
+        .unwrap_or_default();
+    let entities_changed = world
+        .get_resource::<ChangeSet>()
+        .map(|change_set| {
+            change_set
+                .entities
+                .values()
+                .filter(|changed| changed.tick == tick)
+                .count()
+        })
+        .unwrap_or_default();
+    let despawns_pending = world
+        .get_resource::<TrackedDespawns>()
+        .map(|despawns| despawns.despawned_objects.len())
+        .unwrap_or_default();
+
+    world.insert_resource(ReplicationStats {
+        tick,
+        entities_changed,
+        despawns_pending,
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use super::ReplicationStats;
+    use crate::{
+        requests::state_dif::StateDif,
+        testing::{fixtures::TestComponent, test_game},
+    };
+
+    #[test]
+    fn test_replication_stats() {
+        let mut game = test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.enable_replication_stats();
+        let mut sim = game.build_standalone();
+
+        sim.sim_world.world.spawn(TestComponent(10));
+        sim.sim_world.world.spawn(TestComponent(10));
+        sim.simulate();
+        sim.request(StateDif { for_player: 0 });
+
+        let stats = sim.sim_world.world.resource::<ReplicationStats>();
+        assert_eq!(stats.entities_changed, 2);
+        assert!(stats.bytes_per_player[&0] > 0);
+
+        sim.simulate();
+        let stats = sim.sim_world.world.resource::<ReplicationStats>();
+        assert_eq!(stats.entities_changed, 0);
+        assert_eq!(stats.total_bytes(), 0);
+    }
+}
@@ -0,0 +1,244 @@
+//! Helpers for unit testing registrations and commands against a sim, enabled with the `testing`
+//! feature.
+//!
+//! ```ignore
+//! let mut sim = TestSim::with_builder(|builder| {
+//!     builder.register_component::<Health>().unwrap();
+//! });
+//! sim.world().spawn(Health(10));
+//! sim.simulate();
+//!
+//! let state = sim.request(AllState);
+//! assert_state_contains(&state, &Health(10));
+//! ```
+
+use bevy::prelude::World;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+
+use crate::{
+    command::GameCommand,
+    game_builder::GameBuilder,
+    requests::{SimRequest, SimState},
+    runner::{StandaloneSim, TurnBasedGameRunner},
+    saving::{GameSerDeRegistry, SaveId},
+};
+
+/// A minimal prebuilt standalone sim using a [`TurnBasedGameRunner`] with an empty turn schedule
+pub struct TestSim {
+    pub sim: StandaloneSim<TurnBasedGameRunner>,
+}
+
+impl TestSim {
+    /// Creates a sim with no registrations and no players
+    pub fn new() -> TestSim {
+        TestSim::with_builder(|_| {})
+    }
+
+    /// Creates a sim after running the given setup function on the builder. Use this to register
+    /// components, resources, and players
+    pub fn with_builder(setup: impl FnOnce(&mut GameBuilder<TurnBasedGameRunner>)) -> TestSim {
+        let mut game = test_game();
+        setup(&mut game);
+        TestSim {
+            sim: game.build_standalone(),
+        }
+    }
+
+    /// The sim world
+    pub fn world(&mut self) -> &mut World {
+        &mut self.sim.sim_world.world
+    }
+
+    /// Executes all queued commands and simulates the game once
+    pub fn simulate(&mut self) {
+        self.sim.simulate();
+    }
+
+    /// Queues the given command to be executed on the next [`TestSim::simulate`]
+    pub fn add_command<C>(&mut self, command: C)
+    where
+        C: GameCommand + Clone,
+    {
+        self.sim.sim_world.game_commands_mut().add(command);
+    }
+
+    /// Makes a request to the sim world and returns the results
+    pub fn request<Request: SimRequest>(&mut self, request: Request) -> Request::Output {
+        self.sim.request(request)
+    }
+}
+
+impl Default for TestSim {
+    fn default() -> Self {
+        TestSim::new()
+    }
+}
+
+/// A [`GameBuilder`] using a [`TurnBasedGameRunner`] with an empty turn schedule
+pub fn test_game() -> GameBuilder<TurnBasedGameRunner> {
+    GameBuilder::<TurnBasedGameRunner>::new_game(TurnBasedGameRunner {
+        turn_schedule: Default::default(),
+    })
+}
+
+/// Adds the given number of players that need state to the builder and returns their ids
+pub fn mock_players(game: &mut GameBuilder<TurnBasedGameRunner>, count: usize) -> Vec<usize> {
+    (0..count).map(|_| game.add_player(true).0).collect()
+}
+
+/// Panics if no entity or player in the given state has a component of type C equal to expected
+pub fn assert_state_contains<C>(state: &SimState, expected: &C)
+where
+    C: SaveId + DeserializeOwned + PartialEq + Debug,
+{
+    let found = state
+        .components::<C>()
+        .map(|(_, component)| component)
+        .chain(
+            state
+                .players
+                .iter()
+                .filter_map(|player| state.player_component::<C>(player.player_id.id())),
+        )
+        .any(|component| component == *expected);
+
+    assert!(found, "SimState does not contain component {:?}", expected);
+}
+
+/// Checks that every component and resource in the given state can be deserialized using the given
+/// registry. Returns a description of the first failure
+pub fn check_state_round_trip(
+    state: &SimState,
+    registry: &GameSerDeRegistry,
+) -> Result<(), String> {
+    let mut world = World::new();
+
+    let components = state
+        .entities
+        .iter()
+        .map(|entity| &entity.components)
+        .chain(state.players.iter().map(|player| &player.components))
+        .flatten();
+    for component in components {
+        let mut entity = world.spawn_empty();
+        registry
+            .deserialize_component_onto(component, &mut entity)
+            .map_err(|error| error.to_string())?;
+    }
+
+    for resource in state.resources.iter() {
+        registry
+            .deserialize_resource(resource.clone(), &mut world)
+            .map_err(|error| error.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Panics if any component or resource in the given state can't be deserialized using the given
+/// registry
+pub fn assert_state_round_trips(state: &SimState, registry: &GameSerDeRegistry) {
+    if let Err(error) = check_state_round_trip(state, registry) {
+        panic!("SimState failed to round trip: {}", error);
+    }
+}
+
+/// Types shared by the tests in this crate
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use bevy::prelude::{Component, Reflect, World};
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        command::{CommandError, GameCommand},
+        game_builder::GameBuilder,
+        runner::{StandaloneSim, TurnBasedGameRunner},
+    };
+
+    /// Implements [`SaveId`](crate::saving::SaveId) for the given type with the given id, saving
+    /// it with bincode
+    macro_rules! bincode_save_id {
+        ($type:ty, $id:expr) => {
+            impl $crate::saving::SaveId for $type {
+                fn save_id(&self) -> $crate::saving::SimComponentId {
+                    $id
+                }
+
+                fn save_id_const() -> $crate::saving::SimComponentId
+                where
+                    Self: Sized,
+                {
+                    $id
+                }
+
+                fn to_binary(&self) -> Option<Vec<u8>> {
+                    bincode::serialize(self).ok()
+                }
+            }
+        };
+    }
+    pub(crate) use bincode_save_id;
+
+    /// A component saved with bincode under id 25
+    #[derive(Default, Clone, Debug, PartialEq, Component, Reflect, Serialize, Deserialize)]
+    pub struct TestComponent(pub u32);
+
+    bincode_save_id!(TestComponent, 25);
+
+    /// A command spawning a [`TestComponent`] with the given value
+    #[derive(Clone, Debug, Reflect)]
+    pub struct SpawnUnit(pub u32);
+
+    impl GameCommand for SpawnUnit {
+        fn execute(&mut self, world: &mut World) -> Result<(), CommandError> {
+            world.spawn(TestComponent(self.0));
+            Ok(())
+        }
+    }
+
+    /// Builds a [`test_game`](super::test_game) with [`TestComponent`] and [`SpawnUnit`]
+    /// registered, letting the caller configure the builder first
+    pub fn build_sim(
+        configure: impl FnOnce(&mut GameBuilder<TurnBasedGameRunner>),
+    ) -> StandaloneSim<TurnBasedGameRunner> {
+        let mut game = super::test_game();
+        game.register_component::<TestComponent>().unwrap();
+        game.register_command::<SpawnUnit>();
+        configure(&mut game);
+        game.build_standalone()
+    }
+
+    /// The sorted values of every [`TestComponent`] in the sim world
+    pub fn unit_values(sim: &mut StandaloneSim<TurnBasedGameRunner>) -> Vec<u32> {
+        let mut query = sim.sim_world.world.query::<&TestComponent>();
+        let mut values: Vec<u32> = query
+            .iter(&sim.sim_world.world)
+            .map(|unit| unit.0)
+            .collect();
+        values.sort();
+        values
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+
+    use crate::{requests::state_dif::StateDif, testing::fixtures::TestComponent};
+
+    use super::{assert_state_contains, assert_state_round_trips, mock_players, TestSim};
+
+    #[test]
+    fn test_test_sim() {
+        let mut sim = TestSim::with_builder(|builder| {
+            builder.register_component::<TestComponent>().unwrap();
+            mock_players(builder, 2);
+        });
+        sim.world().spawn(TestComponent(4));
+        sim.simulate();
+
+        let state = sim.request(StateDif { for_player: 1 });
+        assert_state_contains(&state, &TestComponent(4));
+        assert_state_round_trips(&state, &sim.sim.sim_world.registry);
+    }
+}